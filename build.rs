@@ -0,0 +1,40 @@
+//! Embed build details shown by `lst version`
+use std::path::Path;
+use std::process::Command;
+
+fn main() {
+    let commit = Command::new("git")
+        .args(["rev-parse", "--short", "HEAD"])
+        .output()
+        .ok()
+        .filter(|out| out.status.success())
+        .and_then(|out| String::from_utf8(out.stdout).ok())
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())
+        .unwrap_or_else(|| "unknown".to_string());
+
+    let mut features: Vec<String> = std::env::vars()
+        .filter_map(|(key, _)| key.strip_prefix("CARGO_FEATURE_").map(|f| f.to_lowercase().replace('_', "-")))
+        .collect();
+    features.sort();
+
+    println!("cargo:rustc-env=LST_GIT_COMMIT={}", commit);
+    println!("cargo:rustc-env=LST_FEATURES={}", features.join(","));
+    println!("cargo:rustc-env=LST_TARGET={}", std::env::var("TARGET").unwrap_or_default());
+    println!("cargo:rustc-env=LST_PROFILE={}", std::env::var("PROFILE").unwrap_or_default());
+
+    // Rebuild when HEAD moves, either to another branch or to a new commit on it
+    let head = Path::new(".git/HEAD");
+    if head.exists() {
+        println!("cargo:rerun-if-changed=.git/HEAD");
+        let reference = std::fs::read_to_string(head).unwrap_or_default();
+        if let Some(reference) = reference.strip_prefix("ref: ") {
+            let path = Path::new(".git").join(reference.trim());
+            if path.exists() {
+                println!("cargo:rerun-if-changed={}", path.display());
+            }
+        }
+    } else {
+        println!("cargo:rerun-if-changed=build.rs");
+    }
+}
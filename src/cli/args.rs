@@ -1,54 +1,557 @@
-use clap::{Parser, Subcommand};
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use clap::{CommandFactory, Parser, Subcommand};
+
+use crate::core::filters::CaseMode;
+use crate::core::meta::TimeField;
+use crate::core::sort::SortKey;
+use crate::core::tree::UNLIMITED_DEPTH;
+use crate::output::columns::Columns;
+use crate::output::formatter::Truncate;
+use crate::output::palette::{PaletteName, SizeThresholds};
+use crate::output::printer::{OutputFormat, RootHeader, DEFAULT_OUTPUT_BUFFER};
+use crate::output::terminal::{ColorMode, TreeStyle};
 
 /// Command-line arguments for lst
 #[derive(Parser, Debug)]
-#[command(name = "lst", about = "A fast, colorful CLI tool for listing directories")]
+#[command(name = "lst", version = env!("CARGO_PKG_VERSION"), about = "A fast, colorful CLI tool for listing directories")]
 pub struct Cli {
-    /// Path to inspect (file or directory)
+    /// Path to inspect (file or directory, or `-` to preview stdin)
     #[arg(global = true)]
     pub path: Option<String>,
 
     #[command(subcommand)]
     pub command: Option<Commands>,
 
-    /// Show hidden files and directories
+    /// Show hidden files and directories (`.` and `..` are never listed)
     #[arg(short, long, global = true, default_value_t = false)]
     pub all: bool,
 
-    /// Max depth of traversal (0 for unlimited)
+    /// Show hidden files and directories like --all, but leave out the root's heavy dot-directories (`.git`, ...) that --all lists unexpanded
+    #[arg(short = 'A', long, global = true, default_value_t = false)]
+    pub almost_all: bool,
+
+    /// Show only hidden entries (and what's inside hidden directories), with their ancestors
+    #[arg(long, global = true, default_value_t = false)]
+    pub hidden_only: bool,
+
+    /// Show only empty directories (hidden entries count with --all) with their ancestors, like `find -type d -empty`
+    #[arg(long, visible_alias = "only-empty-dirs", global = true, default_value_t = false, conflicts_with = "git_modified")]
+    pub empty_dirs: bool,
+
+    /// Show only files with uncommitted changes (staged, modified or untracked) with their ancestors; with --all also ignored files
+    #[arg(long, global = true, default_value_t = false, conflicts_with = "hidden_only")]
+    pub git_modified: bool,
+
+    /// List skipped heavy directories as `name/ [skipped]` without descending into them
+    #[arg(long, global = true, default_value_t = false, conflicts_with = "no_skip")]
+    pub show_skipped: bool,
+
+    /// List directories with this name without descending into them (repeatable), e.g. `--stop-at src`
+    #[arg(long, global = true, value_name = "NAME")]
+    pub stop_at: Vec<OsString>,
+
+    /// List directories containing this file or directory without descending into them (repeatable), e.g. `--stop-at-marker .git`
+    #[arg(long, global = true, value_name = "FILE")]
+    pub stop_at_marker: Vec<OsString>,
+
+    /// Count --depth from the nearest directory containing this file (repeatable), e.g. `--depth-from-marker Cargo.toml`, so each sub-project of a monorepo shows the same levels
+    #[arg(long, global = true, value_name = "FILE")]
+    pub depth_from_marker: Vec<OsString>,
+
+    /// List directories with more than N files below them on one line, with their file count and total size
+    #[arg(long, global = true, value_name = "N")]
+    pub collapse_over: Option<usize>,
+
+    /// Don't skip common heavy directories such as .git, node_modules and target
+    #[arg(long, global = true, default_value_t = false)]
+    pub no_skip: bool,
+
+    /// Detect the project type (Cargo.toml, package.json, go.mod, pyproject.toml) and skip only what it generates, instead of the common list
+    #[arg(long, global = true, default_value_t = false, conflicts_with = "no_skip")]
+    pub smart: bool,
+
+    /// Show only the directory layout: no files, sizes or counts (like `tree -d`)
+    #[arg(long, global = true, default_value_t = false, conflicts_with_all = ["stats", "dupes", "counts", "mark_empty"])]
+    pub skeleton: bool,
+
+    /// Levels below the root to show: 1 lists only immediate children (like `tree -L 1`), 0 is unlimited
     #[arg(short, long, global = true, default_value_t = 1)]
     pub depth: usize,
 
-    /// Export tree to a file (plain text, no color)
+    /// Export tree to a file (no color unless --color always)
     #[arg(short, long, global = true)]
     pub output: Option<String>,
 
-    /// Output format as JSON
-    #[arg(short, long, global = true, default_value_t = false)]
+    /// Append to the output file instead of creating it
+    #[arg(long, global = true, default_value_t = false, requires = "output", conflicts_with = "force")]
+    pub append: bool,
+
+    /// Overwrite the output file if it already exists
+    #[arg(long, global = true, default_value_t = false, requires = "output")]
+    pub force: bool,
+
+    /// Append a type indicator to names (* executable, @ symlink, | FIFO, = socket)
+    #[arg(short = 'F', long, global = true, default_value_t = false)]
+    pub classify: bool,
+
+    /// Language for file previews when it can't be inferred (e.g. `rs` for `lst - --language rs`)
+    #[arg(long, global = true, value_name = "EXT")]
+    pub language: Option<String>,
+
+    /// For a file path, show the tree N levels above it with the file emphasized instead of previewing it
+    #[arg(long, global = true, value_name = "N")]
+    pub context: Option<usize>,
+
+    /// Below a directory's tree, show the first few files matching this glob highlighted (repeatable), e.g. `--preview README.md`
+    #[arg(long, global = true, value_name = "GLOB")]
+    pub preview: Vec<String>,
+
+    /// Stop file previews after N lines
+    #[arg(long, global = true, value_name = "N")]
+    pub max_preview_lines: Option<usize>,
+
+    /// Don't read files larger than this in content search or previews, e.g. `512K` or `1G` (0 for no limit)
+    #[arg(long, global = true, value_name = "SIZE", default_value = "10M", value_parser = parse_size)]
+    pub max_filesize: u64,
+
+    /// Preview binary files as text (invalid UTF-8 is replaced) instead of summarizing them
+    #[arg(long, global = true, default_value_t = false)]
+    pub force_text: bool,
+
+    /// List immediate children one per line, without tree glyphs
+    #[arg(short = '1', long, global = true, default_value_t = false, conflicts_with = "depth")]
+    pub oneline: bool,
+
+    /// Arrange immediate children in columns sized to the terminal, like `ls`
+    #[arg(long, global = true, default_value_t = false, conflicts_with_all = ["depth", "oneline", "format", "json"])]
+    pub grid: bool,
+
+    /// Order siblings by name, size, modified time or extension; later keys break ties (`size,name`)
+    #[arg(long, global = true, value_enum, value_name = "KEY", value_delimiter = ',')]
+    pub sort: Vec<SortKey>,
+
+    /// Sort key for directories, listed before files (overrides --sort)
+    #[arg(long, global = true, value_enum, value_name = "KEY")]
+    pub sort_dirs: Option<SortKey>,
+
+    /// Sort key for files, listed after directories (overrides --sort)
+    #[arg(long, global = true, value_enum, value_name = "KEY")]
+    pub sort_files: Option<SortKey>,
+
+    /// Don't descend into other mounted filesystems, like `find -xdev` (mount points are still listed)
+    #[arg(short = 'x', long, global = true, default_value_t = false)]
+    pub one_file_system: bool,
+
+    /// Write sizes with exactly N decimal places (whole bytes stay `0 B`, `12 B`)
+    #[arg(long, global = true, value_name = "N", value_parser = clap::value_parser!(u8).range(0..=6))]
+    pub size_precision: Option<u8>,
+
+    /// Right-align sizes to at least N columns
+    #[arg(long, global = true, value_name = "N")]
+    pub size_width: Option<usize>,
+
+    /// Shorten names wider than N columns with an `…` (off by default)
+    #[arg(long, global = true, value_name = "N", value_parser = clap::value_parser!(u16).range(2..))]
+    pub max_name_width: Option<u16>,
+
+    /// Where names over --max-name-width are cut: `middle` keeps the extension
+    #[arg(long, global = true, value_enum, value_name = "WHERE", default_value_t = Truncate::Middle, requires = "max_name_width")]
+    pub truncate: Truncate,
+
+    /// Timestamp for the `mtime` column and the time sort: modified, created or accessed
+    #[arg(long, global = true, value_enum, value_name = "FIELD", default_value_t = TimeField::Modified)]
+    pub time_field: TimeField,
+
+    /// With a modified-time sort, date each directory by its newest descendant
+    #[arg(long, global = true, default_value_t = false)]
+    pub dir_mtime_recursive: bool,
+
+    /// Print control characters in names as they are; by default they're escaped on a terminal, like `ls -b`
+    #[arg(long, global = true, default_value_t = false)]
+    pub show_control_chars: bool,
+
+    /// Shell-quote names with spaces or shell metacharacters (whole paths with `--full-path`), for copy-pasting into a shell
+    #[arg(long, global = true, default_value_t = false)]
+    pub quote_names: bool,
+
+    /// Make names in the tree clickable `file://` links in terminals that support OSC 8 (not when redirected or exported)
+    #[arg(long, global = true, default_value_t = false)]
+    pub hyperlinks: bool,
+
+    /// Show each expanded directory's number of listed children, like `src/ (12)`
+    #[arg(long, global = true, default_value_t = false)]
+    pub counts: bool,
+
+    /// List a dim `(empty)` line under expanded directories with nothing listed in them
+    #[arg(long, global = true, default_value_t = false)]
+    pub mark_empty: bool,
+
+    /// Mark files with identical contents as `[dup #N]` (`dup_group` in JSON)
+    #[arg(long, global = true, default_value_t = false)]
+    pub dupes: bool,
+
+    /// Leave out entries matching a glob (repeatable); patterns with `/` match the path below the root
+    #[arg(long, global = true, value_name = "GLOB")]
+    pub exclude: Vec<String>,
+
+    /// Read exclude globs from a file, one per line (`#` starts a comment; repeatable)
+    #[arg(long, global = true, value_name = "PATH")]
+    pub exclude_from: Vec<String>,
+
+    /// Letter case in search patterns and exclude globs: sensitive, insensitive or smart (exact if the pattern has uppercase)
+    #[arg(long, global = true, value_enum, value_name = "MODE", default_value_t = CaseMode::Insensitive)]
+    pub case: CaseMode,
+
+    /// Ignore case for ASCII letters only: faster on huge trees, but `café` no longer finds `CAFÉ`
+    #[arg(long, global = true, default_value_t = false)]
+    pub ascii_case: bool,
+
+    /// Follow the tree with a `N directories, M files` report line, like `tree`
+    #[arg(long, global = true, default_value_t = false, overrides_with = "no_report")]
+    pub report: bool,
+
+    /// Leave out the report line (the default; for `tree --noreport` habits and overriding --report)
+    #[arg(long, global = true, default_value_t = false, alias = "noreport", overrides_with = "report")]
+    pub no_report: bool,
+
+    /// Print only the `N directories, M files` counts, without the tree
+    #[arg(long, global = true, default_value_t = false, conflicts_with_all = ["stats", "grid"])]
+    pub report_only: bool,
+
+    /// Follow the tree with file counts and total size per extension
+    #[arg(long, global = true, default_value_t = false)]
+    pub stats: bool,
+
+    /// Count each hard link of a file in --stats totals (apparent size) instead of the file once
+    #[arg(long, global = true, default_value_t = false)]
+    pub count_links: bool,
+
+    /// Count the contents of skipped directories like `node_modules` in --stats totals, though the tree leaves them out
+    #[arg(long, global = true, default_value_t = false, requires = "stats", conflicts_with = "no_skip")]
+    pub stats_include_skipped: bool,
+
+    /// Output format (default text, or json for an `-o` file ending in .json)
+    #[arg(long, global = true, value_enum)]
+    pub format: Option<OutputFormat>,
+
+    /// Deprecated: same as --format json
+    #[arg(short, long, global = true, default_value_t = false, conflicts_with = "format")]
     pub json: bool,
+
+    /// Bytes of output batched per write to the terminal or pipe
+    #[arg(long, global = true, value_name = "BYTES", default_value_t = DEFAULT_OUTPUT_BUFFER)]
+    pub output_buffer: usize,
+
+    /// Print JSON on a single line, e.g. for piping into `jq` or logs
+    #[arg(long, global = true, default_value_t = false)]
+    pub compact: bool,
+
+    /// When to use colors; `always` also keeps them in files written with -o
+    #[arg(long, global = true, value_enum, value_name = "WHEN", default_value_t = ColorMode::Auto)]
+    pub color: ColorMode,
+
+    /// Print each entry's path relative to the root instead of its name, like `tree -f`
+    #[arg(short = 'f', long, global = true, default_value_t = false)]
+    pub full_path: bool,
+
+    /// Show the root as its full path with the home directory as `~`, also in JSON paths
+    #[arg(long, global = true, default_value_t = false)]
+    pub tilde: bool,
+
+    /// Text for the tree's first line and the root's JSON name, instead of the root directory's name
+    #[arg(long, global = true, value_name = "TEXT")]
+    pub header: Option<String>,
+
+    /// Leave out the root line above the tree
+    #[arg(long, global = true, default_value_t = false, conflicts_with = "header")]
+    pub no_header: bool,
+
+    /// Pad file names so the sizes of siblings line up
+    #[arg(long, global = true, default_value_t = false)]
+    pub pad_names: bool,
+
+    /// Color the tree's branch glyphs by nesting level
+    #[arg(long, global = true, default_value_t = false)]
+    pub depth_colors: bool,
+
+    /// Print names only, without sizes; files aren't stat-ed unless another flag needs it
+    #[arg(long, global = true, default_value_t = false)]
+    pub no_size: bool,
+
+    /// Follow each file's size with a bar (▁ to █) scaled to the largest file beside it
+    #[arg(long, global = true, default_value_t = false)]
+    pub bars: bool,
+
+    /// Leave out the sizes of files with these extensions, e.g. `lock,sum` (case-insensitive)
+    #[arg(long, global = true, value_name = "EXT[,EXT...]", value_delimiter = ',')]
+    pub no_size_for: Vec<String>,
+
+    /// Fields of each line in order, from name, size, mtime and perms (e.g. `perms,size,name`)
+    #[arg(long, global = true, value_name = "LIST", default_value = "name,size")]
+    pub columns: Columns,
+
+    /// Branch glyphs: detected for the terminal and ASCII for `-o` files unless set
+    #[arg(long, global = true, value_enum, default_value_t = TreeStyle::Auto)]
+    pub tree_style: TreeStyle,
+
+    /// Color scheme for names and sizes; single colors can be overridden via LST_COLORS
+    #[arg(long, global = true, value_enum, default_value_t = PaletteName::Default)]
+    pub palette: PaletteName,
+
+    /// Sizes at which the size color turns from small to medium and from medium to large, e.g. `64K,10M`
+    #[arg(long, global = true, value_name = "MEDIUM,LARGE", value_parser = parse_size_thresholds)]
+    pub size_thresholds: Option<SizeThresholds>,
+
+    /// Show entries added, removed or resized since a snapshot saved with `--json -o`
+    #[arg(long, global = true, value_name = "SNAPSHOT", conflicts_with_all = ["output", "watch"])]
+    pub diff: Option<String>,
+
+    /// Keep the tree on screen and redraw it when files change (Ctrl-C to stop)
+    #[arg(short = 'w', long, global = true, default_value_t = false, conflicts_with = "output")]
+    pub watch: bool,
+
+    /// Stop walking after this many seconds and print what was found so far
+    #[arg(long, global = true, value_name = "SECONDS", value_parser = parse_timeout, conflicts_with = "watch")]
+    pub timeout: Option<Duration>,
+
+    /// After the output, print to stderr how long walking, searching, stat-ing and rendering took
+    #[arg(long, global = true, default_value_t = false)]
+    pub time_report: bool,
+
+    /// Log skipped directories and unreadable entries to stderr (-vv adds timings)
+    #[arg(short = 'v', long, global = true, action = clap::ArgAction::Count)]
+    pub verbose: u8,
+
+    /// Never show the progress spinner (it is also skipped when stderr is not a terminal)
+    #[arg(long, global = true, default_value_t = false)]
+    pub no_spinner: bool,
+
+    /// Build the tree from newline-separated paths on stdin instead of walking
+    #[arg(long, default_value_t = false)]
+    pub from_stdin: bool,
+
+    /// Count files per extension across the whole tree instead of printing it
+    #[arg(long, global = true, default_value_t = false, conflicts_with_all = ["output", "watch", "diff"])]
+    pub census: bool,
+
+    /// Print the JSON Schema of the `--json` output and exit
+    #[arg(long, default_value_t = false)]
+    pub print_schema: bool,
+
+    /// Pick a directory at any depth interactively and print only its path, for `cd "$(lst --pick-dir)"`
+    #[arg(long, global = true, default_value_t = false, conflicts_with_all = ["depth", "output", "watch", "diff", "census"])]
+    pub pick_dir: bool,
+
+    /// Print a shell function, `lcd`, that changes to a directory picked with `--pick-dir`, and exit
+    #[arg(long, default_value_t = false)]
+    pub print_shell_integration: bool,
 }
 
 #[derive(Subcommand, Debug)]
 pub enum Commands {
+    /// Print the version, git commit, target and enabled features
+    Version,
+
+    /// Tell which rule lists or leaves out a path below the root, and which flag would change it
+    Explain {
+        /// Entry to explain, e.g. `build` (relative to the current directory)
+        target: PathBuf,
+    },
+
     /// Search for files/directories by name
     Search {
-        /// Pattern to search for (case-insensitive)
+        /// Pattern to search for (case-insensitive unless `--case` says otherwise)
         pattern: String,
+
+        /// Match against the path relative to the root instead of just the name
+        #[arg(long = "path", default_value_t = false, conflicts_with = "content")]
+        match_path: bool,
+
+        /// Search file contents instead of names (binary and very large files are skipped)
+        #[arg(long, default_value_t = false)]
+        content: bool,
+
+        /// Show the number of content matches next to each matching file
+        #[arg(short, long, default_value_t = false, requires = "content")]
+        count: bool,
+
+        /// Print only the paths of matching entries, without the tree
+        #[arg(short = 'l', long, default_value_t = false)]
+        files_with_matches: bool,
+
+        /// Print nothing; exit 0 if anything matched and 1 otherwise
+        #[arg(short, long, default_value_t = false, conflicts_with = "files_with_matches")]
+        quiet: bool,
+
+        /// Walk on several threads, then sort the results into the usual order before printing
+        #[arg(long, default_value_t = false)]
+        parallel: bool,
+
+        /// Print only the shallowest match's path (ties go to the smallest path) and stop walking there
+        #[arg(long, default_value_t = false, conflicts_with_all = ["files_with_matches", "quiet", "count", "max_results"])]
+        first: bool,
+
+        /// Run a command on the matches instead of printing them, like `find -exec`: `{}` is the path,
+        /// and a trailing `+` passes every path to one command (e.g. `--exec 'wc -l {} +'`)
+        #[arg(long, value_name = "CMD", conflicts_with_all = ["files_with_matches", "quiet", "first", "count"])]
+        exec: Option<String>,
+
+        /// With --exec, print the commands instead of running them
+        #[arg(long, default_value_t = false, requires = "exec")]
+        dry_run: bool,
+
+        /// Stop after this many matching entries (ancestor directories are not counted)
+        #[arg(long, value_name = "N")]
+        max_results: Option<usize>,
+
+        /// Extra gitignore-style file to apply (repeatable; lower precedence than .gitignore/.ignore)
+        #[arg(long = "ignore-file", value_name = "PATH")]
+        ignore_files: Vec<String>,
+
+        /// Don't honor .ignore and .rgignore files
+        #[arg(long, default_value_t = false)]
+        no_ignore_dot: bool,
     },
 }
 
 impl Cli {
     /// Parse CLI arguments from environment
     pub fn parse_cli() -> Self {
-        Self::parse()
+        Self::parse().validated().unwrap_or_else(|e| e.exit())
+    }
+
+    /// Reject combinations clap can't express that would otherwise be ignored: listing-only
+    /// flags given to a subcommand, and `--grid` written to a `.json` file
+    fn validated(self) -> Result<Self, clap::Error> {
+        if matches!(self.command, Some(Commands::Search { .. })) {
+            let listing_only = [(self.watch, "--watch"), (self.census, "--census")];
+            if let Some((_, flag)) = listing_only.iter().find(|(set, _)| *set) {
+                let message = format!("{} can't be used with the search subcommand", flag);
+                return Err(Self::command().error(clap::error::ErrorKind::ArgumentConflict, message));
+            }
+        }
+        // A `.json` file would otherwise quietly get JSON instead of the grid
+        if self.grid && self.output_format() == OutputFormat::Json {
+            let message = "--grid can't write JSON; pick an output file that doesn't end in .json";
+            return Err(Self::command().error(clap::error::ErrorKind::ArgumentConflict, message));
+        }
+        Ok(self)
+    }
+
+    /// Output format: `--format` or the deprecated `--json` alias, else what the `-o` file's extension implies
+    pub fn output_format(&self) -> OutputFormat {
+        self.requested_format()
+            .or_else(|| self.output.as_deref().and_then(|file| OutputFormat::from_extension(Path::new(file))))
+            .unwrap_or_default()
+    }
+
+    /// Output format given by `--format` or `--json`, if any
+    pub fn requested_format(&self) -> Option<OutputFormat> {
+        if self.json {
+            Some(OutputFormat::Json)
+        } else {
+            self.format
+        }
+    }
+
+    /// Size limit from `--max-filesize`, where 0 lifts it
+    pub fn max_filesize(&self) -> Option<u64> {
+        (self.max_filesize > 0).then_some(self.max_filesize)
+    }
+
+    /// Root line from `--header` / `--no-header`
+    pub fn root_header(&self) -> RootHeader<'_> {
+        match self.header.as_deref() {
+            _ if self.no_header => RootHeader::Hidden,
+            Some(text) => RootHeader::Custom(text),
+            None => RootHeader::Name,
+        }
+    }
+}
+
+/// Parse a byte count with an optional binary unit: `4096`, `512K`, `10MB`, `1GiB`
+fn parse_size(s: &str) -> Result<u64, String> {
+    let s = s.trim();
+    let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (digits, unit) = s.split_at(split);
+    let number: u64 = digits.parse().map_err(|_| format!("'{}' is not a size like 4096, 512K or 10M", s))?;
+    let shift = match unit.trim().to_ascii_uppercase().trim_end_matches("IB").trim_end_matches('B') {
+        "" => 0,
+        "K" => 10,
+        "M" => 20,
+        "G" => 30,
+        "T" => 40,
+        _ => return Err(format!("unknown size unit '{}' (expected K, M, G or T)", unit.trim())),
+    };
+    number.checked_mul(1 << shift).ok_or_else(|| format!("size '{}' is too large", s))
+}
+
+/// Parse `--size-thresholds`: two sizes, the second larger than the first
+fn parse_size_thresholds(s: &str) -> Result<SizeThresholds, String> {
+    let (medium, large) = s.split_once(',').ok_or_else(|| format!("'{}' is not two sizes like 1M,100M", s))?;
+    let (medium, large) = (parse_size(medium)?, parse_size(large)?);
+    if medium >= large {
+        return Err(format!("the large threshold in '{}' must exceed the medium one", s));
+    }
+    Ok(SizeThresholds { medium, large })
+}
+
+/// Parse `--timeout`: a positive number of seconds, fractions allowed
+fn parse_timeout(s: &str) -> Result<Duration, String> {
+    match s.trim().parse::<f64>() {
+        Ok(secs) if secs > 0.0 && secs.is_finite() => Duration::try_from_secs_f64(secs).map_err(|e| e.to_string()),
+        _ => Err(format!("'{}' is not a positive number of seconds like 5 or 0.5", s)),
     }
 }
 
-/// Helper to compute effective depth
+/// Walk limit for `--depth`: levels below the root, with 0 meaning unlimited
 pub fn effective_depth(depth: usize) -> usize {
     if depth == 0 {
-        usize::MAX
+        UNLIMITED_DEPTH
     } else {
         depth
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_size_units() {
+        assert_eq!(parse_size("4096"), Ok(4096));
+        assert_eq!(parse_size("512K"), Ok(512 * 1024));
+        assert_eq!(parse_size("10MB"), Ok(10 * 1024 * 1024));
+        assert_eq!(parse_size("1gib"), Ok(1 << 30));
+        assert_eq!(parse_size("0"), Ok(0));
+        assert!(parse_size("10X").is_err());
+        assert!(parse_size("M").is_err());
+        assert!(parse_size("99999999999T").is_err());
+    }
+
+    #[test]
+    fn test_parse_size_thresholds() {
+        assert_eq!(parse_size_thresholds("64K, 10M"), Ok(SizeThresholds { medium: 64 << 10, large: 10 << 20 }));
+        assert!(parse_size_thresholds("10M").is_err());
+        assert!(parse_size_thresholds("10M,1M").is_err());
+    }
+
+    #[test]
+    fn test_ignored_flag_combinations_are_rejected() {
+        let parse = |args: &[&str]| Cli::try_parse_from(args).and_then(Cli::validated);
+        let err = parse(&["lst", "search", "main", "-w"]).unwrap_err();
+        assert_eq!(err.kind(), clap::error::ErrorKind::ArgumentConflict);
+        assert!(err.to_string().contains("--watch"), "{}", err);
+        let err = parse(&["lst", "search", "main", "--census"]).unwrap_err();
+        assert!(err.to_string().contains("--census"), "{}", err);
+
+        let err = parse(&["lst", "--grid", "-o", "tree.json"]).unwrap_err();
+        assert_eq!(err.kind(), clap::error::ErrorKind::ArgumentConflict);
+        assert!(parse(&["lst", "--grid", "-o", "tree.txt"]).is_ok());
+        assert!(parse(&["lst", "-w"]).is_ok());
+        assert!(parse(&["lst", "search", "main"]).is_ok());
+    }
+}
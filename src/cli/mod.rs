@@ -2,4 +2,4 @@ pub mod args;
 pub mod parser;
 
 pub use args::Cli;
-pub use parser::run_cli;
+pub use parser::{run, run_cli, write_error};
@@ -1,25 +1,410 @@
-use std::path::Path;
+use std::io::{IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Instant;
+
+use clap::ValueEnum;
 
 use crate::commands;
-use crate::error::Result;
+use crate::core::cancel;
+use crate::core::exec::ExecCommand;
+use crate::core::git::GitStatus;
+use crate::core::filters::{parse_pattern_lines, CaseMode, CollapseOver, DepthFromMarker, GlobFilter, ProjectKind, ShowOptions};
+use crate::core::meta::TimeField;
+use crate::core::roots::resolve_roots;
+use crate::core::search::{MatchTarget, SearchQuery};
+use crate::core::sort::SortOptions;
+use crate::core::timing::{write_time_report, Timings};
+use crate::core::tree::{IgnoreOptions, UNLIMITED_DEPTH};
+use crate::error::{LstError, Result};
+use crate::output::logger;
+use crate::output::formatter::{NameLimit, SizeFormat};
+use crate::output::palette::{Palette, COLORS_ENV};
+use crate::output::schema;
+use crate::output::printer::{ExportMode, OutputFormat, TreeConfig};
 
 use super::args::{effective_depth, Cli, Commands};
 
 /// Entry point for CLI execution: parse args and dispatch to subcommands.
 pub fn run_cli() -> Result<()> {
-    let cli = Cli::parse_cli();
+    run(Cli::parse_cli())
+}
+
+/// Run already parsed arguments; the binary keeps `cli` around to format errors
+pub fn run(cli: Cli) -> Result<()> {
+    logger::init(cli.verbose);
+    cli.color.apply();
+    // Answer before resolving roots, so a bad path can't stand in the way of a bug report
+    if matches!(cli.command, Some(Commands::Version)) {
+        return commands::version::run();
+    }
+    if cli.print_schema {
+        let schema = serde_json::to_string_pretty(&schema::tree_schema()).map_err(std::io::Error::other)?;
+        writeln!(std::io::stdout(), "{}", schema)?;
+        return Ok(());
+    }
+    if cli.print_shell_integration {
+        write!(std::io::stdout(), "{}", commands::pick::SHELL_INTEGRATION)?;
+        return Ok(());
+    }
+    // Only now, so a bad LST_COLORS doesn't stop the commands above that print no colors
+    let palette = palette(&cli)?;
+    if cli.from_stdin {
+        let config = TreeConfig {
+            export_mode: export_mode(&cli),
+            color: cli.color,
+            palette,
+            tree_style: cli.tree_style,
+            escape_names: escapes_names(&cli),
+            no_size: cli.no_size,
+            no_size_for: cli.no_size_for.iter().map(|ext| ext.trim().trim_start_matches('.').to_string()).collect(),
+            name_limit: cli.max_name_width.map(|max_width| NameLimit { max_width: max_width.into(), truncate: cli.truncate }),
+            size_format: SizeFormat { precision: cli.size_precision.map(usize::from), width: cli.size_width },
+            format: cli.output_format(),
+            compact: cli.compact,
+            ..TreeConfig::new(Path::new("."))
+        };
+        return commands::stdin::run(config, cli.output.as_deref());
+    }
+
+    // Ctrl-C and `--timeout` stop walks, and whatever was found still gets printed
+    cancel::install_interrupt_handler();
+    if let Some(timeout) = cli.timeout {
+        cancel::set_timeout(timeout);
+    }
+
     let path_str = cli.path.as_deref().unwrap_or(".");
-    let path = Path::new(path_str);
+    let roots = resolve_roots(path_str)?;
+    let exclude = exclude_filter(&cli.exclude, &cli.exclude_from, cli.case)?;
+
+    // Label each root like `ls` does when a glob expands to several of them
+    // A picked path must be all that reaches stdout
+    let label_roots =
+        roots.len() > 1 && cli.output.is_none() && cli.output_format() == OutputFormat::Text && !cli.pick_dir;
+    // A search fails only when no root had a match, so keep going past empty ones
+    let mut roots_without_matches = 0;
+    for (idx, root) in roots.iter().enumerate() {
+        if label_roots {
+            let mut stdout = std::io::stdout().lock();
+            if idx > 0 {
+                writeln!(stdout)?;
+            }
+            writeln!(stdout, "{}:", root.display())?;
+        }
+        let started = Instant::now();
+        let timings = cli.time_report.then(|| Arc::new(Mutex::new(Timings::default())));
+        let res = run_root(&cli, root, &exclude, &palette, timings.clone());
+        log::debug!("{} done in {:.2?}", root.display(), started.elapsed());
+        // After the output, so the table doesn't land in the middle of it
+        if let Some(timings) = timings {
+            let timings = *timings.lock().unwrap_or_else(PoisonError::into_inner);
+            write_time_report(&mut std::io::stderr().lock(), &timings, started.elapsed())?;
+        }
+        match res {
+            Err(e) if e.is_nothing_found() => roots_without_matches += 1,
+            res => res?,
+        }
+        if let Some(reason) = cancel::cancelled() {
+            return Err(LstError::Cancelled(reason));
+        }
+    }
+    if roots_without_matches == roots.len() {
+        return Err(LstError::NothingFound);
+    }
+    Ok(())
+}
+
+/// How `-o` should treat an existing file, from `--append` / `--force`
+fn export_mode(cli: &Cli) -> ExportMode {
+    if cli.append {
+        ExportMode::Append
+    } else if cli.force {
+        ExportMode::Overwrite
+    } else {
+        ExportMode::CreateNew
+    }
+}
+
+/// The `--palette` preset with any `LST_COLORS` overrides applied
+fn palette(cli: &Cli) -> Result<Palette> {
+    let mut palette = Palette::preset(cli.palette);
+    if let Some(thresholds) = cli.size_thresholds {
+        palette = palette.with_size_thresholds(thresholds);
+    }
+    match std::env::var(COLORS_ENV) {
+        Ok(spec) => palette.with_overrides(&spec),
+        Err(_) => Ok(palette),
+    }
+}
+
+/// Search ignore rules from `--ignore-file` / `--no-ignore-dot`; every ignore file must exist
+fn ignore_options(files: &[String], no_ignore_dot: bool) -> Result<IgnoreOptions> {
+    let files = files
+        .iter()
+        .map(|f| {
+            let path = PathBuf::from(f);
+            if path.is_file() {
+                Ok(path)
+            } else {
+                Err(LstError::InvalidPath(format!("ignore file '{}' does not exist", f)))
+            }
+        })
+        .collect::<Result<Vec<_>>>()?;
+    Ok(IgnoreOptions {
+        dot_ignore: !no_ignore_dot,
+        files,
+    })
+}
+
+/// Exclusion globs from `--exclude` plus every `--exclude-from` file, which must exist
+fn exclude_filter(patterns: &[String], files: &[String], case: CaseMode) -> Result<GlobFilter> {
+    let mut patterns = patterns.to_vec();
+    for file in files {
+        let text = std::fs::read_to_string(file)
+            .map_err(|e| LstError::InvalidPath(format!("cannot read exclude file '{}': {}", file, e)))?;
+        patterns.extend(parse_pattern_lines(&text));
+    }
+    GlobFilter::new(patterns, case)
+}
+
+/// Project types under `path` for `--smart`, reported with `-v`
+fn detect_projects(path: &Path) -> Vec<ProjectKind> {
+    let projects = ProjectKind::detect(path);
+    if projects.is_empty() {
+        log::info!("--smart: no project detected in {}, skipping the common heavy directories", path.display());
+    } else {
+        let names: Vec<&str> = projects.iter().map(|kind| kind.name()).collect();
+        log::info!("--smart: detected {} project in {}", names.join(" + "), path.display());
+    }
+    projects
+}
+
+/// Uncommitted changes below `path` for `--git-modified`; outside a repository everything is listed
+fn git_status(path: &Path, include_ignored: bool) -> Option<Arc<GitStatus>> {
+    let status = GitStatus::load(path, include_ignored);
+    if status.is_none() {
+        eprintln!("Warning: --git-modified: {} is not in a git repository (or git isn't installed); listing everything", path.display());
+    }
+    status.map(Arc::new)
+}
+
+/// Build the tree configuration shared by all commands from the global flags
+fn tree_config<'a>(cli: &'a Cli, path: &'a Path, exclude: &GlobFilter, palette: &Palette) -> TreeConfig<'a> {
+    let time_field = time_field(cli.time_field, path);
+    TreeConfig {
+        max_depth: if cli.oneline || cli.grid {
+            1
+        } else if !cli.depth_from_marker.is_empty() {
+            // The walk filter applies the depth limit below each project
+            UNLIMITED_DEPTH
+        } else {
+            effective_depth(cli.depth)
+        },
+        show: ShowOptions {
+            hidden: cli.all || cli.almost_all,
+            almost_all: cli.almost_all && !cli.all,
+            hidden_only: cli.hidden_only,
+            no_skip: cli.no_skip,
+            dirs_only: cli.skeleton,
+            exclude: exclude.clone(),
+            sort: SortOptions::new(cli.sort.first().copied(), cli.sort_dirs, cli.sort_files)
+                .with_tiebreaks(cli.sort.iter().skip(1).copied().collect())
+                .with_dir_mtime_recursive(cli.dir_mtime_recursive)
+                .with_time_field(time_field),
+            one_file_system: cli.one_file_system,
+            show_skipped: cli.show_skipped,
+            stop_at: cli.stop_at.clone(),
+            stop_markers: cli.stop_at_marker.clone(),
+            projects: if cli.smart { detect_projects(path) } else { Vec::new() },
+            collapse_over: cli.collapse_over.map(CollapseOver::new),
+            depth_from_marker: (!(cli.depth_from_marker.is_empty() || cli.oneline || cli.grid))
+                .then(|| DepthFromMarker::new(cli.depth_from_marker.clone(), effective_depth(cli.depth))),
+            empty_dirs: cli.empty_dirs,
+            git_status: if cli.git_modified { git_status(path, cli.all || cli.almost_all) } else { None },
+            revealed: None,
+        },
+        show_spinner: !cli.no_spinner,
+        export_mode: export_mode(cli),
+        color: cli.color,
+        classify: cli.classify,
+        oneline: cli.oneline,
+        grid: cli.grid,
+        stats: cli.stats,
+        count_links: cli.count_links,
+        stats_include_skipped: cli.stats_include_skipped,
+        dupes: cli.dupes,
+        counts: cli.counts,
+        mark_empty: cli.mark_empty,
+        pad_names: cli.pad_names,
+        full_path: cli.full_path,
+        tilde: cli.tilde,
+        // Escape sequences would only be noise in a pipe or a file
+        hyperlinks: cli.hyperlinks && cli.output.is_none() && std::io::stdout().is_terminal(),
+        quote_names: cli.quote_names,
+        report: cli.report && !cli.no_report,
+        report_only: cli.report_only,
+        // A crafted name could otherwise clear the screen or hide what it really is
+        escape_names: escapes_names(cli),
+        depth_colors: cli.depth_colors,
+        no_size: cli.no_size || cli.skeleton,
+        bars: cli.bars,
+        no_size_for: cli.no_size_for.iter().map(|ext| ext.trim().trim_start_matches('.').to_string()).collect(),
+        name_limit: cli.max_name_width.map(|max_width| NameLimit { max_width: max_width.into(), truncate: cli.truncate }),
+        columns: cli.columns.clone(),
+        time_field,
+        size_format: SizeFormat { precision: cli.size_precision.map(usize::from), width: cli.size_width },
+        palette: palette.clone(),
+        tree_style: cli.tree_style,
+        language: cli.language.as_deref(),
+        max_preview_lines: cli.max_preview_lines,
+        context: cli.context,
+        max_filesize: cli.max_filesize(),
+        force_text: cli.force_text,
+        format: cli.output_format(),
+        header: cli.root_header(),
+        compact: cli.compact,
+        output_buffer: cli.output_buffer,
+        ..TreeConfig::new(path)
+    }
+}
+
+/// Whether names get control characters escaped: on a terminal, unless `--show-control-chars`
+fn escapes_names(cli: &Cli) -> bool {
+    !cli.show_control_chars && cli.output.is_none() && std::io::stdout().is_terminal()
+}
+
+/// `--time-field`, or modified times where the root's filesystem doesn't record the field
+fn time_field(field: TimeField, root: &Path) -> TimeField {
+    if field.is_supported(root) {
+        return field;
+    }
+    let name = field.to_possible_value().map(|v| v.get_name().to_string()).unwrap_or_default();
+    eprintln!("Warning: {} times aren't available on this platform or filesystem; using modified times", name);
+    TimeField::Modified
+}
 
-    match cli.command {
-        Some(Commands::Search { pattern }) => {
-            let max_depth = effective_depth(cli.depth);
-            commands::search::run(&pattern, path, cli.all, max_depth, cli.output.as_deref(), cli.json)
+/// Dispatch a single resolved root to the selected command
+fn run_root(
+    cli: &Cli,
+    path: &Path,
+    exclude: &GlobFilter,
+    palette: &Palette,
+    timings: Option<Arc<Mutex<Timings>>>,
+) -> Result<()> {
+    let preview = GlobFilter::new(cli.preview.clone(), cli.case)?;
+    let config = TreeConfig { timings, preview, ..tree_config(cli, path, exclude, palette) };
+
+    match &cli.command {
+        Some(Commands::Search {
+            pattern,
+            match_path,
+            content,
+            count,
+            files_with_matches,
+            quiet,
+            parallel,
+            first,
+            exec,
+            dry_run,
+            max_results,
+            ignore_files,
+            no_ignore_dot,
+        }) => {
+            // A walk from a missing root would find nothing, reported only as "no match"
+            if !path.is_dir() {
+                return Err(LstError::InvalidPath(format!("'{}' is not a directory to search", path.display())));
+            }
+            let target = if *content {
+                MatchTarget::Content
+            } else if *match_path {
+                MatchTarget::Path
+            } else {
+                MatchTarget::Name
+            };
+            let query = SearchQuery::new(pattern, path)
+                .with_target(target)
+                .with_case(cli.case)
+                .with_ascii_case(cli.ascii_case)
+                .with_counts(*count)
+                .with_max_results(*max_results)
+                .with_max_filesize(cli.max_filesize())
+                .with_parallel(*parallel)
+                .with_ignore(ignore_options(ignore_files, *no_ignore_dot)?);
+            let too_large = Arc::clone(&query.too_large);
+            let config = TreeConfig {
+                search: Some(query),
+                ..config
+            };
+            if *quiet {
+                return commands::search::check(&config);
+            }
+            let res = if let Some(exec) = exec {
+                commands::search::exec(&config, &ExecCommand::parse(exec)?, *dry_run)
+            } else if *first {
+                commands::search::first(&config, cli.output.as_deref())
+            } else {
+                commands::search::run(config, cli.output.as_deref(), *files_with_matches)
+            };
+            // After the results, so they aren't interleaved with the tree
+            let mut skipped: Vec<PathBuf> = too_large.iter().map(|p| p.clone()).collect();
+            skipped.sort();
+            for path in skipped {
+                eprintln!("{} [skipped: too large]", path.display());
+            }
+            res
         }
+        // Normally answered before any root is resolved
+        Some(Commands::Version) => commands::version::run(),
+        Some(Commands::Explain { target }) => commands::explain::run(config, target),
+        None if cli.pick_dir => commands::pick::run(config),
+        None if cli.watch && path.is_dir() => commands::watch::run(config),
+        None if cli.census => commands::census::run(config),
+        None if cli.diff.is_some() => commands::diff::run(config, cli.diff.as_deref().unwrap_or_default()),
         None => {
             // Default behavior: list current directory with global flags
-            let max_depth = effective_depth(cli.depth);
-            commands::list::run(path, cli.all, max_depth, cli.output.as_deref(), cli.json)
+            commands::list::run(config, cli.output.as_deref())
         }
     }
 }
+
+/// Report an error on `writer` (stderr): `Error: ...`, or with JSON output a
+/// `{"error", "kind"}` object, so consumers of both streams can parse it
+pub fn write_error<W: Write>(writer: &mut W, error: &LstError, format: OutputFormat) -> std::io::Result<()> {
+    match format {
+        OutputFormat::Json => writeln!(writer, "{}", error.to_json()),
+        OutputFormat::Text => writeln!(writer, "Error: {}", error),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_json_errors_are_parseable_objects() {
+        let error = LstError::InvalidPath("'missing' is not a directory".to_string());
+        let mut out = Vec::new();
+        write_error(&mut out, &error, OutputFormat::Json).unwrap();
+        let parsed: serde_json::Value = serde_json::from_slice(&out).unwrap();
+        assert_eq!(parsed["kind"], "invalid_path");
+        assert_eq!(parsed["error"], "Invalid path: 'missing' is not a directory");
+
+        let mut out = Vec::new();
+        write_error(&mut out, &error, OutputFormat::Text).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "Error: Invalid path: 'missing' is not a directory\n");
+    }
+
+    #[test]
+    fn test_search_of_a_missing_root_is_an_error_not_a_miss() {
+        use clap::Parser;
+        let missing = std::env::temp_dir().join(format!("lst-missing-{}", std::process::id()));
+        let cli = Cli::parse_from(["lst", "search", "zzz", "-q"]);
+        let res = run_root(&cli, &missing, &GlobFilter::default(), &Palette::default(), None);
+        assert!(matches!(res, Err(LstError::InvalidPath(_))), "{:?}", res);
+
+        std::fs::create_dir_all(&missing).unwrap();
+        let res = run_root(&cli, &missing, &GlobFilter::default(), &Palette::default(), None);
+        assert!(res.is_err_and(|e| e.is_nothing_found()));
+        std::fs::remove_dir_all(&missing).unwrap();
+    }
+}
@@ -0,0 +1,40 @@
+use std::io::Write;
+
+use crate::core::archive::ArchiveKind;
+use crate::core::listing::root_name;
+use crate::core::virtual_tree::build_detached_entries;
+use crate::error::Result;
+use crate::output::formatter::sanitize_name;
+use crate::output::printer::{open_export, OutputFormat, TreeConfig, TreeWriter};
+
+/// List the members of the archive at `config.path` as a tree, without extracting anything
+///
+/// Members come from the archive's own index, so their sizes are the extracted
+/// ones and nothing on disk is looked at besides the archive.
+pub fn run(config: TreeConfig, kind: ArchiveKind, output: Option<&str>) -> Result<()> {
+    let members = kind.members(config.path)?;
+    let mut entries = build_detached_entries(members);
+    for entry in &mut entries {
+        entry.path = config.path.join(&entry.path);
+    }
+
+    let writer = match output {
+        Some(_) => TreeWriter::for_export(config.color),
+        None => TreeWriter::for_terminal(),
+    };
+    let writer = writer.with_palette(config.palette.clone()).with_tree_style(config.tree_style);
+    let mut out: Box<dyn Write> = match output {
+        Some(output_path) => Box::new(open_export(output_path, config.export_mode)?),
+        None => Box::new(std::io::stdout().lock()),
+    };
+    if config.format == OutputFormat::Text {
+        let root = root_name(config.path);
+        writeln!(out, "{}", if config.escape_names { sanitize_name(&root) } else { root })?;
+    }
+    writer.write_virtual(&mut out, &config, &entries)?;
+    out.flush()?;
+    if let Some(output_path) = output {
+        writeln!(std::io::stdout().lock(), "Tree exported to {}", output_path)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,51 @@
+use std::collections::BTreeMap;
+use std::io::Write;
+
+use crate::core::stats::extension_counts;
+use crate::core::tree::{collect_entries, UNLIMITED_DEPTH};
+use crate::error::{LstError, Result};
+use crate::output::formatter::format_count;
+use crate::output::printer::{write_json_value, OutputFormat, TreeConfig};
+
+/// Count the files below `config.path` per extension, most common first, instead of printing the tree
+///
+/// The whole tree is walked whatever `--depth` says, with the usual hidden-file and skip rules.
+pub fn run(config: TreeConfig) -> Result<()> {
+    if !config.path.is_dir() {
+        return Err(LstError::InvalidPath(format!("'{}' is not a directory", config.path.display())));
+    }
+    let entries = collect_entries(config.path, UNLIMITED_DEPTH, &config.show, None);
+    let counts = extension_counts(entries.iter().filter(|e| !e.file_type().is_dir()).map(|e| e.path()));
+
+    let mut stdout = std::io::stdout().lock();
+    match config.format {
+        OutputFormat::Json => {
+            let object: BTreeMap<&str, usize> = counts.iter().map(|(ext, n)| (ext.as_str(), *n)).collect();
+            write_json_value(&mut stdout, &object, config.compact)?;
+        }
+        OutputFormat::Text => write_counts(&mut stdout, &counts)?,
+    }
+    Ok(())
+}
+
+/// One `count  extension` line per extension, counts right-aligned like `uniq -c`
+fn write_counts<W: Write>(writer: &mut W, counts: &[(String, usize)]) -> std::io::Result<()> {
+    let counts: Vec<(String, &str)> = counts.iter().map(|(ext, n)| (format_count(*n), ext.as_str())).collect();
+    let width = counts.iter().map(|(n, _)| n.len()).max().unwrap_or(0);
+    for (count, ext) in counts {
+        writeln!(writer, "{:>width$}  {}", count, ext)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_counts_are_right_aligned() {
+        let mut out = Vec::new();
+        write_counts(&mut out, &[("rs".to_string(), 1200), ("md".to_string(), 3)]).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "1,200  rs\n    3  md\n");
+    }
+}
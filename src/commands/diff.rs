@@ -0,0 +1,273 @@
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::Path;
+
+use colored::Colorize;
+use serde::{Deserialize, Serialize};
+
+use crate::core::listing::{NodeKind, TreeData, TreeNode};
+use crate::core::meta::FsMetadata;
+use crate::core::tree::UNLIMITED_DEPTH;
+use crate::error::{LstError, Result};
+use crate::output::formatter::{format_file_size, sanitize_name};
+use crate::output::printer::{write_json_value, OutputFormat, TreeConfig};
+
+/// How an entry differs from the snapshot
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Change {
+    Added,
+    Removed,
+    Resized,
+}
+
+/// One changed entry, keyed by its path relative to the listed root
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DiffEntry {
+    pub path: String,
+    pub change: Change,
+    #[serde(rename = "type")]
+    pub kind: NodeKind,
+    /// Size in the snapshot
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub old_size: Option<u64>,
+    /// Size now
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub new_size: Option<u64>,
+}
+
+/// Compare the tree under `config` with a snapshot written by `--json -o`
+///
+/// The current tree is listed as deep as the snapshot, and otherwise with the
+/// same flags as a normal run, so use the hidden-file options it was taken with.
+pub fn run(config: TreeConfig, snapshot: &str) -> Result<()> {
+    let before = load_snapshot(Path::new(snapshot))?;
+    let after = current_tree(&config, &before);
+    let changes = diff_trees(&before, &after);
+
+    let mut stdout = std::io::stdout().lock();
+    match config.format {
+        OutputFormat::Json => write_json_value(&mut stdout, &changes, config.compact)?,
+        OutputFormat::Text => write_changes(&mut stdout, &changes, config.escape_names)?,
+    }
+    Ok(())
+}
+
+/// The tree under `config` now, walked to the snapshot's depth when it records one
+fn current_tree(config: &TreeConfig, before: &TreeNode) -> TreeNode {
+    let max_depth = match before.depth_limit {
+        Some(0) => UNLIMITED_DEPTH,
+        Some(depth) => depth,
+        None => config.max_depth,
+    };
+    let data = TreeData::collect(config.path, max_depth, &config.show, None, None, Some(&FsMetadata), false);
+    data.to_node(config.path, None, config.show.walks_hidden())
+}
+
+/// Read a tree saved with `--json`
+pub fn load_snapshot(path: &Path) -> Result<TreeNode> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| LstError::InvalidPath(format!("cannot read snapshot '{}': {}", path.display(), e)))?;
+    // Snapshots of deep trees nest further than serde_json's default limit of 128
+    let mut de = serde_json::Deserializer::from_str(&text);
+    de.disable_recursion_limit();
+    TreeNode::deserialize(&mut de)
+        .and_then(|tree| de.end().map(|()| tree))
+        .map_err(|e| LstError::InvalidSnapshot(format!("'{}': {}", path.display(), e)))
+}
+
+/// Entries added, removed or resized between two trees, ordered by path
+///
+/// An entry that changed between file and directory is reported as removed and added.
+pub fn diff_trees(before: &TreeNode, after: &TreeNode) -> Vec<DiffEntry> {
+    let (old, new) = (flatten(before), flatten(after));
+    let mut changes = Vec::new();
+    for (path, &(kind, size)) in &old {
+        match new.get(path) {
+            Some(&(new_kind, new_size)) if new_kind == kind => {
+                if kind == NodeKind::File && size != new_size {
+                    changes.push(DiffEntry { path: path.clone(), change: Change::Resized, kind, old_size: size, new_size });
+                }
+            }
+            _ => changes.push(DiffEntry { path: path.clone(), change: Change::Removed, kind, old_size: size, new_size: None }),
+        }
+    }
+    for (path, &(kind, size)) in &new {
+        if old.get(path).is_none_or(|&(old_kind, _)| old_kind != kind) {
+            changes.push(DiffEntry { path: path.clone(), change: Change::Added, kind, old_size: None, new_size: size });
+        }
+    }
+    // Stable, so a removal stays ahead of the addition at the same path
+    changes.sort_by(|a, b| a.path.cmp(&b.path));
+    changes
+}
+
+/// Every entry below `root` by its `/`-separated path relative to it
+fn flatten(root: &TreeNode) -> BTreeMap<String, (NodeKind, Option<u64>)> {
+    fn visit(node: &TreeNode, root: &Path, out: &mut BTreeMap<String, (NodeKind, Option<u64>)>) {
+        for child in node.children.iter().flatten() {
+            let rel = child.path.strip_prefix(root).unwrap_or(&child.path);
+            out.insert(rel.to_string_lossy().replace('\\', "/"), (child.kind, child.size));
+            visit(child, root, out);
+        }
+    }
+    let mut out = BTreeMap::new();
+    visit(root, &root.path, &mut out);
+    out
+}
+
+/// Print `+`/`-`/`~` lines and a summary, with control characters in paths escaped when `escape_names` is set
+fn write_changes<W: Write>(writer: &mut W, changes: &[DiffEntry], escape_names: bool) -> std::io::Result<()> {
+    if changes.is_empty() {
+        return writeln!(writer, "No changes since the snapshot");
+    }
+    let size = |s: Option<u64>| s.map(format_file_size).unwrap_or_else(|| "?".to_string());
+    for entry in changes {
+        let slash = if entry.kind == NodeKind::Directory { "/" } else { "" };
+        let path = if escape_names { sanitize_name(&entry.path) } else { entry.path.clone() };
+        let name = format!("{}{}", path, slash);
+        let line = match (entry.change, entry.kind) {
+            (Change::Added, NodeKind::File) => format!("+ {} ({})", name, size(entry.new_size)).green(),
+            (Change::Added, NodeKind::Directory) => format!("+ {}", name).green(),
+            (Change::Removed, NodeKind::File) => format!("- {} ({})", name, size(entry.old_size)).red(),
+            (Change::Removed, NodeKind::Directory) => format!("- {}", name).red(),
+            (Change::Resized, _) => {
+                format!("~ {} ({} -> {})", name, size(entry.old_size), size(entry.new_size)).yellow()
+            }
+        };
+        writeln!(writer, "{}", line)?;
+    }
+    let count = |change: Change| changes.iter().filter(|c| c.change == change).count();
+    writeln!(
+        writer,
+        "\n{} added, {} removed, {} resized",
+        count(Change::Added),
+        count(Change::Removed),
+        count(Change::Resized)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::output::printer::TreeWriter;
+
+    fn node(path: &str, kind: NodeKind, size: Option<u64>, children: Option<Vec<TreeNode>>) -> TreeNode {
+        TreeNode {
+            name: path.rsplit('/').next().unwrap().to_string(),
+            kind,
+            path: PathBuf::from(path),
+            depth: path.matches('/').count(),
+            size,
+            matches: None,
+            dup_group: None,
+            child_count: None,
+            skipped: false,
+            stopped: false,
+            mount_point: false,
+            vanished: false,
+            collapsed: None,
+            empty: false,
+            children,
+            truncated: false,
+            stats: None,
+            summary: None,
+            depth_limit: None,
+        }
+    }
+
+    #[test]
+    fn test_diff_reports_added_removed_and_resized() {
+        let file = |path, size| node(path, NodeKind::File, Some(size), None);
+        let dir = |path, children| node(path, NodeKind::Directory, None, Some(children));
+        let before = dir("old", vec![
+            file("old/same.txt", 1),
+            file("old/grown.log", 10),
+            file("old/gone.txt", 5),
+            dir("old/build", vec![file("old/build/a.o", 3)]),
+            file("old/flip", 2),
+        ]);
+        // Snapshots taken elsewhere still line up: paths are relative to each root
+        let after = dir("new", vec![
+            file("new/same.txt", 1),
+            file("new/grown.log", 20),
+            dir("new/build", vec![file("new/build/a.o", 3), file("new/build/b.o", 4)]),
+            dir("new/flip", vec![]),
+        ]);
+
+        let summary: Vec<(String, Change)> = diff_trees(&before, &after).into_iter().map(|c| (c.path, c.change)).collect();
+        assert_eq!(
+            summary,
+            [
+                ("build/b.o".to_string(), Change::Added),
+                ("flip".to_string(), Change::Removed),
+                ("flip".to_string(), Change::Added),
+                ("gone.txt".to_string(), Change::Removed),
+                ("grown.log".to_string(), Change::Resized),
+            ]
+        );
+        assert!(diff_trees(&after, &after).is_empty());
+    }
+
+    #[test]
+    fn test_changes_escape_control_characters_in_paths() {
+        colored::control::set_override(false);
+        let changes = [DiffEntry {
+            path: "evil\x1b[2J.txt".to_string(),
+            change: Change::Added,
+            kind: NodeKind::File,
+            old_size: None,
+            new_size: Some(1),
+        }];
+        let render = |escape_names| {
+            let mut out = Vec::new();
+            write_changes(&mut out, &changes, escape_names).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        assert!(render(true).starts_with("+ evil\\e[2J.txt (1 B)\n"), "{}", render(true));
+        assert!(render(false).contains('\x1b'));
+        colored::control::unset_override();
+    }
+
+    #[test]
+    fn test_snapshot_round_trips_from_json_export() {
+        let dir = std::env::temp_dir().join(format!("lst-diff-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let tree = node("root", NodeKind::Directory, None, Some(vec![node("root/a", NodeKind::File, Some(1), None)]));
+        let snapshot = dir.join("before.json");
+        std::fs::write(&snapshot, serde_json::to_string_pretty(&tree).unwrap()).unwrap();
+        assert_eq!(load_snapshot(&snapshot).unwrap(), tree);
+
+        std::fs::write(&snapshot, "{\"name\": 1}").unwrap();
+        assert!(matches!(load_snapshot(&snapshot), Err(LstError::InvalidSnapshot(_))));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_current_tree_is_walked_to_the_snapshot_depth() {
+        let dir = std::env::temp_dir().join(format!("lst-diff-depth-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("src/deep")).unwrap();
+        std::fs::write(dir.join("src/deep/lib.rs"), "fn main() {}").unwrap();
+        let saved = dir.with_extension("json");
+        let snapshot = |max_depth| {
+            let config = TreeConfig { max_depth, format: OutputFormat::Json, ..TreeConfig::new(&dir) };
+            let _ = std::fs::remove_file(&saved);
+            TreeWriter::for_file().write_to_file(saved.to_str().unwrap(), &config).unwrap();
+            load_snapshot(&saved).unwrap()
+        };
+
+        // Run with the default `-d 1`, the current tree still goes as deep as each snapshot
+        let config = TreeConfig { max_depth: 1, ..TreeConfig::new(&dir) };
+        for (max_depth, limit) in [(UNLIMITED_DEPTH, 0), (2, 2)] {
+            let before = snapshot(max_depth);
+            assert_eq!(before.depth_limit, Some(limit));
+            assert_eq!(diff_trees(&before, &current_tree(&config, &before)), Vec::new());
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+        std::fs::remove_file(&saved).unwrap();
+    }
+}
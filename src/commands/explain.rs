@@ -0,0 +1,182 @@
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use crate::core::filters::{visibility_rule, Rule, ShowOptions, Visibility};
+use crate::core::tree::{walk_visible, UNLIMITED_DEPTH};
+use crate::error::{LstError, Result};
+use crate::output::printer::{write_json_value, OutputFormat, TreeConfig};
+
+/// Why a path is or isn't listed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Explanation {
+    /// How the listing treats the path
+    pub visibility: Visibility,
+    /// The rule that decided it
+    pub rule: Rule,
+    /// The path the rule applied to: the path itself, or an ancestor that was left out or not descended into
+    pub decided_at: PathBuf,
+}
+
+/// Report which rule lists or leaves out `target` below `config.path`, and the flag that would change it
+pub fn run(config: TreeConfig, target: &Path) -> Result<()> {
+    let root = canonical(config.path)?;
+    let target = canonical(target)?;
+    let explanation = explain(&root, &target, config.max_depth, &config.show)?;
+    let relative = |path: &Path| path.strip_prefix(&root).unwrap_or(path).display().to_string();
+
+    let mut stdout = std::io::stdout().lock();
+    match config.format {
+        OutputFormat::Json => {
+            let value = serde_json::json!({
+                "path": relative(&target),
+                "visibility": visibility_name(explanation.visibility),
+                "rule": explanation.rule.name(),
+                "reason": explanation.rule.reason(),
+                "change": explanation.rule.remedy(),
+                "decided_at": relative(&explanation.decided_at),
+            });
+            write_json_value(&mut stdout, &value, config.compact)?;
+        }
+        OutputFormat::Text => {
+            write!(stdout, "{} is {}", relative(&target), verdict(explanation.visibility))?;
+            if explanation.decided_at != target {
+                write!(stdout, " (decided at {})", relative(&explanation.decided_at))?;
+            }
+            writeln!(stdout)?;
+            writeln!(stdout, "  rule:   {}", explanation.rule.reason())?;
+            if let Some(remedy) = explanation.rule.remedy() {
+                writeln!(stdout, "  change: {}", remedy)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Run the walk's rules on `target` and each directory leading to it, stopping at the first that decides
+///
+/// Both paths must be canonical, with `target` below `root`.
+pub fn explain(root: &Path, target: &Path, max_depth: usize, show: &ShowOptions) -> Result<Explanation> {
+    let relative = target.strip_prefix(root).map_err(|_| {
+        LstError::InvalidPath(format!("'{}' is not below '{}'", target.display(), root.display()))
+    })?;
+    let components: Vec<_> = relative.components().collect();
+    let decided = |visibility, rule, at: &Path| Ok(Explanation { visibility, rule, decided_at: at.to_path_buf() });
+
+    let mut path = root.to_path_buf();
+    for (idx, component) in components.iter().enumerate() {
+        path.push(component);
+        let depth = idx + 1;
+        if depth > max_depth {
+            return decided(Visibility::Hidden, Rule::Depth, &path);
+        }
+        let is_dir = std::fs::symlink_metadata(&path).is_ok_and(|m| m.is_dir());
+        match visibility_rule(&path, depth, is_dir, show) {
+            (Visibility::Shown, _) => {}
+            // A directory listed without its contents hides what's inside it
+            (Visibility::Collapsed, rule) if depth < components.len() => return decided(Visibility::Hidden, rule, &path),
+            (visibility, rule) => return decided(visibility, rule, &path),
+        }
+    }
+    if let Some(rule) = show.narrowing_rule(root, target) {
+        if !leads_to_listed(root, target, show) {
+            return decided(Visibility::Hidden, rule, target);
+        }
+    }
+    decided(Visibility::Shown, Rule::Listed, target)
+}
+
+/// Whether a narrowed listing keeps the directory at `dir` as the way to an entry it lists below it
+fn leads_to_listed(root: &Path, dir: &Path, show: &ShowOptions) -> bool {
+    // Walked from the root, as the listing does, so depth-based rules see the same depths
+    dir.is_dir()
+        && walk_visible(root, UNLIMITED_DEPTH, show)
+            .flatten()
+            .any(|e| e.path().starts_with(dir) && e.path() != dir && show.narrowing_rule(root, e.path()).is_none())
+}
+
+fn canonical(path: &Path) -> Result<PathBuf> {
+    std::fs::canonicalize(path).map_err(|e| LstError::InvalidPath(format!("'{}': {}", path.display(), e)))
+}
+
+fn verdict(visibility: Visibility) -> &'static str {
+    match visibility {
+        Visibility::Hidden => "left out",
+        Visibility::Shown => "listed",
+        Visibility::Collapsed => "listed without its contents",
+    }
+}
+
+fn visibility_name(visibility: Visibility) -> &'static str {
+    match visibility {
+        Visibility::Hidden => "hidden",
+        Visibility::Shown => "shown",
+        Visibility::Collapsed => "collapsed",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::filters::{CaseMode, DepthFromMarker, GlobFilter};
+
+    #[test]
+    fn test_explain_names_the_deciding_rule() {
+        let root = std::env::temp_dir().join(format!("lst-explain-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(root.join("node_modules/pkg")).unwrap();
+        std::fs::create_dir_all(root.join("build")).unwrap();
+        std::fs::create_dir_all(root.join("src/deep")).unwrap();
+        std::fs::write(root.join(".env"), "").unwrap();
+        let root = std::fs::canonicalize(&root).unwrap();
+
+        let show = ShowOptions {
+            exclude: GlobFilter::new(vec!["build".to_string()], CaseMode::Sensitive).unwrap(),
+            ..ShowOptions::default()
+        };
+        let rule_of = |path: &str, max_depth, show: &ShowOptions| {
+            let explanation = explain(&root, &root.join(path), max_depth, show).unwrap();
+            (explanation.visibility, explanation.rule, explanation.decided_at.strip_prefix(&root).unwrap().to_path_buf())
+        };
+        use Visibility::*;
+        assert_eq!(rule_of("build", 1, &show), (Hidden, Rule::Excluded, "build".into()));
+        assert_eq!(rule_of(".env", 1, &show), (Hidden, Rule::Hidden, ".env".into()));
+        assert_eq!(rule_of("node_modules/pkg", 2, &show), (Hidden, Rule::SkipList, "node_modules".into()));
+        assert_eq!(rule_of("src/deep", 1, &show), (Hidden, Rule::Depth, "src/deep".into()));
+        assert_eq!(rule_of("src/deep", 2, &show), (Shown, Rule::Listed, "src/deep".into()));
+
+        let show_skipped = ShowOptions { show_skipped: true, ..show.clone() };
+        assert_eq!(rule_of("node_modules", 2, &show_skipped), (Collapsed, Rule::ShownSkipped, "node_modules".into()));
+        assert_eq!(rule_of("node_modules/pkg", 2, &show_skipped), (Hidden, Rule::ShownSkipped, "node_modules".into()));
+
+        let hidden_only = ShowOptions { hidden_only: true, ..show };
+        assert_eq!(rule_of("src", 2, &hidden_only), (Hidden, Rule::NotHidden, "src".into()));
+        assert_eq!(rule_of(".env", 2, &hidden_only), (Shown, Rule::Listed, ".env".into()));
+
+        // Outside any project, --depth-from-marker counts from the root
+        std::fs::write(root.join("src/deep/notes.md"), "").unwrap();
+        let markers = vec!["Cargo.toml".into()];
+        let by_marker = ShowOptions { depth_from_marker: Some(DepthFromMarker::new(markers, 1)), ..ShowOptions::default() };
+        assert_eq!(rule_of("src/deep/notes.md", UNLIMITED_DEPTH, &by_marker), (Hidden, Rule::Depth, "src/deep/notes.md".into()));
+        // A directory walked only to look for projects is listed just when it leads to one
+        assert_eq!(rule_of("src/deep", UNLIMITED_DEPTH, &by_marker), (Hidden, Rule::Depth, "src/deep".into()));
+        std::fs::write(root.join("src/deep/Cargo.toml"), "").unwrap();
+        // A fresh limit, as the listing caches which directories are projects
+        let markers = vec!["Cargo.toml".into()];
+        let by_marker = ShowOptions { depth_from_marker: Some(DepthFromMarker::new(markers, 1)), ..ShowOptions::default() };
+        assert_eq!(rule_of("src/deep", UNLIMITED_DEPTH, &by_marker), (Shown, Rule::Listed, "src/deep".into()));
+
+        // `vacant` is empty, `src` holds the way to `src/deep/void`, and `full` holds only a file
+        std::fs::create_dir_all(root.join("src/deep/void")).unwrap();
+        std::fs::create_dir_all(root.join("vacant")).unwrap();
+        std::fs::create_dir_all(root.join("full")).unwrap();
+        std::fs::write(root.join("full/file.txt"), "").unwrap();
+        let empty_dirs = ShowOptions { empty_dirs: true, ..ShowOptions::default() };
+        assert_eq!(rule_of("vacant", 2, &empty_dirs), (Shown, Rule::Listed, "vacant".into()));
+        assert_eq!(rule_of("src", 2, &empty_dirs), (Shown, Rule::Listed, "src".into()));
+        assert_eq!(rule_of("full", 2, &empty_dirs), (Hidden, Rule::NotEmpty, "full".into()));
+        assert_eq!(rule_of("full/file.txt", 2, &empty_dirs), (Hidden, Rule::NotEmpty, "full/file.txt".into()));
+
+        assert!(explain(&root.join("src"), &root, 1, &ShowOptions::default()).is_err());
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
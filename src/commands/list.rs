@@ -1,29 +1,68 @@
-use std::path::Path;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, PoisonError};
 
+use crate::core::archive::ArchiveKind;
+use crate::core::filters::ShowOptions;
 use crate::error::{LstError, Result};
-use crate::output::highlight::print_file_with_highlighting;
-use crate::output::printer::{TreeConfig, TreeWriter};
+use crate::output::highlight::{print_file_with_highlighting, print_reader_with_highlighting};
+use crate::output::printer::{OutputFormat, TreeConfig, TreeWriter};
+use crate::output::spinner::Spinner;
+
+/// Lines of each `--preview` file unless `--max-preview-lines` says otherwise
+const PREVIEW_LINES: usize = 40;
+
+/// Files above this size aren't previewed under a tree, even when `--max-filesize` allows more
+const MAX_PREVIEW_BYTES: u64 = 256 * 1024;
+
+pub fn run(config: TreeConfig, output: Option<&str>) -> Result<()> {
+    let path = config.path;
+
+    // `-` previews stdin, like `cat foo.rs | lst - --language rs`
+    if path == Path::new("-") {
+        return print_reader_with_highlighting(std::io::stdin().lock(), config.language, config.max_preview_lines);
+    }
+
+    // With `--context`, show where the file lives instead of what's in it
+    if let (true, Some(levels @ 1..)) = (path.is_file(), config.context) {
+        let (root, target) = context_root(path, levels);
+        return run(context_config(config, &root, &target), output);
+    }
+
+    // Archives list their members instead of dumping bytes
+    if let Some(kind) = path.is_file().then(|| ArchiveKind::detect(path)).flatten() {
+        return super::archive::run(config, kind, output);
+    }
 
-pub fn run(path: &Path, show_all: bool, max_depth: usize, output: Option<&str>, json: bool) -> Result<()> {
     // If it's a file, print with syntax highlighting
     if path.is_file() {
-        return print_file_with_highlighting(path);
+        return print_file_with_highlighting(path, config.language, config.max_preview_lines, config.force_text, config.max_filesize);
     }
 
     if path.is_dir() {
-        let config = TreeConfig {
-            path,
-            max_depth,
-            show_all,
-            search_pattern: None,
-            spinner_stop: None,
-            json_output: json,
-        };
-
         if let Some(output_path) = output {
-            TreeWriter::for_file().write_to_file(output_path, &config)
+            TreeWriter::for_export(config.color).with_palette(config.palette.clone()).write_to_file(output_path, &config)
         } else {
-            TreeWriter::for_terminal().write_to_terminal(&config)
+            // Plain text streams as it walks, but JSON and collected listings need the
+            // whole tree first; show progress until the first line is printed
+            let spinner = Spinner::start_if(config.show_spinner, "Scanning...");
+            let previews = config.format == OutputFormat::Text && !config.preview.is_empty();
+            let previewed = previews.then(|| Arc::new(Mutex::new(Vec::new())));
+            let config = TreeConfig {
+                spinner_stop: spinner.as_ref().map(Spinner::stop_flag),
+                progress: spinner.as_ref().map(Spinner::counter),
+                previewed: previewed.clone(),
+                ..config
+            };
+            let res = TreeWriter::for_terminal().with_palette(config.palette.clone()).write_to_terminal(&config);
+            if let Some(spinner) = spinner {
+                spinner.finish();
+            }
+            res?;
+            match previewed {
+                Some(files) => write_previews(&config, &files.lock().unwrap_or_else(PoisonError::into_inner)),
+                None => Ok(()),
+            }
         }
     } else {
         Err(LstError::InvalidPath(format!(
@@ -32,3 +71,82 @@ pub fn run(path: &Path, show_all: bool, max_depth: usize, output: Option<&str>,
         )))
     }
 }
+
+/// Show `files`, the first few the tree listed that match `--preview`, highlighted below it
+fn write_previews(config: &TreeConfig, files: &[PathBuf]) -> Result<()> {
+    let max_lines = config.max_preview_lines.or(Some(PREVIEW_LINES));
+    let max_filesize = config.max_filesize.map_or(MAX_PREVIEW_BYTES, |max| max.min(MAX_PREVIEW_BYTES));
+    for file in files {
+        let shown = file.strip_prefix(config.path).unwrap_or(file);
+        writeln!(std::io::stdout().lock(), "\n==> {} <==", shown.display())?;
+        print_file_with_highlighting(file, config.language, max_lines, config.force_text, Some(max_filesize))?;
+    }
+    Ok(())
+}
+
+/// `config` for the tree from `root` down to `target`, which is emphasized and listed
+/// even under a hidden or skipped directory
+fn context_config<'a>(config: TreeConfig<'a>, root: &'a Path, target: &'a Path) -> TreeConfig<'a> {
+    TreeConfig {
+        path: root,
+        max_depth: target.components().count() - root.components().count(),
+        show: ShowOptions { revealed: Some(target.to_path_buf()), ..config.show },
+        context: None,
+        emphasized: Some(target),
+        ..config
+    }
+}
+
+/// The directory `levels` above `file` (at most the outermost one it names), and
+/// `file` spelled the way a walk from that directory reaches it
+fn context_root(file: &Path, levels: usize) -> (PathBuf, PathBuf) {
+    let root = match file.ancestors().skip(1).take(levels).last() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+        Some(_) => PathBuf::from("."),
+        None => return (file.to_path_buf(), file.to_path_buf()),
+    };
+    let rest = file.strip_prefix(&root).unwrap_or(file);
+    let target = root.join(rest);
+    (root, target)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::sort::{SortKey, SortOptions};
+
+    #[test]
+    fn test_context_root_walks_up_levels() {
+        let file = Path::new("src/core/filters.rs");
+        assert_eq!(context_root(file, 1), (PathBuf::from("src/core"), file.to_path_buf()));
+        assert_eq!(context_root(file, 2), (PathBuf::from("src"), file.to_path_buf()));
+        // Past the first named directory the walk starts at `.`
+        for levels in [3, 10] {
+            assert_eq!(context_root(file, levels), (PathBuf::from("."), PathBuf::from("./src/core/filters.rs")));
+        }
+        assert_eq!(context_root(Path::new("/etc/hosts"), 5), (PathBuf::from("/"), PathBuf::from("/etc/hosts")));
+    }
+
+    #[test]
+    fn test_context_lists_a_file_under_hidden_and_skipped_directories() {
+        let dir = std::env::temp_dir().join(format!("lst-context-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join(".config/node_modules")).unwrap();
+        std::fs::create_dir_all(dir.join(".cache")).unwrap();
+        std::fs::write(dir.join(".config/node_modules/pkg.json"), "").unwrap();
+        std::fs::write(dir.join("visible.txt"), "").unwrap();
+
+        let file = dir.join(".config/node_modules/pkg.json");
+        let (root, target) = context_root(&file, 3);
+        let show = ShowOptions { sort: SortOptions::new(Some(SortKey::Name), None, None), ..ShowOptions::default() };
+        let config = context_config(TreeConfig { no_size: true, show, ..TreeConfig::new(&file) }, &root, &target);
+        let mut out = Vec::new();
+        TreeWriter::for_file().write(&mut out, &config).unwrap();
+        // Only the way to the file is revealed; `.cache` stays hidden
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "+-- .config/\n|   `-- node_modules/\n|       `-- pkg.json <--\n`-- visible.txt\n"
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
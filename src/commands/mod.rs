@@ -1,2 +1,10 @@
+pub mod archive;
+pub mod census;
+pub mod diff;
+pub mod explain;
 pub mod list;
+pub mod pick;
 pub mod search;
+pub mod stdin;
+pub mod version;
+pub mod watch;
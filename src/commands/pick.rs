@@ -0,0 +1,332 @@
+use std::fs::File;
+use std::io::{IsTerminal, Read, Write};
+use std::path::{Path, PathBuf};
+
+use crate::core::cancel::Cancelled;
+use crate::core::filters::ShowOptions;
+use crate::core::tree::{walk_visible, UNLIMITED_DEPTH};
+use crate::error::{LstError, Result};
+use crate::output::printer::TreeConfig;
+
+/// Rows of directories shown under the query line
+const MAX_ROWS: usize = 15;
+
+/// Shell function printed by `--print-shell-integration`
+pub const SHELL_INTEGRATION: &str = r#"# lst shell integration: add to ~/.bashrc or ~/.zshrc, then run `lcd [PATH]`
+lcd() {
+    local dir
+    dir="$(lst --pick-dir "$@")" && cd -- "$dir"
+}
+"#;
+
+/// Let the user pick a directory below `config.path` on the terminal and print its path, and only that, to stdout
+///
+/// Keys are read from and the list is drawn on `/dev/tty`, so `cd "$(lst --pick-dir)"` works.
+pub fn run(config: TreeConfig) -> Result<()> {
+    let tty = open_terminal()?;
+    let candidates = directories(config.path, &config.show);
+    let names: Vec<String> = candidates.iter().map(|dir| dir.display().to_string()).collect();
+    let Some(idx) = pick(tty, &names)? else {
+        return Err(LstError::Cancelled(Cancelled::Interrupted));
+    };
+    let dir = &candidates[idx];
+    let path = if dir == Path::new(".") {
+        config.path.to_path_buf()
+    } else if config.path == Path::new(".") {
+        dir.clone()
+    } else {
+        config.path.join(dir)
+    };
+    writeln!(std::io::stdout().lock(), "{}", path.display())?;
+    Ok(())
+}
+
+/// The root (as `.`) and every visible directory below it, relative to it
+fn directories(root: &Path, show: &ShowOptions) -> Vec<PathBuf> {
+    let below = walk_visible(root, UNLIMITED_DEPTH, show)
+        .flatten()
+        .filter(|entry| entry.file_type().is_dir())
+        .filter_map(|entry| entry.path().strip_prefix(root).ok().map(Path::to_path_buf));
+    std::iter::once(PathBuf::from(".")).chain(below).collect()
+}
+
+/// The controlling terminal; an error instead of a picker that waits on a pipe forever
+fn open_terminal() -> Result<File> {
+    let not_a_terminal = |reason: String| LstError::NotATerminal(format!("--pick-dir needs a terminal to pick from ({})", reason));
+    let tty = std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open("/dev/tty")
+        .map_err(|e| not_a_terminal(format!("/dev/tty: {}", e)))?;
+    if !tty.is_terminal() {
+        return Err(not_a_terminal("/dev/tty is not a terminal".to_string()));
+    }
+    Ok(tty)
+}
+
+#[cfg(unix)]
+fn pick(mut tty: File, names: &[String]) -> Result<Option<usize>> {
+    use std::os::fd::AsRawFd;
+
+    let _raw = RawMode::enable(tty.as_raw_fd())?;
+    let (width, height) = window_size(tty.as_raw_fd());
+    let rows = MAX_ROWS.min(height.saturating_sub(1)).max(1);
+    let mut picker = Picker::new(names);
+    let mut buf = [0u8; 64];
+    let outcome = 'keys: loop {
+        draw(&mut tty, &picker, rows, width)?;
+        let n = tty.read(&mut buf)?;
+        if n == 0 {
+            break None;
+        }
+        let mut bytes = &buf[..n];
+        while !bytes.is_empty() {
+            let (key, used) = decode_key(bytes);
+            bytes = &bytes[used..];
+            match picker.handle(key) {
+                Step::Continue => {}
+                Step::Picked(idx) => break 'keys Some(idx),
+                Step::Cancelled => break 'keys None,
+            }
+        }
+    };
+    // Leave the terminal as it was: no list, cursor on the prompt's line
+    write!(tty, "\r\x1B[J\x1B[?25h")?;
+    tty.flush()?;
+    Ok(outcome)
+}
+
+#[cfg(not(unix))]
+fn pick(_tty: File, _names: &[String]) -> Result<Option<usize>> {
+    Err(LstError::NotATerminal("--pick-dir is only supported on Unix terminals".to_string()))
+}
+
+/// Keys read without echo or line buffering until dropped
+#[cfg(unix)]
+struct RawMode {
+    fd: std::os::fd::RawFd,
+    saved: libc::termios,
+}
+
+#[cfg(unix)]
+impl RawMode {
+    fn enable(fd: std::os::fd::RawFd) -> std::io::Result<Self> {
+        let mut saved: libc::termios = unsafe { std::mem::zeroed() };
+        // SAFETY: tcgetattr/tcsetattr only read and write the termios struct we pass
+        if unsafe { libc::tcgetattr(fd, &mut saved) } != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        let mut raw = saved;
+        // Ctrl-C arrives as a key, so the terminal is restored on the way out
+        raw.c_lflag &= !(libc::ICANON | libc::ECHO | libc::ISIG | libc::IEXTEN);
+        raw.c_iflag &= !(libc::IXON | libc::ICRNL);
+        raw.c_cc[libc::VMIN] = 1;
+        raw.c_cc[libc::VTIME] = 0;
+        if unsafe { libc::tcsetattr(fd, libc::TCSAFLUSH, &raw) } != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(RawMode { fd, saved })
+    }
+}
+
+#[cfg(unix)]
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        unsafe { libc::tcsetattr(self.fd, libc::TCSAFLUSH, &self.saved) };
+    }
+}
+
+/// Columns and rows of the terminal, 80x24 when it won't say
+#[cfg(unix)]
+fn window_size(fd: std::os::fd::RawFd) -> (usize, usize) {
+    let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+    // SAFETY: TIOCGWINSZ only writes into the winsize struct we pass
+    let ok = unsafe { libc::ioctl(fd, libc::TIOCGWINSZ, &mut size) } == 0;
+    if ok && size.ws_col > 0 && size.ws_row > 0 {
+        (size.ws_col as usize, size.ws_row as usize)
+    } else {
+        (80, 24)
+    }
+}
+
+/// Redraw the query line and the matches under it, leaving the cursor after the query
+fn draw<W: Write>(out: &mut W, picker: &Picker, rows: usize, width: usize) -> std::io::Result<()> {
+    let prompt = format!("{}/{} > {}", picker.matches.len(), picker.names.len(), picker.query);
+    write!(out, "\x1B[?25l\r\x1B[J{}", truncate(&prompt, width))?;
+    // Scroll the window so the selection stays in it
+    let first = picker.selected.saturating_sub(rows - 1);
+    let shown = picker.matches.len().saturating_sub(first).min(rows);
+    for (row, &idx) in picker.matches.iter().enumerate().skip(first).take(rows) {
+        let name = truncate(&picker.names[idx], width.saturating_sub(2));
+        if row == picker.selected {
+            write!(out, "\r\n\x1B[7m> {}\x1B[0m", name)?;
+        } else {
+            write!(out, "\r\n  {}", name)?;
+        }
+    }
+    if shown > 0 {
+        write!(out, "\x1B[{}A", shown)?;
+    }
+    write!(out, "\r\x1B[{}C\x1B[?25h", prompt.chars().count().min(width.saturating_sub(1)))?;
+    out.flush()
+}
+
+fn truncate(text: &str, width: usize) -> String {
+    text.chars().take(width).collect()
+}
+
+/// A key press, decoded from the bytes the terminal sends in raw mode
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Key {
+    Char(char),
+    Backspace,
+    ClearQuery,
+    Up,
+    Down,
+    Enter,
+    Cancel,
+    Other,
+}
+
+/// The key at the start of `bytes`, and how many bytes it took
+///
+/// A lone Esc is Cancel; arrow keys arrive as `Esc [ A` in a single read.
+fn decode_key(bytes: &[u8]) -> (Key, usize) {
+    match bytes {
+        [b'\r' | b'\n', ..] => (Key::Enter, 1),
+        [0x7f | 0x08, ..] => (Key::Backspace, 1),
+        [0x15, ..] => (Key::ClearQuery, 1),
+        [0x10, ..] => (Key::Up, 1),
+        [0x0e, ..] => (Key::Down, 1),
+        [0x03 | 0x04 | 0x07, ..] => (Key::Cancel, 1),
+        [0x1b] => (Key::Cancel, 1),
+        [0x1b, b'[' | b'O', b'A', ..] => (Key::Up, 3),
+        [0x1b, b'[' | b'O', b'B', ..] => (Key::Down, 3),
+        [0x1b, b'[' | b'O', rest @ ..] => {
+            // Skip the rest of an unknown sequence, up to its final byte
+            let len = rest.iter().position(|b| (0x40..=0x7e).contains(b)).map_or(rest.len(), |end| end + 1);
+            (Key::Other, 2 + len)
+        }
+        [0x1b, ..] => (Key::Other, 1),
+        [first, ..] => {
+            let len = match first {
+                0x00..=0x7f => 1,
+                0xc0..=0xdf => 2,
+                0xe0..=0xef => 3,
+                _ => 4,
+            }
+            .min(bytes.len());
+            match std::str::from_utf8(&bytes[..len]).ok().and_then(|s| s.chars().next()) {
+                Some(c) if !c.is_control() => (Key::Char(c), len),
+                _ => (Key::Other, len),
+            }
+        }
+        [] => (Key::Other, 0),
+    }
+}
+
+/// What a key press did to the picker
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Step {
+    Continue,
+    Picked(usize),
+    Cancelled,
+}
+
+/// The query, the names matching it and which one is selected
+struct Picker<'a> {
+    names: &'a [String],
+    query: String,
+    /// Indexes into `names`, in their original order
+    matches: Vec<usize>,
+    /// Index into `matches`
+    selected: usize,
+}
+
+impl<'a> Picker<'a> {
+    fn new(names: &'a [String]) -> Self {
+        Picker { names, query: String::new(), matches: (0..names.len()).collect(), selected: 0 }
+    }
+
+    fn handle(&mut self, key: Key) -> Step {
+        match key {
+            Key::Char(c) => {
+                self.query.push(c);
+                self.refilter();
+            }
+            Key::Backspace => {
+                self.query.pop();
+                self.refilter();
+            }
+            Key::ClearQuery => {
+                self.query.clear();
+                self.refilter();
+            }
+            Key::Up => self.selected = self.selected.saturating_sub(1),
+            Key::Down => self.selected = (self.selected + 1).min(self.matches.len().saturating_sub(1)),
+            Key::Enter => {
+                if let Some(&idx) = self.matches.get(self.selected) {
+                    return Step::Picked(idx);
+                }
+            }
+            Key::Cancel => return Step::Cancelled,
+            Key::Other => {}
+        }
+        Step::Continue
+    }
+
+    fn refilter(&mut self) {
+        self.matches = (0..self.names.len()).filter(|&idx| fuzzy_match(&self.query, &self.names[idx])).collect();
+        self.selected = 0;
+    }
+}
+
+/// Whether the query's characters appear in `name` in order, ignoring case: `srccl` matches `src/cli`
+fn fuzzy_match(query: &str, name: &str) -> bool {
+    let mut name = name.chars().flat_map(char::to_lowercase);
+    query.chars().flat_map(char::to_lowercase).all(|q| name.any(|c| c == q))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_keys() {
+        assert_eq!(decode_key(b"\x1b[A"), (Key::Up, 3));
+        assert_eq!(decode_key(b"\x1bOB"), (Key::Down, 3));
+        assert_eq!(decode_key(b"\x1b"), (Key::Cancel, 1));
+        assert_eq!(decode_key(b"\x1b[3~x"), (Key::Other, 4));
+        assert_eq!(decode_key(b"\r"), (Key::Enter, 1));
+        assert_eq!(decode_key(b"\x03"), (Key::Cancel, 1));
+        assert_eq!(decode_key("éa".as_bytes()), (Key::Char('é'), 2));
+        assert_eq!(decode_key(b"s"), (Key::Char('s'), 1));
+    }
+
+    #[test]
+    fn test_picker_filters_and_selects() {
+        let names: Vec<String> = [".", "src", "src/cli", "src/core", "tests"].iter().map(|s| s.to_string()).collect();
+        let mut picker = Picker::new(&names);
+        assert_eq!(picker.handle(Key::Up), Step::Continue);
+        assert_eq!(picker.handle(Key::Down), Step::Continue);
+        assert_eq!(picker.handle(Key::Enter), Step::Picked(1));
+
+        for c in "SRCco".chars() {
+            picker.handle(Key::Char(c));
+        }
+        assert_eq!(picker.matches, [3]);
+        picker.handle(Key::Backspace);
+        picker.handle(Key::Backspace);
+        assert_eq!(picker.matches, [1, 2, 3]);
+        picker.handle(Key::Down);
+        picker.handle(Key::Down);
+        picker.handle(Key::Down);
+        assert_eq!(picker.handle(Key::Enter), Step::Picked(3));
+
+        picker.handle(Key::Char('z'));
+        assert_eq!(picker.handle(Key::Enter), Step::Continue);
+        picker.handle(Key::ClearQuery);
+        assert_eq!(picker.matches.len(), names.len());
+        assert_eq!(picker.handle(Key::Cancel), Step::Cancelled);
+    }
+}
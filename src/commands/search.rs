@@ -3,59 +3,199 @@ use std::path::Path;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 
-use crate::error::Result;
-use crate::output::printer::{TreeConfig, TreeWriter};
-
-pub fn run(
-    pattern: &str,
-    path: &Path,
-    show_all: bool,
-    max_depth: usize,
-    output: Option<&str>,
-    json: bool,
-) -> Result<()> {
+use crate::core::cancel::cancelled;
+use crate::core::exec::{quote_arg, shell_quote, ExecCommand};
+use crate::core::search::{build_search_filter, SearchFilter, SearchQuery};
+use crate::core::timing::Timings;
+use crate::core::tree::{first_match, search_candidates};
+use crate::error::{LstError, Result};
+use crate::output::formatter::sanitize_name;
+use crate::output::printer::{open_export, TreeConfig, TreeWriter};
+use crate::output::spinner::Spinner;
+
+/// Print the search results; fails with [`LstError::NothingFound`] when nothing matched
+pub fn run(config: TreeConfig, output: Option<&str>, list_only: bool) -> Result<()> {
+    if list_only {
+        return list_matches(&config, output);
+    }
+
+    let found = Arc::new(AtomicBool::new(false));
     let config = TreeConfig {
-        path,
-        max_depth,
-        show_all,
-        search_pattern: Some(pattern),
-        spinner_stop: None,
-        json_output: json,
+        found: Some(Arc::clone(&found)),
+        ..config
+    };
+    write_tree(config, output)?;
+    if found.load(Ordering::Relaxed) {
+        Ok(())
+    } else {
+        Err(LstError::NothingFound)
+    }
+}
+
+/// Print only the path of the shallowest match (`--first`), for `cd "$(lst search config --first)"`
+pub fn first(config: &TreeConfig, output: Option<&str>) -> Result<()> {
+    let Some(query) = &config.search else {
+        return Ok(());
+    };
+    let found = first_match(config.path, config.max_depth, &config.show, query).ok_or(LstError::NothingFound)?;
+    let line = format!("{}\n", shown_path(&found, config));
+    match output {
+        Some(output_path) => {
+            open_export(output_path, config.export_mode)?.write_all(line.as_bytes())?;
+            writeln!(std::io::stdout().lock(), "Match exported to {}", output_path)?;
+        }
+        None => std::io::stdout().lock().write_all(line.as_bytes())?,
+    }
+    Ok(())
+}
+
+/// Run `command` on every match instead of printing them (`--exec`), or only print
+/// the command lines with `dry_run`
+///
+/// Like `find`, a failing command doesn't stop the rest; the run fails afterwards.
+/// Ctrl-C or `--timeout` does stop it, before the next command.
+pub fn exec(config: &TreeConfig, command: &ExecCommand, dry_run: bool) -> Result<()> {
+    let Some(query) = &config.search else {
+        return Ok(());
     };
+    let filter = run_filter(config, query);
+    if filter.matches.is_empty() {
+        return Err(LstError::NothingFound);
+    }
+
+    let mut stdout = std::io::stdout().lock();
+    let mut failed = 0;
+    for args in command.invocations(&filter.matched_paths()) {
+        // Ctrl-C reaches the running command too; don't start the next one
+        if let Some(reason) = cancelled() {
+            return Err(LstError::Cancelled(reason));
+        }
+        if dry_run {
+            writeln!(stdout, "{}", shell_quote(&args))?;
+            continue;
+        }
+        let status = std::process::Command::new(&args[0])
+            .args(&args[1..])
+            .status()
+            .map_err(|e| LstError::ExecError(format!("cannot run '{}': {}", args[0].to_string_lossy(), e)))?;
+        if !status.success() {
+            failed += 1;
+        }
+    }
+    match failed {
+        0 => Ok(()),
+        n => Err(LstError::ExecError(format!("{} command(s) failed", n))),
+    }
+}
+
+/// Only report through the result whether anything matched (`--quiet`)
+pub fn check(config: &TreeConfig) -> Result<()> {
+    let Some(query) = &config.search else {
+        return Ok(());
+    };
+    if run_filter(config, query).matches.is_empty() {
+        Err(LstError::NothingFound)
+    } else {
+        Ok(())
+    }
+}
 
+fn write_tree(config: TreeConfig, output: Option<&str>) -> Result<()> {
     if let Some(output_path) = output {
         // Write to file without spinner
-        TreeWriter::for_file().write_to_file(output_path, &config)
+        TreeWriter::for_export(config.color).with_palette(config.palette.clone()).write_to_file(output_path, &config)
     } else {
         // Terminal output with spinner
-        let stop = Arc::new(AtomicBool::new(false));
-        let stop_flag = Arc::clone(&stop);
-
-        let spinner_handle = std::thread::spawn(move || {
-            use std::time::Duration;
-            let frames = ["|", "/", "-", "\\"];
-            let mut i = 0usize;
-            eprint!("\x1B[?25l"); // Hide cursor
-            let _ = std::io::stderr().flush();
-            while !stop_flag.load(Ordering::Relaxed) {
-                eprint!("\rSearching... {}", frames[i % frames.len()]);
-                let _ = std::io::stderr().flush();
-                i = i.wrapping_add(1);
-                std::thread::sleep(Duration::from_millis(120));
-            }
-            eprint!("\r                \r\x1B[?25h"); // Clear and show cursor
-            let _ = std::io::stderr().flush();
-        });
-
+        let spinner = Spinner::start_if(config.show_spinner, "Searching...");
         let config_with_spinner = TreeConfig {
-            spinner_stop: Some(Arc::clone(&stop)),
-            json_output: json,
+            spinner_stop: spinner.as_ref().map(Spinner::stop_flag),
+            progress: spinner.as_ref().map(Spinner::counter),
             ..config
         };
 
-        let res = TreeWriter::for_terminal().write_to_terminal(&config_with_spinner);
-        stop.store(true, Ordering::Relaxed);
-        let _ = spinner_handle.join();
+        let res = TreeWriter::for_terminal().with_palette(config_with_spinner.palette.clone()).write_to_terminal(&config_with_spinner);
+        if let Some(spinner) = spinner {
+            spinner.finish();
+        }
         res
     }
 }
+
+/// Print one matching path per line (`path:count` when counts are requested), like `grep -l`
+fn list_matches(config: &TreeConfig, output: Option<&str>) -> Result<()> {
+    let Some(query) = &config.search else {
+        return Ok(());
+    };
+    let filter = run_filter(config, query);
+    let mut lines = String::new();
+    for path in filter.matched_paths() {
+        match filter.displayed_match_count(&path, query) {
+            Some(count) => lines.push_str(&format!("{}:{}\n", shown_path(&path, config), count)),
+            None => lines.push_str(&format!("{}\n", shown_path(&path, config))),
+        }
+    }
+
+    match output {
+        Some(output_path) => {
+            open_export(output_path, config.export_mode)?.write_all(lines.as_bytes())?;
+            writeln!(std::io::stdout().lock(), "Matches exported to {}", output_path)?;
+        }
+        None => {
+            let stdout = std::io::stdout();
+            stdout.lock().write_all(lines.as_bytes())?;
+        }
+    }
+    // After the results, so the note closes the list even with stderr on the same terminal
+    if filter.truncated {
+        eprintln!("... and more");
+    }
+    if filter.matches.is_empty() {
+        return Err(LstError::NothingFound);
+    }
+    Ok(())
+}
+
+/// A matched path as printed, shell-quoted with `--quote-names` or with control characters escaped
+fn shown_path(path: &Path, config: &TreeConfig) -> String {
+    let path = path.to_string_lossy();
+    if config.quote_names {
+        quote_arg(&path).into_owned()
+    } else if config.escape_names {
+        sanitize_name(&path)
+    } else {
+        path.into_owned()
+    }
+}
+
+/// Run the search pre-pass behind a spinner
+fn run_filter(config: &TreeConfig, query: &SearchQuery) -> SearchFilter {
+    let spinner = Spinner::start_if(config.show_spinner, "Searching...");
+    let progress = spinner.as_ref().map(Spinner::counter);
+    let mut timings = Timings::default();
+    let entries = timings.time(|t| &mut t.walk, || {
+        search_candidates(config.path, config.max_depth, &config.show, query, progress.as_deref())
+    });
+    timings.entries = entries.len();
+    let filter = timings.time(|t| &mut t.search, || build_search_filter(&entries, query));
+    config.record_timings(&timings);
+    if let Some(spinner) = spinner {
+        spinner.finish();
+    }
+    filter
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shown_paths_are_escaped_or_quoted() {
+        let path = Path::new("dir/evil\x1b[2J.txt");
+        let shown = |escape_names, quote_names| {
+            shown_path(path, &TreeConfig { escape_names, quote_names, ..TreeConfig::new(Path::new("dir")) })
+        };
+        assert_eq!(shown(false, false), "dir/evil\x1b[2J.txt");
+        assert_eq!(shown(true, false), "dir/evil\\e[2J.txt");
+        assert_eq!(shown(true, true), "$'dir/evil\\e[2J.txt'");
+    }
+}
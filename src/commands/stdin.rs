@@ -0,0 +1,34 @@
+use std::io::{BufRead, Write};
+
+use crate::core::virtual_tree::build_virtual_entries;
+use crate::error::Result;
+use crate::output::printer::{open_export, write_header, OutputFormat, TreeConfig, TreeWriter};
+
+/// Render newline-separated paths read from stdin as a tree, without walking the filesystem
+///
+/// `config.path` is `.`, the root the paths are relative to.
+pub fn run(config: TreeConfig, output: Option<&str>) -> Result<()> {
+    let stdin = std::io::stdin();
+    let mut paths = Vec::new();
+    for line in stdin.lock().split(b'\n') {
+        paths.push(String::from_utf8_lossy(&line?).into_owned());
+    }
+
+    let entries = build_virtual_entries(&paths);
+
+    if let Some(output_path) = output {
+        let mut file = open_export(output_path, config.export_mode)?;
+        if config.format == OutputFormat::Text {
+            write_header(&mut file, &config)?;
+        }
+        let writer = TreeWriter::for_export(config.color).with_palette(config.palette.clone()).with_tree_style(config.tree_style);
+        writer.write_virtual(&mut file, &config, &entries)?;
+        writeln!(std::io::stdout().lock(), "Tree exported to {}", output_path)?;
+        Ok(())
+    } else {
+        let stdout = std::io::stdout();
+        let mut handle = stdout.lock();
+        let writer = TreeWriter::for_terminal().with_palette(config.palette.clone()).with_tree_style(config.tree_style);
+        writer.write_virtual(&mut handle, &config, &entries)
+    }
+}
@@ -0,0 +1,37 @@
+use std::io::Write;
+
+use crate::error::Result;
+
+/// Print the version with the build details useful in bug reports
+pub fn run() -> Result<()> {
+    write!(std::io::stdout(), "{}", version_info())?;
+    Ok(())
+}
+
+/// Version, git commit, target, profile and enabled features, one per line
+pub fn version_info() -> String {
+    let features = env!("LST_FEATURES");
+    format!(
+        "lst {}\ncommit: {}\ntarget: {}\nprofile: {}\nfeatures: {}\n",
+        env!("CARGO_PKG_VERSION"),
+        env!("LST_GIT_COMMIT"),
+        env!("LST_TARGET"),
+        env!("LST_PROFILE"),
+        if features.is_empty() { "none" } else { features },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_version_info_lists_build_details() {
+        let info = version_info();
+        assert!(info.starts_with(&format!("lst {}\n", env!("CARGO_PKG_VERSION"))));
+        for label in ["commit: ", "target: ", "profile: ", "features: "] {
+            assert!(info.contains(label), "{}", info);
+        }
+        assert!(!info.contains("commit: \n"), "{}", info);
+    }
+}
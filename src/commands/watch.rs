@@ -0,0 +1,118 @@
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::io::Write;
+use std::path::Path;
+use std::time::Duration;
+
+use crate::core::cancel::{install_interrupt_handler, interrupted};
+use crate::core::filters::ShowOptions;
+use crate::core::tree::{ok_or_warn, walk_visible};
+use crate::error::Result;
+use crate::output::printer::{TreeConfig, TreeWriter};
+
+/// How often the tree is rescanned for changes
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Granularity of the Ctrl-C check while waiting for the next poll
+const STOP_CHECK_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Render the tree, then re-render it whenever something below the root changes
+///
+/// Changes are detected by polling a fingerprint of every visible entry's path,
+/// size and mtime. A redraw waits until the fingerprint holds still for one
+/// poll, so bursts such as an editor's write-to-temp-then-rename save refresh
+/// the screen once. Ctrl-C stops watching and restores the cursor.
+pub fn run(config: TreeConfig) -> Result<()> {
+    install_interrupt_handler();
+    let mut stdout = std::io::stdout();
+    write!(stdout, "\x1B[?25l")?; // Hide cursor
+    let res = watch_loop(&config);
+    write!(stdout, "\x1B[?25h")?; // Show cursor
+    stdout.flush()?;
+    res
+}
+
+fn watch_loop(config: &TreeConfig) -> Result<()> {
+    let mut rendered = fingerprint(config.path, config.max_depth, &config.show);
+    redraw(config)?;
+    let mut pending: Option<u64> = None;
+    while sleep_unless_interrupted(POLL_INTERVAL) {
+        let current = fingerprint(config.path, config.max_depth, &config.show);
+        match pending {
+            // Settled since the last poll: draw the new state
+            Some(previous) if previous == current => {
+                pending = None;
+                if current != rendered {
+                    rendered = current;
+                    redraw(config)?;
+                }
+            }
+            _ if current != rendered => pending = Some(current),
+            _ => pending = None,
+        }
+    }
+    Ok(())
+}
+
+/// Clear the screen and render the tree from the top
+fn redraw(config: &TreeConfig) -> Result<()> {
+    let mut stdout = std::io::stdout();
+    write!(stdout, "\x1B[2J\x1B[H")?;
+    stdout.flush()?;
+    TreeWriter::for_terminal().with_palette(config.palette.clone()).write_to_terminal(config)
+}
+
+/// Hash of every visible entry's path, size and modification time
+fn fingerprint(path: &Path, max_depth: usize, show: &ShowOptions) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    for entry in walk_visible(path, max_depth, show).filter_map(ok_or_warn) {
+        entry.path().hash(&mut hasher);
+        if let Ok(meta) = entry.metadata() {
+            meta.len().hash(&mut hasher);
+            meta.modified().ok().hash(&mut hasher);
+        }
+    }
+    hasher.finish()
+}
+
+/// Sleep for `duration`, returning false early once Ctrl-C was pressed
+fn sleep_unless_interrupted(duration: Duration) -> bool {
+    let mut waited = Duration::ZERO;
+    while waited < duration {
+        if interrupted() {
+            return false;
+        }
+        std::thread::sleep(STOP_CHECK_INTERVAL);
+        waited += STOP_CHECK_INTERVAL;
+    }
+    !interrupted()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::tree::UNLIMITED_DEPTH;
+
+    #[test]
+    fn test_fingerprint_tracks_adds_edits_and_renames() {
+        let dir = std::env::temp_dir().join(format!("lst-watch-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("a.txt"), "one").unwrap();
+        let print = || fingerprint(&dir, UNLIMITED_DEPTH, &ShowOptions::default());
+
+        let initial = print();
+        assert_eq!(print(), initial);
+        std::fs::write(dir.join("a.txt"), "three").unwrap();
+        let edited = print();
+        assert_ne!(edited, initial);
+        std::fs::rename(dir.join("a.txt"), dir.join("b.txt")).unwrap();
+        assert_ne!(print(), edited);
+        // Hidden entries aren't listed, so they don't trigger a redraw
+        let renamed = print();
+        std::fs::write(dir.join(".swp"), "").unwrap();
+        assert_eq!(print(), renamed);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
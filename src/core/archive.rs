@@ -0,0 +1,410 @@
+use std::fs::File;
+use std::io::{self, BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+
+use flate2::read::GzDecoder;
+
+use crate::error::{LstError, Result};
+
+/// Size of a tar header and of the blocks member data is padded to
+const TAR_BLOCK: usize = 512;
+
+/// An archive format whose member list can be read without extracting anything
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveKind {
+    Zip,
+    Tar,
+    /// A tar stream compressed with gzip (`.tar.gz`, `.tgz`)
+    TarGz,
+}
+
+impl ArchiveKind {
+    /// Recognize an archive by its magic bytes, falling back to the extension for old tars without one
+    pub fn detect(path: &Path) -> Option<Self> {
+        let mut head = Vec::with_capacity(TAR_BLOCK);
+        File::open(path).ok()?.take(TAR_BLOCK as u64).read_to_end(&mut head).ok()?;
+        if head.starts_with(b"PK\x03\x04") || head.starts_with(b"PK\x05\x06") {
+            return Some(ArchiveKind::Zip);
+        }
+        if is_ustar(&head) {
+            return Some(ArchiveKind::Tar);
+        }
+        if head.starts_with(b"\x1f\x8b") {
+            let mut inner = Vec::with_capacity(TAR_BLOCK);
+            let decoder = GzDecoder::new(File::open(path).ok()?);
+            if decoder.take(TAR_BLOCK as u64).read_to_end(&mut inner).is_ok() && is_ustar(&inner) {
+                return Some(ArchiveKind::TarGz);
+            }
+        }
+        let name = path.file_name()?.to_str()?.to_ascii_lowercase();
+        if name.ends_with(".tar") {
+            Some(ArchiveKind::Tar)
+        } else {
+            None
+        }
+    }
+
+    /// Member paths, directories ending in `/`, with the size each would take once extracted
+    pub fn members(self, path: &Path) -> Result<Vec<(String, Option<u64>)>> {
+        let file = File::open(path)?;
+        let members = match self {
+            ArchiveKind::Zip => zip_members(file),
+            ArchiveKind::Tar => tar_members(BufReader::new(file)),
+            ArchiveKind::TarGz => tar_members(GzDecoder::new(BufReader::new(file))),
+        };
+        members.map_err(|e| LstError::InvalidPath(format!("cannot read archive '{}': {}", path.display(), e)))
+    }
+}
+
+/// Whether a block is a POSIX (or GNU) tar header
+fn is_ustar(block: &[u8]) -> bool {
+    block.get(257..262) == Some(b"ustar")
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+/// Members of a tar stream, read header by header and skipping over the data
+///
+/// Understands ustar name prefixes, GNU long names (`L`) and pax `path`/`size`
+/// records; pax global headers are ignored.
+fn tar_members<R: Read>(mut reader: R) -> io::Result<Vec<(String, Option<u64>)>> {
+    let mut members = Vec::new();
+    let mut header = [0u8; TAR_BLOCK];
+    // Overrides for the next member, from a GNU long name or a pax header
+    let mut long_name: Option<String> = None;
+    let mut pax_size: Option<u64> = None;
+    loop {
+        if !read_block(&mut reader, &mut header)? || header.iter().all(|&b| b == 0) {
+            break;
+        }
+        let size = tar_number(&header[124..136]).ok_or_else(|| invalid("bad size in tar header"))?;
+        match header[156] {
+            b'L' => {
+                let data = read_data(&mut reader, size)?;
+                long_name = Some(c_string(&data));
+            }
+            b'x' => {
+                let data = read_data(&mut reader, size)?;
+                for (key, value) in pax_records(&data) {
+                    match key {
+                        "path" => long_name = Some(value.to_string()),
+                        "size" => pax_size = value.parse().ok(),
+                        _ => {}
+                    }
+                }
+            }
+            kind => {
+                let mut name = long_name.take().unwrap_or_else(|| {
+                    let name = c_string(&header[..100]);
+                    let prefix = if is_ustar(&header) { c_string(&header[345..500]) } else { String::new() };
+                    if prefix.is_empty() { name } else { format!("{}/{}", prefix, name) }
+                });
+                // A pax size replaces the header's, which can't hold sizes of 8 GiB and more
+                let size = pax_size.take().unwrap_or(size);
+                if kind == b'5' && !name.ends_with('/') {
+                    name.push('/');
+                }
+                match kind {
+                    b'5' => members.push((name, None)),
+                    // Pax global headers describe the archive, not a member
+                    b'g' => {}
+                    b'0' | 0 | b'7' => members.push((name, Some(size))),
+                    // Links and special files take no space of their own
+                    _ => members.push((name, Some(0))),
+                }
+                io::copy(&mut (&mut reader).take(padded(size)), &mut io::sink())?;
+            }
+        }
+    }
+    Ok(members)
+}
+
+/// Fill `block`, or return false at a clean end of the stream
+fn read_block<R: Read>(reader: &mut R, block: &mut [u8; TAR_BLOCK]) -> io::Result<bool> {
+    let mut filled = 0;
+    while filled < TAR_BLOCK {
+        match reader.read(&mut block[filled..])? {
+            0 if filled == 0 => return Ok(false),
+            0 => return Err(invalid("truncated tar header")),
+            n => filled += n,
+        }
+    }
+    Ok(true)
+}
+
+/// Read `size` bytes of member data and skip the padding after them
+fn read_data<R: Read>(reader: &mut R, size: u64) -> io::Result<Vec<u8>> {
+    let mut data = Vec::new();
+    reader.take(size).read_to_end(&mut data)?;
+    io::copy(&mut reader.take(padded(size) - size), &mut io::sink())?;
+    Ok(data)
+}
+
+/// Bytes `size` bytes of member data take in the stream, rounded up to whole blocks
+fn padded(size: u64) -> u64 {
+    size.div_ceil(TAR_BLOCK as u64) * TAR_BLOCK as u64
+}
+
+/// A tar numeric field: octal text, or big-endian binary when the high bit is set (GNU)
+fn tar_number(field: &[u8]) -> Option<u64> {
+    if field[0] & 0x80 != 0 {
+        return Some(field[1..].iter().fold(u64::from(field[0] & 0x7f), |acc, &b| (acc << 8) | u64::from(b)));
+    }
+    let text = std::str::from_utf8(field).ok()?.trim_matches(|c: char| c == '\0' || c == ' ');
+    if text.is_empty() {
+        Some(0)
+    } else {
+        u64::from_str_radix(text, 8).ok()
+    }
+}
+
+/// Text up to the first NUL
+fn c_string(bytes: &[u8]) -> String {
+    let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+    String::from_utf8_lossy(&bytes[..end]).into_owned()
+}
+
+/// The `key=value` records of a pax header, each written as `<length> key=value\n`
+fn pax_records(data: &[u8]) -> Vec<(&str, &str)> {
+    let mut records = Vec::new();
+    let mut rest = data;
+    while let Some(space) = rest.iter().position(|&b| b == b' ') {
+        let Some(len) = std::str::from_utf8(&rest[..space]).ok().and_then(|n| n.parse::<usize>().ok()) else { break };
+        if len <= space || len > rest.len() {
+            break;
+        }
+        let record = std::str::from_utf8(&rest[space + 1..len]).unwrap_or_default().trim_end_matches('\n');
+        if let Some((key, value)) = record.split_once('=') {
+            records.push((key, value));
+        }
+        rest = &rest[len..];
+    }
+    records
+}
+
+/// Members of a zip file, read from its central directory at the end (zip64 included)
+fn zip_members<R: Read + Seek>(mut file: R) -> io::Result<Vec<(String, Option<u64>)>> {
+    let len = file.seek(SeekFrom::End(0))?;
+    // The end record is 22 bytes plus a comment of up to 64 KiB
+    let tail_len = len.min(22 + 0xFFFF);
+    file.seek(SeekFrom::Start(len - tail_len))?;
+    let mut tail = vec![0u8; tail_len as usize];
+    file.read_exact(&mut tail)?;
+    let end = (0..tail.len().saturating_sub(21))
+        .rev()
+        .find(|&i| tail[i..].starts_with(b"PK\x05\x06"))
+        .ok_or_else(|| invalid("no zip end of central directory record"))?;
+    let record = &tail[end..];
+    let mut count = u64::from(le16(record, 10));
+    let mut offset = u64::from(le32(record, 16));
+
+    // Too many members or too large an archive: the real numbers are in the zip64 end record
+    if count == 0xFFFF || offset == 0xFFFF_FFFF {
+        let locator = end.checked_sub(20).filter(|&i| tail[i..].starts_with(b"PK\x06\x07"));
+        let locator = locator.ok_or_else(|| invalid("missing zip64 end of central directory locator"))?;
+        file.seek(SeekFrom::Start(le64(&tail, locator + 8)))?;
+        let mut zip64 = [0u8; 56];
+        file.read_exact(&mut zip64)?;
+        if !zip64.starts_with(b"PK\x06\x06") {
+            return Err(invalid("bad zip64 end of central directory record"));
+        }
+        count = le64(&zip64, 32);
+        offset = le64(&zip64, 48);
+    }
+
+    file.seek(SeekFrom::Start(offset))?;
+    let mut reader = BufReader::new(file);
+    let mut members = Vec::new();
+    for _ in 0..count {
+        let mut header = [0u8; 46];
+        reader.read_exact(&mut header)?;
+        if !header.starts_with(b"PK\x01\x02") {
+            return Err(invalid("bad zip central directory entry"));
+        }
+        let mut variable = vec![0u8; usize::from(le16(&header, 28)) + usize::from(le16(&header, 30)) + usize::from(le16(&header, 32))];
+        reader.read_exact(&mut variable)?;
+        let (name, rest) = variable.split_at(usize::from(le16(&header, 28)));
+        let extra = &rest[..usize::from(le16(&header, 30))];
+        let name = String::from_utf8_lossy(name).into_owned();
+        let mut size = u64::from(le32(&header, 24));
+        if size == 0xFFFF_FFFF {
+            size = zip64_size(extra).unwrap_or(size);
+        }
+        let size = (!name.ends_with('/')).then_some(size);
+        members.push((name, size));
+    }
+    Ok(members)
+}
+
+/// Uncompressed size from a zip64 extended information field, the first value it holds
+fn zip64_size(mut extra: &[u8]) -> Option<u64> {
+    while extra.len() >= 4 {
+        let (id, len) = (le16(extra, 0), usize::from(le16(extra, 2)));
+        if id == 0x0001 && len >= 8 && extra.len() >= 12 {
+            return Some(le64(extra, 4));
+        }
+        extra = extra.get(4 + len..)?;
+    }
+    None
+}
+
+fn le16(bytes: &[u8], at: usize) -> u16 {
+    u16::from_le_bytes([bytes[at], bytes[at + 1]])
+}
+
+fn le32(bytes: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes(bytes[at..at + 4].try_into().expect("4 bytes"))
+}
+
+fn le64(bytes: &[u8], at: usize) -> u64 {
+    u64::from_le_bytes(bytes[at..at + 8].try_into().expect("8 bytes"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Cursor, Write};
+
+    /// A ustar header for `name` with `size` bytes of data, checksum included
+    fn tar_header(name: &str, kind: u8, size: usize) -> [u8; TAR_BLOCK] {
+        let mut header = [0u8; TAR_BLOCK];
+        header[..name.len()].copy_from_slice(name.as_bytes());
+        header[124..136].copy_from_slice(format!("{:011o}\0", size).as_bytes());
+        header[156] = kind;
+        header[257..263].copy_from_slice(b"ustar\0");
+        header[148..156].fill(b' ');
+        let sum: u32 = header.iter().map(|&b| u32::from(b)).sum();
+        header[148..156].copy_from_slice(format!("{:06o}\0 ", sum).as_bytes());
+        header
+    }
+
+    fn tar_member(out: &mut Vec<u8>, name: &str, kind: u8, data: &[u8]) {
+        out.extend_from_slice(&tar_header(name, kind, data.len()));
+        out.extend_from_slice(data);
+        out.resize(out.len().div_ceil(TAR_BLOCK) * TAR_BLOCK, 0);
+    }
+
+    #[test]
+    fn test_tar_members_with_long_names_and_pax_paths() {
+        let long = format!("deep/{}.txt", "n".repeat(120));
+        let mut tar = Vec::new();
+        tar_member(&mut tar, "src/", b'5', b"");
+        tar_member(&mut tar, "src/main.rs", b'0', b"fn main() {}\n");
+        tar_member(&mut tar, "././@LongLink", b'L', format!("{}\0", long).as_bytes());
+        tar_member(&mut tar, "truncated", b'0', b"abc");
+        tar_member(&mut tar, "PaxHeader", b'x', b"20 path=pax/name.md\n");
+        tar_member(&mut tar, "short", b'0', b"");
+        tar_member(&mut tar, "link", b'2', b"");
+        tar.extend_from_slice(&[0u8; 2 * TAR_BLOCK]);
+
+        let members = tar_members(Cursor::new(tar.clone())).unwrap();
+        assert_eq!(
+            members,
+            vec![
+                ("src/".to_string(), None),
+                ("src/main.rs".to_string(), Some(13)),
+                (long, Some(3)),
+                ("pax/name.md".to_string(), Some(0)),
+                ("link".to_string(), Some(0)),
+            ]
+        );
+
+        let mut gz = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::fast());
+        gz.write_all(&tar).unwrap();
+        let compressed = gz.finish().unwrap();
+        assert_eq!(tar_members(GzDecoder::new(Cursor::new(compressed))).unwrap().len(), 5);
+    }
+
+    #[test]
+    fn test_pax_size_decides_how_much_data_to_skip() {
+        let mut tar = Vec::new();
+        tar_member(&mut tar, "PaxHeader", b'x', b"12 size=600\n");
+        // The header says 0 bytes, as for members too large for its size field
+        tar.extend_from_slice(&tar_header("huge.bin", b'0', 0));
+        tar.extend_from_slice(&[0xff; 1024]);
+        tar_member(&mut tar, "after.txt", b'0', b"ok");
+        tar.extend_from_slice(&[0u8; 2 * TAR_BLOCK]);
+
+        let members = tar_members(Cursor::new(tar)).unwrap();
+        assert_eq!(members, vec![("huge.bin".to_string(), Some(600)), ("after.txt".to_string(), Some(2))]);
+    }
+
+    #[test]
+    fn test_tar_numbers_in_octal_and_base_256() {
+        assert_eq!(tar_number(b"00000000644\0"), Some(0o644));
+        assert_eq!(tar_number(b"\0\0\0\0\0\0\0\0\0\0\0\0"), Some(0));
+        assert_eq!(tar_number(&[0x80, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x01, 0x00]), Some(256));
+    }
+
+    /// A stored (uncompressed) zip of the given members, directories ending in `/`
+    fn zip_of(members: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut out = Vec::new();
+        let mut central = Vec::new();
+        for (name, data) in members {
+            let offset = out.len() as u32;
+            let sizes = [(data.len() as u32).to_le_bytes(), (data.len() as u32).to_le_bytes()].concat();
+            out.extend_from_slice(b"PK\x03\x04\x14\0\0\0\0\0\0\0\0\0\0\0\0\0");
+            out.extend_from_slice(&sizes);
+            out.extend_from_slice(&(name.len() as u16).to_le_bytes());
+            out.extend_from_slice(&[0, 0]);
+            out.extend_from_slice(name.as_bytes());
+            out.extend_from_slice(data);
+
+            central.extend_from_slice(b"PK\x01\x02\x14\0\x14\0\0\0\0\0\0\0\0\0\0\0\0\0");
+            central.extend_from_slice(&sizes);
+            central.extend_from_slice(&(name.len() as u16).to_le_bytes());
+            central.extend_from_slice(&[0; 12]);
+            central.extend_from_slice(&offset.to_le_bytes());
+            central.extend_from_slice(name.as_bytes());
+        }
+        let central_offset = out.len() as u32;
+        out.extend_from_slice(&central);
+        out.extend_from_slice(b"PK\x05\x06\0\0\0\0");
+        out.extend_from_slice(&(members.len() as u16).to_le_bytes());
+        out.extend_from_slice(&(members.len() as u16).to_le_bytes());
+        out.extend_from_slice(&(central.len() as u32).to_le_bytes());
+        out.extend_from_slice(&central_offset.to_le_bytes());
+        out.extend_from_slice(&[0, 0]);
+        out
+    }
+
+    #[test]
+    fn test_zip_members_from_the_central_directory() {
+        let zip = zip_of(&[("docs/", b""), ("docs/readme.md", b"hello"), ("run.sh", b"#!/bin/sh\n")]);
+        let members = zip_members(Cursor::new(zip)).unwrap();
+        assert_eq!(
+            members,
+            vec![
+                ("docs/".to_string(), None),
+                ("docs/readme.md".to_string(), Some(5)),
+                ("run.sh".to_string(), Some(10)),
+            ]
+        );
+        assert!(zip_members(Cursor::new(b"not a zip".to_vec())).is_err());
+    }
+
+    #[test]
+    fn test_detects_archives_by_magic_before_extension() {
+        let dir = std::env::temp_dir().join(format!("lst-archive-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("bundle.bin"), zip_of(&[("a.txt", b"a")])).unwrap();
+        let mut tar = Vec::new();
+        tar_member(&mut tar, "a.txt", b'0', b"a");
+        let mut gz = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::fast());
+        gz.write_all(&tar).unwrap();
+        std::fs::write(dir.join("backup"), gz.finish().unwrap()).unwrap();
+        std::fs::write(dir.join("plain.tar"), &tar).unwrap();
+        std::fs::write(dir.join("notes.txt"), "just text").unwrap();
+
+        assert_eq!(ArchiveKind::detect(&dir.join("bundle.bin")), Some(ArchiveKind::Zip));
+        assert_eq!(ArchiveKind::detect(&dir.join("backup")), Some(ArchiveKind::TarGz));
+        assert_eq!(ArchiveKind::detect(&dir.join("plain.tar")), Some(ArchiveKind::Tar));
+        assert_eq!(ArchiveKind::detect(&dir.join("notes.txt")), None);
+        assert_eq!(ArchiveKind::TarGz.members(&dir.join("backup")).unwrap(), vec![("a.txt".to_string(), Some(1))]);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
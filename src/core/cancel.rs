@@ -0,0 +1,99 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+
+/// Set by the SIGINT handler; walks and the watch loop stop once they see it
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+/// When walks give up (`--timeout`); unset means never
+static DEADLINE: OnceLock<Instant> = OnceLock::new();
+
+/// Why walks stopped before reaching every entry
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Cancelled {
+    /// `--timeout` ran out
+    TimedOut,
+    /// Ctrl-C was pressed
+    Interrupted,
+}
+
+impl Cancelled {
+    /// Line printed after a tree that was cut short
+    pub fn note(self) -> &'static str {
+        match self {
+            Cancelled::TimedOut => "[timed out]",
+            Cancelled::Interrupted => "[interrupted]",
+        }
+    }
+}
+
+/// Give up on walks `timeout` from now; only the first call counts
+pub fn set_timeout(timeout: Duration) {
+    let _ = DEADLINE.set(Instant::now() + timeout);
+}
+
+/// Whether walks should stop, and why
+///
+/// Checked once per walked entry, so it costs an atomic load and, with a
+/// timeout, a clock read.
+pub fn cancelled() -> Option<Cancelled> {
+    check(DEADLINE.get().copied(), INTERRUPTED.load(Ordering::Relaxed), Instant::now())
+}
+
+/// Whether Ctrl-C was pressed
+pub fn interrupted() -> bool {
+    INTERRUPTED.load(Ordering::Relaxed)
+}
+
+fn check(deadline: Option<Instant>, interrupted: bool, now: Instant) -> Option<Cancelled> {
+    if interrupted {
+        Some(Cancelled::Interrupted)
+    } else if deadline.is_some_and(|deadline| now >= deadline) {
+        Some(Cancelled::TimedOut)
+    } else {
+        None
+    }
+}
+
+/// Make Ctrl-C stop walks instead of killing the process, so what was found
+/// is still printed and the spinner restores the cursor
+///
+/// A second Ctrl-C restores the cursor and exits at once, for anything stuck
+/// in a blocking call.
+#[cfg(unix)]
+pub fn install_interrupt_handler() {
+    extern "C" fn on_interrupt(_: libc::c_int) {
+        if INTERRUPTED.swap(true, Ordering::Relaxed) {
+            const SHOW_CURSOR: &[u8] = b"\x1B[?25h\n";
+            // SAFETY: write and _exit are async-signal-safe
+            unsafe {
+                libc::write(libc::STDERR_FILENO, SHOW_CURSOR.as_ptr().cast(), SHOW_CURSOR.len());
+                libc::_exit(130);
+            }
+        }
+    }
+    // SAFETY: the handler only touches an atomic, or calls async-signal-safe functions
+    unsafe {
+        libc::signal(libc::SIGINT, on_interrupt as extern "C" fn(libc::c_int) as libc::sighandler_t);
+    }
+}
+
+/// Elsewhere Ctrl-C keeps its default behavior and ends the process directly
+#[cfg(not(unix))]
+pub fn install_interrupt_handler() {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_interrupts_win_over_deadlines() {
+        let now = Instant::now();
+        let later = now + Duration::from_secs(1);
+        assert_eq!(check(None, false, now), None);
+        assert_eq!(check(Some(later), false, now), None);
+        assert_eq!(check(Some(later), false, later), Some(Cancelled::TimedOut));
+        assert_eq!(check(Some(later), true, now), Some(Cancelled::Interrupted));
+        assert_eq!(Cancelled::TimedOut.note(), "[timed out]");
+    }
+}
@@ -0,0 +1,160 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::fs::File;
+use std::hash::Hasher;
+use std::io::{self, BufReader, Read};
+use std::path::{Path, PathBuf};
+use rayon::prelude::*;
+
+/// Read buffer size for hashing and comparing
+const CHUNK_BYTES: usize = 64 * 1024;
+
+/// Find files with identical contents, returning each duplicate's group number
+///
+/// `files` pairs each path with its size. Only files sharing a non-zero size
+/// are hashed; files whose hashes agree are then compared byte for byte, so a
+/// hash collision can never report a false duplicate. Groups are numbered from
+/// 1 in order of their first path; files without a twin are left out.
+pub fn find_duplicates(files: &[(PathBuf, u64)]) -> HashMap<PathBuf, usize> {
+    let mut by_size: HashMap<u64, Vec<&Path>> = HashMap::new();
+    for (path, size) in files {
+        if *size > 0 {
+            by_size.entry(*size).or_default().push(path);
+        }
+    }
+
+    let candidates: Vec<(u64, &Path)> = by_size
+        .into_iter()
+        .filter(|(_, paths)| paths.len() > 1)
+        .flat_map(|(size, paths)| paths.into_iter().map(move |path| (size, path)))
+        .collect();
+    let hashed: Vec<((u64, u64), &Path)> = candidates
+        .par_iter()
+        .filter_map(|&(size, path)| match hash_file(path) {
+            Ok(hash) => Some(((size, hash), path)),
+            Err(e) => {
+                log::warn!("cannot hash {}: {}", path.display(), e);
+                None
+            }
+        })
+        .collect();
+
+    let mut by_hash: HashMap<(u64, u64), Vec<&Path>> = HashMap::new();
+    for (key, path) in hashed {
+        by_hash.entry(key).or_default().push(path);
+    }
+
+    let mut groups: Vec<Vec<PathBuf>> = by_hash
+        .into_values()
+        .filter(|paths| paths.len() > 1)
+        .collect::<Vec<_>>()
+        .par_iter()
+        .flat_map(|paths| confirm_identical(paths))
+        .collect();
+    for group in &mut groups {
+        group.sort();
+    }
+    groups.sort();
+
+    groups
+        .into_iter()
+        .enumerate()
+        .flat_map(|(idx, group)| group.into_iter().map(move |path| (path, idx + 1)))
+        .collect()
+}
+
+/// Split same-hash files into groups whose bytes really are equal, dropping singletons
+fn confirm_identical(paths: &[&Path]) -> Vec<Vec<PathBuf>> {
+    let mut groups: Vec<Vec<PathBuf>> = Vec::new();
+    for path in paths {
+        match groups
+            .iter_mut()
+            .find(|group| same_contents(&group[0], path).unwrap_or(false))
+        {
+            Some(group) => group.push(path.to_path_buf()),
+            None => groups.push(vec![path.to_path_buf()]),
+        }
+    }
+    groups.retain(|group| group.len() > 1);
+    groups
+}
+
+/// Hash a file's contents in chunks
+fn hash_file(path: &Path) -> io::Result<u64> {
+    let mut reader = BufReader::with_capacity(CHUNK_BYTES, File::open(path)?);
+    let mut hasher = DefaultHasher::new();
+    let mut buf = vec![0u8; CHUNK_BYTES];
+    loop {
+        let n = reader.read(&mut buf)?;
+        if n == 0 {
+            return Ok(hasher.finish());
+        }
+        hasher.write(&buf[..n]);
+    }
+}
+
+/// Compare two files byte for byte
+fn same_contents(a: &Path, b: &Path) -> io::Result<bool> {
+    let mut a = BufReader::with_capacity(CHUNK_BYTES, File::open(a)?);
+    let mut b = BufReader::with_capacity(CHUNK_BYTES, File::open(b)?);
+    let mut buf_a = vec![0u8; CHUNK_BYTES];
+    let mut buf_b = vec![0u8; CHUNK_BYTES];
+    loop {
+        let n = read_full(&mut a, &mut buf_a)?;
+        if n != read_full(&mut b, &mut buf_b)? || buf_a[..n] != buf_b[..n] {
+            return Ok(false);
+        }
+        if n == 0 {
+            return Ok(true);
+        }
+    }
+}
+
+/// Fill `buf` as far as the reader allows, returning the number of bytes read
+fn read_full<R: Read>(reader: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..])? {
+            0 => break,
+            n => filled += n,
+        }
+    }
+    Ok(filled)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_duplicates_groups_identical_files() {
+        let dir = std::env::temp_dir().join(format!("lst-dupes-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let write = |name: &str, content: &str| {
+            let path = dir.join(name);
+            std::fs::write(&path, content).unwrap();
+            (path, content.len() as u64)
+        };
+        let files = vec![
+            write("a.txt", "same"),
+            write("b.txt", "same"),
+            write("c.txt", "diff"), // same size, different bytes
+            write("d.bin", "xy"),
+            write("e.bin", "xy"),
+            write("empty1", ""),
+            write("empty2", ""),
+        ];
+
+        let dupes = find_duplicates(&files);
+        assert_eq!(dupes.len(), 4);
+        assert_eq!(dupes[&dir.join("a.txt")], 1);
+        assert_eq!(dupes[&dir.join("b.txt")], 1);
+        assert_eq!(dupes[&dir.join("d.bin")], 2);
+        assert_eq!(dupes[&dir.join("e.bin")], 2);
+        assert!(!dupes.contains_key(&dir.join("c.txt")));
+        assert!(!dupes.contains_key(&dir.join("empty1")));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
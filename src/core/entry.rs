@@ -0,0 +1,70 @@
+use std::ffi::OsStr;
+use std::fs::FileType;
+use std::path::Path;
+
+/// What rendering needs from an entry, whichever walker produced it
+///
+/// `walkdir` drives listings and `ignore` drives searches; implementing this for
+/// both lets an entry line be formatted by one function.
+pub trait FsEntry {
+    fn path(&self) -> &Path;
+
+    fn file_name(&self) -> &OsStr;
+
+    /// Levels below the walk root, which is at 0
+    fn depth(&self) -> usize;
+
+    /// The entry's type without following symlinks; `None` when the walker couldn't tell (stdin)
+    fn file_type(&self) -> Option<FileType>;
+
+    /// Whether the path itself is a symlink
+    fn is_symlink(&self) -> bool;
+
+    fn is_dir(&self) -> bool {
+        self.file_type().is_some_and(|ft| ft.is_dir())
+    }
+}
+
+impl FsEntry for walkdir::DirEntry {
+    fn path(&self) -> &Path {
+        self.path()
+    }
+
+    fn file_name(&self) -> &OsStr {
+        self.file_name()
+    }
+
+    fn depth(&self) -> usize {
+        self.depth()
+    }
+
+    fn file_type(&self) -> Option<FileType> {
+        Some(self.file_type())
+    }
+
+    fn is_symlink(&self) -> bool {
+        self.path_is_symlink()
+    }
+}
+
+impl FsEntry for ignore::DirEntry {
+    fn path(&self) -> &Path {
+        self.path()
+    }
+
+    fn file_name(&self) -> &OsStr {
+        self.file_name()
+    }
+
+    fn depth(&self) -> usize {
+        self.depth()
+    }
+
+    fn file_type(&self) -> Option<FileType> {
+        self.file_type()
+    }
+
+    fn is_symlink(&self) -> bool {
+        self.path_is_symlink()
+    }
+}
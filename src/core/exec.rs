@@ -0,0 +1,197 @@
+use std::borrow::Cow;
+use std::ffi::OsString;
+use std::path::PathBuf;
+
+use crate::error::{LstError, Result};
+
+/// Placeholder replaced by the matched path(s)
+const PLACEHOLDER: &str = "{}";
+
+/// Paths passed to one command in batch mode, to stay well under the system's argument limit
+const BATCH_SIZE: usize = 512;
+
+/// A command run on search matches (`search --exec`), like `find -exec`
+///
+/// Ending the command with a lone `+` runs it once with every path in place of
+/// `{}` (in batches); otherwise it runs once per path, with `{}` substituted
+/// wherever it appears.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExecCommand {
+    args: Vec<String>,
+    batch: bool,
+}
+
+impl ExecCommand {
+    /// Parse a command line, honoring single and double quotes and backslash escapes
+    pub fn parse(command: &str) -> Result<Self> {
+        let mut args = split_command(command)?;
+        let batch = args.last().is_some_and(|a| a == "+");
+        if batch {
+            args.pop();
+        }
+        if args.is_empty() {
+            return Err(LstError::ExecError("the command is empty".to_string()));
+        }
+        if batch && !args.iter().any(|a| a == PLACEHOLDER) {
+            return Err(LstError::ExecError("a command ending in '+' needs a separate '{}' argument".to_string()));
+        }
+        Ok(Self { args, batch })
+    }
+
+    /// The argument lists to run for `paths`, program first
+    pub fn invocations(&self, paths: &[PathBuf]) -> Vec<Vec<OsString>> {
+        if self.batch {
+            return paths
+                .chunks(BATCH_SIZE)
+                .map(|chunk| {
+                    self.args
+                        .iter()
+                        .flat_map(|arg| match arg.as_str() {
+                            PLACEHOLDER => chunk.iter().map(|p| p.clone().into_os_string()).collect(),
+                            _ => vec![OsString::from(arg)],
+                        })
+                        .collect()
+                })
+                .collect();
+        }
+        paths
+            .iter()
+            .map(|path| {
+                let shown = path.to_string_lossy();
+                self.args
+                    .iter()
+                    .map(|arg| match arg.as_str() {
+                        // Whole-argument paths keep their exact bytes
+                        PLACEHOLDER => path.clone().into_os_string(),
+                        _ => OsString::from(arg.replace(PLACEHOLDER, &shown)),
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+}
+
+/// Split a command line into arguments the way a POSIX shell would, without expansions
+fn split_command(command: &str) -> Result<Vec<String>> {
+    let mut args = Vec::new();
+    let mut current: Option<String> = None;
+    let mut chars = command.chars();
+    while let Some(ch) = chars.next() {
+        match ch {
+            c if c.is_whitespace() => args.extend(current.take()),
+            '\'' => {
+                let arg = current.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => arg.push(c),
+                        None => return Err(LstError::ExecError("unterminated ' quote".to_string())),
+                    }
+                }
+            }
+            '"' => {
+                let arg = current.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => arg.extend(chars.next()),
+                        Some(c) => arg.push(c),
+                        None => return Err(LstError::ExecError("unterminated \" quote".to_string())),
+                    }
+                }
+            }
+            '\\' => current.get_or_insert_with(String::new).extend(chars.next()),
+            c => current.get_or_insert_with(String::new).push(c),
+        }
+    }
+    args.extend(current);
+    Ok(args)
+}
+
+/// An argument list as a shell would need it typed, for `--dry-run`
+pub fn shell_quote(args: &[OsString]) -> String {
+    args.iter()
+        .map(|arg| quote_arg(&arg.to_string_lossy()).into_owned())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// One argument as a POSIX shell would need it typed
+///
+/// Plain words are left alone and anything else is single-quoted. Control
+/// characters can't be typed inside single quotes, so those arguments use
+/// bash's `$'...'` form with C-style escapes instead.
+pub fn quote_arg(arg: &str) -> Cow<'_, str> {
+    if !arg.is_empty() && arg.chars().all(|c| c.is_ascii_alphanumeric() || "_-./:@%+=,".contains(c)) {
+        return Cow::Borrowed(arg);
+    }
+    if !arg.chars().any(char::is_control) {
+        return Cow::Owned(format!("'{}'", arg.replace('\'', "'\\''")));
+    }
+    let mut quoted = String::from("$'");
+    for ch in arg.chars() {
+        match ch {
+            '\\' => quoted.push_str("\\\\"),
+            '\'' => quoted.push_str("\\'"),
+            '\n' => quoted.push_str("\\n"),
+            '\t' => quoted.push_str("\\t"),
+            '\r' => quoted.push_str("\\r"),
+            '\x1b' => quoted.push_str("\\e"),
+            c if c.is_control() => {
+                for byte in c.encode_utf8(&mut [0; 4]).bytes() {
+                    quoted.push_str(&format!("\\x{:02x}", byte));
+                }
+            }
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('\'');
+    Cow::Owned(quoted)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(invocations: Vec<Vec<OsString>>) -> Vec<Vec<String>> {
+        invocations
+            .into_iter()
+            .map(|args| args.into_iter().map(|a| a.into_string().unwrap()).collect())
+            .collect()
+    }
+
+    #[test]
+    fn test_split_command_handles_quotes() {
+        assert_eq!(split_command("cp  {} '/tmp/my dir' \"a\\\"b\" c\\ d ''").unwrap(), ["cp", "{}", "/tmp/my dir", "a\"b", "c d", ""]);
+        assert!(split_command("echo 'open").is_err());
+    }
+
+    #[test]
+    fn test_per_entry_and_batch_invocations() {
+        let paths = [PathBuf::from("a.rs"), PathBuf::from("b c.rs")];
+        let each = ExecCommand::parse("cp {} {}.bak").unwrap();
+        assert_eq!(strings(each.invocations(&paths)), [["cp", "a.rs", "a.rs.bak"], ["cp", "b c.rs", "b c.rs.bak"]]);
+
+        let batch = ExecCommand::parse("wc -l {} +").unwrap();
+        assert_eq!(strings(batch.invocations(&paths)), [["wc", "-l", "a.rs", "b c.rs"]]);
+        let many: Vec<PathBuf> = (0..BATCH_SIZE + 1).map(|i| PathBuf::from(i.to_string())).collect();
+        assert_eq!(batch.invocations(&many).len(), 2);
+
+        assert!(ExecCommand::parse("  ").is_err());
+        assert!(ExecCommand::parse("echo {}.bak +").is_err());
+    }
+
+    #[test]
+    fn test_shell_quote() {
+        let args: Vec<OsString> = ["rm", "-f", "my file", "it's", ""].iter().map(OsString::from).collect();
+        assert_eq!(shell_quote(&args), "rm -f 'my file' 'it'\\''s' ''");
+    }
+
+    #[test]
+    fn test_quote_arg_handles_metacharacters_and_control_characters() {
+        assert_eq!(quote_arg("src/main.rs"), "src/main.rs");
+        assert_eq!(quote_arg("$HOME & more"), "'$HOME & more'");
+        assert_eq!(quote_arg("say \"hi\"; rm *"), "'say \"hi\"; rm *'");
+        assert_eq!(quote_arg("two\nlines, it's\u{9b}"), "$'two\\nlines, it\\'s\\xc2\\x9b'");
+    }
+}
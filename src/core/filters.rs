@@ -1,22 +1,640 @@
 use walkdir::DirEntry;
-use std::ffi::OsStr;
+use std::collections::HashMap;
+use std::ffi::{OsStr, OsString};
+use std::fs::Metadata;
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, OnceLock};
+
+use dashmap::{DashMap, DashSet};
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+
+use super::git::GitStatus;
+use super::sort::SortOptions;
+use super::stats::ExtensionStats;
+use super::tree::{walk_visible, UNLIMITED_DEPTH};
+use crate::error::{LstError, Result};
+
+/// How name patterns treat letter case (`--case`), shared by search and exclude globs
+///
+/// The default is insensitive, which is how search has always matched names.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum CaseMode {
+    /// Letters must match exactly
+    Sensitive,
+    /// Letters match regardless of case
+    #[default]
+    Insensitive,
+    /// Insensitive unless the pattern has an uppercase letter
+    Smart,
+}
+
+impl CaseMode {
+    /// Whether `pattern` is matched ignoring case
+    pub fn ignores_case(self, pattern: &str) -> bool {
+        match self {
+            CaseMode::Sensitive => false,
+            CaseMode::Insensitive => true,
+            CaseMode::Smart => !pattern.chars().any(char::is_uppercase),
+        }
+    }
+}
 
 /// Check if a directory entry is hidden (starts with '.' but not '.' or '..')
 pub fn is_hidden(entry: &DirEntry) -> bool {
-    entry
-        .file_name()
-        .to_str()
-        .map(|s| s.starts_with('.') && s != "." && s != "..")
-        .unwrap_or(false)
+    is_hidden_name(entry.file_name())
+}
+
+/// Whether `name` is a hidden entry's (see [`is_hidden`])
+pub fn is_hidden_name(name: &OsStr) -> bool {
+    name.to_str().is_some_and(|s| s.starts_with('.') && s != "." && s != "..")
+}
+
+/// Whether any component of `path` below `root` is hidden (e.g. `.config/app.toml`)
+pub fn has_hidden_component(path: &Path, root: &Path) -> bool {
+    path.strip_prefix(root)
+        .unwrap_or(path)
+        .components()
+        .any(|c| c.as_os_str().to_str().is_some_and(|s| s.starts_with('.') && s != "." && s != ".."))
 }
 
-/// Filter predicate for walkdir that respects the show_hidden flag
-pub fn should_show_entry(entry: &DirEntry, show_hidden: bool) -> bool {
-    // Always skip common heavy directories
-    if is_common_skip_os(entry.file_name()) {
+/// Whether `path` is a directory with nothing in it; hidden entries only count when `show` lists them (`--all`)
+pub fn is_empty_dir(path: &Path, show: &ShowOptions) -> bool {
+    std::fs::read_dir(path).is_ok_and(|mut children| {
+        children.all(|child| child.is_ok_and(|child| !show.walks_hidden() && is_hidden_name(&child.file_name())))
+    })
+}
+
+/// Whether a file is executable: any `x` permission bit on Unix, a program or script extension elsewhere
+pub fn is_executable(meta: &Metadata, path: &Path) -> bool {
+    if !meta.is_file() {
         return false;
     }
-    show_hidden || !is_hidden(entry)
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let _ = path;
+        meta.permissions().mode() & 0o111 != 0
+    }
+    #[cfg(not(unix))]
+    {
+        has_executable_extension(path)
+    }
+}
+
+/// Windows treats these extensions as runnable
+pub fn has_executable_extension(path: &Path) -> bool {
+    path.extension()
+        .and_then(OsStr::to_str)
+        .is_some_and(|ext| ["exe", "bat", "cmd", "ps1", "com"].iter().any(|e| ext.eq_ignore_ascii_case(e)))
+}
+
+/// Glob patterns for entries to leave out (`--exclude` / `--exclude-from`)
+///
+/// A pattern containing `/` is matched against the path relative to the walk
+/// root; any other pattern is matched against each entry's name at any depth.
+/// Excluding a directory excludes everything below it.
+#[derive(Debug, Clone, Default)]
+pub struct GlobFilter {
+    patterns: Vec<String>,
+    case: CaseMode,
+    set: Option<Arc<GlobSet>>,
+}
+
+impl GlobFilter {
+    /// Compile the patterns, each matching case as `case` decides; an empty list excludes nothing
+    pub fn new(patterns: Vec<String>, case: CaseMode) -> Result<Self> {
+        if patterns.is_empty() {
+            return Ok(Self { case, ..Self::default() });
+        }
+        let mut builder = GlobSetBuilder::new();
+        for pattern in &patterns {
+            let trimmed = pattern.trim_start_matches('/').trim_end_matches('/');
+            let anchored = if pattern.trim_end_matches('/').contains('/') {
+                trimmed.to_string()
+            } else {
+                format!("**/{}", trimmed)
+            };
+            let glob = GlobBuilder::new(&anchored)
+                .literal_separator(true)
+                .case_insensitive(case.ignores_case(pattern))
+                .build()
+                .map_err(|e| LstError::InvalidPattern(e.to_string()))?;
+            builder.add(glob);
+        }
+        let set = builder.build().map_err(|e| LstError::InvalidPattern(e.to_string()))?;
+        Ok(Self {
+            patterns,
+            case,
+            set: Some(Arc::new(set)),
+        })
+    }
+
+    /// Whether there are no patterns, so nothing matches
+    pub fn is_empty(&self) -> bool {
+        self.set.is_none()
+    }
+
+    /// Whether the entry at `path`, `depth` levels below the walk root, is excluded
+    pub fn is_excluded(&self, path: &Path, depth: usize) -> bool {
+        self.is_match(path, depth)
+    }
+
+    /// Whether any pattern matches the entry at `path`, `depth` levels below the walk root
+    pub fn is_match(&self, path: &Path, depth: usize) -> bool {
+        match &self.set {
+            Some(set) if depth > 0 => set.is_match(relative_to_walk_root(path, depth)),
+            _ => false,
+        }
+    }
+}
+
+impl PartialEq for GlobFilter {
+    fn eq(&self, other: &Self) -> bool {
+        self.patterns == other.patterns && self.case == other.case
+    }
+}
+
+impl Eq for GlobFilter {}
+
+/// Read exclude patterns from a file: one per line, skipping blank lines and `#` comments
+pub fn parse_pattern_lines(text: &str) -> Vec<String> {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::to_string)
+        .collect()
+}
+
+/// The last `depth` components of `path`, i.e. its path below the walk root
+fn relative_to_walk_root(path: &Path, depth: usize) -> PathBuf {
+    let components: Vec<Component> = path.components().collect();
+    components[components.len().saturating_sub(depth)..].iter().collect()
+}
+
+/// Which entries a walk lists, and in what order
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct ShowOptions {
+    /// Include hidden entries (`--all`)
+    pub hidden: bool,
+    /// With `hidden`, keep the root's heavy dot-directories skipped instead of listing them collapsed (`--almost-all`)
+    pub almost_all: bool,
+    /// List only hidden entries, their contents and their ancestors (`--hidden-only`)
+    pub hidden_only: bool,
+    /// Don't skip common heavy directories (`--no-skip`)
+    pub no_skip: bool,
+    /// List directories only (`--skeleton`)
+    pub dirs_only: bool,
+    /// Entries left out regardless of the other options
+    pub exclude: GlobFilter,
+    /// Order of siblings (`--sort`, `--sort-dirs`, `--sort-files`)
+    pub sort: SortOptions,
+    /// List mount points but don't descend into other filesystems (`--one-file-system`)
+    pub one_file_system: bool,
+    /// List common heavy directories without their contents instead of leaving them out (`--show-skipped`)
+    pub show_skipped: bool,
+    /// Directory names listed without their contents (`--stop-at`)
+    pub stop_at: Vec<OsString>,
+    /// Directories holding one of these entries are listed without their contents (`--stop-at-marker`)
+    pub stop_markers: Vec<OsString>,
+    /// Project types whose tuned skip lists replace the common one (`--smart`); empty keeps the common list
+    pub projects: Vec<ProjectKind>,
+    /// Directories with more files than this below them are listed without their contents (`--collapse-over`)
+    pub collapse_over: Option<CollapseOver>,
+    /// Count `--depth` from the nearest directory holding one of these entries (`--depth-from-marker`)
+    pub depth_from_marker: Option<DepthFromMarker>,
+    /// List only empty directories, and their ancestors (`--empty-dirs`)
+    pub empty_dirs: bool,
+    /// List only entries with uncommitted changes, and their ancestors (`--git-modified`)
+    pub git_status: Option<Arc<GitStatus>>,
+    /// Entry listed with its ancestors whatever the other options say, such as the file `--context` locates
+    pub revealed: Option<PathBuf>,
+}
+
+impl ShowOptions {
+    /// Whether the walk has to descend into hidden entries
+    pub fn walks_hidden(&self) -> bool {
+        self.hidden || self.hidden_only
+    }
+
+    /// Whether only some of the walked entries are listed, with their ancestors
+    /// (`--hidden-only`, `--empty-dirs`, `--git-modified`, `--depth-from-marker`)
+    pub fn narrows(&self) -> bool {
+        self.hidden_only || self.empty_dirs || self.git_status.is_some() || self.depth_from_marker.is_some()
+    }
+
+    /// Whether `name` is a heavy directory the walk skips: one of the detected
+    /// projects' generated directories, or with none detected one on the common list
+    pub fn skips(&self, name: &OsStr) -> bool {
+        if self.no_skip {
+            return false;
+        }
+        if self.projects.is_empty() {
+            return is_common_skip_os(name);
+        }
+        name.to_str().is_some_and(|name| {
+            VCS_DIRS.contains(&name) || self.projects.iter().any(|kind| kind.skipped_dirs().contains(&name))
+        })
+    }
+
+    /// The rule of a narrowing option (see [`Self::narrows`]) that leaves out the walked entry at `path`
+    /// below `root`, unless a listed entry below it keeps it as an ancestor
+    pub fn narrowing_rule(&self, root: &Path, path: &Path) -> Option<Rule> {
+        let depth = || path.strip_prefix(root).map_or(0, |relative| relative.components().count());
+        if self.hidden_only && !has_hidden_component(path, root) {
+            Some(Rule::NotHidden)
+        } else if self.empty_dirs && !is_empty_dir(path, self) {
+            Some(Rule::NotEmpty)
+        } else if self.git_status.as_ref().is_some_and(|git| git.change(path).is_none()) {
+            Some(Rule::NotModified)
+        } else if self.depth_from_marker.as_ref().is_some_and(|limit| !limit.lists(path, depth())) {
+            Some(Rule::Depth)
+        } else {
+            None
+        }
+    }
+
+    /// Whether the walk lists the directory at `path` but stops there (`--stop-at`, `--stop-at-marker`)
+    pub fn stops_at(&self, path: &Path) -> bool {
+        self.stop_rule(path).is_some()
+    }
+
+    /// Which of `--stop-at` and `--stop-at-marker` stops the walk at the directory at `path`
+    fn stop_rule(&self, path: &Path) -> Option<Rule> {
+        if path.file_name().is_some_and(|name| self.stop_at.iter().any(|stop| stop == name)) {
+            Some(Rule::StopAt)
+        } else if self.stop_markers.iter().any(|marker| path.join(marker).exists()) {
+            Some(Rule::StopMarker)
+        } else {
+            None
+        }
+    }
+
+    /// Files and total size below a directory that `--collapse-over` lists without its contents
+    pub fn collapsed(&self, entry: &DirEntry) -> Option<ExtensionStats> {
+        if !entry.file_type().is_dir() {
+            return None;
+        }
+        self.collapse_over.as_ref()?.totals(entry.path(), self)
+    }
+}
+
+/// The `--collapse-over` limit, with the directories counted against it so far
+///
+/// Clones share the counts, so however often a walk asks about a directory
+/// its contents are only walked once.
+#[derive(Debug, Clone)]
+pub struct CollapseOver {
+    limit: usize,
+    counted: Arc<DashMap<PathBuf, Option<ExtensionStats>>>,
+    /// Files below each directory, from the walk of an enclosing one
+    files: Arc<DashMap<PathBuf, usize>>,
+}
+
+impl CollapseOver {
+    pub fn new(limit: usize) -> Self {
+        Self { limit, counted: Arc::default(), files: Arc::default() }
+    }
+
+    /// Files and total size below `dir` if there are more than the limit, counting what `show` would list
+    ///
+    /// The first directory asked about is walked once, counting the files below every
+    /// directory inside it too, so a walk descending further finds their counts ready.
+    /// Only collapsed directories are walked again, to stat their files for the size.
+    fn totals(&self, dir: &Path, show: &ShowOptions) -> Option<ExtensionStats> {
+        if let Some(known) = self.counted.get(dir) {
+            return *known;
+        }
+        let rules = ShowOptions { collapse_over: None, sort: SortOptions::default(), ..show.clone() };
+        let files = match self.files.get(dir) {
+            Some(files) => *files,
+            None => self.count_files(dir, &rules),
+        };
+        let totals = (files > self.limit).then(|| {
+            walk_visible(dir, UNLIMITED_DEPTH, &rules)
+                .flatten()
+                .filter(|e| !e.file_type().is_dir())
+                .fold(ExtensionStats::default(), |acc, e| ExtensionStats {
+                    files: acc.files + 1,
+                    size: acc.size + e.metadata().map_or(0, |m| m.len()),
+                })
+        });
+        self.counted.insert(dir.to_path_buf(), totals);
+        totals
+    }
+
+    /// Walk `dir` once, recording the files below it and below each directory inside it
+    fn count_files(&self, dir: &Path, rules: &ShowOptions) -> usize {
+        let mut counts: HashMap<PathBuf, usize> = HashMap::from([(dir.to_path_buf(), 0)]);
+        for entry in walk_visible(dir, UNLIMITED_DEPTH, rules).flatten() {
+            if entry.file_type().is_dir() {
+                counts.insert(entry.into_path(), 0);
+                continue;
+            }
+            // Directories come before their contents, so every ancestor is already counted
+            for ancestor in entry.path().ancestors().skip(1) {
+                match counts.get_mut(ancestor) {
+                    Some(count) => *count += 1,
+                    None => break,
+                }
+                if ancestor == dir {
+                    break;
+                }
+            }
+        }
+        let files = counts[dir];
+        for (path, count) in counts {
+            self.files.insert(path, count);
+        }
+        files
+    }
+}
+
+impl PartialEq for CollapseOver {
+    fn eq(&self, other: &Self) -> bool {
+        self.limit == other.limit
+    }
+}
+
+impl Eq for CollapseOver {}
+
+/// A `--depth` limit counted from the nearest project root, a directory holding one
+/// of the markers, instead of from the walk root (`--depth-from-marker`)
+///
+/// Clones share which directories were found to be project roots.
+#[derive(Debug, Clone)]
+pub struct DepthFromMarker {
+    markers: Vec<OsString>,
+    limit: usize,
+    projects: Arc<DashMap<PathBuf, bool>>,
+}
+
+impl DepthFromMarker {
+    pub fn new(markers: Vec<OsString>, limit: usize) -> Self {
+        Self { markers, limit, projects: Arc::default() }
+    }
+
+    /// The rule leaving out the entry at `path`, `depth` levels below the walk root, when it's
+    /// more than the limit below its nearest project root, or below the walk root outside any project
+    ///
+    /// Directories outside every project are walked at any depth, so the walk still reaches
+    /// projects that sit deeper than the limit; [`Self::lists`] decides whether they show.
+    fn too_deep(&self, path: &Path, depth: usize, is_dir: bool) -> Option<Rule> {
+        match self.below_project(path, depth) {
+            Some(levels) => (levels > self.limit).then_some(Rule::ProjectDepth),
+            None => (!is_dir && depth > self.limit).then_some(Rule::Depth),
+        }
+    }
+
+    /// Whether a walked entry is listed for itself, rather than only as the way to a deeper project:
+    /// it's in a project, within the limit of the walk root, or a project root
+    pub fn lists(&self, path: &Path, depth: usize) -> bool {
+        depth <= self.limit || self.below_project(path, depth).is_some() || self.is_project(path)
+    }
+
+    /// Levels between `path`, `depth` levels below the walk root, and its nearest project root
+    fn below_project(&self, path: &Path, depth: usize) -> Option<usize> {
+        let up = path.ancestors().skip(1).take(depth.saturating_sub(1)).position(|dir| self.is_project(dir))?;
+        Some(up + 1)
+    }
+
+    fn is_project(&self, dir: &Path) -> bool {
+        if let Some(known) = self.projects.get(dir) {
+            return *known;
+        }
+        let found = self.markers.iter().any(|marker| dir.join(marker).exists());
+        self.projects.insert(dir.to_path_buf(), found);
+        found
+    }
+}
+
+impl PartialEq for DepthFromMarker {
+    fn eq(&self, other: &Self) -> bool {
+        self.markers == other.markers && self.limit == other.limit
+    }
+}
+
+impl Eq for DepthFromMarker {}
+
+/// How a walk treats an entry
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Visibility {
+    /// Left out, along with everything below it
+    Hidden,
+    /// Listed and, for directories, descended into
+    Shown,
+    /// Listed but never descended into
+    Collapsed,
+}
+
+/// The rule that decided how a walk treats an entry, as `lst explain` reports it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rule {
+    /// Deeper than the `--depth` limit
+    Depth,
+    /// Deeper than `--depth` below its nearest `--depth-from-marker` project
+    ProjectDepth,
+    /// Matches an `--exclude` or `--exclude-from` pattern
+    Excluded,
+    /// A file while `--skeleton` lists directories only
+    NotADirectory,
+    /// A skipped dot-directory at the top level, which `--all` lists without its contents
+    TopLevelDotDirectory,
+    /// A skipped directory listed without its contents (`--show-skipped`)
+    ShownSkipped,
+    /// On the common list of heavy directories
+    SkipList,
+    /// Generated by a project type `--smart` detected
+    SmartPreset,
+    /// A dot entry, without `--all`
+    Hidden,
+    /// Not hidden, while `--hidden-only` lists only hidden entries and their ancestors
+    NotHidden,
+    /// No uncommitted changes, while `--git-modified` lists only changed entries and their ancestors
+    NotModified,
+    /// Not an empty directory, while `--empty-dirs` lists only those and their ancestors
+    NotEmpty,
+    /// Named by `--stop-at`
+    StopAt,
+    /// Holds a `--stop-at-marker` entry
+    StopMarker,
+    /// More files below it than `--collapse-over` allows
+    CollapseOver,
+    /// Nothing leaves it out
+    Listed,
+}
+
+impl Rule {
+    /// Stable snake_case name, for JSON
+    pub fn name(self) -> &'static str {
+        match self {
+            Rule::Depth => "depth",
+            Rule::ProjectDepth => "project_depth",
+            Rule::Excluded => "excluded",
+            Rule::NotADirectory => "not_a_directory",
+            Rule::TopLevelDotDirectory => "top_level_dot_directory",
+            Rule::ShownSkipped => "shown_skipped",
+            Rule::SkipList => "skip_list",
+            Rule::SmartPreset => "smart_preset",
+            Rule::Hidden => "hidden",
+            Rule::NotHidden => "not_hidden",
+            Rule::NotModified => "not_modified",
+            Rule::NotEmpty => "not_empty",
+            Rule::StopAt => "stop_at",
+            Rule::StopMarker => "stop_marker",
+            Rule::CollapseOver => "collapse_over",
+            Rule::Listed => "listed",
+        }
+    }
+
+    /// What the rule found about the entry
+    pub fn reason(self) -> &'static str {
+        match self {
+            Rule::Depth => "it is deeper than --depth",
+            Rule::ProjectDepth => "it is deeper than --depth below the nearest directory holding a --depth-from-marker entry",
+            Rule::Excluded => "it matches an --exclude or --exclude-from pattern",
+            Rule::NotADirectory => "--skeleton lists directories only",
+            Rule::TopLevelDotDirectory => "it is a heavy dot-directory in the root, which --all lists without its contents",
+            Rule::ShownSkipped => "it is a common heavy directory, which --show-skipped lists without its contents",
+            Rule::SkipList => "it is on the list of common heavy directories",
+            Rule::SmartPreset => "it is generated by the project type --smart detected",
+            Rule::Hidden => "its name starts with a dot",
+            Rule::NotHidden => "--hidden-only lists only hidden entries and the directories leading to them",
+            Rule::NotModified => "--git-modified lists only entries with uncommitted changes and the directories leading to them",
+            Rule::NotEmpty => "--empty-dirs lists only empty directories and the directories leading to them",
+            Rule::StopAt => "its name is given to --stop-at",
+            Rule::StopMarker => "it holds a --stop-at-marker entry",
+            Rule::CollapseOver => "it has more files below it than --collapse-over allows",
+            Rule::Listed => "no rule leaves it out",
+        }
+    }
+
+    /// The flag that would change the outcome, if any
+    pub fn remedy(self) -> Option<&'static str> {
+        match self {
+            Rule::Depth | Rule::ProjectDepth => Some("raise --depth, or pass -d 0 for no limit"),
+            Rule::Excluded => Some("drop the matching pattern"),
+            Rule::NotADirectory => Some("drop --skeleton"),
+            Rule::TopLevelDotDirectory | Rule::ShownSkipped => Some("--no-skip lists its contents"),
+            Rule::SkipList => Some("--no-skip lists it, --show-skipped lists it without its contents"),
+            Rule::SmartPreset => Some("--no-skip lists it, or drop --smart"),
+            Rule::Hidden => Some("-a/--all lists hidden entries"),
+            Rule::NotHidden => Some("drop --hidden-only"),
+            Rule::NotModified => Some("drop --git-modified"),
+            Rule::NotEmpty => Some("drop --empty-dirs"),
+            Rule::StopAt => Some("drop the name from --stop-at"),
+            Rule::StopMarker => Some("drop the --stop-at-marker"),
+            Rule::CollapseOver => Some("raise --collapse-over, or run lst on the directory itself"),
+            Rule::Listed => None,
+        }
+    }
+}
+
+/// Decide whether an entry is listed, respecting the show options
+///
+/// See [`visibility_rule`] for the rules, which this applies to a walked entry.
+pub fn entry_visibility(entry: &DirEntry, show: &ShowOptions) -> Visibility {
+    let (visibility, rule) = visibility_rule(entry.path(), entry.depth(), entry.file_type().is_dir(), show);
+    log_rule(entry.path(), rule);
+    visibility
+}
+
+/// Entries whose rule was logged already
+static LOGGED: OnceLock<DashSet<PathBuf>> = OnceLock::new();
+
+/// Log under `-v` why a walk leaves out or stops at `path`, once however many walks reach it
+pub fn log_rule(path: &Path, rule: Rule) {
+    let level = match rule {
+        Rule::SkipList | Rule::SmartPreset => log::Level::Info,
+        Rule::Excluded | Rule::StopAt | Rule::StopMarker => log::Level::Debug,
+        _ => return,
+    };
+    if !log::log_enabled!(level) || !LOGGED.get_or_init(DashSet::new).insert(path.to_path_buf()) {
+        return;
+    }
+    match rule {
+        Rule::Excluded => log::debug!("excluded {}", path.display()),
+        Rule::StopAt | Rule::StopMarker => log::debug!("stopped at {}", path.display()),
+        _ => log::info!("skipped {} (heavy directory; --no-skip lists it)", path.display()),
+    }
+}
+
+/// How a walk treats the entry at `path`, `depth` levels below the root, and the rule that decided it
+///
+/// Common heavy directories are skipped unless `no_skip` is set, except that
+/// with `--all` the root's own dot-directories (`.git`, `.vscode`, `.cargo`, ...)
+/// are listed collapsed, so `-a` reliably shows every dot entry at the top level.
+/// With `show_skipped` every otherwise visible one is listed collapsed.
+pub fn visibility_rule(path: &Path, depth: usize, is_dir: bool, show: &ShowOptions) -> (Visibility, Rule) {
+    if show.revealed.as_deref().is_some_and(|revealed| revealed.starts_with(path)) {
+        return (Visibility::Shown, Rule::Listed);
+    }
+    if let Some(rule) = show.depth_from_marker.as_ref().and_then(|limit| limit.too_deep(path, depth, is_dir)) {
+        return (Visibility::Hidden, rule);
+    }
+    if show.exclude.is_excluded(path, depth) {
+        return (Visibility::Hidden, Rule::Excluded);
+    }
+    if show.dirs_only && !is_dir {
+        return (Visibility::Hidden, Rule::NotADirectory);
+    }
+    let name = path.file_name().unwrap_or(path.as_os_str());
+    let hidden = is_hidden_name(name);
+    if show.skips(name) {
+        if show.hidden && !show.almost_all && hidden && depth == 1 {
+            return (Visibility::Collapsed, Rule::TopLevelDotDirectory);
+        }
+        if show.show_skipped && (show.walks_hidden() || !hidden) {
+            return (Visibility::Collapsed, Rule::ShownSkipped);
+        }
+        let rule = if show.projects.is_empty() { Rule::SkipList } else { Rule::SmartPreset };
+        return (Visibility::Hidden, rule);
+    }
+    if !show.walks_hidden() && hidden {
+        return (Visibility::Hidden, Rule::Hidden);
+    }
+    if let Some(rule) = is_dir.then(|| show.stop_rule(path)).flatten() {
+        return (Visibility::Collapsed, rule);
+    }
+    if is_dir && show.collapse_over.as_ref().is_some_and(|over| over.totals(path, show).is_some()) {
+        return (Visibility::Collapsed, Rule::CollapseOver);
+    }
+    (Visibility::Shown, Rule::Listed)
+}
+
+/// Whether an entry is a skipped directory that `--show-skipped` lists without its contents
+pub fn is_shown_skipped(name: &OsStr, is_dir: bool, show: &ShowOptions) -> bool {
+    show.show_skipped && is_dir && show.skips(name)
+}
+
+/// Whether an entry is a directory that `--stop-at` or `--stop-at-marker` lists without its contents
+pub fn is_stopped(path: &Path, depth: usize, is_dir: bool, show: &ShowOptions) -> bool {
+    let stopping = !show.stop_at.is_empty() || !show.stop_markers.is_empty();
+    stopping && is_dir && depth > 0 && matches!(visibility_rule(path, depth, is_dir, show).1, Rule::StopAt | Rule::StopMarker)
+}
+
+/// Whether an entry is a mount point that `--one-file-system` lists without descending into,
+/// i.e. a directory on another device than its parent
+pub fn is_mount_point(path: &Path, is_dir: bool, show: &ShowOptions) -> bool {
+    if !show.one_file_system || !is_dir {
+        return false;
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        let device = |p: &Path| std::fs::symlink_metadata(p).map(|m| m.dev()).ok();
+        let parent = path.parent().and_then(device);
+        parent.is_some() && device(path).is_some_and(|dev| Some(dev) != parent)
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = path;
+        false
+    }
+}
+
+/// Filter predicate for walkdir that respects the show options
+pub fn should_show_entry(entry: &DirEntry, show: &ShowOptions) -> bool {
+    entry_visibility(entry, show) != Visibility::Hidden
 }
 
 pub fn is_common_skip_name(name: &str) -> bool {
@@ -57,10 +675,310 @@ pub fn is_common_skip_name(name: &str) -> bool {
     )
 }
 
-
-
-
 /// Same check for OsStr names
 pub fn is_common_skip_os(name: &OsStr) -> bool {
     name.to_str().map(is_common_skip_name).unwrap_or(false)
 }
+
+/// Version control directories, skipped under every project preset
+const VCS_DIRS: [&str; 3] = [".git", ".hg", ".svn"];
+
+/// A kind of project recognized by a marker file in the root (`--smart`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProjectKind {
+    Rust,
+    Node,
+    Go,
+    Python,
+}
+
+impl ProjectKind {
+    const MARKERS: [(&'static str, ProjectKind); 4] = [
+        ("Cargo.toml", ProjectKind::Rust),
+        ("package.json", ProjectKind::Node),
+        ("go.mod", ProjectKind::Go),
+        ("pyproject.toml", ProjectKind::Python),
+    ];
+
+    /// Every kind of project with its marker file directly in `root`; a polyglot repo can be several
+    pub fn detect(root: &Path) -> Vec<ProjectKind> {
+        Self::MARKERS
+            .iter()
+            .filter(|(marker, _)| root.join(marker).is_file())
+            .map(|&(_, kind)| kind)
+            .collect()
+    }
+
+    /// Directories this kind of project generates, which its preset skips
+    fn skipped_dirs(self) -> &'static [&'static str] {
+        match self {
+            ProjectKind::Rust => &["target"],
+            ProjectKind::Node => &["node_modules", "dist", "build", "coverage", ".next", ".nuxt", ".vercel", ".cache"],
+            ProjectKind::Go => &["bin"],
+            ProjectKind::Python => &["__pycache__", ".venv", "venv", ".tox", ".pytest_cache", ".mypy_cache", ".ruff_cache", "build", "dist"],
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ProjectKind::Rust => "Rust",
+            ProjectKind::Node => "Node",
+            ProjectKind::Go => "Go",
+            ProjectKind::Python => "Python",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use walkdir::WalkDir;
+
+    fn visibilities(root: &Path, show: &ShowOptions) -> Vec<(String, Visibility)> {
+        let mut out: Vec<(String, Visibility)> = WalkDir::new(root)
+            .min_depth(1)
+            .into_iter()
+            .filter_map(|e| e.ok())
+            .map(|e| {
+                let rel = e.path().strip_prefix(root).unwrap().to_string_lossy().replace('\\', "/");
+                (rel, entry_visibility(&e, show))
+            })
+            .collect();
+        out.sort_by(|a, b| a.0.cmp(&b.0));
+        out
+    }
+
+    #[test]
+    fn test_all_and_skip_list_interaction() {
+        let root = std::env::temp_dir().join(format!("lst-visibility-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(root.join(".git")).unwrap();
+        std::fs::create_dir_all(root.join("src/.cache")).unwrap();
+        std::fs::create_dir_all(root.join("target")).unwrap();
+        std::fs::write(root.join(".env"), "").unwrap();
+
+        use Visibility::*;
+        assert_eq!(
+            visibilities(&root, &ShowOptions::default()),
+            [
+                (".env".to_string(), Hidden),
+                (".git".to_string(), Hidden),
+                ("src".to_string(), Shown),
+                ("src/.cache".to_string(), Hidden),
+                ("target".to_string(), Hidden),
+            ]
+        );
+        // Root dot-directories on the skip list are listed but not expanded;
+        // nested ones and non-dot heavy directories stay skipped
+        assert_eq!(
+            visibilities(&root, &ShowOptions { hidden: true, ..ShowOptions::default() }),
+            [
+                (".env".to_string(), Shown),
+                (".git".to_string(), Collapsed),
+                ("src".to_string(), Shown),
+                ("src/.cache".to_string(), Hidden),
+                ("target".to_string(), Hidden),
+            ]
+        );
+        // --almost-all shows the same hidden entries but skips those directories
+        let almost_all = ShowOptions { hidden: true, almost_all: true, ..ShowOptions::default() };
+        assert_eq!(visibilities(&root, &almost_all)[0], (".env".to_string(), Shown));
+        assert_eq!(visibilities(&root, &almost_all)[1], (".git".to_string(), Hidden));
+
+        // --hidden-only keeps the skip list in full; --no-skip lifts it
+        let hidden_only = ShowOptions { hidden_only: true, ..ShowOptions::default() };
+        assert_eq!(visibilities(&root, &hidden_only)[1], (".git".to_string(), Hidden));
+        let no_skip = ShowOptions { no_skip: true, ..hidden_only.clone() };
+        assert_eq!(visibilities(&root, &no_skip)[1], (".git".to_string(), Shown));
+        assert_eq!(visibilities(&root, &no_skip)[4], ("target".to_string(), Shown));
+
+        // --show-skipped lists visible heavy directories collapsed instead of leaving them out
+        let show_skipped = ShowOptions { show_skipped: true, ..ShowOptions::default() };
+        assert_eq!(visibilities(&root, &show_skipped)[1], (".git".to_string(), Hidden));
+        assert_eq!(visibilities(&root, &show_skipped)[4], ("target".to_string(), Collapsed));
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_project_presets_replace_the_common_skip_list() {
+        let root = std::env::temp_dir().join(format!("lst-smart-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(&root).unwrap();
+        assert!(ProjectKind::detect(&root).is_empty());
+        std::fs::write(root.join("Cargo.toml"), "").unwrap();
+        std::fs::write(root.join("pyproject.toml"), "").unwrap();
+        std::fs::create_dir_all(root.join("package.json")).unwrap();
+        let projects = ProjectKind::detect(&root);
+        assert_eq!(projects, [ProjectKind::Rust, ProjectKind::Python], "markers must be files");
+
+        let smart = ShowOptions { projects, ..ShowOptions::default() };
+        for skipped in ["target", "__pycache__", ".venv", ".git"] {
+            assert!(smart.skips(OsStr::new(skipped)), "{}", skipped);
+        }
+        // Common-list names that are ordinary source folders in these projects
+        for listed in ["examples", "bin", "packages", "node_modules"] {
+            assert!(!smart.skips(OsStr::new(listed)), "{}", listed);
+            assert_eq!(ShowOptions::default().skips(OsStr::new(listed)), is_common_skip_name(listed));
+        }
+        assert!(!ShowOptions { no_skip: true, ..smart }.skips(OsStr::new("target")));
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_stop_at_names_and_markers_collapse_directories() {
+        let root = std::env::temp_dir().join(format!("lst-stop-at-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(root.join("app/src/deep")).unwrap();
+        std::fs::create_dir_all(root.join("lib/.git")).unwrap();
+        std::fs::write(root.join("lib/lib.rs"), "").unwrap();
+        std::fs::write(root.join("src"), "").unwrap();
+
+        let show = ShowOptions {
+            hidden: true,
+            no_skip: true,
+            stop_at: vec!["src".into()],
+            stop_markers: vec![".git".into()],
+            ..ShowOptions::default()
+        };
+        use Visibility::*;
+        let seen = visibilities(&root, &show);
+        assert!(seen.contains(&("app".to_string(), Shown)));
+        assert!(seen.contains(&("app/src".to_string(), Collapsed)));
+        assert!(seen.contains(&("lib".to_string(), Collapsed)));
+        // A file with the name is listed as usual
+        assert!(seen.contains(&("src".to_string(), Shown)));
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_collapse_over_lists_only_the_outermost_large_directories() {
+        let root = std::env::temp_dir().join(format!("lst-collapse-over-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(root.join("big/inner")).unwrap();
+        std::fs::create_dir_all(root.join("small")).unwrap();
+        for name in ["a", "b", "inner/c"] {
+            std::fs::write(root.join("big").join(name), "12345").unwrap();
+        }
+        std::fs::write(root.join("small/d"), "").unwrap();
+
+        let show = ShowOptions { no_skip: true, collapse_over: Some(CollapseOver::new(2)), ..ShowOptions::default() };
+        use Visibility::*;
+        let seen = visibilities(&root, &show);
+        assert!(seen.contains(&("big".to_string(), Collapsed)));
+        assert!(seen.contains(&("big/inner".to_string(), Shown)));
+        assert!(seen.contains(&("small".to_string(), Shown)));
+        let big = walk_visible(&root, 1, &show).flatten().find(|e| e.file_name() == "big").unwrap();
+        assert_eq!(show.collapsed(&big), Some(ExtensionStats { files: 3, size: 15 }));
+        // Walking `big` counted `big/inner` too
+        let counted = |dir: &str| show.collapse_over.as_ref().unwrap().files.get(&root.join(dir)).map(|n| *n);
+        assert_eq!(counted("big/inner"), Some(1));
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_depth_from_marker_counts_from_each_project() {
+        let root = std::env::temp_dir().join(format!("lst-depth-from-marker-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(root.join("crates/app/src/cli")).unwrap();
+        std::fs::create_dir_all(root.join("docs/guide/part")).unwrap();
+        std::fs::write(root.join("docs/guide/part/intro.md"), "").unwrap();
+        std::fs::write(root.join("crates/app/Cargo.toml"), "").unwrap();
+
+        let markers = vec![OsString::from("Cargo.toml")];
+        let show = ShowOptions { depth_from_marker: Some(DepthFromMarker::new(markers, 2)), ..ShowOptions::default() };
+        use Visibility::*;
+        let seen = visibilities(&root, &show);
+        // Outside a project the walk root counts for files, while directories are walked to find projects
+        assert!(seen.contains(&("docs/guide".to_string(), Shown)));
+        assert!(seen.contains(&("docs/guide/part".to_string(), Shown)));
+        assert!(seen.contains(&("docs/guide/part/intro.md".to_string(), Hidden)));
+        let (_, rule) = visibility_rule(&root.join("docs/guide/part/intro.md"), 4, false, &show);
+        assert_eq!(rule, Rule::Depth);
+        let limit = show.depth_from_marker.as_ref().unwrap();
+        assert!(limit.lists(&root.join("docs/guide"), 2));
+        assert!(!limit.lists(&root.join("docs/guide/part"), 3));
+        // Two levels inside the project, four below the walk root
+        assert!(seen.contains(&("crates/app/src".to_string(), Shown)));
+        assert!(seen.contains(&("crates/app/src/cli".to_string(), Shown)));
+        let (_, rule) = visibility_rule(&root.join("crates/app/src/cli/main.rs"), 5, false, &show);
+        assert_eq!(rule, Rule::ProjectDepth);
+
+        // A project deeper than the limit is still reached
+        let markers = vec![OsString::from("Cargo.toml")];
+        let show = ShowOptions { depth_from_marker: Some(DepthFromMarker::new(markers, 1)), ..ShowOptions::default() };
+        let seen = visibilities(&root, &show);
+        assert!(seen.contains(&("crates/app/Cargo.toml".to_string(), Shown)));
+        assert!(seen.contains(&("crates/app/src".to_string(), Shown)));
+        assert!(seen.contains(&("crates/app/src/cli".to_string(), Hidden)));
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_exclude_patterns_match_names_and_relative_paths() {
+        let patterns = parse_pattern_lines("# build output\n*.log\n\n  src/gen/  \n");
+        assert_eq!(patterns, ["*.log", "src/gen/"]);
+        let filter = GlobFilter::new(patterns, CaseMode::Sensitive).unwrap();
+
+        assert!(filter.is_excluded(Path::new("/repo/app.log"), 1));
+        assert!(filter.is_excluded(Path::new("/repo/src/deep/app.log"), 3));
+        assert!(filter.is_excluded(Path::new("/repo/src/gen"), 2));
+        assert!(!filter.is_excluded(Path::new("/repo/lib/gen"), 2));
+        assert!(!filter.is_excluded(Path::new("/repo/src/main.rs"), 2));
+        assert!(!GlobFilter::default().is_excluded(Path::new("/repo/app.log"), 1));
+        assert!(!filter.is_excluded(Path::new("/repo/APP.LOG"), 1));
+        assert!(GlobFilter::new(vec!["[".to_string()], CaseMode::Sensitive).is_err());
+    }
+
+    #[test]
+    fn test_case_mode_governs_exclude_globs() {
+        assert!(CaseMode::Smart.ignores_case("readme"));
+        assert!(!CaseMode::Smart.ignores_case("README"));
+
+        let patterns = || vec!["*.LOG".to_string(), "build".to_string()];
+        let insensitive = GlobFilter::new(patterns(), CaseMode::Insensitive).unwrap();
+        assert!(insensitive.is_excluded(Path::new("/repo/app.log"), 1));
+        assert!(insensitive.is_excluded(Path::new("/repo/Build"), 1));
+        // Smart decides per pattern: only the one with uppercase letters is exact
+        let smart = GlobFilter::new(patterns(), CaseMode::Smart).unwrap();
+        assert!(!smart.is_excluded(Path::new("/repo/app.log"), 1));
+        assert!(smart.is_excluded(Path::new("/repo/app.LOG"), 1));
+        assert!(smart.is_excluded(Path::new("/repo/BUILD"), 1));
+    }
+
+    #[test]
+    fn test_is_executable() {
+        let dir = std::env::temp_dir().join(format!("lst-executable-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let script = dir.join("run.sh");
+        std::fs::write(&script, "").unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let meta = std::fs::metadata(&script).unwrap();
+            assert!(!is_executable(&meta, &script));
+            std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o744)).unwrap();
+            assert!(is_executable(&std::fs::metadata(&script).unwrap(), &script));
+        }
+        assert!(!is_executable(&std::fs::metadata(&dir).unwrap(), &dir));
+        assert!(has_executable_extension(Path::new("setup.EXE")));
+        assert!(has_executable_extension(Path::new("build.ps1")));
+        assert!(!has_executable_extension(Path::new("notes.txt")));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_has_hidden_component() {
+        let root = Path::new("/repo");
+        assert!(has_hidden_component(Path::new("/repo/.env"), root));
+        assert!(has_hidden_component(Path::new("/repo/.config/app.toml"), root));
+        assert!(!has_hidden_component(Path::new("/repo/src/main.rs"), root));
+        assert!(!has_hidden_component(Path::new("./src"), Path::new(".")));
+    }
+}
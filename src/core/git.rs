@@ -0,0 +1,121 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// How a path differs from the last commit, from `git status`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Change {
+    /// Changes added to the index, possibly with more in the working tree
+    Staged,
+    /// Changes in the working tree only
+    Modified,
+    /// Not tracked by git
+    Untracked,
+    /// Matched by a gitignore rule; only reported when asked for
+    Ignored,
+}
+
+/// Paths with uncommitted changes below a walk root (`--git-modified`)
+///
+/// Paths are joined onto the root as given, so they compare equal to the walk's own paths.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GitStatus {
+    changes: HashMap<PathBuf, Change>,
+    /// Directories git reports as a whole, such as an ignored `target/`
+    whole_dirs: Vec<PathBuf>,
+}
+
+impl GitStatus {
+    /// Run `git status` for the repository holding `root`; `None` outside a repository or without git
+    pub fn load(root: &Path, include_ignored: bool) -> Option<Self> {
+        let toplevel = git(root, &["rev-parse", "--show-toplevel"])?;
+        let toplevel = PathBuf::from(String::from_utf8_lossy(&toplevel).trim_end_matches('\n'));
+        let mut args = vec!["status", "--porcelain=v1", "-z", "--untracked-files=all"];
+        if include_ignored {
+            args.push("--ignored");
+        }
+        args.extend(["--", "."]);
+        let output = git(root, &args)?;
+        let canonical_root = std::fs::canonicalize(root).ok()?;
+        let below_root = canonical_root.strip_prefix(&toplevel).ok()?;
+        Some(Self::parse(&output, below_root, root))
+    }
+
+    /// Parse `git status --porcelain=v1 -z`, whose paths are relative to the repository,
+    /// keeping those below `below_root` (the walk root's path in the repository) joined onto `root`
+    fn parse(output: &[u8], below_root: &Path, root: &Path) -> Self {
+        let mut status = Self::default();
+        let mut records = output.split(|&b| b == 0);
+        while let Some(record) = records.next() {
+            // The trailing terminator leaves an empty record; nothing else is this short
+            if record.len() < 4 {
+                continue;
+            }
+            let (code, path) = (&record[..2], String::from_utf8_lossy(&record[3..]));
+            // A rename or copy is followed by the path it came from, however short
+            if code.contains(&b'R') || code.contains(&b'C') {
+                records.next();
+            }
+            let change = match code {
+                b"??" => Change::Untracked,
+                b"!!" => Change::Ignored,
+                [b' ', _] => Change::Modified,
+                _ => Change::Staged,
+            };
+            let Ok(relative) = Path::new(path.as_ref()).strip_prefix(below_root) else { continue };
+            let path = root.join(relative);
+            if record.ends_with(b"/") {
+                status.whole_dirs.push(path.clone());
+            }
+            status.changes.insert(path, change);
+        }
+        status
+    }
+
+    /// How `path` changed, if it did; entries inside a directory reported as a whole share its change
+    pub fn change(&self, path: &Path) -> Option<Change> {
+        self.changes.get(path).copied().or_else(|| {
+            let dir = self.whole_dirs.iter().find(|dir| path.starts_with(dir))?;
+            self.changes.get(dir).copied()
+        })
+    }
+}
+
+/// Stdout of `git -C dir args...`, or `None` when git is missing or fails
+fn git(dir: &Path, args: &[&str]) -> Option<Vec<u8>> {
+    let output = Command::new("git").arg("-C").arg(dir).args(args).output().ok()?;
+    output.status.success().then_some(output.stdout)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_porcelain_keeps_paths_below_root() {
+        let output = b"M  src/staged.rs\0 M src/edited.rs\0MM src/both.rs\0R  src/new.rs\0src/old.rs\0?? src/cli/draft.rs\0!! src/target/\0 M README.md\0";
+        let status = GitStatus::parse(output, Path::new("src"), Path::new("."));
+        assert_eq!(status.change(Path::new("./staged.rs")), Some(Change::Staged));
+        assert_eq!(status.change(Path::new("./edited.rs")), Some(Change::Modified));
+        assert_eq!(status.change(Path::new("./both.rs")), Some(Change::Staged));
+        assert_eq!(status.change(Path::new("./new.rs")), Some(Change::Staged));
+        assert_eq!(status.change(Path::new("./old.rs")), None);
+        assert_eq!(status.change(Path::new("./cli/draft.rs")), Some(Change::Untracked));
+        assert_eq!(status.change(Path::new("./target/debug/lst")), Some(Change::Ignored));
+        // Outside the walk root
+        assert_eq!(status.change(Path::new("./README.md")), None);
+        assert_eq!(status.changes.len(), 6);
+    }
+
+    #[test]
+    fn test_short_rename_sources_dont_swallow_the_next_record() {
+        let output = b"R  b.c\0a.c\0 M other.rs\0R  x\0y\0?? new.rs\0";
+        let status = GitStatus::parse(output, Path::new(""), Path::new("."));
+        assert_eq!(status.change(Path::new("./b.c")), Some(Change::Staged));
+        assert_eq!(status.change(Path::new("./other.rs")), Some(Change::Modified));
+        assert_eq!(status.change(Path::new("./x")), Some(Change::Staged));
+        assert_eq!(status.change(Path::new("./new.rs")), Some(Change::Untracked));
+        assert_eq!(status.change(Path::new("./a.c")), None);
+        assert_eq!(status.changes.len(), 4);
+    }
+}
@@ -0,0 +1,781 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::AtomicUsize;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use walkdir::DirEntry;
+
+use super::dupes::find_duplicates;
+use super::filters::{entry_visibility, is_mount_point, is_shown_skipped, is_stopped, ShowOptions, Visibility};
+use super::meta::{EntryMeta, FsMetadata, MetadataSource};
+use super::search::{build_search_filter, should_print_entry, MatchTarget, SearchFilter, SearchQuery};
+use super::stats::{extension_stats, ExtensionStats, Summary};
+use super::timing::Timings;
+use super::tree::{collect_entries, par_read_meta, search_candidates, UNLIMITED_DEPTH};
+use crate::error::{LstError, Result};
+
+/// Options for [`list_tree`]
+#[derive(Debug, Clone)]
+pub struct ListOptions {
+    /// Max depth of traversal; `UNLIMITED_DEPTH` for unlimited
+    pub max_depth: usize,
+    /// Include hidden files and directories
+    pub show_hidden: bool,
+    /// Keep only entries matching this pattern (case-insensitive), plus their ancestors
+    pub pattern: Option<String>,
+    /// What the pattern is matched against
+    pub target: MatchTarget,
+    /// Stop after this many matches
+    pub max_results: Option<usize>,
+    /// Number groups of files with identical contents
+    pub dupes: bool,
+    /// Count each expanded directory's listed children
+    pub counts: bool,
+    /// Flag expanded directories that list no children
+    pub mark_empty: bool,
+    /// Skip stat-ing files, leaving every `size` unset
+    pub no_size: bool,
+}
+
+impl Default for ListOptions {
+    fn default() -> Self {
+        Self {
+            max_depth: 1,
+            show_hidden: false,
+            pattern: None,
+            target: MatchTarget::default(),
+            max_results: None,
+            dupes: false,
+            counts: false,
+            mark_empty: false,
+            no_size: false,
+        }
+    }
+}
+
+/// Kind of entry a [`TreeNode`] describes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NodeKind {
+    Directory,
+    File,
+}
+
+/// One entry of a listed tree, and the node emitted by `--json`
+///
+/// Fields serialize in declaration order and absent options are omitted;
+/// `--print-schema` describes the same shape, so keep the two in sync.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TreeNode {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub kind: NodeKind,
+    /// Written as a string, or as a byte array when it isn't valid UTF-8 (Unix)
+    #[serde(serialize_with = "serialize_path", deserialize_with = "deserialize_path")]
+    pub path: PathBuf,
+    /// Levels below the listed root, which is at 0
+    #[serde(default)]
+    pub depth: usize,
+    /// Size in bytes, for files whose metadata could be read
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub size: Option<u64>,
+    /// Content match count, for files found by a content search
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub matches: Option<usize>,
+    /// Group number shared by files with identical contents (with `dupes`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dup_group: Option<usize>,
+    /// Number of listed children of an expanded directory (with `counts`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub child_count: Option<usize>,
+    /// Set on a common heavy directory listed without its contents (`--show-skipped`)
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub skipped: bool,
+    /// Set on a directory the walk listed without descending into (`--stop-at`, `--stop-at-marker`)
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub stopped: bool,
+    /// Set on a mount point `--one-file-system` didn't descend into
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub mount_point: bool,
+    /// Set on an entry deleted between the walk and its stat
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub vanished: bool,
+    /// Files and total size below a large directory listed without its contents (`--collapse-over`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub collapsed: Option<ExtensionStats>,
+    /// Set on an expanded directory with no listed children (`--mark-empty`),
+    /// and on the empty directories an `--empty-dirs` listing is made of
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub empty: bool,
+    /// Listed children; `None` for files and for directories a search pruned to nothing
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub children: Option<Vec<TreeNode>>,
+    /// Set on the root when `max_results` cut the search short
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub truncated: bool,
+    /// Per-extension totals, on the root with `--stats`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stats: Option<BTreeMap<String, ExtensionStats>>,
+    /// Listed directories and files, on the root with `--report`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub summary: Option<Summary>,
+    /// `--depth` the tree was listed with (0 for unlimited), on the root of `--json` output
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub depth_limit: Option<usize>,
+}
+
+/// Name of a tree's root: its last component, resolved for paths like `.` and `..`
+///
+/// A filesystem root such as `/` keeps its path.
+pub fn root_name(root: &Path) -> String {
+    let resolved;
+    let path = if root.file_name().is_some() {
+        root
+    } else {
+        resolved = std::fs::canonicalize(root).unwrap_or_else(|_| root.to_path_buf());
+        &resolved
+    };
+    path.file_name().unwrap_or(path.as_os_str()).to_string_lossy().into_owned()
+}
+
+/// `path` made absolute, with the home directory abbreviated to `~` (`--tilde`)
+///
+/// Paths outside the home directory are only made absolute.
+pub fn tilde_path(path: &Path) -> PathBuf {
+    let absolute = normalize_lexically(&std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf()));
+    match home_dir() {
+        Some(home) => abbreviate_home(&absolute, &home),
+        None => absolute,
+    }
+}
+
+/// `path` with `.` components dropped and each `..` removing the one before it, without
+/// touching the filesystem; `..` at the root stays at the root, like the kernel treats it
+fn normalize_lexically(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir if normalized.file_name().is_some() => {
+                normalized.pop();
+            }
+            Component::ParentDir if normalized.has_root() => {}
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+fn home_dir() -> Option<PathBuf> {
+    let var = if cfg!(windows) { "USERPROFILE" } else { "HOME" };
+    std::env::var_os(var).filter(|home| !home.is_empty()).map(PathBuf::from)
+}
+
+fn abbreviate_home(path: &Path, home: &Path) -> PathBuf {
+    // A home of `/` would turn every path into `~/...`
+    if home.parent().is_none() {
+        return path.to_path_buf();
+    }
+    match path.strip_prefix(home) {
+        Ok(rest) if rest.as_os_str().is_empty() => PathBuf::from("~"),
+        Ok(rest) => Path::new("~").join(rest),
+        Err(_) => path.to_path_buf(),
+    }
+}
+
+/// A path as a string, or on Unix as its raw bytes when it isn't valid UTF-8
+///
+/// Names stay lossy strings for display, but the bytes let consumers (and
+/// `--diff`) recover the exact path. Elsewhere such paths are written lossily.
+fn serialize_path<S: Serializer>(path: &Path, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+    match path.to_str() {
+        Some(text) => serializer.serialize_str(text),
+        #[cfg(unix)]
+        None => {
+            use std::os::unix::ffi::OsStrExt;
+            serializer.collect_seq(path.as_os_str().as_bytes())
+        }
+        #[cfg(not(unix))]
+        None => serializer.serialize_str(&path.to_string_lossy()),
+    }
+}
+
+fn deserialize_path<'de, D: Deserializer<'de>>(deserializer: D) -> std::result::Result<PathBuf, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum RawPath {
+        Text(String),
+        Bytes(Vec<u8>),
+    }
+    Ok(match RawPath::deserialize(deserializer)? {
+        RawPath::Text(text) => PathBuf::from(text),
+        #[cfg(unix)]
+        RawPath::Bytes(bytes) => {
+            use std::os::unix::ffi::OsStringExt;
+            PathBuf::from(std::ffi::OsString::from_vec(bytes))
+        }
+        #[cfg(not(unix))]
+        RawPath::Bytes(bytes) => PathBuf::from(String::from_utf8_lossy(&bytes).into_owned()),
+    })
+}
+
+impl TreeNode {
+    /// Render the node as the JSON emitted by `--json`
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::to_value(self).expect("tree nodes have string keys and lossy paths")
+    }
+
+    /// Replace the `root` prefix of every path in the tree with `shown` (`--tilde`)
+    pub fn rebase(&mut self, root: &Path, shown: &Path) {
+        let mut stack = vec![self];
+        while let Some(node) = stack.pop() {
+            node.path = match node.path.strip_prefix(root) {
+                Ok(rest) if rest.as_os_str().is_empty() => shown.to_path_buf(),
+                Ok(rest) => shown.join(rest),
+                Err(_) => continue,
+            };
+            stack.extend(node.children.iter_mut().flatten());
+        }
+    }
+
+    /// Drop the size of this node and everything below it (`--no-size`)
+    pub fn clear_sizes(&mut self) {
+        self.size = None;
+        for child in self.children.iter_mut().flatten() {
+            child.clear_sizes();
+        }
+    }
+}
+
+/// Optional per-entry notes computed once the walk is complete
+#[derive(Debug, Default)]
+pub struct Annotations {
+    /// Duplicate group of each file, filled by [`TreeData::mark_duplicates`]
+    pub dup_groups: HashMap<PathBuf, usize>,
+    /// Listed children of each expanded directory, filled by [`TreeData::count_children`]
+    pub child_counts: HashMap<PathBuf, usize>,
+    /// Skipped directories listed without their contents (`--show-skipped`), filled by [`TreeData::collect`]
+    pub skipped: HashSet<PathBuf>,
+    /// Directories `--stop-at` and `--stop-at-marker` listed without their contents, filled by [`TreeData::collect`]
+    pub stopped: HashSet<PathBuf>,
+    /// Mount points `--one-file-system` didn't descend into, filled by [`TreeData::collect`]
+    pub mount_points: HashSet<PathBuf>,
+    /// Totals of directories listed without their contents (`--collapse-over`), filled by [`TreeData::collect`]
+    pub collapsed: HashMap<PathBuf, ExtensionStats>,
+    /// Expanded directories with no listed children, filled by [`TreeData::mark_empty`]
+    pub empty: HashSet<PathBuf>,
+    /// Entries deleted between the walk and their stat, filled by [`TreeData::collect`]
+    pub vanished: HashSet<PathBuf>,
+    /// The empty directories listed by `--empty-dirs`, as opposed to their ancestors, filled by [`TreeData::collect`]
+    pub empty_dirs: HashSet<PathBuf>,
+}
+
+/// Entries, search filter and metadata collected for one root
+pub(crate) struct TreeData {
+    pub entries: Vec<DirEntry>,
+    pub filter: SearchFilter,
+    pub metas: HashMap<PathBuf, EntryMeta>,
+    pub annotations: Annotations,
+    pub timings: Timings,
+}
+
+impl TreeData {
+    /// Walk `path` once, run the search pre-pass if any, and stat every file
+    ///
+    /// Without a metadata `source` files aren't stat-ed and carry no size;
+    /// directories are only stat-ed with `stat_dirs`.
+    pub fn collect(
+        path: &Path,
+        max_depth: usize,
+        show: &ShowOptions,
+        search: Option<&SearchQuery>,
+        progress: Option<&AtomicUsize>,
+        source: Option<&dyn MetadataSource>,
+        stat_dirs: bool,
+    ) -> Self {
+        let mut timings = Timings::default();
+        // A narrowed listing keeps the ancestors of entries below the depth limit too
+        let narrowing = search.is_none() && show.narrows();
+        let walk_depth = if narrowing { UNLIMITED_DEPTH } else { max_depth };
+        // A search counts its own walk of the same entries below
+        let walk_progress = if search.is_some() { None } else { progress };
+        let mut entries = timings.time(|t| &mut t.walk, || collect_entries(path, walk_depth, show, walk_progress));
+        timings.entries = entries.len();
+        let filter = if let Some(query) = search {
+            let (filter, scanned) = timings.time(|t| &mut t.search, || {
+                let candidates = search_candidates(path, max_depth, show, query, progress);
+                (build_search_filter(&candidates, query), candidates.len())
+            });
+            timings.entries += scanned;
+            filter
+        } else if narrowing {
+            let filter = SearchFilter::narrowed(path, show, entries.iter().map(DirEntry::path));
+            entries.retain(|e| e.depth() <= max_depth);
+            filter
+        } else {
+            SearchFilter::default()
+        };
+        let (metas, vanished) = timings.time(|t| &mut t.metadata, || {
+            source.map(|source| par_read_meta(&entries, source, stat_dirs)).unwrap_or_default()
+        });
+
+        let skipped = entries
+            .iter()
+            .filter(|e| is_shown_skipped(e.file_name(), e.file_type().is_dir(), show))
+            .map(|e| e.path().to_path_buf())
+            .collect();
+        let stopped = entries
+            .iter()
+            .filter(|e| is_stopped(e.path(), e.depth(), e.file_type().is_dir(), show))
+            .map(|e| e.path().to_path_buf())
+            .collect();
+        let mount_points = entries
+            .iter()
+            .filter(|e| is_mount_point(e.path(), e.file_type().is_dir(), show))
+            .map(|e| e.path().to_path_buf())
+            .collect();
+        let collapsed = entries.iter().filter_map(|e| Some((e.path().to_path_buf(), show.collapsed(e)?))).collect();
+        let empty_dirs = if narrowing && show.empty_dirs { filter.matches.clone() } else { HashSet::new() };
+
+        Self {
+            entries,
+            filter,
+            metas,
+            annotations: Annotations { skipped, stopped, mount_points, collapsed, vanished, empty_dirs, ..Annotations::default() },
+            timings,
+        }
+    }
+
+    /// Group the displayed files that have identical contents
+    pub fn mark_duplicates(&mut self, search: Option<&SearchQuery>) {
+        let files: Vec<(PathBuf, u64)> = self
+            .entries
+            .iter()
+            .filter(|e| e.file_type().is_file())
+            .filter(|e| should_print_entry(e, search, &self.filter, true))
+            .filter_map(|e| self.metas.get(e.path()).map(|m| (e.path().to_path_buf(), m.size)))
+            .collect();
+        self.annotations.dup_groups = find_duplicates(&files);
+    }
+
+    /// Count the listed children of the root and of every directory the walk expanded
+    ///
+    /// Children hidden by the show options or pruned by a search aren't counted;
+    /// directories at the depth limit or collapsed get no count at all.
+    pub fn count_children(&mut self, root: &Path, search: Option<&SearchQuery>, show: &ShowOptions, max_depth: usize) {
+        self.annotations.child_counts = self.listed_children(root, search, show, max_depth);
+    }
+
+    /// Note the root and expanded directories that list no children, counted as in [`Self::count_children`]
+    pub fn mark_empty(&mut self, root: &Path, search: Option<&SearchQuery>, show: &ShowOptions, max_depth: usize) {
+        let counts = self.listed_children(root, search, show, max_depth);
+        self.annotations.empty = counts.into_iter().filter(|&(_, count)| count == 0).map(|(dir, _)| dir).collect();
+    }
+
+    /// Number of listed children of the root and of every listed, expanded directory
+    fn listed_children(
+        &self,
+        root: &Path,
+        search: Option<&SearchQuery>,
+        show: &ShowOptions,
+        max_depth: usize,
+    ) -> HashMap<PathBuf, usize> {
+        let listed = |entry: &DirEntry| should_print_entry(entry, search, &self.filter, show.walks_hidden());
+        let mut counts: HashMap<PathBuf, usize> = HashMap::new();
+        counts.insert(root.to_path_buf(), 0);
+        for entry in self.entries.iter().filter(|e| e.file_type().is_dir() && e.depth() < max_depth) {
+            if listed(entry) && entry_visibility(entry, show) != Visibility::Collapsed {
+                counts.insert(entry.path().to_path_buf(), 0);
+            }
+        }
+        for entry in self.entries.iter().filter(|e| listed(e)) {
+            if let Some(count) = entry.path().parent().and_then(|p| counts.get_mut(p)) {
+                *count += 1;
+            }
+        }
+        counts
+    }
+
+    /// Per-extension totals over the files that are displayed; see [`extension_stats`] for `count_links`
+    pub fn extension_stats(&self, search: Option<&SearchQuery>, count_links: bool) -> Vec<(String, ExtensionStats)> {
+        let files = self
+            .entries
+            .iter()
+            .filter(|e| !e.file_type().is_dir())
+            .filter(|e| should_print_entry(e, search, &self.filter, true))
+            .map(|e| e.path());
+        extension_stats(files, &self.metas, count_links)
+    }
+
+    /// Directories and files that are displayed, as the `--report` line counts them
+    pub fn summary(&self, search: Option<&SearchQuery>, show_hidden: bool) -> Summary {
+        let mut summary = Summary::default();
+        for entry in self.entries.iter().filter(|e| should_print_entry(e, search, &self.filter, show_hidden)) {
+            summary.add(entry.file_type().is_dir(), self.annotations.mount_points.contains(entry.path()));
+        }
+        summary
+    }
+
+    /// Assemble the collected entries under `root` into a tree
+    pub fn to_node(&self, root: &Path, search: Option<&SearchQuery>, show_hidden: bool) -> TreeNode {
+        // Group visible entries by parent once, so building stays linear
+        let mut by_parent: HashMap<&Path, Vec<&DirEntry>> = HashMap::new();
+        for entry in &self.entries {
+            if !should_print_entry(entry, search, &self.filter, show_hidden) {
+                continue;
+            }
+            if let Some(parent) = entry.path().parent() {
+                by_parent.entry(parent).or_default().push(entry);
+            }
+        }
+
+        TreeNode {
+            name: root_name(root),
+            path: root.to_path_buf(),
+            depth: 0,
+            kind: NodeKind::Directory,
+            size: None,
+            matches: None,
+            dup_group: None,
+            child_count: self.annotations.child_counts.get(root).copied(),
+            skipped: false,
+            stopped: false,
+            mount_point: false,
+            vanished: false,
+            collapsed: None,
+            empty: self.annotations.empty.contains(root),
+            children: Some(self.children_of(root, &by_parent, self.filter.active)),
+            truncated: self.filter.truncated,
+            stats: None,
+            summary: None,
+            depth_limit: None,
+        }
+    }
+
+    /// Nodes for the listed children of `parent`, nested to any depth
+    ///
+    /// Uses an explicit stack rather than recursion, so very deep trees can't
+    /// overflow the call stack.
+    fn children_of(
+        &self,
+        parent: &Path,
+        by_parent: &HashMap<&Path, Vec<&DirEntry>>,
+        filtering: bool,
+    ) -> Vec<TreeNode> {
+        /// A directory being filled: its listed children and the nodes built so far
+        struct Frame<'e> {
+            entries: &'e [&'e DirEntry],
+            built: Vec<TreeNode>,
+        }
+        let frame = |dir: &Path| Frame {
+            entries: by_parent.get(dir).map(Vec::as_slice).unwrap_or_default(),
+            built: Vec::new(),
+        };
+
+        let mut stack = vec![frame(parent)];
+        loop {
+            let top = stack.last_mut().expect("parent frame");
+            match top.entries.get(top.built.len()) {
+                Some(entry) if entry.file_type().is_dir() => stack.push(frame(entry.path())),
+                Some(entry) => {
+                    let node = self.node(entry, None);
+                    top.built.push(node);
+                }
+                None => {
+                    let done = stack.pop().expect("checked above");
+                    let Some(up) = stack.last_mut() else { return done.built };
+                    let dir = up.entries[up.built.len()];
+                    // Matched directories don't list unrelated contents during a search
+                    let children = (!done.built.is_empty() || !filtering).then_some(done.built);
+                    let node = self.node(dir, children);
+                    up.built.push(node);
+                }
+            }
+        }
+    }
+
+    fn node(&self, entry: &DirEntry, children: Option<Vec<TreeNode>>) -> TreeNode {
+        let is_dir = entry.file_type().is_dir();
+        TreeNode {
+            name: entry.file_name().to_string_lossy().into_owned(),
+            path: entry.path().to_path_buf(),
+            depth: entry.depth(),
+            kind: if is_dir { NodeKind::Directory } else { NodeKind::File },
+            size: if is_dir { None } else { self.metas.get(entry.path()).map(|m| m.size) },
+            matches: self.filter.match_count(entry.path()),
+            dup_group: self.annotations.dup_groups.get(entry.path()).copied(),
+            child_count: self.annotations.child_counts.get(entry.path()).copied(),
+            skipped: self.annotations.skipped.contains(entry.path()),
+            stopped: self.annotations.stopped.contains(entry.path()),
+            mount_point: self.annotations.mount_points.contains(entry.path()),
+            vanished: self.annotations.vanished.contains(entry.path()),
+            collapsed: self.annotations.collapsed.get(entry.path()).copied(),
+            empty: self.annotations.empty.contains(entry.path()) || self.annotations.empty_dirs.contains(entry.path()),
+            children,
+            truncated: false,
+            stats: None,
+            summary: None,
+            depth_limit: None,
+        }
+    }
+}
+
+/// List the directory tree under `path` without printing anything
+///
+/// This is the structured tree the CLI renders for `--json`.
+pub fn list_tree(path: &Path, options: &ListOptions) -> Result<TreeNode> {
+    if !path.is_dir() {
+        return Err(LstError::InvalidPath(format!("'{}' is not a directory", path.display())));
+    }
+
+    let query = options.pattern.as_deref().map(|pattern| {
+        SearchQuery::new(pattern, path)
+            .with_target(options.target)
+            .with_max_results(options.max_results)
+    });
+    let show = ShowOptions {
+        hidden: options.show_hidden,
+        ..ShowOptions::default()
+    };
+    // Duplicate detection compares sizes first, so it still needs metadata
+    let source: Option<&dyn MetadataSource> = (!options.no_size || options.dupes).then_some(&FsMetadata);
+    let mut data = TreeData::collect(path, options.max_depth, &show, query.as_ref(), None, source, false);
+    if options.dupes {
+        data.mark_duplicates(query.as_ref());
+    }
+    if options.counts {
+        data.count_children(path, query.as_ref(), &show, options.max_depth);
+    }
+    if options.mark_empty {
+        data.mark_empty(path, query.as_ref(), &show, options.max_depth);
+    }
+    let mut tree = data.to_node(path, query.as_ref(), options.show_hidden);
+    if options.no_size {
+        tree.clear_sizes();
+    }
+    Ok(tree)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture(name: &str) -> PathBuf {
+        let root = std::env::temp_dir().join(format!("lst-listing-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(root.join("src/nested")).unwrap();
+        std::fs::write(root.join("src/main.rs"), "fn main() {}").unwrap();
+        std::fs::write(root.join("src/nested/lib.rs"), "").unwrap();
+        std::fs::write(root.join("README.md"), "hi").unwrap();
+        root
+    }
+
+    fn child<'a>(node: &'a TreeNode, name: &str) -> &'a TreeNode {
+        node.children
+            .as_ref()
+            .and_then(|c| c.iter().find(|n| n.name == name))
+            .unwrap_or_else(|| panic!("no child {} in {:?}", name, node))
+    }
+
+    #[test]
+    fn test_to_node_handles_very_deep_trees() {
+        let root = std::env::temp_dir().join(format!("lst-listing-deep-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        let deepest: PathBuf = std::iter::repeat_n("d", 1000).fold(root.clone(), |p, c| p.join(c));
+        std::fs::create_dir_all(&deepest).unwrap();
+        std::fs::write(deepest.join("leaf.txt"), "x").unwrap();
+
+        let data = TreeData::collect(&root, UNLIMITED_DEPTH, &ShowOptions::default(), None, None, Some(&FsMetadata), false);
+        let tree = data.to_node(&root, None, false);
+        let mut node = &tree;
+        let mut depth = 0;
+        while let Some(next) = node.children.as_ref().and_then(|c| c.first()) {
+            node = next;
+            depth += 1;
+        }
+        assert_eq!(depth, 1001);
+        assert_eq!((node.name.as_str(), node.size), ("leaf.txt", Some(1)));
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_progress_counts_each_entry_once() {
+        let root = fixture("progress");
+        let scanned = |search: Option<&SearchQuery>| {
+            let progress = AtomicUsize::new(0);
+            TreeData::collect(&root, UNLIMITED_DEPTH, &ShowOptions::default(), search, Some(&progress), None, false);
+            progress.into_inner()
+        };
+        let listed = scanned(None);
+        assert!(listed >= 5, "{}", listed);
+        assert_eq!(scanned(Some(&SearchQuery::new("lib", &root))), listed);
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_list_tree_returns_nested_nodes() {
+        let root = fixture("nested");
+        let options = ListOptions {
+            max_depth: UNLIMITED_DEPTH,
+            ..ListOptions::default()
+        };
+
+        let tree = list_tree(&root, &options).unwrap();
+        assert_eq!(tree.kind, NodeKind::Directory);
+        assert_eq!(child(&tree, "README.md").size, Some(2));
+        let main = child(child(&tree, "src"), "main.rs");
+        assert_eq!(main.kind, NodeKind::File);
+        assert_eq!(main.size, Some(12));
+        assert_eq!((tree.depth, child(&tree, "src").depth, main.depth), (0, 1, 2));
+        assert!(child(child(&tree, "src"), "nested").children.is_some());
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_list_tree_search_keeps_only_matches_and_ancestors() {
+        let root = fixture("search");
+        let options = ListOptions {
+            max_depth: UNLIMITED_DEPTH,
+            pattern: Some("lib".to_string()),
+            ..ListOptions::default()
+        };
+
+        let tree = list_tree(&root, &options).unwrap();
+        let src = child(&tree, "src");
+        assert_eq!(tree.children.as_ref().unwrap().len(), 1);
+        assert_eq!(src.children.as_ref().unwrap().len(), 1);
+        child(child(src, "nested"), "lib.rs");
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_list_tree_marks_duplicates() {
+        let root = fixture("dupes");
+        std::fs::write(root.join("src/copy.rs"), "fn main() {}").unwrap();
+        let options = ListOptions {
+            max_depth: UNLIMITED_DEPTH,
+            dupes: true,
+            ..ListOptions::default()
+        };
+
+        let tree = list_tree(&root, &options).unwrap();
+        let src = child(&tree, "src");
+        assert_eq!(child(src, "main.rs").dup_group, Some(1));
+        assert_eq!(child(src, "copy.rs").dup_group, Some(1));
+        assert_eq!(child(&tree, "README.md").dup_group, None);
+        assert_eq!(child(src, "main.rs").to_json()["dup_group"], 1);
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_list_tree_counts_listed_children() {
+        let root = fixture("counts");
+        std::fs::write(root.join("src/.hidden"), "").unwrap();
+        let options = ListOptions {
+            max_depth: 2,
+            counts: true,
+            ..ListOptions::default()
+        };
+
+        let tree = list_tree(&root, &options).unwrap();
+        assert_eq!(tree.child_count, Some(2));
+        let src = child(&tree, "src");
+        assert_eq!(src.child_count, Some(2));
+        // Not expanded at this depth, so there is nothing to count
+        assert_eq!(child(src, "nested").child_count, None);
+        assert_eq!(child(&tree, "README.md").child_count, None);
+        assert_eq!(tree.to_json()["child_count"], 2);
+
+        let searched = list_tree(&root, &ListOptions { pattern: Some("main".to_string()), ..options }).unwrap();
+        assert_eq!(searched.child_count, Some(1));
+        assert_eq!(child(&searched, "src").child_count, Some(1));
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_list_tree_marks_empty_directories() {
+        let root = fixture("empty");
+        std::fs::create_dir_all(root.join("src/vacant")).unwrap();
+        std::fs::write(root.join("src/nested/.hidden"), "").unwrap();
+        std::fs::remove_file(root.join("src/nested/lib.rs")).unwrap();
+        let options = ListOptions { max_depth: 3, mark_empty: true, ..ListOptions::default() };
+
+        let tree = list_tree(&root, &options).unwrap();
+        let src = child(&tree, "src");
+        assert!(!tree.empty && !src.empty);
+        assert!(child(src, "vacant").empty);
+        // Only hidden contents, so nothing is listed
+        assert!(child(src, "nested").empty);
+        assert_eq!(child(src, "nested").to_json()["empty"], true);
+        assert!(child(src, "main.rs").to_json().get("empty").is_none());
+
+        // Beyond the depth limit there's no telling
+        let shallow = list_tree(&root, &ListOptions { max_depth: 2, ..options }).unwrap();
+        assert!(!child(child(&shallow, "src"), "vacant").empty);
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_abbreviate_home() {
+        let home = Path::new("/home/ada");
+        assert_eq!(abbreviate_home(Path::new("/home/ada/src/lst"), home), Path::new("~/src/lst"));
+        assert_eq!(abbreviate_home(home, home), Path::new("~"));
+        assert_eq!(abbreviate_home(Path::new("/home/adam/x"), home), Path::new("/home/adam/x"));
+        assert_eq!(abbreviate_home(Path::new("/etc"), Path::new("/")), Path::new("/etc"));
+    }
+
+    #[test]
+    fn test_normalize_lexically_resolves_dot_dot() {
+        assert_eq!(normalize_lexically(Path::new("/home/ada/src/../docs/./x")), Path::new("/home/ada/docs/x"));
+        assert_eq!(normalize_lexically(Path::new("/home/ada/..")), Path::new("/home"));
+        assert_eq!(normalize_lexically(Path::new("/../..")), Path::new("/"));
+        assert_eq!(normalize_lexically(Path::new("../a/..")), Path::new(".."));
+    }
+
+    #[test]
+    fn test_rebase_rewrites_every_path() {
+        let root = fixture("rebase");
+        let options = ListOptions { max_depth: UNLIMITED_DEPTH, ..ListOptions::default() };
+        let mut tree = list_tree(&root, &options).unwrap();
+        tree.rebase(&root, Path::new("~/proj"));
+        assert_eq!(tree.path, Path::new("~/proj"));
+        assert_eq!(child(child(&tree, "src"), "main.rs").path, Path::new("~/proj/src/main.rs"));
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_non_utf8_names_keep_their_bytes() {
+        use std::os::unix::ffi::OsStrExt;
+
+        let root = fixture("non-utf8");
+        let raw = std::ffi::OsStr::from_bytes(b"caf\xe9.txt");
+        std::fs::write(root.join(raw), "x").unwrap();
+
+        // Search sees the real bytes, not U+FFFD replacements
+        let query = SearchQuery::new("caf", &root);
+        assert!(query.is_match(&root.join(raw)));
+        assert!(!SearchQuery::new("\u{FFFD}", &root).is_match(&root.join(raw)));
+
+        let options = ListOptions { pattern: Some("caf".to_string()), ..ListOptions::default() };
+        let tree = list_tree(&root, &options).unwrap();
+        let node = child(&tree, "caf\u{FFFD}.txt");
+        let json = serde_json::to_value(node).unwrap();
+        assert_eq!(json["path"].as_array().map(Vec::len), Some(root.join(raw).as_os_str().len()));
+        let back: TreeNode = serde_json::from_value(json).unwrap();
+        assert_eq!(back.path, root.join(raw));
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_list_tree_rejects_files() {
+        let root = fixture("file");
+        assert!(list_tree(&root.join("README.md"), &ListOptions::default()).is_err());
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,207 @@
+use std::fs::Metadata;
+use std::io;
+use std::path::Path;
+use std::time::SystemTime;
+
+use super::filters::is_executable;
+
+/// The metadata fields lst displays, fetched once per entry
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct EntryMeta {
+    /// Size in bytes
+    pub size: u64,
+    /// Last modification time, when the platform reports it
+    pub modified: Option<SystemTime>,
+    /// Creation (birth) time, when the platform and filesystem report it
+    pub created: Option<SystemTime>,
+    /// Last access time, when the platform reports it
+    pub accessed: Option<SystemTime>,
+    /// Unix permission bits, when available
+    pub mode: Option<u32>,
+    /// Whether the file can be run (see [`is_executable`])
+    pub executable: bool,
+    /// `(device, inode)` of a file with more than one hard link (Unix only)
+    pub link_id: Option<(u64, u64)>,
+}
+
+impl EntryMeta {
+    /// Extract the displayed fields from the filesystem metadata of `path`
+    pub fn from_metadata(meta: &Metadata, path: &Path) -> Self {
+        Self {
+            size: meta.len(),
+            modified: meta.modified().ok(),
+            created: meta.created().ok(),
+            accessed: meta.accessed().ok(),
+            mode: mode_of(meta),
+            executable: is_executable(meta, path),
+            link_id: link_id_of(meta),
+        }
+    }
+
+    /// The timestamp `field` selects
+    pub fn time(&self, field: TimeField) -> Option<SystemTime> {
+        match field {
+            TimeField::Modified => self.modified,
+            TimeField::Created => self.created,
+            TimeField::Accessed => self.accessed,
+        }
+    }
+}
+
+/// Which timestamp the time column and the time sort use (`--time-field`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum TimeField {
+    /// Last modification (mtime)
+    #[default]
+    Modified,
+    /// Creation (btime); not every platform and filesystem records it
+    Created,
+    /// Last access (atime); often coarse, e.g. under `relatime` mounts
+    Accessed,
+}
+
+impl TimeField {
+    /// The field's time in `meta`, when reported
+    pub fn of(self, meta: &Metadata) -> Option<SystemTime> {
+        match self {
+            TimeField::Modified => meta.modified(),
+            TimeField::Created => meta.created(),
+            TimeField::Accessed => meta.accessed(),
+        }
+        .ok()
+    }
+
+    /// Whether the filesystem holding `probe` reports this field at all
+    pub fn is_supported(self, probe: &Path) -> bool {
+        match std::fs::symlink_metadata(probe) {
+            Ok(meta) => self.of(&meta).is_some(),
+            // Nothing to learn from a path that can't be read; the listing reports it
+            Err(_) => true,
+        }
+    }
+}
+
+#[cfg(unix)]
+fn mode_of(meta: &Metadata) -> Option<u32> {
+    use std::os::unix::fs::PermissionsExt;
+    Some(meta.permissions().mode())
+}
+
+#[cfg(not(unix))]
+fn mode_of(_meta: &Metadata) -> Option<u32> {
+    None
+}
+
+#[cfg(unix)]
+fn link_id_of(meta: &Metadata) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+    (meta.is_file() && meta.nlink() > 1).then(|| (meta.dev(), meta.ino()))
+}
+
+#[cfg(not(unix))]
+fn link_id_of(_meta: &Metadata) -> Option<(u64, u64)> {
+    None
+}
+
+/// Whether the entry at `path` (not following symlinks) has extended attributes
+///
+/// Shown as the `@` after `--columns perms`, like `ls -l` on macOS; always false
+/// where lst can't list attributes. Attributes the system sets on every file, such
+/// as `security.selinux`, don't count.
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+pub fn has_xattr(path: &Path) -> bool {
+    use std::os::unix::ffi::OsStrExt;
+    let Ok(path) = std::ffi::CString::new(path.as_os_str().as_bytes()) else { return false };
+    let list = |buf: *mut libc::c_char, size: usize| {
+        // SAFETY: `path` is NUL-terminated and `buf` is null or points to `size` writable bytes
+        #[cfg(target_os = "linux")]
+        let len = unsafe { libc::llistxattr(path.as_ptr(), buf, size) };
+        #[cfg(target_os = "macos")]
+        let len = unsafe { libc::listxattr(path.as_ptr(), buf, size, libc::XATTR_NOFOLLOW) };
+        usize::try_from(len).ok()
+    };
+    // With no buffer, the call returns the size the attribute names would need
+    let Some(size @ 1..) = list(std::ptr::null_mut(), 0) else { return false };
+    let mut names = vec![0u8; size];
+    match list(names.as_mut_ptr().cast(), size) {
+        Some(len) => has_user_xattr(&names[..len.min(size)]),
+        None => false,
+    }
+}
+
+/// Whether a NUL-separated list of attribute names holds any beyond the `security.*` ones
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+fn has_user_xattr(names: &[u8]) -> bool {
+    names.split(|&b| b == 0).any(|name| !name.is_empty() && !name.starts_with(b"security."))
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+pub fn has_xattr(_path: &Path) -> bool {
+    false
+}
+
+/// Where entry metadata comes from
+///
+/// Abstracted so rendering never stats an entry more than once, and so tests
+/// can count the calls.
+pub trait MetadataSource: Send + Sync {
+    /// Read the metadata of `path` without following symlinks
+    fn stat(&self, path: &Path) -> io::Result<EntryMeta>;
+}
+
+/// Metadata read straight from the filesystem
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FsMetadata;
+
+impl MetadataSource for FsMetadata {
+    fn stat(&self, path: &Path) -> io::Result<EntryMeta> {
+        std::fs::symlink_metadata(path).map(|m| EntryMeta::from_metadata(&m, path))
+    }
+}
+
+/// What reading an entry's metadata for display found
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MetaRead {
+    Found(EntryMeta),
+    /// Deleted between the walk listing it and the stat, printed as `[vanished]` rather than `(0 B)`
+    Vanished,
+    /// Unreadable for another reason, such as permissions; logged as a warning
+    Unreadable,
+}
+
+impl MetaRead {
+    /// Stat `path` through `source`, telling a vanished entry (`NotFound`) from other errors
+    pub fn read(source: &dyn MetadataSource, path: &Path) -> Self {
+        match source.stat(path) {
+            Ok(meta) => MetaRead::Found(meta),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                log::debug!("{} vanished during the walk", path.display());
+                MetaRead::Vanished
+            }
+            Err(e) => {
+                log::warn!("{}", e);
+                MetaRead::Unreadable
+            }
+        }
+    }
+
+    pub fn found(self) -> Option<EntryMeta> {
+        match self {
+            MetaRead::Found(meta) => Some(meta),
+            MetaRead::Vanished | MetaRead::Unreadable => None,
+        }
+    }
+}
+
+#[cfg(all(test, any(target_os = "linux", target_os = "macos")))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_security_xattrs_are_not_marked() {
+        assert!(!has_user_xattr(b""));
+        assert!(!has_user_xattr(b"security.selinux\0"));
+        assert!(has_user_xattr(b"security.selinux\0user.note\0"));
+        assert!(has_user_xattr(b"com.apple.quarantine\0"));
+    }
+}
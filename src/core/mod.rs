@@ -1,3 +1,16 @@
+pub mod archive;
+pub mod cancel;
+pub mod dupes;
+pub mod entry;
+pub mod exec;
 pub mod filters;
+pub mod git;
+pub mod listing;
+pub mod meta;
 pub mod tree;
+pub mod roots;
 pub mod search;
+pub mod sort;
+pub mod stats;
+pub mod timing;
+pub mod virtual_tree;
@@ -0,0 +1,192 @@
+use std::path::{Component, Path, PathBuf};
+
+use globset::GlobBuilder;
+use walkdir::WalkDir;
+
+use super::filters::{is_common_skip_os, is_hidden};
+use crate::error::{LstError, Result};
+
+/// Check whether a path argument contains glob metacharacters
+pub fn is_glob(path: &str) -> bool {
+    path.contains(['*', '?', '['])
+}
+
+/// Resolve the path argument into one or more roots to print
+///
+/// Literal paths that exist always win; otherwise brace patterns like
+/// `src/{core,output}` are expanded first, in order, and a path containing
+/// glob metacharacters is expanded relative to the current directory.
+pub fn resolve_roots(path: &str) -> Result<Vec<PathBuf>> {
+    if Path::new(path).exists() {
+        return Ok(vec![PathBuf::from(path)]);
+    }
+    let alternatives = expand_braces(path);
+    if alternatives.len() == 1 && !is_glob(path) {
+        return Ok(vec![PathBuf::from(path)]);
+    }
+
+    let mut roots: Vec<PathBuf> = Vec::new();
+    for alternative in &alternatives {
+        let expanded = if is_glob(alternative) {
+            expand_glob(alternative)?
+        } else if Path::new(alternative).exists() {
+            vec![PathBuf::from(alternative)]
+        } else {
+            log::warn!("{} doesn't exist (from {})", alternative, path);
+            Vec::new()
+        };
+        for root in expanded {
+            if !roots.contains(&root) {
+                roots.push(root);
+            }
+        }
+    }
+    if roots.is_empty() {
+        return Err(LstError::NoMatches(path.to_string()));
+    }
+    Ok(roots)
+}
+
+/// Expand shell-style brace alternatives: `a{b,c{d,e}}f` gives `abf`, `acdf`, `acef`
+///
+/// Like bash, braces without a top-level comma (`{}`, `{x}`) and unbalanced
+/// ones stay literal, and `{,x}` has an empty alternative.
+pub fn expand_braces(pattern: &str) -> Vec<String> {
+    let bytes = pattern.as_bytes();
+    for open in (0..bytes.len()).filter(|&i| bytes[i] == b'{') {
+        let mut depth = 0;
+        let mut commas = Vec::new();
+        let mut close = None;
+        for (i, &byte) in bytes.iter().enumerate().skip(open) {
+            match byte {
+                b'{' => depth += 1,
+                b'}' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        close = Some(i);
+                        break;
+                    }
+                }
+                b',' if depth == 1 => commas.push(i),
+                _ => {}
+            }
+        }
+        let Some(close) = close else { break };
+        if commas.is_empty() {
+            continue;
+        }
+
+        let (prefix, suffix) = (&pattern[..open], &pattern[close + 1..]);
+        let bounds: Vec<usize> = std::iter::once(open).chain(commas).chain([close]).collect();
+        return bounds
+            .windows(2)
+            .flat_map(|w| expand_braces(&format!("{}{}{}", prefix, &pattern[w[0] + 1..w[1]], suffix)))
+            .collect();
+    }
+    vec![pattern.to_string()]
+}
+
+/// Expand a glob pattern into the sorted list of matching paths
+pub fn expand_glob(pattern: &str) -> Result<Vec<PathBuf>> {
+    let matcher = GlobBuilder::new(pattern)
+        .literal_separator(true)
+        .build()
+        .map_err(|e| LstError::InvalidPattern(e.to_string()))?
+        .compile_matcher();
+
+    // Walk only from the literal prefix of the pattern, and only as deep as it can reach
+    let mut base = PathBuf::new();
+    let mut remaining = 0usize;
+    let mut recursive = false;
+    for component in Path::new(pattern).components() {
+        let text = component.as_os_str().to_string_lossy();
+        if remaining == 0 && !is_glob(&text) {
+            base.push(component);
+            continue;
+        }
+        if matches!(component, Component::Normal(_)) {
+            remaining += 1;
+            recursive |= text.contains("**");
+        }
+    }
+
+    let walk_root = if base.as_os_str().is_empty() { Path::new(".") } else { base.as_path() };
+    let max_depth = if recursive { usize::MAX } else { remaining };
+    // Like the shell, `*` should not pick up dotfiles unless asked for explicitly
+    let show_hidden = pattern.starts_with('.') || pattern.contains("/.") || pattern.contains("\\.");
+
+    let mut matches: Vec<PathBuf> = WalkDir::new(walk_root)
+        .min_depth(1)
+        .max_depth(max_depth)
+        .into_iter()
+        .filter_entry(|e| !is_common_skip_os(e.file_name()) && (show_hidden || !is_hidden(e)))
+        .filter_map(|e| e.ok())
+        .map(|e| e.into_path())
+        .map(|p| {
+            if base.as_os_str().is_empty() {
+                p.strip_prefix(".").map(Path::to_path_buf).unwrap_or(p)
+            } else {
+                p
+            }
+        })
+        .filter(|p| matcher.is_match(p))
+        .collect();
+
+    matches.sort();
+    Ok(matches)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_glob() {
+        assert!(is_glob("*.rs"));
+        assert!(is_glob("src/?ore"));
+        assert!(is_glob("[ab]"));
+        assert!(!is_glob("src/core"));
+    }
+
+    #[test]
+    fn test_literal_path_is_kept() {
+        let roots = resolve_roots("src").unwrap();
+        assert_eq!(roots, vec![PathBuf::from("src")]);
+    }
+
+    #[test]
+    fn test_glob_expands_relative_to_cwd() {
+        let roots = resolve_roots("src/c*").unwrap();
+        assert!(roots.contains(&PathBuf::from("src/cli")));
+        assert!(roots.contains(&PathBuf::from("src/core")));
+        assert!(roots.contains(&PathBuf::from("src/commands")));
+    }
+
+    #[test]
+    fn test_expand_braces() {
+        assert_eq!(expand_braces("src/{core,output}"), ["src/core", "src/output"]);
+        assert_eq!(expand_braces("a{b,c{d,e}}f"), ["abf", "acdf", "acef"]);
+        assert_eq!(expand_braces("{x,y}{1,2}"), ["x1", "x2", "y1", "y2"]);
+        assert_eq!(expand_braces("file{,.bak}"), ["file", "file.bak"]);
+        assert_eq!(expand_braces("{a{b,c}}"), ["{ab}", "{ac}"]);
+        for literal in ["plain", "{}", "{x}", "open{a,b", "close}", "{a,b"] {
+            assert_eq!(expand_braces(literal), [literal]);
+        }
+    }
+
+    #[test]
+    fn test_braces_expand_into_roots_in_order() {
+        let roots = resolve_roots("src/{output,core,missing}").unwrap();
+        assert_eq!(roots, [PathBuf::from("src/output"), PathBuf::from("src/core")]);
+        let roots = resolve_roots("src/{core/t*,cli}").unwrap();
+        assert_eq!(roots[0], PathBuf::from("src/core/timing.rs"));
+        assert_eq!(roots.last(), Some(&PathBuf::from("src/cli")));
+        assert!(matches!(resolve_roots("src/{nope,nada}"), Err(LstError::NoMatches(_))));
+    }
+
+    #[test]
+    fn test_unmatched_glob_errors() {
+        let err = resolve_roots("no-such-*.nothing").unwrap_err();
+        assert!(matches!(err, LstError::NoMatches(_)));
+    }
+}
@@ -1,36 +1,374 @@
-use std::collections::HashSet;
-use std::path::PathBuf;
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::io::Read;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 use walkdir::DirEntry;
-use super::filters::is_hidden;
+use ignore::DirEntry as IgnoreDirEntry;
+use super::cancel::cancelled;
+use super::filters::{is_hidden, CaseMode, ShowOptions};
+use super::tree::IgnoreOptions;
 use rayon::prelude::*;
-use dashmap::DashSet;
+use dashmap::{DashMap, DashSet};
 use aho_corasick::AhoCorasick;
 
-/// Build a set of directories that should be shown based on search pattern
-/// 
+/// What part of an entry a search pattern is matched against
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MatchTarget {
+    /// The entry's file name only
+    #[default]
+    Name,
+    /// The entry's path relative to the search root
+    Path,
+    /// The contents of regular files
+    Content,
+}
+
+/// Default size above which files aren't read by content search or previews (`--max-filesize`)
+pub const MAX_CONTENT_BYTES: u64 = 10 * 1024 * 1024;
+
+/// How many leading bytes are inspected when deciding whether a file is binary
+pub const BINARY_SNIFF_BYTES: usize = 8 * 1024;
+
+/// A search pattern together with how and where it is matched
+#[derive(Debug, Clone)]
+pub struct SearchQuery<'a> {
+    /// Original pattern as typed by the user
+    pattern: String,
+    /// Matcher for the pattern, built once and rebuilt when the case mode changes
+    matcher: AhoCorasick,
+    /// How subjects are folded before the matcher sees them, derived from the case options
+    fold: CaseFold,
+    /// How the pattern treats letter case
+    pub case: CaseMode,
+    /// Fold only ASCII letters when ignoring case, even for a non-ASCII pattern (`--ascii-case`)
+    pub ascii_case: bool,
+    /// What the pattern is matched against
+    pub target: MatchTarget,
+    /// Whether content match counts are shown next to matching files
+    pub show_counts: bool,
+    /// Stop after this many matching entries (ancestors are not counted)
+    pub max_results: Option<usize>,
+    /// Root that relative paths are computed from
+    pub root: &'a Path,
+    /// Ignore files honored by the search walk
+    pub ignore: IgnoreOptions,
+    /// Files above this size aren't read by content search; `None` reads every file
+    pub max_filesize: Option<u64>,
+    /// Files content search skipped for their size, shared by every clone of the query
+    pub too_large: Arc<DashSet<PathBuf>>,
+    /// Walk the candidates on several threads (`search --parallel`)
+    pub parallel: bool,
+}
+
+impl<'a> SearchQuery<'a> {
+    /// Create a name search for `pattern` under `root`
+    pub fn new(pattern: &str, root: &'a Path) -> Self {
+        let case = CaseMode::default();
+        let fold = CaseFold::new(pattern, case, false);
+        Self {
+            pattern: pattern.to_string(),
+            matcher: build_matcher(pattern, fold),
+            fold,
+            case,
+            ascii_case: false,
+            target: MatchTarget::Name,
+            show_counts: false,
+            max_results: None,
+            root,
+            ignore: IgnoreOptions::default(),
+            max_filesize: Some(MAX_CONTENT_BYTES),
+            too_large: Arc::default(),
+            parallel: false,
+        }
+    }
+
+    /// Match letter case as `case` decides instead of always ignoring it
+    pub fn with_case(mut self, case: CaseMode) -> Self {
+        self.case = case;
+        self.rebuild_matcher()
+    }
+
+    /// Fold only ASCII letters when ignoring case, the faster path for huge trees
+    pub fn with_ascii_case(mut self, ascii_case: bool) -> Self {
+        self.ascii_case = ascii_case;
+        self.rebuild_matcher()
+    }
+
+    fn rebuild_matcher(mut self) -> Self {
+        self.fold = CaseFold::new(&self.pattern, self.case, self.ascii_case);
+        self.matcher = build_matcher(&self.pattern, self.fold);
+        self
+    }
+
+    /// Walk the candidates on several threads; they are sorted into walk order afterwards
+    pub fn with_parallel(mut self, parallel: bool) -> Self {
+        self.parallel = parallel;
+        self
+    }
+
+    /// Skip reading files larger than `max_filesize` bytes in content search (`None` for no limit)
+    pub fn with_max_filesize(mut self, max_filesize: Option<u64>) -> Self {
+        self.max_filesize = max_filesize;
+        self
+    }
+
+    /// Use different ignore-file rules for the search walk
+    pub fn with_ignore(mut self, ignore: IgnoreOptions) -> Self {
+        self.ignore = ignore;
+        self
+    }
+
+    /// Match against a different part of each entry
+    pub fn with_target(mut self, target: MatchTarget) -> Self {
+        self.target = target;
+        self
+    }
+
+    /// Show content match counts next to matching files
+    pub fn with_counts(mut self, show_counts: bool) -> Self {
+        self.show_counts = show_counts;
+        self
+    }
+
+    /// Keep at most `max_results` matches, in walk order
+    pub fn with_max_results(mut self, max_results: Option<usize>) -> Self {
+        self.max_results = max_results;
+        self
+    }
+
+    /// The pattern as typed by the user
+    pub fn pattern(&self) -> &str {
+        &self.pattern
+    }
+
+    /// The text the pattern is matched against for `path`
+    ///
+    /// Relative paths always use `/` separators so patterns behave the same on every platform.
+    /// Content searches fall back to the name, which is only used for display.
+    pub fn subject<'p>(&self, path: &'p Path) -> Cow<'p, str> {
+        match self.target {
+            MatchTarget::Name | MatchTarget::Content => path
+                .file_name()
+                .map(|n| n.to_string_lossy())
+                .unwrap_or_default(),
+            MatchTarget::Path => {
+                let relative = path.strip_prefix(self.root).unwrap_or(path).to_string_lossy();
+                if std::path::MAIN_SEPARATOR == '/' {
+                    relative
+                } else {
+                    Cow::Owned(relative.replace(std::path::MAIN_SEPARATOR, "/"))
+                }
+            }
+        }
+    }
+
+    /// The raw bytes a name or path pattern is matched against
+    ///
+    /// Unix exposes the bytes of names that aren't valid UTF-8, so those match
+    /// exactly instead of through `U+FFFD` replacements; elsewhere this is [`Self::subject`].
+    fn subject_bytes<'p>(&self, path: &'p Path) -> Cow<'p, [u8]> {
+        #[cfg(unix)]
+        {
+            use std::os::unix::ffi::OsStrExt;
+            let raw = match self.target {
+                MatchTarget::Name | MatchTarget::Content => path.file_name().unwrap_or_default(),
+                MatchTarget::Path => path.strip_prefix(self.root).unwrap_or(path).as_os_str(),
+            };
+            Cow::Borrowed(raw.as_bytes())
+        }
+        #[cfg(not(unix))]
+        match self.subject(path) {
+            Cow::Borrowed(text) => Cow::Borrowed(text.as_bytes()),
+            Cow::Owned(text) => Cow::Owned(text.into_bytes()),
+        }
+    }
+
+    /// Check whether the entry at `path` matches
+    ///
+    /// Content searches read the file; prefer [`SearchFilter::is_visible`] which caches the result.
+    pub fn is_match(&self, path: &Path) -> bool {
+        match self.target {
+            MatchTarget::Content => self.count_content_matches(path).unwrap_or(0) > 0,
+            _ => self.pattern.is_empty() || self.matcher.is_match(self.fold.apply(self.subject_bytes(path)).as_ref()),
+        }
+    }
+
+    /// Count pattern occurrences in a file's contents
+    ///
+    /// Returns `None` for files that are skipped: unreadable, larger than
+    /// `max_filesize` (noted in [`Self::too_large`]), or binary (a NUL byte near the start).
+    pub fn count_content_matches(&self, path: &Path) -> Option<usize> {
+        // Checked before opening, so huge files cost a stat and nothing more
+        let meta = std::fs::metadata(path).ok()?;
+        if !meta.is_file() {
+            return None;
+        }
+        // Reported once, after the results, as `[skipped: too large]`
+        if self.max_filesize.is_some_and(|max| meta.len() > max) {
+            self.too_large.insert(path.to_path_buf());
+            return None;
+        }
+
+        let mut bytes = Vec::with_capacity(meta.len() as usize);
+        std::fs::File::open(path).ok()?.read_to_end(&mut bytes).ok()?;
+        let sniff = &bytes[..bytes.len().min(BINARY_SNIFF_BYTES)];
+        if sniff.contains(&0) {
+            return None;
+        }
+
+        Some(self.matcher.find_iter(self.fold.apply(Cow::Owned(bytes)).as_ref()).count())
+    }
+
+    /// Byte range of the match that falls within the entry's file name, if any
+    ///
+    /// For path matches only the part of the match inside the final component is returned.
+    pub fn name_match(&self, path: &Path) -> Option<Range<usize>> {
+        if self.target == MatchTarget::Content {
+            return None;
+        }
+        let subject = self.subject(path);
+        let range = find_match(&subject, &self.pattern, self.fold)?;
+        let name_len = path.file_name().map(|n| n.to_string_lossy().len()).unwrap_or(0);
+        let name_start = subject.len().saturating_sub(name_len);
+        if range.end <= name_start {
+            return None;
+        }
+        Some(range.start.max(name_start) - name_start..range.end - name_start)
+    }
+}
+
+/// Result of the search pre-pass: which entries are visible and why
+#[derive(Debug, Default)]
+pub struct SearchFilter {
+    /// Entries that matched the query themselves
+    pub matches: HashSet<PathBuf>,
+    /// Ancestor directories of every match
+    pub show_dirs: HashSet<PathBuf>,
+    /// Files whose contents matched, with their match counts
+    pub content_matches: HashMap<PathBuf, usize>,
+    /// Whether matches were dropped because of `max_results`
+    pub truncated: bool,
+    /// Whether this filter restricts the output (set for searches and [`ShowOptions::narrows`])
+    pub active: bool,
+}
+
+impl SearchFilter {
+    /// Filter for the options that list only some entries, plus their ancestors (see [`ShowOptions::narrows`]):
+    /// entries at or below a hidden component with `--hidden-only`, empty directories with `--empty-dirs`,
+    /// uncommitted files with `--git-modified`, entries within `--depth` of a project or the root with
+    /// `--depth-from-marker`
+    pub fn narrowed<'p>(root: &Path, show: &ShowOptions, paths: impl IntoIterator<Item = &'p Path>) -> Self {
+        let matches: HashSet<PathBuf> = paths
+            .into_iter()
+            .filter(|p| show.narrowing_rule(root, p).is_none())
+            .map(Path::to_path_buf)
+            .collect();
+        let show_dirs = matches
+            .iter()
+            .flat_map(|p| p.ancestors().skip(1))
+            .map(Path::to_path_buf)
+            .collect();
+        Self {
+            matches,
+            show_dirs,
+            active: true,
+            ..Self::default()
+        }
+    }
+
+    /// Check whether `path` should be shown: it matched, or leads to a match
+    ///
+    /// Only entries seen by the search walker can be visible, so callers that
+    /// render from a different walk stay consistent with the search.
+    pub fn is_visible(&self, path: &Path) -> bool {
+        self.matches.contains(path) || self.show_dirs.contains(path)
+    }
+
+    /// Number of content matches recorded for `path`
+    pub fn match_count(&self, path: &Path) -> Option<usize> {
+        self.content_matches.get(path).copied()
+    }
+
+    /// Match count to print next to `path`, when the query asks for counts
+    pub fn displayed_match_count(&self, path: &Path, query: &SearchQuery) -> Option<usize> {
+        if query.show_counts {
+            self.match_count(path)
+        } else {
+            None
+        }
+    }
+
+    /// Keep only the first `max` matches in `entries` order, rebuilding ancestors to match
+    fn truncate(&mut self, entries: &[IgnoreDirEntry], max: usize) {
+        if self.matches.len() <= max {
+            return;
+        }
+
+        let kept: HashSet<PathBuf> = entries
+            .iter()
+            .map(|e| e.path())
+            .filter(|p| self.matches.contains(*p))
+            .take(max)
+            .map(Path::to_path_buf)
+            .collect();
+
+        self.show_dirs = kept
+            .iter()
+            .flat_map(|p| p.ancestors().skip(1))
+            .map(Path::to_path_buf)
+            .collect();
+        self.content_matches.retain(|p, _| kept.contains(p));
+        self.matches = kept;
+        self.truncated = true;
+    }
+
+    /// Paths of every directly matching entry (not ancestors), sorted
+    pub fn matched_paths(&self) -> Vec<PathBuf> {
+        let mut paths: Vec<PathBuf> = self.matches.iter().cloned().collect();
+        paths.sort();
+        paths
+    }
+}
+
+/// Build the set of entries that should be shown based on search pattern
+///
+/// `entries` should come from [`search_candidates`](super::tree::search_candidates),
+/// which already applies hidden, gitignore and skip-list rules.
+///
 /// When searching, we need to show:
 /// 1. Files/dirs that match the pattern
 /// 2. All parent directories leading to matches
-pub fn build_search_filter(
-    entries: &[DirEntry],
-    pattern: &str,
-    show_hidden: bool,
-) -> HashSet<PathBuf> {
-    // Lowercase pattern once using ASCII for speed; build fast matcher
-    let pattern_lower = pattern.to_ascii_lowercase();
-    let matcher = AhoCorasick::new([pattern_lower.clone()]).expect("failed to build matcher");
-
-    // Concurrent set to collect parent directories without intermediate Vecs
+pub fn build_search_filter(entries: &[IgnoreDirEntry], query: &SearchQuery) -> SearchFilter {
+    // Concurrent sets to collect results without intermediate Vecs
+    let matches = DashSet::new();
     let show_dirs = DashSet::new();
+    let content_matches = DashMap::new();
 
     entries.par_iter().for_each(|entry| {
-        // Skip hidden directories entirely when searching, unless explicitly showing hidden
-        if entry.file_type().is_dir() && !show_hidden && is_hidden(entry) {
+        // Content searches read every file, so they stop promptly too
+        if cancelled().is_some() {
             return;
         }
-        let name = entry.file_name().to_string_lossy();
-        let name_lc = name.to_ascii_lowercase();
-        if matcher.is_match(&name_lc) {
+        let matched = match query.target {
+            MatchTarget::Content => {
+                if !entry.file_type().map(|ft| ft.is_file()).unwrap_or(false) {
+                    return;
+                }
+                match query.count_content_matches(entry.path()) {
+                    Some(count) if count > 0 => {
+                        content_matches.insert(entry.path().to_path_buf(), count);
+                        true
+                    }
+                    _ => false,
+                }
+            }
+            _ => query.is_match(entry.path()),
+        };
+
+        if matched {
+            matches.insert(entry.path().to_path_buf());
             // Insert parent chain directly into concurrent set
             let mut path = entry.path();
             while let Some(parent) = path.parent() {
@@ -40,28 +378,263 @@ pub fn build_search_filter(
         }
     });
 
-    // Convert to HashSet for downstream usage
-    show_dirs.into_iter().collect()
+    let mut filter = SearchFilter {
+        matches: matches.into_iter().collect(),
+        show_dirs: show_dirs.into_iter().collect(),
+        content_matches: content_matches.into_iter().collect(),
+        truncated: false,
+        active: true,
+    };
+    if let Some(max) = query.max_results {
+        filter.truncate(entries, max);
+    }
+    filter
 }
 
-/// Check if an entry should be printed based on search criteria
+/// Check if an entry should be printed based on search criteria and the active filter
 pub fn should_print_entry(
     entry: &DirEntry,
-    search_pattern: Option<&str>,
-    show_dirs: &HashSet<PathBuf>,
+    search: Option<&SearchQuery>,
+    filter: &SearchFilter,
     show_hidden: bool,
 ) -> bool {
-    match search_pattern {
-        Some(pattern) => {
+    match search {
+        Some(_) => {
             // Do not print hidden directories while searching unless overridden
             if entry.file_type().is_dir() && !show_hidden && is_hidden(entry) {
                 return false;
             }
-            let name = entry.file_name().to_string_lossy();
-            let name_lc = name.to_ascii_lowercase();
-            let pattern_lower = pattern.to_ascii_lowercase();
-            name_lc.contains(&pattern_lower) || show_dirs.contains(entry.path())
+            filter.is_visible(entry.path())
         }
+        None if filter.active => filter.is_visible(entry.path()),
         None => true,
     }
 }
+
+/// How letter case is folded before matching
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CaseFold {
+    /// No folding: letters match exactly
+    Exact,
+    /// ASCII letters only, done by the matcher itself
+    Ascii,
+    /// Every letter, lowercased with Unicode's (locale-independent) mappings
+    Unicode,
+}
+
+impl CaseFold {
+    /// Folding for `pattern`: Unicode when case is ignored, unless the pattern is pure ASCII or `ascii_case` asks for the fast path
+    ///
+    /// An ASCII pattern can only match ASCII letters, so skipping the Unicode
+    /// lowercasing of every subject doesn't change what it finds.
+    pub fn new(pattern: &str, case: CaseMode, ascii_case: bool) -> Self {
+        if !case.ignores_case(pattern) {
+            CaseFold::Exact
+        } else if ascii_case || pattern.is_ascii() {
+            CaseFold::Ascii
+        } else {
+            CaseFold::Unicode
+        }
+    }
+
+    /// Fold a subject for the matcher; bytes that aren't valid UTF-8 are kept as they are
+    fn apply(self, bytes: Cow<'_, [u8]>) -> Cow<'_, [u8]> {
+        if self != CaseFold::Unicode {
+            return bytes;
+        }
+        let mut folded = Vec::with_capacity(bytes.len());
+        for chunk in bytes.utf8_chunks() {
+            folded.extend_from_slice(fold_str(chunk.valid()).as_bytes());
+            folded.extend_from_slice(chunk.invalid());
+        }
+        Cow::Owned(folded)
+    }
+}
+
+/// Lowercase `text` char by char, so the result lines up with a per-char offset map
+fn fold_str(text: &str) -> String {
+    text.chars().flat_map(char::to_lowercase).collect()
+}
+
+/// Matcher for `pattern` under `fold`; a Unicode-folded pattern is matched exactly against folded subjects
+fn build_matcher(pattern: &str, fold: CaseFold) -> AhoCorasick {
+    let pattern = match fold {
+        CaseFold::Unicode => Cow::Owned(fold_str(pattern)),
+        _ => Cow::Borrowed(pattern),
+    };
+    AhoCorasick::builder()
+        .ascii_case_insensitive(fold == CaseFold::Ascii)
+        .build([pattern.as_ref()])
+        .expect("failed to build matcher")
+}
+
+/// Locate the byte range of the first match of `pattern` in `name`
+pub fn find_match(name: &str, pattern: &str, fold: CaseFold) -> Option<Range<usize>> {
+    if pattern.is_empty() {
+        return None;
+    }
+    match fold {
+        CaseFold::Exact => name.find(pattern).map(|start| start..start + pattern.len()),
+        CaseFold::Ascii => {
+            // ASCII lowercasing keeps byte offsets identical to the original name
+            let name_lc = name.to_ascii_lowercase();
+            let pattern_lc = pattern.to_ascii_lowercase();
+            name_lc
+                .find(&pattern_lc)
+                .map(|start| start..start + pattern_lc.len())
+        }
+        CaseFold::Unicode => {
+            // Lowercasing can change a char's length (`İ` becomes `i̇`), so
+            // each folded byte remembers where its original char starts
+            let mut folded = String::with_capacity(name.len());
+            let mut origin = Vec::with_capacity(name.len());
+            for (start, c) in name.char_indices() {
+                for lower in c.to_lowercase() {
+                    folded.push(lower);
+                    origin.resize(folded.len(), start);
+                }
+            }
+            let pattern = fold_str(pattern);
+            let start = folded.find(&pattern)?;
+            let last = origin[start + pattern.len() - 1];
+            let end = last + name[last..].chars().next().map_or(0, char::len_utf8);
+            Some(origin[start]..end)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::tree::UNLIMITED_DEPTH;
+
+    #[test]
+    fn test_find_match_is_case_insensitive() {
+        use CaseFold::*;
+        assert_eq!(find_match("Parser.rs", "pars", Ascii), Some(0..4));
+        assert_eq!(find_match("my_PARSER.rs", "parser", Ascii), Some(3..9));
+        assert_eq!(find_match("main.rs", "lib", Ascii), None);
+        assert_eq!(find_match("main.rs", "", Ascii), None);
+        assert_eq!(find_match("my_PARSER.rs", "parser", Exact), None);
+        assert_eq!(find_match("parser_PARSER.rs", "PARSER", Exact), Some(7..13));
+    }
+
+    #[test]
+    fn test_unicode_folding_matches_accented_and_turkish_letters() {
+        let root = Path::new(".");
+        let cafe = SearchQuery::new("café", root);
+        assert!(cafe.is_match(Path::new("./CAFÉ.txt")));
+        assert!(cafe.is_match(Path::new("./Café-menu.md")));
+        assert_eq!(cafe.name_match(Path::new("./le CAFÉ.txt")), Some(3..8));
+        // The ASCII fast path leaves non-ASCII letters alone
+        assert!(!cafe.clone().with_ascii_case(true).is_match(Path::new("./CAFÉ.txt")));
+        assert!(!cafe.with_case(CaseMode::Sensitive).is_match(Path::new("./CAFÉ.txt")));
+
+        // `İ` lowercases to two chars (`i` and a combining dot); ranges still cover whole original chars
+        let istanbul = SearchQuery::new("İstanbul", root);
+        assert!(istanbul.is_match(Path::new("./İSTANBUL.jpg")));
+        assert_eq!(istanbul.name_match(Path::new("./xİSTANBUL.jpg")), Some(1..10));
+        assert_eq!(find_match("İİ", "i\u{307}", CaseFold::Unicode), Some(0..2));
+        // Folding isn't tailored to Turkish: dotless `ı` and dotted `İ` stay apart from plain `I`
+        assert!(!istanbul.is_match(Path::new("./ISTANBUL.jpg")));
+        assert!(!SearchQuery::new("ıstanbul", root).is_match(Path::new("./ISTANBUL.jpg")));
+        assert!(SearchQuery::new("ISTANBUL", root).is_match(Path::new("./istanbul.jpg")));
+    }
+
+    #[test]
+    fn test_case_fold_picks_the_ascii_path_for_ascii_patterns() {
+        assert_eq!(CaseFold::new("readme", CaseMode::Insensitive, false), CaseFold::Ascii);
+        assert_eq!(CaseFold::new("résumé", CaseMode::Insensitive, false), CaseFold::Unicode);
+        assert_eq!(CaseFold::new("résumé", CaseMode::Insensitive, true), CaseFold::Ascii);
+        assert_eq!(CaseFold::new("Résumé", CaseMode::Smart, false), CaseFold::Exact);
+    }
+
+    #[test]
+    fn test_case_mode_governs_search() {
+        let root = Path::new(".");
+        let sensitive = SearchQuery::new("readme", root).with_case(CaseMode::Sensitive);
+        assert!(!sensitive.is_match(Path::new("./README.md")));
+        assert!(sensitive.is_match(Path::new("./readme.txt")));
+
+        let smart = SearchQuery::new("README", root).with_case(CaseMode::Smart);
+        assert!(smart.is_match(Path::new("./README.md")));
+        assert!(!smart.is_match(Path::new("./readme.txt")));
+        assert_eq!(smart.name_match(Path::new("./README.md")), Some(0..6));
+        assert!(SearchQuery::new("readme", root).with_case(CaseMode::Smart).is_match(Path::new("./README.md")));
+    }
+
+    #[test]
+    fn test_name_target_ignores_directories() {
+        let query = SearchQuery::new("core", Path::new("."));
+        assert!(!query.is_match(Path::new("./src/core/tree.rs")));
+        assert!(query.is_match(Path::new("./src/core")));
+    }
+
+    #[test]
+    fn test_path_target_matches_relative_path() {
+        let query = SearchQuery::new("src/core", Path::new("root")).with_target(MatchTarget::Path);
+        assert!(query.is_match(Path::new("root/src/core/tree.rs")));
+        assert!(!query.is_match(Path::new("root/src/cli/args.rs")));
+        // The root prefix itself is not part of the subject
+        let query = SearchQuery::new("root", Path::new("root")).with_target(MatchTarget::Path);
+        assert!(!query.is_match(Path::new("root/src")));
+    }
+
+    #[test]
+    fn test_content_matches_skip_binary_files() {
+        let dir = std::env::temp_dir().join(format!("lst-content-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("notes.txt"), "TODO one\ntodo two\nÜBER über\n").unwrap();
+        std::fs::write(dir.join("blob.bin"), b"todo\0todo").unwrap();
+
+        let query = SearchQuery::new("todo", &dir).with_target(MatchTarget::Content);
+        assert_eq!(query.count_content_matches(&dir.join("notes.txt")), Some(2));
+        assert_eq!(query.count_content_matches(&dir.join("blob.bin")), None);
+        let umlaut = SearchQuery::new("über", &dir).with_target(MatchTarget::Content);
+        assert_eq!(umlaut.count_content_matches(&dir.join("notes.txt")), Some(2));
+        assert!(!query.is_match(&dir));
+        assert!(query.too_large.is_empty());
+
+        let small = query.clone().with_max_filesize(Some(8));
+        assert_eq!(small.count_content_matches(&dir.join("notes.txt")), None);
+        assert!(query.too_large.contains(&dir.join("notes.txt")), "clones share the list");
+        assert_eq!(small.with_max_filesize(None).count_content_matches(&dir.join("notes.txt")), Some(2));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_max_results_keeps_first_matches_and_their_ancestors() {
+        let dir = std::env::temp_dir().join(format!("lst-max-results-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        for sub in ["a", "b", "c"] {
+            std::fs::create_dir_all(dir.join(sub)).unwrap();
+            std::fs::write(dir.join(sub).join("hit.txt"), "").unwrap();
+        }
+
+        let entries = crate::core::tree::collect_search_entries(&dir, UNLIMITED_DEPTH, &crate::core::filters::ShowOptions::default(), &IgnoreOptions::default(), None);
+        let query = SearchQuery::new("hit", &dir).with_max_results(Some(2));
+        let filter = build_search_filter(&entries, &query);
+
+        assert!(filter.truncated);
+        assert_eq!(filter.matches.len(), 2);
+        let visible_dirs = ["a", "b", "c"]
+            .iter()
+            .filter(|sub| filter.is_visible(&dir.join(sub)))
+            .count();
+        assert_eq!(visible_dirs, 2);
+
+        let query = SearchQuery::new("hit", &dir).with_max_results(Some(3));
+        assert!(!build_search_filter(&entries, &query).truncated);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_name_match_clips_path_match_to_name() {
+        let query = SearchQuery::new("e/tr", Path::new(".")).with_target(MatchTarget::Path);
+        assert_eq!(query.name_match(Path::new("./core/tree.rs")), Some(0..2));
+        let query = SearchQuery::new("cor", Path::new(".")).with_target(MatchTarget::Path);
+        assert_eq!(query.name_match(Path::new("./core/tree.rs")), None);
+    }
+}
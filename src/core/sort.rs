@@ -0,0 +1,439 @@
+use std::cmp::{Ordering, Reverse};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use super::meta::TimeField;
+
+/// What siblings are ordered by (`--sort`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum SortKey {
+    /// Directory order as returned by the filesystem
+    #[default]
+    None,
+    /// Name, case-insensitively
+    Name,
+    /// Size, largest first
+    Size,
+    /// Modification time (or the `--time-field` time), newest first
+    #[value(alias = "mtime")]
+    Modified,
+    /// Extension, then name
+    Extension,
+    /// Name with runs of digits compared by value, like `ls -v`: `img2` before `img10`
+    #[value(alias = "version")]
+    Natural,
+}
+
+/// Sibling ordering, with separate keys for directories and files
+///
+/// Siblings equal under every key are ordered by name, so sorted output is deterministic.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct SortOptions {
+    pub dirs: SortKey,
+    pub files: SortKey,
+    /// Keys consulted in order when the primary key ties (`--sort size,extension`)
+    pub tiebreaks: Vec<SortKey>,
+    /// List directories before files (set when either type has its own key)
+    pub dirs_first: bool,
+    /// Date directories by their newest descendant rather than their own mtime
+    pub dir_mtime_recursive: bool,
+    /// Timestamp the time key compares (`--time-field`)
+    pub time_field: TimeField,
+}
+
+impl SortOptions {
+    /// Combine `--sort` with the `--sort-dirs` / `--sort-files` overrides
+    pub fn new(all: Option<SortKey>, dirs: Option<SortKey>, files: Option<SortKey>) -> Self {
+        let fallback = all.unwrap_or_default();
+        Self {
+            dirs: dirs.unwrap_or(fallback),
+            files: files.unwrap_or(fallback),
+            dirs_first: dirs.is_some() || files.is_some(),
+            tiebreaks: Vec::new(),
+            dir_mtime_recursive: false,
+            time_field: TimeField::Modified,
+        }
+    }
+
+    /// Compare times by `field` instead of the modification time (`--time-field`)
+    pub fn with_time_field(mut self, field: TimeField) -> Self {
+        self.time_field = field;
+        self
+    }
+
+    /// Break ties of the primary key with `keys`, in order
+    pub fn with_tiebreaks(mut self, keys: Vec<SortKey>) -> Self {
+        self.tiebreaks = keys;
+        self
+    }
+
+    /// Date directories by the newest mtime anywhere below them (`--dir-mtime-recursive`)
+    pub fn with_dir_mtime_recursive(mut self, enabled: bool) -> Self {
+        self.dir_mtime_recursive = enabled;
+        self
+    }
+
+    /// Whether ordering needs [`DirTimes`] gathered before the walk
+    pub fn needs_dir_times(&self) -> bool {
+        let uses_mtime = [self.dirs, self.files].iter().chain(&self.tiebreaks).any(|&k| k == SortKey::Modified);
+        self.dir_mtime_recursive && uses_mtime
+    }
+
+    /// Whether walks can keep the filesystem's order
+    pub fn is_unsorted(&self) -> bool {
+        self.dirs == SortKey::None && self.files == SortKey::None && !self.dirs_first
+    }
+
+    /// Order two siblings
+    pub fn compare(&self, a: &Path, a_is_dir: bool, b: &Path, b_is_dir: bool) -> Ordering {
+        self.compare_with(a, a_is_dir, b, b_is_dir, &DirTimes::default())
+    }
+
+    /// Order two siblings, dating directories from `times` where it has them
+    pub fn compare_with(&self, a: &Path, a_is_dir: bool, b: &Path, b_is_dir: bool, times: &DirTimes) -> Ordering {
+        self.key(a, a_is_dir, times).cmp(&self.key(b, b_is_dir, times))
+    }
+
+    /// What an entry is ordered by among its siblings, with its size and time read once
+    ///
+    /// Sorts compute it once per entry (`sort_by_cached_key`, [`SiblingKeys`]) rather
+    /// than stat-ing both sides of every comparison.
+    pub fn key(&self, path: &Path, is_dir: bool, times: &DirTimes) -> SiblingKey {
+        // Without `dirs_first` both types share the `--sort` key, so mixed siblings compare alike
+        let key = if is_dir { self.dirs } else { self.files };
+        let values = if key == SortKey::None {
+            Vec::new()
+        } else {
+            std::iter::once(key)
+                .chain(self.tiebreaks.iter().copied())
+                .filter_map(|key| match key {
+                    SortKey::None => None,
+                    SortKey::Name => Some(KeyValue::name(path)),
+                    SortKey::Size => Some(KeyValue::Size(Reverse(size_of(path)))),
+                    SortKey::Modified => Some(KeyValue::Time(Reverse(times.time(path, is_dir, self.time_field)))),
+                    SortKey::Extension => Some(KeyValue::Extension(extension_of(path))),
+                    SortKey::Natural => Some(KeyValue::Natural(NaturalName(name_of(path)))),
+                })
+                .chain(std::iter::once(KeyValue::name(path)))
+                .collect()
+        };
+        SiblingKey { after_dirs: self.dirs_first && !is_dir, values }
+    }
+}
+
+/// Sort position of an entry among its siblings, from [`SortOptions::key`]
+///
+/// Equal keys (every key of an unsorted type) keep the walk's order in a stable sort.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct SiblingKey {
+    /// A file listed after the directories (`--sort-dirs` / `--sort-files`)
+    after_dirs: bool,
+    /// The primary key, each tiebreak, then the name; empty when the type is unsorted
+    values: Vec<KeyValue>,
+}
+
+/// One sort key's value; siblings always hold the same kinds in the same order
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+enum KeyValue {
+    /// Case-insensitive name, then the exact name
+    Name(String, String),
+    /// Largest first
+    Size(Reverse<u64>),
+    /// Newest first
+    Time(Reverse<Option<SystemTime>>),
+    Extension(String),
+    Natural(NaturalName),
+}
+
+impl KeyValue {
+    fn name(path: &Path) -> Self {
+        let name = name_of(path);
+        KeyValue::Name(name.to_lowercase(), name)
+    }
+}
+
+/// A name ordered by [`natural_cmp`]
+#[derive(Debug, Clone)]
+struct NaturalName(String);
+
+impl PartialEq for NaturalName {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for NaturalName {}
+
+impl PartialOrd for NaturalName {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for NaturalName {
+    fn cmp(&self, other: &Self) -> Ordering {
+        natural_cmp(&self.0, &other.0)
+    }
+}
+
+/// Sibling keys cached for the directory being sorted, for sorts that only hand over pairs
+///
+/// walkdir and ignore compare siblings pairwise, so each entry's key is kept
+/// until the sort moves on to another directory.
+#[derive(Debug)]
+pub struct SiblingKeys {
+    sort: SortOptions,
+    times: Arc<DirTimes>,
+    dir: PathBuf,
+    keys: HashMap<PathBuf, SiblingKey>,
+}
+
+impl SiblingKeys {
+    pub fn new(sort: SortOptions, times: Arc<DirTimes>) -> Self {
+        Self { sort, times, dir: PathBuf::new(), keys: HashMap::new() }
+    }
+
+    /// Order two siblings, reading each one's key at most once
+    pub fn compare(&mut self, a: &Path, a_is_dir: bool, b: &Path, b_is_dir: bool) -> Ordering {
+        let parent = a.parent().unwrap_or(a);
+        if parent != self.dir {
+            self.dir = parent.to_path_buf();
+            self.keys.clear();
+        }
+        for (path, is_dir) in [(a, a_is_dir), (b, b_is_dir)] {
+            if !self.keys.contains_key(path) {
+                let key = self.sort.key(path, is_dir, &self.times);
+                self.keys.insert(path.to_path_buf(), key);
+            }
+        }
+        self.keys[a].cmp(&self.keys[b])
+    }
+}
+
+/// Newest time below each directory of a tree
+#[derive(Debug, Clone, Default)]
+pub struct DirTimes {
+    newest: HashMap<PathBuf, SystemTime>,
+}
+
+impl DirTimes {
+    /// Fold each entry's mtime into every directory above it, up to and including `root`
+    ///
+    /// `entries` yields every descendant of `root` with its mtime; a directory's
+    /// own mtime counts as well, so empty directories keep theirs.
+    pub fn from_entries(root: &Path, entries: impl IntoIterator<Item = (PathBuf, SystemTime)>) -> Self {
+        let mut newest: HashMap<PathBuf, SystemTime> = HashMap::new();
+        for (path, time) in entries {
+            for dir in path.ancestors() {
+                let slot = newest.entry(dir.to_path_buf()).or_insert(time);
+                *slot = (*slot).max(time);
+                if dir == root {
+                    break;
+                }
+            }
+        }
+        Self { newest }
+    }
+
+    /// Sort time of an entry: the aggregated time for known directories, else its own `field` time
+    fn time(&self, path: &Path, is_dir: bool, field: TimeField) -> Option<SystemTime> {
+        let aggregated = if is_dir { self.newest.get(path).copied() } else { None };
+        aggregated.or_else(|| std::fs::symlink_metadata(path).ok().and_then(|m| field.of(&m)))
+    }
+}
+
+fn name_of(path: &Path) -> String {
+    path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default()
+}
+
+/// Compare names chunk by chunk, runs of ASCII digits by their value and the rest case-insensitively
+///
+/// `a2` < `a10` < `a10b`; equal numbers with more leading zeros come later (`a1` < `a01`).
+pub fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let (mut a, mut b) = (a, b);
+    loop {
+        let (x, y) = match (a.chars().next(), b.chars().next()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) => (x, y),
+        };
+        let order = if x.is_ascii_digit() && y.is_ascii_digit() {
+            let (digits_a, rest_a) = split_digits(a);
+            let (digits_b, rest_b) = split_digits(b);
+            (a, b) = (rest_a, rest_b);
+            let (value_a, value_b) = (digits_a.trim_start_matches('0'), digits_b.trim_start_matches('0'));
+            value_a
+                .len()
+                .cmp(&value_b.len())
+                .then_with(|| value_a.cmp(value_b))
+                .then_with(|| digits_a.len().cmp(&digits_b.len()))
+        } else {
+            (a, b) = (&a[x.len_utf8()..], &b[y.len_utf8()..]);
+            x.to_lowercase().cmp(y.to_lowercase())
+        };
+        if order != Ordering::Equal {
+            return order;
+        }
+    }
+}
+
+/// The leading run of ASCII digits of `s`, and the rest
+fn split_digits(s: &str) -> (&str, &str) {
+    s.split_at(s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len()))
+}
+
+/// Files report their length; directories sort as empty
+fn size_of(path: &Path) -> u64 {
+    std::fs::symlink_metadata(path)
+        .ok()
+        .filter(|m| !m.is_dir())
+        .map(|m| m.len())
+        .unwrap_or(0)
+}
+
+fn extension_of(path: &Path) -> String {
+    path.extension().map(|e| e.to_string_lossy().to_lowercase()).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dirs_and_files_sorted_independently() {
+        let dir = std::env::temp_dir().join(format!("lst-sort-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("beta")).unwrap();
+        std::fs::create_dir_all(dir.join("Alpha")).unwrap();
+        std::fs::write(dir.join("a.txt"), "x").unwrap();
+        std::fs::write(dir.join("z.md"), "xxxxxxxx").unwrap();
+
+        let sorted = |sort: SortOptions| {
+            let mut names = vec!["z.md", "beta", "a.txt", "Alpha"];
+            names.sort_by(|a, b| {
+                let (a, b) = (dir.join(a), dir.join(b));
+                sort.compare(&a, a.is_dir(), &b, b.is_dir())
+            });
+            // The cached keys of pairwise sorts and the keys of `sort_by_cached_key` agree
+            let mut keys = SiblingKeys::new(sort.clone(), Arc::default());
+            let mut paired = names.clone();
+            paired.reverse();
+            paired.sort_by(|a, b| {
+                let (a, b) = (dir.join(a), dir.join(b));
+                keys.compare(&a, a.is_dir(), &b, b.is_dir())
+            });
+            let mut cached = paired.clone();
+            cached.sort_by_cached_key(|name| {
+                let path = dir.join(name);
+                sort.key(&path, path.is_dir(), &DirTimes::default())
+            });
+            if sort.files != SortKey::None {
+                assert_eq!(paired, names);
+                assert_eq!(cached, names);
+            }
+            names
+        };
+
+        assert_eq!(sorted(SortOptions::new(Some(SortKey::Name), None, None)), ["a.txt", "Alpha", "beta", "z.md"]);
+        assert_eq!(sorted(SortOptions::new(Some(SortKey::Size), None, None)), ["z.md", "a.txt", "Alpha", "beta"]);
+        assert_eq!(
+            sorted(SortOptions::new(Some(SortKey::Name), None, Some(SortKey::Size))),
+            ["Alpha", "beta", "z.md", "a.txt"]
+        );
+        // Files without a key keep their original order, after the directories
+        assert_eq!(sorted(SortOptions::new(None, Some(SortKey::Name), None)), ["Alpha", "beta", "z.md", "a.txt"]);
+        assert!(SortOptions::default().is_unsorted());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_ties_fall_back_to_tiebreaks_then_name() {
+        let dir = std::env::temp_dir().join(format!("lst-sort-ties-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        for (name, len) in [("b.txt", 0), ("A.md", 0), ("c.md", 0), ("big.rs", 4)] {
+            std::fs::write(dir.join(name), "x".repeat(len)).unwrap();
+        }
+        let sorted = |sort: SortOptions| {
+            let mut names = vec!["c.md", "b.txt", "big.rs", "A.md"];
+            names.sort_by(|a, b| sort.compare(&dir.join(a), false, &dir.join(b), false));
+            names
+        };
+
+        let by_size = SortOptions::new(Some(SortKey::Size), None, None);
+        assert_eq!(sorted(by_size.clone()), ["big.rs", "A.md", "b.txt", "c.md"]);
+        let by_size_then_ext = by_size.with_tiebreaks(vec![SortKey::Extension]);
+        assert_eq!(sorted(by_size_then_ext), ["big.rs", "A.md", "c.md", "b.txt"]);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_natural_order_compares_numbers_by_value() {
+        let mut names = vec!["a10b", "a10", "A2", "a1", "a01", "img10.png", "b", "a", "img2.png", "a10a"];
+        names.sort_by(|a, b| natural_cmp(a, b));
+        assert_eq!(names, ["a", "a1", "a01", "A2", "a10", "a10a", "a10b", "b", "img2.png", "img10.png"]);
+        // Digit runs too long for any integer type still compare
+        assert_eq!(natural_cmp("v99999999999999999999999", "v100000000000000000000000"), Ordering::Less);
+
+        let sort = SortOptions::new(Some(SortKey::Natural), None, None);
+        assert_eq!(sort.compare(Path::new("x/img2.png"), false, Path::new("x/img10.png"), false), Ordering::Less);
+    }
+
+    #[test]
+    fn test_recursive_dir_times_use_newest_descendant() {
+        let root = Path::new("/p");
+        let at = |secs| SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(secs);
+        let times = DirTimes::from_entries(
+            root,
+            [
+                (PathBuf::from("/p/old"), at(10)),
+                (PathBuf::from("/p/old/deep/touched.rs"), at(500)),
+                (PathBuf::from("/p/new"), at(100)),
+                (PathBuf::from("/p/new/file"), at(90)),
+            ],
+        );
+        assert_eq!(times.time(Path::new("/p/old"), true, TimeField::Modified), Some(at(500)));
+        assert_eq!(times.time(Path::new("/p/old/deep"), true, TimeField::Modified), Some(at(500)));
+        assert_eq!(times.time(Path::new("/p/new"), true, TimeField::Modified), Some(at(100)));
+        assert_eq!(times.time(root, true, TimeField::Modified), Some(at(500)));
+
+        let sort = SortOptions::new(Some(SortKey::Modified), None, None).with_dir_mtime_recursive(true);
+        assert!(sort.needs_dir_times());
+        let order = sort.compare_with(Path::new("/p/old"), true, Path::new("/p/new"), true, &times);
+        assert_eq!(order, Ordering::Less, "the directory with the newest descendant comes first");
+        assert!(!SortOptions::new(Some(SortKey::Name), None, None).with_dir_mtime_recursive(true).needs_dir_times());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_time_sort_follows_the_time_field() {
+        use std::fs::{File, FileTimes};
+
+        use crate::core::meta::MetadataSource;
+
+        let dir = std::env::temp_dir().join(format!("lst-sort-time-field-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let at = |secs| SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(secs);
+        // `a` was modified last, `b` read last
+        for (name, modified, accessed) in [("a", 2_000, 1_000), ("b", 1_000, 2_000)] {
+            let file = File::create(dir.join(name)).unwrap();
+            file.set_times(FileTimes::new().set_modified(at(modified)).set_accessed(at(accessed))).unwrap();
+        }
+        let (a, b) = (dir.join("a"), dir.join("b"));
+
+        let by_time = SortOptions::new(Some(SortKey::Modified), None, None);
+        assert_eq!(by_time.compare(&a, false, &b, false), Ordering::Less);
+        let by_access = by_time.with_time_field(TimeField::Accessed);
+        assert_eq!(by_access.compare(&a, false, &b, false), Ordering::Greater);
+
+        let meta = crate::core::meta::FsMetadata.stat(&a).unwrap();
+        assert_eq!((meta.time(TimeField::Modified), meta.time(TimeField::Accessed)), (Some(at(2_000)), Some(at(1_000))));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,199 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use super::meta::EntryMeta;
+
+/// Group name for files without an extension
+pub const NO_EXTENSION: &str = "(none)";
+
+/// File count and total size for one extension
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct ExtensionStats {
+    pub files: usize,
+    pub size: u64,
+}
+
+/// Directories and files listed below the root, for `--report` (the root itself isn't counted)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct Summary {
+    pub directories: usize,
+    pub files: usize,
+    /// Listed mount points that `--one-file-system` didn't descend into
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub mount_points: usize,
+}
+
+fn is_zero(n: &usize) -> bool {
+    *n == 0
+}
+
+impl Summary {
+    /// Count one listed entry
+    pub fn add(&mut self, is_dir: bool, mount_point: bool) {
+        if is_dir {
+            self.directories += 1;
+        } else {
+            self.files += 1;
+        }
+        if mount_point {
+            self.mount_points += 1;
+        }
+    }
+}
+
+impl fmt::Display for Summary {
+    /// `tree`'s closing line: `3 directories, 1 file`, noting any `--one-file-system`
+    /// mount points: `3 directories, 1 file (2 mount points not entered)`
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let plural = |n: usize, one: &'static str, many: &'static str| if n == 1 { one } else { many };
+        write!(
+            f,
+            "{} {}, {} {}",
+            self.directories,
+            plural(self.directories, "directory", "directories"),
+            self.files,
+            plural(self.files, "file", "files")
+        )?;
+        if self.mount_points > 0 {
+            write!(f, " ({} {} not entered)", self.mount_points, plural(self.mount_points, "mount point", "mount points"))?;
+        }
+        Ok(())
+    }
+}
+
+/// Files gathered while a tree is written, for a `--stats` table without walking again
+#[derive(Debug, Default)]
+pub struct StatsTally {
+    files: Vec<PathBuf>,
+    metas: HashMap<PathBuf, EntryMeta>,
+}
+
+impl StatsTally {
+    /// Count a written file, with its metadata when it was read
+    pub fn add(&mut self, path: &Path, meta: Option<EntryMeta>) {
+        self.files.push(path.to_path_buf());
+        if let Some(meta) = meta {
+            self.metas.insert(path.to_path_buf(), meta);
+        }
+    }
+
+    /// The files counted so far, aggregated as [`extension_stats`] does
+    pub fn finish(&self, count_links: bool) -> Vec<(String, ExtensionStats)> {
+        extension_stats(self.files.iter().map(PathBuf::as_path), &self.metas, count_links)
+    }
+}
+
+/// Aggregate files by extension, largest total size first
+///
+/// `files` yields each file's path; sizes come from `metas` (missing entries count as 0 bytes).
+/// Like `du`, a file reached through several hard links adds its size once,
+/// unless `count_links` asks for every link to count.
+pub fn extension_stats<'a>(
+    files: impl IntoIterator<Item = &'a Path>,
+    metas: &HashMap<PathBuf, EntryMeta>,
+    count_links: bool,
+) -> Vec<(String, ExtensionStats)> {
+    let mut groups: HashMap<String, ExtensionStats> = HashMap::new();
+    let mut seen_links = HashSet::new();
+    for path in files {
+        let group = groups.entry(extension_group(path)).or_default();
+        group.files += 1;
+        let meta = metas.get(path);
+        let repeated_link = !count_links && meta.and_then(|m| m.link_id).is_some_and(|id| !seen_links.insert(id));
+        if !repeated_link {
+            group.size += meta.map(|m| m.size).unwrap_or(0);
+        }
+    }
+
+    let mut stats: Vec<(String, ExtensionStats)> = groups.into_iter().collect();
+    stats.sort_by(|a, b| b.1.size.cmp(&a.1.size).then_with(|| a.0.cmp(&b.0)));
+    stats
+}
+
+/// Number of files per extension, most common first (ties by extension)
+pub fn extension_counts<'a>(files: impl IntoIterator<Item = &'a Path>) -> Vec<(String, usize)> {
+    let mut counts: HashMap<String, usize> = HashMap::new();
+    for path in files {
+        *counts.entry(extension_group(path)).or_default() += 1;
+    }
+    let mut counts: Vec<(String, usize)> = counts.into_iter().collect();
+    counts.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    counts
+}
+
+/// Lowercase extension of `path`, or [`NO_EXTENSION`]
+fn extension_group(path: &Path) -> String {
+    path.extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .unwrap_or_else(|| NO_EXTENSION.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extension_stats_groups_and_sorts_by_size() {
+        let paths = [
+            PathBuf::from("a.rs"),
+            PathBuf::from("b.RS"),
+            PathBuf::from("notes.md"),
+            PathBuf::from("Makefile"),
+        ];
+        let metas: HashMap<PathBuf, EntryMeta> = [("a.rs", 10), ("b.RS", 5), ("notes.md", 100)]
+            .into_iter()
+            .map(|(p, size)| (PathBuf::from(p), EntryMeta { size, ..EntryMeta::default() }))
+            .collect();
+
+        let stats = extension_stats(paths.iter().map(PathBuf::as_path), &metas, false);
+        assert_eq!(
+            stats,
+            [
+                ("md".to_string(), ExtensionStats { files: 1, size: 100 }),
+                ("rs".to_string(), ExtensionStats { files: 2, size: 15 }),
+                (NO_EXTENSION.to_string(), ExtensionStats { files: 1, size: 0 }),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_summary_reads_like_tree() {
+        assert_eq!(Summary { directories: 3, files: 1, mount_points: 0 }.to_string(), "3 directories, 1 file");
+        assert_eq!(Summary { directories: 1, files: 0, mount_points: 0 }.to_string(), "1 directory, 0 files");
+        let mounts = Summary { directories: 3, files: 1, mount_points: 2 };
+        assert_eq!(mounts.to_string(), "3 directories, 1 file (2 mount points not entered)");
+    }
+
+    #[test]
+    fn test_extension_counts_most_common_first() {
+        let paths = ["b.md", "a.RS", "c.rs", "Makefile", "d.md", "e.txt"].map(PathBuf::from);
+        let counts = extension_counts(paths.iter().map(PathBuf::as_path));
+        let expected = [("md", 2), ("rs", 2), (NO_EXTENSION, 1), ("txt", 1)].map(|(e, n)| (e.to_string(), n));
+        assert_eq!(counts, expected);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_hard_links_count_once_unless_asked() {
+        use crate::core::meta::{FsMetadata, MetadataSource};
+
+        let dir = std::env::temp_dir().join(format!("lst-links-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let (a, b) = (dir.join("a.bin"), dir.join("b.bin"));
+        std::fs::write(&a, [0u8; 100]).unwrap();
+        std::fs::hard_link(&a, &b).unwrap();
+        let paths = [a, b];
+        let metas: HashMap<PathBuf, EntryMeta> =
+            paths.iter().map(|p| (p.clone(), FsMetadata.stat(p).unwrap())).collect();
+
+        let deduped = extension_stats(paths.iter().map(PathBuf::as_path), &metas, false);
+        assert_eq!(deduped, [("bin".to_string(), ExtensionStats { files: 2, size: 100 })]);
+        let naive = extension_stats(paths.iter().map(PathBuf::as_path), &metas, true);
+        assert_eq!(naive, [("bin".to_string(), ExtensionStats { files: 2, size: 200 })]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,81 @@
+use std::io::Write;
+use std::time::{Duration, Instant};
+
+/// Where a run's time went, for `--time-report`
+///
+/// Filled by every collection of a run and summed, so a tree collected twice
+/// (e.g. once more for `--stats`) reports both passes.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Timings {
+    /// Walking the tree for the entries to list
+    pub walk: Duration,
+    /// The search pre-pass: walking the candidates and matching them
+    pub search: Duration,
+    /// Stat-ing entries for sizes and columns
+    pub metadata: Duration,
+    /// Entries read from the filesystem, across all walks
+    pub entries: usize,
+}
+
+impl Timings {
+    /// Run `f`, adding its duration to the phase picked by `phase`
+    pub fn time<T>(&mut self, phase: fn(&mut Self) -> &mut Duration, f: impl FnOnce() -> T) -> T {
+        let started = Instant::now();
+        let out = f();
+        *phase(self) += started.elapsed();
+        out
+    }
+
+    pub fn add(&mut self, other: &Timings) {
+        self.walk += other.walk;
+        self.search += other.search;
+        self.metadata += other.metadata;
+        self.entries += other.entries;
+    }
+
+    /// Time of the collection phases; the rest of a run is rendering
+    fn collection(&self) -> Duration {
+        self.walk + self.search + self.metadata
+    }
+}
+
+/// Print the `--time-report` table for a run that took `total`
+///
+/// Rendering is whatever `total` leaves after collection; streamed text output
+/// walks while it renders, so its walk shows up there.
+pub fn write_time_report<W: Write>(writer: &mut W, timings: &Timings, total: Duration) -> std::io::Result<()> {
+    let rows = [
+        ("walk", timings.walk),
+        ("search", timings.search),
+        ("metadata", timings.metadata),
+        ("render", total.saturating_sub(timings.collection())),
+        ("total", total),
+    ];
+    for (phase, time) in rows {
+        writeln!(writer, "{:<9}{:>12}", phase, format!("{:.2?}", time))?;
+    }
+    writeln!(writer, "{:<9}{:>12}", "entries", timings.entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_report_attributes_the_rest_to_rendering() {
+        let mut timings = Timings { entries: 42, ..Timings::default() };
+        timings.time(|t| &mut t.walk, || std::thread::sleep(Duration::from_millis(2)));
+        assert!(timings.walk >= Duration::from_millis(2));
+        timings.walk = Duration::from_millis(3);
+        timings.add(&Timings { search: Duration::from_millis(1), entries: 8, ..Timings::default() });
+
+        let mut out = Vec::new();
+        write_time_report(&mut out, &timings, Duration::from_millis(10)).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(lines.len(), 6);
+        assert_eq!(lines[0], "walk           3.00ms");
+        assert!(lines[3].starts_with("render") && lines[3].ends_with("6.00ms"));
+        assert_eq!(lines[5], "entries            50");
+    }
+}
@@ -1,15 +1,596 @@
-use std::path::Path;
+use std::collections::{HashMap, HashSet};
+use std::fmt::Display;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+use ignore::{WalkBuilder, WalkState};
+use rayon::prelude::*;
 use walkdir::{DirEntry, WalkDir};
 
-use super::filters::should_show_entry;
+use super::cancel::cancelled;
+use super::filters::{
+    entry_visibility, has_hidden_component, log_rule, visibility_rule, Rule, ShowOptions, Visibility,
+};
+use super::meta::{EntryMeta, FsMetadata, MetaRead, MetadataSource};
+use super::search::SearchQuery;
+use super::sort::{DirTimes, SiblingKey, SiblingKeys, SortOptions};
 
-/// Collect directory entries for the given path with specified depth and visibility options
-pub fn collect_entries(path: &Path, max_depth: usize, show_hidden: bool) -> Vec<DirEntry> {
-    WalkDir::new(path)
+/// Walk of the entries below a root that applies the visibility rules
+///
+/// Hidden entries are pruned with their subtrees; collapsed directories are
+/// yielded without descending into them. The walk ends early once it's
+/// [cancelled](super::cancel::cancelled).
+pub struct VisibleWalk {
+    inner: walkdir::IntoIter,
+    show: ShowOptions,
+    times: Arc<DirTimes>,
+}
+
+impl VisibleWalk {
+    /// A tracker of which entry of this walk comes last among its siblings
+    pub fn last_children(&self) -> LastChildren {
+        LastChildren { show: self.show.clone(), times: self.times.clone(), stack: Vec::new() }
+    }
+}
+
+/// The last listed child of each directory on a streaming walk's current path
+///
+/// A directory's children are read and sorted once, when the walk first reaches one
+/// of them, so an entry can be drawn as the last one before its subtree is walked.
+pub struct LastChildren {
+    show: ShowOptions,
+    times: Arc<DirTimes>,
+    /// Each directory from the root down, with its last listed child
+    stack: Vec<(PathBuf, Option<PathBuf>)>,
+}
+
+impl LastChildren {
+    /// Whether `path`, `depth` levels below the root, is the last listed entry of its directory
+    pub fn is_last(&mut self, path: &Path, depth: usize) -> bool {
+        let Some(parent) = path.parent() else { return true };
+        self.stack.truncate(depth);
+        if self.stack.get(depth - 1).is_none_or(|(dir, _)| dir != parent) {
+            let last = self.last_child(parent, depth);
+            self.stack.resize(depth - 1, (PathBuf::new(), None));
+            self.stack.push((parent.to_path_buf(), last));
+        }
+        self.stack[depth - 1].1.as_deref().is_none_or(|last| last == path)
+    }
+
+    /// The child of `dir` the walk lists last, sorted the way the walk sorts
+    fn last_child(&self, dir: &Path, depth: usize) -> Option<PathBuf> {
+        let listed = std::fs::read_dir(dir).ok()?.filter_map(|e| {
+            let e = e.ok()?;
+            let is_dir = e.file_type().ok()?.is_dir();
+            let (visibility, _) = visibility_rule(&e.path(), depth, is_dir, &self.show);
+            (visibility != Visibility::Hidden).then(|| (e.path(), is_dir))
+        });
+        let last = if self.show.sort.is_unsorted() {
+            listed.last()
+        } else {
+            listed.max_by_key(|(path, is_dir)| self.show.sort.key(path, *is_dir, &self.times))
+        };
+        last.map(|(path, _)| path)
+    }
+}
+
+impl Iterator for VisibleWalk {
+    type Item = walkdir::Result<DirEntry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if cancelled().is_some() {
+                return None;
+            }
+            let entry = match self.inner.next()? {
+                Ok(entry) => entry,
+                Err(e) => return Some(Err(e)),
+            };
+            match entry_visibility(&entry, &self.show) {
+                Visibility::Shown => return Some(Ok(entry)),
+                Visibility::Collapsed => {
+                    if entry.file_type().is_dir() {
+                        self.inner.skip_current_dir();
+                    }
+                    return Some(Ok(entry));
+                }
+                Visibility::Hidden => {
+                    if entry.file_type().is_dir() {
+                        self.inner.skip_current_dir();
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// `max_depth` for a walk without a depth limit
+///
+/// Every walker here counts depth from the root's children: `max_depth` 1
+/// yields only the immediate children (like `tree -L 1`), 2 adds grandchildren.
+pub const UNLIMITED_DEPTH: usize = usize::MAX;
+
+/// Walk `path` (excluding the root) up to `max_depth`, applying the visibility rules
+pub fn walk_visible(path: &Path, max_depth: usize, show: &ShowOptions) -> VisibleWalk {
+    let mut walk = WalkDir::new(path)
         .min_depth(1)
         .max_depth(max_depth)
+        .same_file_system(show.one_file_system);
+    let times = Arc::new(dir_times(path, show));
+    if !show.sort.is_unsorted() {
+        let mut keys = SiblingKeys::new(show.sort.clone(), times.clone());
+        walk = walk.sort_by(move |a, b| keys.compare(a.path(), a.file_type().is_dir(), b.path(), b.file_type().is_dir()));
+    }
+    VisibleWalk {
+        inner: walk.into_iter(),
+        show: show.clone(),
+        times,
+    }
+}
+
+/// Newest time (of the sort's time field) below each directory, when the sort dates directories recursively
+///
+/// Looks at every visible descendant regardless of the listing's depth, since a
+/// collapsed directory's contents still count.
+fn dir_times(path: &Path, show: &ShowOptions) -> DirTimes {
+    if !show.sort.needs_dir_times() {
+        return DirTimes::default();
+    }
+    let unsorted = ShowOptions { sort: SortOptions::default(), ..show.clone() };
+    DirTimes::from_entries(
+        path,
+        walk_visible(path, UNLIMITED_DEPTH, &unsorted)
+            .filter_map(ok_or_warn)
+            .filter_map(|e| Some((e.path().to_path_buf(), show.sort.time_field.of(&e.metadata().ok()?)?))),
+    )
+}
+
+/// Collect directory entries for the given path with specified depth and visibility options
+///
+/// `progress`, when given, is bumped once per entry so a spinner can show how far the scan got.
+pub fn collect_entries(
+    path: &Path,
+    max_depth: usize,
+    show: &ShowOptions,
+    progress: Option<&AtomicUsize>,
+) -> Vec<DirEntry> {
+    walk_visible(path, max_depth, show)
+        .filter_map(ok_or_warn)
+        .inspect(|_| tick(progress))
+        .collect()
+}
+
+/// Keep a walked entry, logging why it was dropped on error (`-v`)
+pub fn ok_or_warn<T, E: Display>(res: Result<T, E>) -> Option<T> {
+    res.map_err(|e| log::warn!("{}", e)).ok()
+}
+
+/// Count one scanned entry
+pub fn tick(progress: Option<&AtomicUsize>) {
+    if let Some(counter) = progress {
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Ignore rules beyond git's that the search walker applies
+///
+/// Precedence, highest first: `.rgignore`, `.ignore`, `.gitignore`, git's
+/// exclude and global files, then the extra `files`.
+#[derive(Debug, Clone)]
+pub struct IgnoreOptions {
+    /// Honor `.ignore` and `.rgignore` files
+    pub dot_ignore: bool,
+    /// Extra gitignore-syntax files applied to the whole walk (`--ignore-file`)
+    pub files: Vec<PathBuf>,
+}
+
+impl Default for IgnoreOptions {
+    fn default() -> Self {
+        Self {
+            dot_ignore: true,
+            files: Vec::new(),
+        }
+    }
+}
+
+/// Configure the gitignore-aware walker used for searches
+///
+/// Both the search pre-pass and the streaming search output use this builder,
+/// so the set of matches always agrees with what gets printed.
+pub fn search_walker(path: &Path, max_depth: usize, show: &ShowOptions, ignore: &IgnoreOptions) -> WalkBuilder {
+    let mut builder = WalkBuilder::new(path);
+    builder
+        .max_depth((max_depth != UNLIMITED_DEPTH).then_some(max_depth))
+        .hidden(!show.walks_hidden())
+        .ignore(ignore.dot_ignore)
+        .git_ignore(true)
+        .git_global(true)
+        .git_exclude(true)
+        .same_file_system(show.one_file_system)
+        .filter_entry({
+            let dirs_only = show.dirs_only;
+            let show_skipped = show.show_skipped;
+            let exclude = show.exclude.clone();
+            let rules = show.clone();
+            move |e| {
+                // Pruning everything drains sequential and parallel walks alike
+                if cancelled().is_some() {
+                    return false;
+                }
+                if dirs_only && !e.file_type().is_some_and(|t| t.is_dir()) {
+                    return false;
+                }
+                if rules.skips(e.file_name()) && !show_skipped {
+                    log_rule(e.path(), if rules.projects.is_empty() { Rule::SkipList } else { Rule::SmartPreset });
+                    return false;
+                }
+                // The walker can't skip a directory it yields, so a listed skipped
+                // directory is read but everything in it is left out
+                let in_skipped = || e.depth() > 1 && e.path().parent().and_then(Path::file_name).is_some_and(|n| rules.skips(n));
+                if show_skipped && in_skipped() {
+                    return false;
+                }
+                // Likewise for the contents of a directory the walk stops at
+                if e.depth() > 1 && e.path().parent().is_some_and(|p| rules.stops_at(p)) {
+                    return false;
+                }
+                if exclude.is_excluded(e.path(), e.depth()) {
+                    log_rule(e.path(), Rule::Excluded);
+                    return false;
+                }
+                true
+            }
+        });
+    if ignore.dot_ignore {
+        builder.add_custom_ignore_filename(".rgignore");
+    }
+    if !show.sort.is_unsorted() {
+        // The serial walk sorts one directory at a time, so the lock is never contended
+        let keys = Mutex::new(SiblingKeys::new(show.sort.clone(), Arc::new(dir_times(path, show))));
+        builder.sort_by_file_path(move |a, b| {
+            keys.lock().unwrap_or_else(PoisonError::into_inner).compare(a, a.is_dir(), b, b.is_dir())
+        });
+    }
+    for file in &ignore.files {
+        // Unreadable files were rejected when the options were built; partial parse errors are tolerated
+        let _ = builder.add_ignore(file);
+    }
+    builder
+}
+
+/// Collect the entries a search will consider, excluding the root itself
+///
+/// With `hidden_only` only entries at or below a hidden component are candidates.
+pub fn collect_search_entries(
+    path: &Path,
+    max_depth: usize,
+    show: &ShowOptions,
+    ignore: &IgnoreOptions,
+    progress: Option<&AtomicUsize>,
+) -> Vec<ignore::DirEntry> {
+    search_walker(path, max_depth, show, ignore)
+        .build()
+        .filter_map(ok_or_warn)
+        .filter(|e| e.depth() > 0)
+        .filter(|e| !show.hidden_only || has_hidden_component(e.path(), path))
+        .inspect(|_| tick(progress))
+        .collect()
+}
+
+/// [`collect_search_entries`] on several threads, sorted into the order the serial walk yields
+///
+/// Siblings follow `show.sort`, falling back to their names, so the order is the
+/// same from run to run whatever order the threads finished in.
+pub fn par_collect_search_entries(
+    path: &Path,
+    max_depth: usize,
+    show: &ShowOptions,
+    ignore: &IgnoreOptions,
+    progress: Option<&AtomicUsize>,
+) -> Vec<ignore::DirEntry> {
+    let found = Mutex::new(Vec::new());
+    search_walker(path, max_depth, show, ignore).build_parallel().run(|| {
+        Box::new(|res| {
+            if let Some(entry) = ok_or_warn(res) {
+                if entry.depth() > 0 && (!show.hidden_only || has_hidden_component(entry.path(), path)) {
+                    tick(progress);
+                    found.lock().unwrap_or_else(PoisonError::into_inner).push(entry);
+                }
+            }
+            WalkState::Continue
+        })
+    });
+    let mut entries = found.into_inner().unwrap_or_else(PoisonError::into_inner);
+    if show.sort.is_unsorted() {
+        // Comparing component lists is pre-order with siblings by name; splitting
+        // each path once is far cheaper than on every comparison
+        entries.sort_by_cached_key(|e| e.path().components().map(|c| c.as_os_str().to_os_string()).collect::<Vec<_>>());
+    } else {
+        // Pre-order: each entry sorts by the keys of its ancestors below the root, then its own,
+        // so an ancestor comes first and separate subtrees follow their diverging siblings
+        let times = dir_times(path, show);
+        let keys: HashMap<PathBuf, SiblingKey> = entries
+            .iter()
+            .map(|e| (e.path().to_path_buf(), show.sort.key(e.path(), e.file_type().is_some_and(|t| t.is_dir()), &times)))
+            .collect();
+        entries.sort_by_cached_key(|e| {
+            let mut chain: Vec<(SiblingKey, PathBuf)> = e
+                .path()
+                .ancestors()
+                .take(e.depth())
+                .map(|p| {
+                    // Ancestors a `hidden_only` search left out are directories all the same
+                    let key = keys.get(p).cloned().unwrap_or_else(|| show.sort.key(p, true, &times));
+                    (key, p.to_path_buf())
+                })
+                .collect();
+            chain.reverse();
+            chain
+        });
+    }
+    entries
+}
+
+/// The candidates a search considers, walked in parallel when the query asks for it
+pub fn search_candidates(
+    path: &Path,
+    max_depth: usize,
+    show: &ShowOptions,
+    query: &SearchQuery,
+    progress: Option<&AtomicUsize>,
+) -> Vec<ignore::DirEntry> {
+    if query.parallel {
+        par_collect_search_entries(path, max_depth, show, &query.ignore, progress)
+    } else {
+        collect_search_entries(path, max_depth, show, &query.ignore, progress)
+    }
+}
+
+/// The shallowest matching entry, the lexicographically smallest path among equally shallow ones
+///
+/// One walk keeps the best `(depth, path)` seen so far; entries deeper than it can't
+/// win, so their names aren't matched.
+pub fn first_match(path: &Path, max_depth: usize, show: &ShowOptions, query: &SearchQuery) -> Option<PathBuf> {
+    let mut best: Option<(usize, PathBuf)> = None;
+    let candidates = search_walker(path, max_depth, show, &query.ignore)
+        .build()
+        .filter_map(ok_or_warn)
+        .filter(|e| e.depth() > 0)
+        .filter(|e| !show.hidden_only || has_hidden_component(e.path(), path));
+    for entry in candidates {
+        let depth = entry.depth();
+        let better = best.as_ref().is_none_or(|(d, b)| (depth, entry.path()) < (*d, b.as_path()));
+        if better && query.is_match(entry.path()) {
+            best = Some((depth, entry.into_path()));
+        }
+    }
+    best.map(|(_, found)| found)
+}
+
+/// Fetch metadata for all non-directory entries (and directories with `include_dirs`) in parallel
+///
+/// Gives the collect-all rendering paths a single place to read metadata so
+/// each file is stat-ed once. Entries whose metadata can't be read are left out.
+pub fn par_compute_meta(
+    entries: &[DirEntry],
+    source: &dyn MetadataSource,
+    include_dirs: bool,
+) -> HashMap<PathBuf, EntryMeta> {
+    par_read_meta(entries, source, include_dirs).0
+}
+
+/// [`par_compute_meta`], also returning the entries that vanished between the walk and the stat
+pub fn par_read_meta(
+    entries: &[DirEntry],
+    source: &dyn MetadataSource,
+    include_dirs: bool,
+) -> (HashMap<PathBuf, EntryMeta>, HashSet<PathBuf>) {
+    let reads: Vec<(PathBuf, MetaRead)> = entries
+        .par_iter()
+        .filter(|e| include_dirs || !e.file_type().is_dir())
+        .map(|e| (e.path().to_path_buf(), MetaRead::read(source, e.path())))
+        .collect();
+    let mut metas = HashMap::with_capacity(reads.len());
+    let mut vanished = HashSet::new();
+    for (path, read) in reads {
+        match read {
+            MetaRead::Found(meta) => {
+                metas.insert(path, meta);
+            }
+            MetaRead::Vanished => {
+                vanished.insert(path);
+            }
+            MetaRead::Unreadable => {}
+        }
+    }
+    (metas, vanished)
+}
+
+/// Fetch the sizes of all non-directory entries in parallel
+///
+/// Results don't depend on the thread count: each entry's size is read on its own.
+pub fn par_compute_sizes(entries: &[DirEntry]) -> HashMap<PathBuf, u64> {
+    par_compute_meta(entries, &FsMetadata, false)
         .into_iter()
-        .filter_entry(|e| should_show_entry(e, show_hidden))
-        .filter_map(Result::ok)
+        .map(|(path, meta)| (path, meta.size))
         .collect()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::filters::is_mount_point;
+    use crate::core::listing::TreeData;
+
+    #[test]
+    fn test_par_compute_meta_and_sizes_skip_directories() {
+        let dir = std::env::temp_dir().join(format!("lst-sizes-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("sub")).unwrap();
+        std::fs::write(dir.join("a.txt"), "hello").unwrap();
+        std::fs::write(dir.join("sub/b.txt"), "hi").unwrap();
+
+        let entries = collect_entries(&dir, UNLIMITED_DEPTH, &ShowOptions::default(), None);
+        let metas = par_compute_meta(&entries, &FsMetadata, false);
+
+        assert_eq!(metas.len(), 2);
+        assert_eq!(metas.get(&dir.join("a.txt")).map(|m| m.size), Some(5));
+        assert_eq!(metas.get(&dir.join("sub/b.txt")).map(|m| m.size), Some(2));
+        assert!(!metas.contains_key(&dir.join("sub")));
+
+        // Sizes alone come out the same whatever the thread count
+        let sizes = par_compute_sizes(&entries);
+        assert_eq!(sizes, metas.iter().map(|(path, meta)| (path.clone(), meta.size)).collect());
+        let one_thread = rayon::ThreadPoolBuilder::new().num_threads(1).build().unwrap();
+        assert_eq!(one_thread.install(|| par_compute_sizes(&entries)), sizes);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_parallel_search_entries_come_in_walk_order() {
+        use super::super::sort::SortKey;
+
+        let dir = std::env::temp_dir().join(format!("lst-par-search-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        for sub in ["b/y", "b/x", "a", "c/z/deep"] {
+            std::fs::create_dir_all(dir.join(sub)).unwrap();
+        }
+        for file in ["a/2.txt", "a/10.txt", "b/x/f", "top.txt", "c/z/deep/g"] {
+            std::fs::write(dir.join(file), "").unwrap();
+        }
+
+        let names = |entries: Vec<ignore::DirEntry>| -> Vec<String> {
+            entries.iter().map(|e| e.path().strip_prefix(&dir).unwrap().to_string_lossy().into_owned()).collect()
+        };
+        let ignore = IgnoreOptions::default();
+        // Unsorted, directory order is up to the filesystem, so the parallel walk orders by name
+        assert_eq!(
+            names(par_collect_search_entries(&dir, UNLIMITED_DEPTH, &ShowOptions::default(), &ignore, None)),
+            ["a", "a/10.txt", "a/2.txt", "b", "b/x", "b/x/f", "b/y", "c", "c/z", "c/z/deep", "c/z/deep/g", "top.txt"]
+        );
+        // Sorted, it matches the serial walk
+        let show = ShowOptions { sort: SortOptions::new(Some(SortKey::Extension), None, None), ..ShowOptions::default() };
+        assert_eq!(
+            names(par_collect_search_entries(&dir, UNLIMITED_DEPTH, &show, &ignore, None)),
+            names(collect_search_entries(&dir, UNLIMITED_DEPTH, &show, &ignore, None))
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_first_match_prefers_shallow_then_lexicographic() {
+        let dir = std::env::temp_dir().join(format!("lst-first-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("a/config")).unwrap();
+        std::fs::create_dir_all(dir.join("z")).unwrap();
+        std::fs::write(dir.join("z/config.toml"), "").unwrap();
+        std::fs::write(dir.join("z/app.config"), "").unwrap();
+
+        let show = ShowOptions::default();
+        let query = SearchQuery::new("config", &dir);
+        assert_eq!(first_match(&dir, UNLIMITED_DEPTH, &show, &query), Some(dir.join("a/config")));
+
+        std::fs::write(dir.join("config.yml"), "").unwrap();
+        assert_eq!(first_match(&dir, UNLIMITED_DEPTH, &show, &query), Some(dir.join("config.yml")));
+        assert_eq!(first_match(&dir, UNLIMITED_DEPTH, &show, &SearchQuery::new("missing", &dir)), None);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_depth_limits_agree_across_walkers() {
+        let dir = std::env::temp_dir().join(format!("lst-depths-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("a/b/c")).unwrap();
+        std::fs::write(dir.join("top.txt"), "").unwrap();
+        std::fs::write(dir.join("a/b/c/deep.txt"), "").unwrap();
+
+        let show = ShowOptions::default();
+        for (max_depth, expected) in [(1, 2), (2, 3), (3, 4), (UNLIMITED_DEPTH, 5)] {
+            let listed = collect_entries(&dir, max_depth, &show, None);
+            assert_eq!(listed.len(), expected, "listing at depth {}", max_depth);
+            assert!(listed.iter().all(|e| e.depth() <= max_depth));
+            let searched = collect_search_entries(&dir, max_depth, &show, &IgnoreOptions::default(), None);
+            assert_eq!(searched.len(), expected, "search at depth {}", max_depth);
+        }
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_one_file_system_lists_mount_points_without_descending() {
+        use std::os::unix::fs::MetadataExt;
+
+        let dev = |p: &str| std::fs::metadata(p).map(|m| m.dev()).ok();
+        // Needs /proc mounted separately from /, as on most Linux systems
+        if dev("/proc").is_none() || dev("/proc") == dev("/") {
+            return;
+        }
+        let show = ShowOptions { one_file_system: true, ..ShowOptions::default() };
+        let listed = collect_entries(Path::new("/"), 2, &show, None);
+        assert!(listed.iter().any(|e| e.path() == Path::new("/proc")));
+        let below_proc = |p: &Path| p.starts_with("/proc") && p != Path::new("/proc");
+        assert!(!listed.iter().any(|e| below_proc(e.path())));
+        let searched = collect_search_entries(Path::new("/"), 2, &show, &IgnoreOptions::default(), None);
+        assert!(!searched.iter().any(|e| below_proc(e.path())));
+
+        // It is marked as one, and the summary counts it
+        assert!(is_mount_point(Path::new("/proc"), true, &show));
+        assert!(!is_mount_point(Path::new("/proc"), true, &ShowOptions::default()));
+        let data = TreeData::collect(Path::new("/"), 1, &show, None, None, None, false);
+        assert!(data.annotations.mount_points.contains(Path::new("/proc")));
+        assert!(data.summary(None, false).mount_points >= 1);
+    }
+
+    #[test]
+    fn test_search_walker_ignore_files() {
+        let dir = std::env::temp_dir().join(format!("lst-ignore-files-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        for name in ["a.log", "b.tmp", "c.rs"] {
+            std::fs::write(dir.join(name), "").unwrap();
+        }
+        std::fs::write(dir.join(".ignore"), "*.log\n").unwrap();
+        let extra = std::env::temp_dir().join(format!("lst-extra-ignore-{}", std::process::id()));
+        std::fs::write(&extra, "*.tmp\n").unwrap();
+
+        let names = |ignore: &IgnoreOptions| -> Vec<String> {
+            let mut names: Vec<String> = collect_search_entries(&dir, UNLIMITED_DEPTH, &ShowOptions::default(), ignore, None)
+                .iter()
+                .map(|e| e.file_name().to_string_lossy().into_owned())
+                .collect();
+            names.sort();
+            names
+        };
+        assert_eq!(names(&IgnoreOptions::default()), ["b.tmp", "c.rs"]);
+        let no_dot = IgnoreOptions { dot_ignore: false, files: Vec::new() };
+        assert_eq!(names(&no_dot), ["a.log", "b.tmp", "c.rs"]);
+        let extra_files = IgnoreOptions { files: vec![extra.clone()], ..IgnoreOptions::default() };
+        assert_eq!(names(&extra_files), ["c.rs"]);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+        std::fs::remove_file(&extra).unwrap();
+    }
+
+    #[test]
+    fn test_collapsed_root_dot_dirs_are_not_descended() {
+        let dir = std::env::temp_dir().join(format!("lst-collapse-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join(".git/objects")).unwrap();
+        std::fs::write(dir.join(".git/HEAD"), "").unwrap();
+
+        let names = |hidden| -> Vec<PathBuf> {
+            collect_entries(&dir, UNLIMITED_DEPTH, &ShowOptions { hidden, ..ShowOptions::default() }, None)
+                .into_iter()
+                .map(|e| e.path().strip_prefix(&dir).unwrap().to_path_buf())
+                .collect()
+        };
+        assert!(names(false).is_empty());
+        assert_eq!(names(true), [PathBuf::from(".git")]);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
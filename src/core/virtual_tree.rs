@@ -0,0 +1,169 @@
+use std::collections::BTreeMap;
+use std::path::{Component, Path, PathBuf};
+
+/// A tree entry synthesized from a list of paths rather than a filesystem walk
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VirtualEntry {
+    /// Path as it should be looked up on disk
+    pub path: PathBuf,
+    /// Display name of this path component
+    pub name: String,
+    /// Depth below the virtual root (top-level entries are at depth 1)
+    pub depth: usize,
+    /// Whether the entry is a directory (listed, implied by children, or on disk)
+    pub is_dir: bool,
+    /// Size given with the path, e.g. by an archive's index
+    pub size: Option<u64>,
+    /// Whether `path` names a file on disk that sizes and colors may be looked up from
+    pub on_disk: bool,
+}
+
+#[derive(Default)]
+struct Node {
+    path: PathBuf,
+    listed_dir: bool,
+    size: Option<u64>,
+    children: BTreeMap<String, Node>,
+}
+
+/// Build pre-ordered virtual entries from a list of relative or absolute paths
+///
+/// Intermediate directories that are not listed themselves are synthesized so
+/// every path appears in its tree context. Siblings are sorted by name.
+pub fn build_virtual_entries<I, S>(paths: I) -> Vec<VirtualEntry>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<str>,
+{
+    build(paths.into_iter().map(|path| (path, None)), true)
+}
+
+/// Build pre-ordered virtual entries from paths that aren't on disk, such as an archive's members
+///
+/// Only paths ending in `/` or holding others are directories, and sizes are
+/// the ones given; the disk is never consulted.
+pub fn build_detached_entries<I, S>(paths: I) -> Vec<VirtualEntry>
+where
+    I: IntoIterator<Item = (S, Option<u64>)>,
+    S: AsRef<str>,
+{
+    build(paths, false)
+}
+
+fn build<I, S>(paths: I, on_disk: bool) -> Vec<VirtualEntry>
+where
+    I: IntoIterator<Item = (S, Option<u64>)>,
+    S: AsRef<str>,
+{
+    let mut root = Node::default();
+
+    for (raw, size) in paths {
+        let raw = raw.as_ref().trim_end_matches(['\r', '\n']);
+        if raw.trim().is_empty() {
+            continue;
+        }
+
+        let mut node = &mut root;
+        let mut path = PathBuf::new();
+        for name in path_segments(Path::new(raw)) {
+            path.push(&name);
+            let child_path = path.clone();
+            node = node.children.entry(name).or_insert_with(|| Node {
+                path: child_path,
+                ..Node::default()
+            });
+        }
+        if raw.ends_with('/') || raw.ends_with('\\') {
+            node.listed_dir = true;
+        }
+        node.size = size.or(node.size);
+    }
+
+    let mut entries = Vec::new();
+    flatten(&root, 1, on_disk, &mut entries);
+    entries
+}
+
+/// Split a path into display segments, keeping any root/prefix as the first one
+fn path_segments(path: &Path) -> Vec<String> {
+    let mut segments: Vec<String> = Vec::new();
+    let mut anchor = String::new();
+
+    for component in path.components() {
+        match component {
+            Component::Prefix(p) => anchor.push_str(&p.as_os_str().to_string_lossy()),
+            Component::RootDir => anchor.push(std::path::MAIN_SEPARATOR),
+            Component::CurDir => {}
+            Component::ParentDir => segments.push("..".to_string()),
+            Component::Normal(name) => segments.push(name.to_string_lossy().into_owned()),
+        }
+    }
+
+    if !anchor.is_empty() {
+        segments.insert(0, anchor);
+    }
+    segments
+}
+
+fn flatten(node: &Node, depth: usize, on_disk: bool, out: &mut Vec<VirtualEntry>) {
+    for (name, child) in &node.children {
+        let is_dir = child.listed_dir || !child.children.is_empty() || (on_disk && child.path.is_dir());
+        out.push(VirtualEntry {
+            path: child.path.clone(),
+            name: name.clone(),
+            depth,
+            is_dir,
+            size: if is_dir { None } else { child.size },
+            on_disk,
+        });
+        flatten(child, depth + 1, on_disk, out);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_synthesizes_intermediate_directories() {
+        let entries = build_virtual_entries(["src/core/tree.rs", "./src/main.rs", "README.md"]);
+        let summary: Vec<(&str, usize, bool)> = entries
+            .iter()
+            .map(|e| (e.name.as_str(), e.depth, e.is_dir))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("README.md", 1, false),
+                ("src", 1, true),
+                ("core", 2, true),
+                ("tree.rs", 3, false),
+                ("main.rs", 2, false),
+            ]
+        );
+        assert_eq!(entries[3].path, PathBuf::from("src/core/tree.rs"));
+    }
+
+    #[test]
+    fn test_absolute_paths_keep_root() {
+        let entries = build_virtual_entries(["/definitely/not/here.txt"]);
+        assert_eq!(entries[0].name, std::path::MAIN_SEPARATOR.to_string());
+        assert_eq!(entries[3].path, PathBuf::from("/definitely/not/here.txt"));
+        assert!(!entries[3].is_dir);
+    }
+
+    #[test]
+    fn test_detached_entries_keep_given_sizes() {
+        let entries = build_detached_entries([("src/", None), ("src/main.rs", Some(42)), ("Cargo.toml", Some(7))]);
+        let summary: Vec<(&str, bool, Option<u64>)> =
+            entries.iter().map(|e| (e.name.as_str(), e.is_dir, e.size)).collect();
+        assert_eq!(summary, vec![("Cargo.toml", false, Some(7)), ("src", true, None), ("main.rs", false, Some(42))]);
+        assert!(entries.iter().all(|e| !e.on_disk));
+    }
+
+    #[test]
+    fn test_skips_blank_lines_and_dedupes() {
+        let entries = build_virtual_entries(["a/b", "", "a/b\r", "  "]);
+        assert_eq!(entries.len(), 2);
+    }
+}
@@ -1,6 +1,8 @@
 use std::fmt;
 use std::io;
 
+use crate::core::cancel::Cancelled;
+
 /// Custom error type for the lst application
 #[derive(Debug)]
 pub enum LstError {
@@ -12,6 +14,33 @@ pub enum LstError {
 
     /// Syntax highlighting failed
     HighlightError(String),
+
+    /// A glob or search pattern could not be compiled
+    InvalidPattern(String),
+
+    /// `LST_COLORS` holds an entry that isn't `key=color` or names an unknown key
+    InvalidColors(String),
+
+    /// A glob pattern matched nothing
+    NoMatches(String),
+
+    /// The output file exists and neither `--force` nor `--append` was given
+    OutputExists(String),
+
+    /// A `--diff` snapshot isn't a tree written by `--json`
+    InvalidSnapshot(String),
+
+    /// A `search --exec` command couldn't be parsed, started or didn't succeed
+    ExecError(String),
+
+    /// A search finished without matching anything; exits 1 like `grep`
+    NothingFound,
+
+    /// An interactive mode was asked for without a terminal to run on
+    NotATerminal(String),
+
+    /// Walks stopped early on `--timeout` or Ctrl-C; what was found has been printed
+    Cancelled(Cancelled),
 }
 
 impl fmt::Display for LstError {
@@ -20,10 +49,65 @@ impl fmt::Display for LstError {
             LstError::Io(e) => write!(f, "I/O error: {}", e),
             LstError::InvalidPath(path) => write!(f, "Invalid path: {}", path),
             LstError::HighlightError(e) => write!(f, "Syntax highlighting error: {}", e),
+            LstError::InvalidPattern(e) => write!(f, "Invalid pattern: {}", e),
+            LstError::InvalidColors(e) => write!(f, "Invalid colors: {}", e),
+            LstError::NoMatches(pattern) => write!(f, "No matches for '{}'", pattern),
+            LstError::OutputExists(path) => write!(
+                f,
+                "Refusing to overwrite '{}' (use --force to replace it or --append to add to it)",
+                path
+            ),
+            LstError::InvalidSnapshot(e) => write!(f, "Invalid snapshot: {}", e),
+            LstError::ExecError(e) => write!(f, "Exec error: {}", e),
+            LstError::NothingFound => write!(f, "No matches found"),
+            LstError::NotATerminal(e) => write!(f, "Not a terminal: {}", e),
+            LstError::Cancelled(Cancelled::TimedOut) => write!(f, "Timed out (--timeout); the output is incomplete"),
+            LstError::Cancelled(Cancelled::Interrupted) => write!(f, "Interrupted; the output is incomplete"),
         }
     }
 }
 
+impl LstError {
+    /// Whether this error is a write to a closed pipe (e.g. `lst | head`)
+    pub fn is_broken_pipe(&self) -> bool {
+        matches!(self, LstError::Io(e) if e.kind() == io::ErrorKind::BrokenPipe)
+    }
+
+    /// Whether this is a search that found nothing, which exits 1 without a message
+    pub fn is_nothing_found(&self) -> bool {
+        matches!(self, LstError::NothingFound)
+    }
+
+    /// Stable snake_case name of the variant, the `kind` of a JSON error
+    pub fn kind(&self) -> &'static str {
+        match self {
+            LstError::Io(_) => "io",
+            LstError::InvalidPath(_) => "invalid_path",
+            LstError::HighlightError(_) => "highlight",
+            LstError::InvalidPattern(_) => "invalid_pattern",
+            LstError::InvalidColors(_) => "invalid_colors",
+            LstError::NoMatches(_) => "no_matches",
+            LstError::OutputExists(_) => "output_exists",
+            LstError::InvalidSnapshot(_) => "invalid_snapshot",
+            LstError::ExecError(_) => "exec",
+            LstError::NothingFound => "nothing_found",
+            LstError::NotATerminal(_) => "not_a_terminal",
+            LstError::Cancelled(Cancelled::TimedOut) => "timed_out",
+            LstError::Cancelled(Cancelled::Interrupted) => "interrupted",
+        }
+    }
+
+    /// The error as `{"error": message, "kind": kind}`, written instead of `Error: ...` in JSON mode
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({ "error": self.to_string(), "kind": self.kind() })
+    }
+
+    /// Whether the run was stopped with Ctrl-C, which exits 130 without a message like other tools
+    pub fn is_interrupted(&self) -> bool {
+        matches!(self, LstError::Cancelled(Cancelled::Interrupted))
+    }
+}
+
 impl std::error::Error for LstError {
     fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
         match self {
@@ -47,3 +131,17 @@ impl From<Box<dyn std::error::Error>> for LstError {
 
 /// Type alias for Result with LstError
 pub type Result<T> = std::result::Result<T, LstError>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_broken_pipe() {
+        let pipe = LstError::from(io::Error::new(io::ErrorKind::BrokenPipe, "closed"));
+        assert!(pipe.is_broken_pipe());
+        let other = LstError::from(io::Error::new(io::ErrorKind::NotFound, "missing"));
+        assert!(!other.is_broken_pipe());
+        assert!(!LstError::InvalidPath("x".into()).is_broken_pipe());
+    }
+}
@@ -5,3 +5,5 @@ pub mod error;
 pub mod output;
 
 pub use cli::run_cli;
+pub use core::listing::{list_tree, ListOptions, NodeKind, TreeNode};
+pub use core::search::MatchTarget;
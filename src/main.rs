@@ -1,9 +1,24 @@
-// Minimal binary entry; delegate to crate run_cli()
-use lst::run_cli;
+// Minimal binary entry; delegate to the crate's runner
+use lst::cli::{run, write_error, Cli};
 
 fn main() {
-    if let Err(e) = run_cli() {
-        eprintln!("Error: {}", e);
-        std::process::exit(1);
+    let cli = Cli::parse_cli();
+    // Kept for the error report, which is JSON when stdout's output is
+    let format = cli.requested_format().unwrap_or_default();
+    if let Err(e) = run(cli) {
+        // The reader went away (e.g. `lst | head`); exit quietly like other Unix tools
+        if e.is_broken_pipe() {
+            return;
+        }
+        // Like `grep`, a search without matches only reports through the exit code
+        if e.is_nothing_found() {
+            std::process::exit(1);
+        }
+        if e.is_interrupted() {
+            std::process::exit(130);
+        }
+        // Exit 2 like `grep`, so scripts can tell a failure from a search without matches
+        let _ = write_error(&mut std::io::stderr().lock(), &e, format);
+        std::process::exit(2);
     }
 }
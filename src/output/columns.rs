@@ -0,0 +1,281 @@
+use std::path::Path;
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use colored::Colorize;
+
+use super::formatter::{format_size_colored, SizeFormat};
+use super::palette::Palette;
+use crate::core::meta::{has_xattr, EntryMeta, TimeField};
+
+/// A field of an entry line (`--columns`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Column {
+    /// The tree glyphs and entry name
+    Name,
+    /// Human-readable file size
+    Size,
+    /// Last modification time, or the `--time-field` time, `YYYY-MM-DD HH:MM` in local time
+    Mtime,
+    /// `ls -l` style permission string, followed by `@` when the entry has extended attributes
+    Perms,
+}
+
+/// Plain-text renderer of a column; `None` leaves the cell empty
+type Render = fn(&Cell) -> Option<String>;
+
+impl Column {
+    const ALL: [(&'static str, Column); 4] =
+        [("name", Column::Name), ("size", Column::Size), ("mtime", Column::Mtime), ("perms", Column::Perms)];
+
+    /// Width of the column when it comes before the name
+    fn width(self) -> usize {
+        match self {
+            Column::Name => 0,
+            Column::Size => 9,
+            Column::Mtime => 16,
+            Column::Perms => 11,
+        }
+    }
+
+    fn renderer(self) -> Render {
+        match self {
+            Column::Name => |_| None,
+            Column::Size => |cell| (!cell.is_dir).then(|| cell.size_format.format(cell.meta.map(|m| m.size).unwrap_or(0))),
+            Column::Mtime => |cell| cell.meta.and_then(|m| m.time(cell.time_field)).map(format_mtime),
+            Column::Perms => |cell| {
+                let perms = format_permissions(cell.meta?.mode?);
+                // Only checked when the column is shown, since it costs a syscall per entry
+                let marker = if cell.path.is_some_and(has_xattr) { "@" } else { "" };
+                Some(perms + marker)
+            },
+        }
+    }
+
+    fn paint(self, text: &str, cell: &Cell) -> String {
+        match (self, cell.colors) {
+            (Column::Size, colors) => format_size_colored(text, cell.meta.map(|m| m.size).unwrap_or(0), colors),
+            (Column::Mtime, Some(_)) => text.dimmed().to_string(),
+            _ => text.to_string(),
+        }
+    }
+}
+
+/// The columns of each entry line, in order; always includes [`Column::Name`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Columns(Vec<Column>);
+
+impl Default for Columns {
+    /// `name (size)`, the classic line
+    fn default() -> Self {
+        Self(vec![Column::Name, Column::Size])
+    }
+}
+
+impl FromStr for Columns {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut columns = Vec::new();
+        for item in s.split(',').map(str::trim) {
+            let column = Column::ALL
+                .iter()
+                .find(|(name, _)| item.eq_ignore_ascii_case(name))
+                .map(|&(_, column)| column)
+                .ok_or_else(|| {
+                    let names: Vec<&str> = Column::ALL.iter().map(|(name, _)| *name).collect();
+                    format!("unknown column '{}' (expected {})", item, names.join(", "))
+                })?;
+            if columns.contains(&column) {
+                return Err(format!("column '{}' listed twice", item));
+            }
+            columns.push(column);
+        }
+        if !columns.contains(&Column::Name) {
+            return Err("the columns must include 'name'".to_string());
+        }
+        Ok(Self(columns))
+    }
+}
+
+impl Columns {
+    /// Whether a column other than the name and size needs metadata (also for directories)
+    pub fn needs_entry_meta(&self) -> bool {
+        self.0.iter().any(|c| matches!(c, Column::Mtime | Column::Perms))
+    }
+
+    pub fn as_slice(&self) -> &[Column] {
+        &self.0
+    }
+}
+
+/// What the column renderers see of one entry
+pub struct Cell<'a> {
+    /// The entry's path, for fields read on demand; `None` for placeholder lines
+    pub path: Option<&'a Path>,
+    pub meta: Option<&'a EntryMeta>,
+    pub is_dir: bool,
+    pub colors: Option<&'a Palette>,
+    /// Timestamp shown in the time column
+    pub time_field: TimeField,
+    pub size_format: SizeFormat,
+}
+
+/// Render the columns around the name: fixed-width fields before it, and a ` (a, b)` group after
+///
+/// Empty cells before the name are padded with blanks so later fields stay aligned;
+/// empty cells after it are dropped, as is the whole group when nothing remains.
+pub fn render_cells(columns: impl IntoIterator<Item = Column>, cell: &Cell) -> (String, String) {
+    let mut leading = String::new();
+    let mut trailing = Vec::new();
+    let mut after_name = false;
+    for column in columns {
+        if column == Column::Name {
+            after_name = true;
+            continue;
+        }
+        let text = (column.renderer())(cell);
+        if after_name {
+            trailing.extend(text.map(|t| column.paint(&t, cell)));
+        } else {
+            let text = text.unwrap_or_default();
+            let pad = " ".repeat(column.width().saturating_sub(text.chars().count()));
+            let painted = column.paint(&text, cell);
+            // Sizes read best right-aligned, like `ls -l`
+            if column == Column::Size {
+                leading.push_str(&format!("{}{}  ", pad, painted));
+            } else {
+                leading.push_str(&format!("{}{}  ", painted, pad));
+            }
+        }
+    }
+    let trailing = if trailing.is_empty() { String::new() } else { format!(" ({})", trailing.join(", ")) };
+    (leading, trailing)
+}
+
+/// `drwxr-xr-x` style string for unix mode bits
+fn format_permissions(mode: u32) -> String {
+    let kind = match mode & 0o170000 {
+        0o040000 => 'd',
+        0o120000 => 'l',
+        _ => '-',
+    };
+    let mut out = String::with_capacity(10);
+    out.push(kind);
+    for shift in [6, 3, 0] {
+        let bits = mode >> shift;
+        out.push(if bits & 4 != 0 { 'r' } else { '-' });
+        out.push(if bits & 2 != 0 { 'w' } else { '-' });
+        out.push(if bits & 1 != 0 { 'x' } else { '-' });
+    }
+    out
+}
+
+/// `YYYY-MM-DD HH:MM` in local time (UTC where the offset is unknown)
+fn format_mtime(time: SystemTime) -> String {
+    let secs = match time.duration_since(UNIX_EPOCH) {
+        Ok(d) => d.as_secs() as i64,
+        Err(e) => -(e.duration().as_secs() as i64),
+    };
+    let local = secs + utc_offset(secs);
+    let (days, rem) = (local.div_euclid(86_400), local.rem_euclid(86_400));
+    let (year, month, day) = civil_from_days(days);
+    format!("{:04}-{:02}-{:02} {:02}:{:02}", year, month, day, rem / 3600, rem % 3600 / 60)
+}
+
+#[cfg(unix)]
+fn utc_offset(secs: i64) -> i64 {
+    let t = secs as libc::time_t;
+    let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+    if unsafe { libc::localtime_r(&t, &mut tm) }.is_null() {
+        0
+    } else {
+        tm.tm_gmtoff as i64
+    }
+}
+
+#[cfg(not(unix))]
+fn utc_offset(_secs: i64) -> i64 {
+    0
+}
+
+/// Proleptic Gregorian date of a day count since 1970-01-01 (Howard Hinnant's algorithm)
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_columns_parse_and_validate() {
+        let columns: Columns = "perms, size,name,MTIME".parse().unwrap();
+        assert_eq!(columns.as_slice(), [Column::Perms, Column::Size, Column::Name, Column::Mtime]);
+        assert!(columns.needs_entry_meta());
+        assert!(!Columns::default().needs_entry_meta());
+
+        assert!("size,owner,name".parse::<Columns>().unwrap_err().contains("unknown column 'owner'"));
+        assert!("size,mtime".parse::<Columns>().unwrap_err().contains("'name'"));
+        assert!("name,size,size".parse::<Columns>().is_err());
+    }
+
+    #[test]
+    fn test_cells_align_before_name_and_group_after() {
+        let meta = EntryMeta { size: 1200, modified: Some(UNIX_EPOCH), mode: Some(0o100644), ..EntryMeta::default() };
+        let file = Cell { path: None, meta: Some(&meta), is_dir: false, colors: None, time_field: TimeField::Modified, size_format: SizeFormat::default() };
+        let columns: Columns = "perms,size,name".parse().unwrap();
+        assert_eq!(render_cells(columns.as_slice().iter().copied(), &file), ("-rw-r--r--     1.20 kB  ".to_string(), String::new()));
+
+        let dir = Cell { path: None, meta: None, is_dir: true, colors: None, time_field: TimeField::Modified, size_format: SizeFormat::default() };
+        assert_eq!(render_cells(columns.as_slice().iter().copied(), &dir).0.len(), 11 + 2 + 9 + 2);
+
+        let (leading, trailing) = render_cells(Columns::default().as_slice().iter().copied(), &file);
+        assert_eq!((leading.as_str(), trailing.as_str()), ("", " (1.20 kB)"));
+        assert_eq!(render_cells(Columns::default().as_slice().iter().copied(), &dir).1, "");
+
+        let created = Cell { time_field: TimeField::Created, ..file };
+        let mtime: Columns = "name,mtime".parse().unwrap();
+        assert_eq!(render_cells(mtime.as_slice().iter().copied(), &created).1, "", "no creation time recorded");
+
+        let wide = Cell { size_format: SizeFormat { precision: Some(1), width: Some(10) }, ..file };
+        assert_eq!(render_cells(columns.as_slice().iter().copied(), &wide).0, "-rw-r--r--       1.2 kB  ");
+        assert_eq!(render_cells(Columns::default().as_slice().iter().copied(), &wide).1, " (    1.2 kB)");
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_perms_mark_extended_attributes() {
+        let path = std::env::temp_dir().join(format!("lst-xattr-{}", std::process::id()));
+        std::fs::write(&path, "").unwrap();
+        let meta = EntryMeta { mode: Some(0o100644), ..EntryMeta::default() };
+        let cell = Cell { path: Some(&path), meta: Some(&meta), is_dir: false, colors: None, time_field: TimeField::Modified, size_format: SizeFormat::default() };
+        let perms = || render_cells([Column::Name, Column::Perms], &cell).1;
+        assert_eq!(perms(), " (-rw-r--r--)");
+
+        let name = std::ffi::CString::new("user.lst-test").unwrap();
+        let c_path = std::ffi::CString::new(path.to_str().unwrap()).unwrap();
+        let set = unsafe { libc::lsetxattr(c_path.as_ptr(), name.as_ptr(), b"1".as_ptr().cast(), 1, 0) };
+        // tmpfs without user xattrs (and some containers) refuse; nothing to check then
+        if set == 0 {
+            assert_eq!(perms(), " (-rw-r--r--@)");
+        }
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_civil_from_days() {
+        assert_eq!(civil_from_days(0), (1970, 1, 1));
+        assert_eq!(civil_from_days(19_782), (2024, 2, 29));
+        assert_eq!(civil_from_days(-1), (1969, 12, 31));
+    }
+}
@@ -1,57 +1,528 @@
-use colored::Colorize;
+use std::borrow::Cow;
+use std::ops::Range;
+use std::path::Path;
+
+use colored::{Color, Colorize};
+
+use super::palette::{paint, Palette};
 use humansize::{format_size, DECIMAL};
 use walkdir::DirEntry;
 
 use super::terminal::CharacterSet;
+use crate::core::stats::ExtensionStats;
 
 /// Format a file size in human-readable format
 pub fn format_file_size(size: u64) -> String {
     format_size(size, DECIMAL)
 }
 
-/// Format a directory name with optional color
-pub fn format_directory_name(name: &str, use_color: bool) -> String {
-    if use_color {
-        name.blue().to_string()
-    } else {
-        name.to_string()
+/// How the size column writes sizes (`--size-precision`, `--size-width`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct SizeFormat {
+    /// Decimal places, always written; `None` gives up to two without trailing zeroes
+    pub precision: Option<usize>,
+    /// Right-align sizes to at least this many columns
+    pub width: Option<usize>,
+}
+
+impl SizeFormat {
+    /// Format `size`; whole bytes never get decimals, so an empty file is always `0 B`
+    pub fn format(self, size: u64) -> String {
+        let text = match self.precision {
+            Some(places) if size >= 1000 => format_size(size, DECIMAL.decimal_places(places).decimal_zeroes(places)),
+            _ => format_file_size(size),
+        };
+        format!("{:>width$}", text, width = self.width.unwrap_or(0))
     }
 }
 
-/// Format a file name with optional color
-pub fn format_file_name(name: &str, use_color: bool) -> String {
-    if use_color {
-        name.green().to_string()
-    } else {
-        name.to_string()
+/// Where a name longer than [`NameLimit::max_width`] loses characters (`--truncate`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum Truncate {
+    /// `…ongname.rs`
+    Start,
+    /// `verylo…name.rs`, keeping the extension
+    #[default]
+    Middle,
+    /// `verylongna…`
+    End,
+}
+
+/// Longest name printed, in terminal columns (`--max-name-width`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NameLimit {
+    pub max_width: usize,
+    pub truncate: Truncate,
+}
+
+impl NameLimit {
+    /// `name` cut down to [`Self::max_width`] columns with an `…`, or unchanged when it fits
+    pub fn apply(self, name: &str) -> Cow<'_, str> {
+        if display_width(name) <= self.max_width {
+            return Cow::Borrowed(name);
+        }
+        // The ellipsis takes one column
+        let budget = self.max_width.saturating_sub(1);
+        let (head, tail) = match self.truncate {
+            Truncate::Start => (0, budget),
+            Truncate::End => (budget, 0),
+            Truncate::Middle => {
+                // The extension tells the file type, so the tail gets at least that much
+                let extension = name.rfind('.').filter(|&dot| dot > 0).map_or(0, |dot| display_width(&name[dot..]));
+                let tail = (budget / 2).max(extension).min(budget.saturating_sub(1));
+                (budget - tail, tail)
+            }
+        };
+        let start = width_prefix_len(name.chars(), head);
+        let end = name.len() - width_prefix_len(name.chars().rev(), tail);
+        Cow::Owned(format!("{}…{}", &name[..start], &name[end.max(start)..]))
+    }
+}
+
+/// Bytes taken by the leading `chars` that fit in `width` columns
+fn width_prefix_len(chars: impl Iterator<Item = char>, width: usize) -> usize {
+    let mut used = 0;
+    chars
+        .take_while(|&ch| {
+            used += char_width(ch);
+            used <= width
+        })
+        .map(char::len_utf8)
+        .sum()
+}
+
+/// Format a count with thousands separators (e.g. `12,430`)
+pub fn format_count(count: usize) -> String {
+    let digits = count.to_string();
+    let mut out = String::with_capacity(digits.len() + digits.len() / 3);
+    for (i, ch) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            out.push(',');
+        }
+        out.push(ch);
+    }
+    out
+}
+
+/// Number of columns `text` occupies once ANSI escape sequences are stripped
+pub fn display_width(text: &str) -> usize {
+    let mut width = 0;
+    let mut chars = text.chars();
+    while let Some(ch) = chars.next() {
+        if ch == '\x1b' {
+            match chars.next() {
+                // Skip a CSI sequence: ESC [ params... final byte in '@'..='~'
+                Some('[') => {
+                    for c in chars.by_ref() {
+                        if ('@'..='~').contains(&c) {
+                            break;
+                        }
+                    }
+                }
+                // Skip an OSC sequence such as a hyperlink: ESC ] ... ended by BEL or ESC \
+                Some(']') => {
+                    while let Some(c) = chars.next() {
+                        if c == '\x07' || (c == '\x1b' && chars.next() == Some('\\')) {
+                            break;
+                        }
+                    }
+                }
+                _ => {}
+            }
+        } else {
+            width += char_width(ch);
+        }
+    }
+    width
+}
+
+/// Escape the control characters in a name so it can't move the cursor or inject terminal escapes
+///
+/// Like `ls -b`: `\n`, `\t`, `\r` and ESC (`\e`) get C-style escapes, other control
+/// characters `\xNN`, or `\u{NN}` above ASCII. Everything else is kept as is.
+pub fn sanitize_name(name: &str) -> String {
+    let mut out = String::with_capacity(name.len());
+    for ch in name.chars() {
+        match ch {
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            '\r' => out.push_str("\\r"),
+            '\x1b' => out.push_str("\\e"),
+            c if c.is_control() && c.is_ascii() => out.push_str(&format!("\\x{:02x}", c as u32)),
+            c if c.is_control() => out.push_str(&format!("\\u{{{:x}}}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// `file://` URI of `path` for `--hyperlinks`: absolute, with everything but
+/// unreserved characters and `/` percent-encoded
+pub fn file_uri(path: &Path) -> String {
+    let path = std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+    #[cfg(unix)]
+    let bytes = {
+        use std::os::unix::ffi::OsStrExt;
+        path.as_os_str().as_bytes().to_vec()
+    };
+    // Windows paths become `/C:/dir/file`
+    #[cfg(not(unix))]
+    let bytes = format!("/{}", path.to_string_lossy().replace('\\', "/")).into_bytes();
+
+    let mut uri = String::from("file://");
+    for byte in bytes {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => uri.push(byte as char),
+            // Keep the drive letter's colon readable
+            b':' if cfg!(windows) => uri.push(':'),
+            _ => uri.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    uri
+}
+
+/// Wrap `text` in an OSC 8 hyperlink to `uri`, which terminals that support it make clickable
+pub fn hyperlink(text: &str, uri: &str) -> String {
+    format!("\x1b]8;;{}\x1b\\{}\x1b]8;;\x1b\\", uri, text)
+}
+
+/// Terminal columns taken by a character: 0 for combining marks, 2 for wide CJK and emoji
+fn char_width(ch: char) -> usize {
+    match ch as u32 {
+        0x0300..=0x036F | 0x200B..=0x200F | 0xFE00..=0xFE0F => 0,
+        0x1100..=0x115F
+        | 0x2E80..=0x303E
+        | 0x3041..=0x33FF
+        | 0x3400..=0x4DBF
+        | 0x4E00..=0x9FFF
+        | 0xA000..=0xA4CF
+        | 0xAC00..=0xD7A3
+        | 0xF900..=0xFAFF
+        | 0xFE30..=0xFE4F
+        | 0xFF00..=0xFF60
+        | 0xFFE0..=0xFFE6
+        | 0x1F300..=0x1F64F
+        | 0x1F900..=0x1F9FF
+        | 0x20000..=0x3FFFD => 2,
+        _ => 1,
+    }
+}
+
+/// Lay out cells in `ls`-style columns, filled top to bottom, fitting `width`
+///
+/// Returns the lines to print; a single column is used when nothing wider fits.
+pub fn grid_lines(cells: &[String], width: usize) -> Vec<String> {
+    const GAP: usize = 2;
+    if cells.is_empty() {
+        return Vec::new();
+    }
+    let widths: Vec<usize> = cells.iter().map(|c| display_width(c)).collect();
+
+    // Try the widest layout first; column widths follow the longest cell in each column.
+    // No more columns fit than would with every cell as narrow as the narrowest one.
+    let narrowest = widths.iter().copied().min().unwrap_or(0);
+    let max_cols = cells.len().min((width + GAP) / (narrowest + GAP));
+    let mut layout = (cells.len(), vec![widths.iter().copied().max().unwrap_or(0)]);
+    for cols in (2..=max_cols).rev() {
+        let rows = cells.len().div_ceil(cols);
+        let col_widths: Vec<usize> = widths
+            .chunks(rows)
+            .map(|col| col.iter().copied().max().unwrap_or(0))
+            .collect();
+        let total = col_widths.iter().sum::<usize>() + GAP * (col_widths.len() - 1);
+        if total <= width {
+            layout = (rows, col_widths);
+            break;
+        }
+    }
+
+    let (rows, col_widths) = layout;
+    (0..rows)
+        .map(|row| {
+            let mut line = String::new();
+            for (col, col_width) in col_widths.iter().enumerate() {
+                let idx = col * rows + row;
+                if idx >= cells.len() {
+                    break;
+                }
+                if col > 0 {
+                    line.push_str(&" ".repeat(GAP));
+                }
+                line.push_str(&cells[idx]);
+                let is_last_in_row = col + 1 == col_widths.len() || (col + 1) * rows + row >= cells.len();
+                if !is_last_in_row {
+                    line.push_str(&" ".repeat(col_width - widths[idx]));
+                }
+            }
+            line
+        })
+        .collect()
+}
+
+/// Format a directory name, colored when a palette is given
+pub fn format_directory_name(name: &str, colors: Option<&Palette>) -> String {
+    match colors {
+        Some(palette) => paint(name, palette.dir).to_string(),
+        None => name.to_string(),
+    }
+}
+
+/// How an entry's name is colored
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NameKind {
+    Directory,
+    File,
+    /// A file with an executable bit (or a runnable extension on Windows)
+    Executable,
+    Symlink,
+}
+
+impl NameKind {
+    /// Color of an entry of this kind named `name`
+    fn color(self, name: &str, palette: &Palette) -> Option<Color> {
+        match self {
+            NameKind::Directory => palette.dir,
+            NameKind::File => palette.file_color(name),
+            NameKind::Executable => palette.executable,
+            NameKind::Symlink => palette.symlink,
+        }
+    }
+}
+
+/// Format a non-directory name, colored by kind when a palette is given
+///
+/// Executables are also bold so they stand out like in `ls`, even without hues.
+pub fn format_file_name(name: &str, kind: NameKind, colors: Option<&Palette>) -> String {
+    match colors {
+        Some(palette) => paint_file_name(name, kind, kind.color(name, palette)),
+        None => name.to_string(),
     }
 }
 
-/// Format a file size with optional color
-pub fn format_size_colored(size: &str, use_color: bool) -> String {
-    if use_color {
-        size.yellow().to_string()
+fn paint_file_name(text: &str, kind: NameKind, color: Option<Color>) -> String {
+    if kind == NameKind::Executable {
+        paint(text, color).bold().to_string()
     } else {
-        size.to_string()
+        paint(text, color).to_string()
     }
 }
 
+/// Format an entry name, emphasizing the span that matched a search
+///
+/// The surrounding text keeps the usual name color; without color or without
+/// a match this is the same as the plain name formatters.
+pub fn format_name_with_match(
+    name: &str,
+    matched: Option<Range<usize>>,
+    kind: NameKind,
+    colors: Option<&Palette>,
+) -> String {
+    // Resolved from the whole name, so a match can't change a file's extension color
+    let color = colors.and_then(|palette| kind.color(name, palette));
+    let base = |text: &str| match kind {
+        _ if text.is_empty() => String::new(),
+        _ if colors.is_none() => text.to_string(),
+        NameKind::Directory => format_directory_name(text, colors),
+        _ => paint_file_name(text, kind, color),
+    };
+
+    match (matched, colors) {
+        (Some(range), Some(_)) if name.get(range.clone()).is_some() => format!(
+            "{}{}{}",
+            base(&name[..range.start]),
+            paint(&name[range.clone()], color).bold().underline(),
+            base(&name[range.end..])
+        ),
+        _ => base(name),
+    }
+}
+
+/// Format the entry `--context` locates: bold reverse video when colored, else followed by an arrow
+///
+/// Also checks whether color is on at all, since plain text would otherwise hide the emphasis.
+pub fn format_emphasized_name(name: &str, colors: Option<&Palette>) -> String {
+    match colors {
+        Some(_) if colored::control::SHOULD_COLORIZE.should_colorize() => name.bold().reversed().to_string(),
+        _ => format!("{} <--", name),
+    }
+}
+
+/// `ls -F` style indicator for a non-directory entry: `*` executable, `@` symlink, `|` FIFO, `=` socket
+///
+/// `file_type` must not follow symlinks; `mode` is the entry's permission bits.
+/// Only `@` is meaningful on Windows.
+pub fn classify_suffix(file_type: std::fs::FileType, mode: Option<u32>) -> &'static str {
+    if file_type.is_symlink() {
+        return "@";
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::FileTypeExt;
+        if file_type.is_fifo() {
+            return "|";
+        }
+        if file_type.is_socket() {
+            return "=";
+        }
+        if file_type.is_file() && mode.is_some_and(|m| m & 0o111 != 0) {
+            return "*";
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = mode;
+    ""
+}
+
+/// Format a file size, colored by the magnitude of `bytes` when a palette is given
+pub fn format_size_colored(size: &str, bytes: u64, colors: Option<&Palette>) -> String {
+    match colors {
+        Some(palette) => paint(size, palette.size_color(bytes)).to_string(),
+        None => size.to_string(),
+    }
+}
+
+/// Eighths of a block, the glyphs of a `--bars` bar
+const BAR_GLYPHS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+/// Format a `--bars` bar for `size` relative to `largest`: one block glyph from
+/// `▁` to `█`, or with ASCII glyphs one to eight `#`, colored like the size
+///
+/// Empty for empty files, so only files with content get a bar.
+pub fn format_size_bar(size: u64, largest: u64, charset: CharacterSet, colors: Option<&Palette>) -> String {
+    if size == 0 || largest == 0 {
+        return String::new();
+    }
+    let level = ((size as f64 / largest as f64) * 8.0).ceil().clamp(1.0, 8.0) as usize;
+    let bar = match charset {
+        CharacterSet::Unicode => BAR_GLYPHS[level - 1].to_string(),
+        CharacterSet::Ascii => "#".repeat(level),
+    };
+    format_size_colored(&bar, size, colors)
+}
+
+/// Format a content-search match count, colored when a palette is given
+pub fn format_match_count(count: usize, colors: Option<&Palette>) -> String {
+    let label = if count == 1 { "match" } else { "matches" };
+    let text = format!("[{} {}]", count, label);
+    match colors {
+        Some(palette) => paint(&text, palette.match_count).to_string(),
+        None => text,
+    }
+}
+
+/// The `[skipped]` note after a directory listed without its contents, dimmed when colored
+pub fn format_skipped_marker(colors: Option<&Palette>) -> String {
+    match colors {
+        Some(_) => "[skipped]".dimmed().to_string(),
+        None => "[skipped]".to_string(),
+    }
+}
+
+/// The `[stopped]` note after a directory `--stop-at` listed without its contents, dimmed when colored
+pub fn format_stopped_marker(colors: Option<&Palette>) -> String {
+    match colors {
+        Some(_) => "[stopped]".dimmed().to_string(),
+        None => "[stopped]".to_string(),
+    }
+}
+
+/// The `[mount point]` note after a directory `--one-file-system` didn't descend into, dimmed when colored
+pub fn format_mount_point_marker(colors: Option<&Palette>) -> String {
+    match colors {
+        Some(_) => "[mount point]".dimmed().to_string(),
+        None => "[mount point]".to_string(),
+    }
+}
+
+/// Format the `--collapse-over` totals of a directory listed without its contents, like `[41,203 files, 210 MB]`
+pub fn format_collapsed_marker(totals: ExtensionStats, colors: Option<&Palette>) -> String {
+    let noun = if totals.files == 1 { "file" } else { "files" };
+    let text = format!("[{} {}, {}]", format_count(totals.files), noun, format_file_size(totals.size));
+    match colors {
+        Some(_) => text.dimmed().to_string(),
+        None => text,
+    }
+}
+
+/// Format the marker of an entry deleted while the tree was read, colored when a palette is given
+pub fn format_vanished_marker(colors: Option<&Palette>) -> String {
+    match colors {
+        Some(palette) => paint("[vanished]", palette.vanished).to_string(),
+        None => "[vanished]".to_string(),
+    }
+}
+
+/// Format the `--mark-empty` placeholder listed under an empty directory, dimmed when colored
+pub fn format_empty_marker(colors: Option<&Palette>) -> String {
+    match colors {
+        Some(_) => "(empty)".dimmed().to_string(),
+        None => "(empty)".to_string(),
+    }
+}
+
+/// Format a `--counts` child count, dimmed when colored
+pub fn format_child_count(count: usize, colors: Option<&Palette>) -> String {
+    let text = format!("({})", format_count(count));
+    match colors {
+        Some(_) => text.dimmed().to_string(),
+        None => text,
+    }
+}
+
+/// Format a `--dupes` group marker, colored when a palette is given
+pub fn format_dup_marker(group: usize, colors: Option<&Palette>) -> String {
+    let text = format!("[dup #{}]", group);
+    match colors {
+        Some(palette) => paint(&text, palette.dup).to_string(),
+        None => text,
+    }
+}
+
+/// Colors cycled through by `--depth-colors`, one per nesting level
+const DEPTH_PALETTE: [Color; 7] = [
+    Color::Cyan,
+    Color::Magenta,
+    Color::Yellow,
+    Color::Green,
+    Color::Blue,
+    Color::Red,
+    Color::BrightWhite,
+];
+
 /// Tree formatter with efficient single-pass rendering
 pub struct TreeFormatter {
     charset: CharacterSet,
+    depth_colors: bool,
 }
 
 impl TreeFormatter {
     /// Create a new tree formatter with auto-detected character set
     pub fn new() -> Self {
-        Self {
-            charset: CharacterSet::detect(),
-        }
+        Self::with_charset(CharacterSet::detect())
     }
 
     /// Create a tree formatter with a specific character set
     pub fn with_charset(charset: CharacterSet) -> Self {
-        Self { charset }
+        Self {
+            charset,
+            depth_colors: false,
+        }
+    }
+
+    /// Color each level's glyphs from a cycling palette (`--depth-colors`)
+    pub fn with_depth_colors(mut self, enabled: bool) -> Self {
+        self.depth_colors = enabled;
+        self
+    }
+
+    /// Append one level's glyphs, colored by level when depth colors are on
+    fn push_segment(&self, indent: &mut String, level: usize, segment: &str) {
+        if self.depth_colors {
+            let color = DEPTH_PALETTE[level % DEPTH_PALETTE.len()];
+            indent.push_str(&segment.color(color).to_string());
+        } else {
+            indent.push_str(segment);
+        }
     }
 
     /// Generate indentation string for a tree entry
@@ -68,21 +539,23 @@ impl TreeFormatter {
 
         // Build the prefix based on ancestor states
         for i in 0..depth.saturating_sub(1) {
-            if i < is_last.len() && is_last[i] {
-                indent.push_str(self.charset.empty());
+            let segment = if i < is_last.len() && is_last[i] {
+                self.charset.empty()
             } else {
-                indent.push_str(self.charset.continuation());
-            }
+                self.charset.continuation()
+            };
+            self.push_segment(&mut indent, i, segment);
         }
 
         // Add the branch character for this entry
         if depth > 0 {
             let current_is_last = is_last.get(depth - 1).copied().unwrap_or(false);
-            if current_is_last {
-                indent.push_str(self.charset.branch_last());
+            let segment = if current_is_last {
+                self.charset.branch_last()
             } else {
-                indent.push_str(self.charset.branch_middle());
-            }
+                self.charset.branch_middle()
+            };
+            self.push_segment(&mut indent, depth - 1, segment);
         }
 
         indent
@@ -91,45 +564,217 @@ impl TreeFormatter {
     /// Compute which entries are last children at each depth level
     /// This enables proper tree drawing in a single pass
     pub fn compute_last_child_map(&self, entries: &[DirEntry]) -> Vec<Vec<bool>> {
-        if entries.is_empty() {
-            return Vec::new();
+        let depths: Vec<usize> = entries.iter().map(|e| e.depth()).collect();
+        self.compute_last_child_map_for_depths(&depths)
+    }
+
+    /// Compute last-child states from the pre-order depths of a tree
+    ///
+    /// An entry is the last child when no later entry shares its depth before
+    /// the walk climbs back above it; each entry inherits its ancestors' states.
+    pub fn compute_last_child_map_for_depths(&self, depths: &[usize]) -> Vec<Vec<bool>> {
+        // Backward pass: does a later sibling exist at this depth?
+        let mut own_last = vec![false; depths.len()];
+        let mut sibling_follows: Vec<bool> = Vec::new();
+        for (idx, &depth) in depths.iter().enumerate().rev() {
+            if depth == 0 {
+                sibling_follows.clear();
+                continue;
+            }
+            sibling_follows.resize(depth, false);
+            own_last[idx] = !sibling_follows[depth - 1];
+            sibling_follows[depth - 1] = true;
         }
 
-        let mut result = vec![Vec::new(); entries.len()];
+        // Forward pass: combine each entry's state with its ancestors'
+        let mut ancestors: Vec<bool> = Vec::new();
+        depths
+            .iter()
+            .zip(own_last)
+            .map(|(&depth, last)| {
+                if depth == 0 {
+                    ancestors.clear();
+                    return Vec::new();
+                }
+                ancestors.resize(depth, false);
+                ancestors[depth - 1] = last;
+                ancestors.clone()
+            })
+            .collect()
+    }
+}
+
+impl Default for TreeFormatter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output::palette::PaletteName;
 
-        // For each entry, determine if it's the last child at each depth
-        for idx in 0..entries.len() {
-            let current_depth = entries[idx].depth();
-            let mut is_last = vec![false; current_depth];
+    #[test]
+    fn test_size_format_fixes_precision_and_width() {
+        let default = SizeFormat::default();
+        assert_eq!((default.format(1000), default.format(1234), default.format(0)), ("1 kB".into(), "1.23 kB".into(), "0 B".into()));
+        let fixed = SizeFormat { precision: Some(2), width: Some(9) };
+        assert_eq!(fixed.format(1000), "  1.00 kB");
+        assert_eq!(fixed.format(0), "      0 B");
+        let whole = SizeFormat { precision: Some(0), width: None };
+        assert_eq!((whole.format(1_560_000), whole.format(12)), ("2 MB".into(), "12 B".into()));
+    }
+
+    #[test]
+    fn test_format_count_groups_thousands() {
+        assert_eq!(format_count(0), "0");
+        assert_eq!(format_count(999), "999");
+        assert_eq!(format_count(1000), "1,000");
+        assert_eq!(format_count(12430), "12,430");
+        assert_eq!(format_count(1234567), "1,234,567");
+    }
 
-            if current_depth > 0 {
-                // Check if this is the last entry at its depth, or if next entry has lower/equal depth
-                let is_last_at_depth = if idx + 1 < entries.len() {
-                    entries[idx + 1].depth() < current_depth
-                } else {
-                    true // Last entry overall
-                };
+    #[test]
+    fn test_name_limit_truncates_by_display_width() {
+        let limit = |max_width, truncate| NameLimit { max_width, truncate };
+        let middle = limit(15, Truncate::Middle);
+        assert_eq!(middle.apply("verylongfilename.rs"), "verylon…name.rs");
+        assert_eq!(middle.apply("short.rs"), "short.rs");
+        assert!(matches!(middle.apply("exactly-fifteen"), Cow::Borrowed(_)));
+        // End truncation would cut the extension that middle keeps
+        assert_eq!(limit(15, Truncate::End).apply("verylongfilename.rs"), "verylongfilena…");
+        assert_eq!(limit(15, Truncate::Start).apply("verylongfilename.rs"), "…ongfilename.rs");
+        // A long extension still gets the tail, and the stem keeps a character
+        assert_eq!(limit(8, Truncate::Middle).apply("report.component.tsx"), "rep….tsx");
+        assert_eq!(limit(6, Truncate::Middle).apply("abcdefghij.markdown"), "a…down");
+        // Wide characters count as two columns and are never split
+        assert_eq!(limit(7, Truncate::Middle).apply("日本語の名前.txt"), "日….txt");
+        assert_eq!(limit(7, Truncate::End).apply("日本語の名前.txt"), "日本語…");
+        assert_eq!(display_width(&limit(7, Truncate::End).apply("日本語の名前.txt")), 7);
+    }
 
-                is_last[current_depth - 1] = is_last_at_depth;
+    #[test]
+    fn test_display_width_ignores_escapes() {
+        assert_eq!(display_width("\u{1b}[1;34msrc\u{1b}[0m/"), 4);
+        assert_eq!(display_width("héllo"), 5);
+        assert_eq!(display_width("日本.txt"), 8);
+        assert_eq!(display_width("e\u{301}"), 1);
+        assert_eq!(display_width(&hyperlink("a.rs", "file:///a.rs")), 4);
+    }
 
-                // For ancestor depths, check if any following entry exists at that depth
-                for ancestor_depth in 1..current_depth {
-                    let has_following_at_depth = entries[idx + 1..]
-                        .iter()
-                        .any(|e| e.depth() == ancestor_depth);
-                    is_last[ancestor_depth - 1] = !has_following_at_depth;
-                }
-            }
+    #[test]
+    fn test_sanitize_name_escapes_control_characters() {
+        assert_eq!(sanitize_name("evil\x1b[2J.txt"), "evil\\e[2J.txt");
+        assert_eq!(sanitize_name("two\nlines\tand\r"), "two\\nlines\\tand\\r");
+        assert_eq!(sanitize_name("bell\x07\x7f\u{9b}"), "bell\\x07\\x7f\\u{9b}");
+        assert_eq!(sanitize_name("caf\u{e9} \u{65e5}.rs"), "caf\u{e9} \u{65e5}.rs");
+    }
 
-            result[idx] = is_last;
+    #[cfg(unix)]
+    #[test]
+    fn test_file_uri_is_absolute_and_encoded() {
+        assert_eq!(file_uri(Path::new("/tmp/my notes/100%.md")), "file:///tmp/my%20notes/100%25.md");
+        assert_eq!(file_uri(Path::new("/srv/caf\u{e9}")), "file:///srv/caf%C3%A9");
+        let relative = file_uri(Path::new("src/main.rs"));
+        assert!(relative.starts_with("file:///") && relative.ends_with("/src/main.rs"), "{}", relative);
+        assert_eq!(
+            hyperlink("x", "file:///x"),
+            "\u{1b}]8;;file:///x\u{1b}\\x\u{1b}]8;;\u{1b}\\"
+        );
+    }
+
+    #[test]
+    fn test_grid_lines_fill_columns_top_to_bottom() {
+        let cells: Vec<String> = ["a", "bb", "c", "dd", "e"].iter().map(|s| s.to_string()).collect();
+        assert_eq!(grid_lines(&cells, 80), ["a  bb  c  dd  e"]);
+        assert_eq!(grid_lines(&cells, 10), ["a   c   e", "bb  dd"]);
+        assert_eq!(grid_lines(&cells, 1), ["a", "bb", "c", "dd", "e"]);
+
+        // Many cells only try the column counts that could fit
+        let many: Vec<String> = (0..100_000).map(|i| format!("f{:05}", i)).collect();
+        let lines = grid_lines(&many, 80);
+        assert_eq!(lines.len(), 10_000);
+        assert!(lines.iter().all(|line| display_width(line) <= 80));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_classify_suffix() {
+        let dir = std::env::temp_dir().join(format!("lst-classify-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("plain");
+        std::fs::write(&file, "").unwrap();
+        let link = dir.join("link");
+        std::os::unix::fs::symlink(&file, &link).unwrap();
+
+        let file_type = std::fs::symlink_metadata(&file).unwrap().file_type();
+        assert_eq!(classify_suffix(file_type, Some(0o644)), "");
+        assert_eq!(classify_suffix(file_type, Some(0o755)), "*");
+        let link_type = std::fs::symlink_metadata(&link).unwrap().file_type();
+        assert_eq!(classify_suffix(link_type, Some(0o777)), "@");
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_match_highlight_disabled_without_color() {
+        assert_eq!(format_name_with_match("parser.rs", Some(0..4), NameKind::File, None), "parser.rs");
+    }
+
+    #[test]
+    fn test_no_color_is_plain_for_every_palette() {
+        colored::control::set_override(true);
+        for name in [PaletteName::Default, PaletteName::Colorblind] {
+            assert_ne!(format_directory_name("src", Some(&Palette::preset(name))), "src");
         }
+        assert_eq!(format_directory_name("src", None), "src");
+        assert_eq!(format_file_name("run.sh", NameKind::Executable, None), "run.sh");
+        assert_eq!(format_size_colored("1 KB", 1000, None), "1 KB");
+        assert_eq!(format_dup_marker(2, None), "[dup #2]");
+        let mono = Palette::preset(PaletteName::Mono);
+        assert_eq!(format_file_name("a.txt", NameKind::File, Some(&mono)), "a.txt");
+        colored::control::unset_override();
+    }
 
-        result
+    #[test]
+    fn test_match_highlight_wraps_only_matched_span() {
+        colored::control::set_override(true);
+        let out = format_name_with_match("parser.rs", Some(0..4), NameKind::File, Some(&Palette::default()));
+        colored::control::unset_override();
+        assert!(out.starts_with("\u{1b}[1;4;32mpars\u{1b}[0m"));
+        assert!(out.ends_with("\u{1b}[32mer.rs\u{1b}[0m"));
     }
-}
 
-impl Default for TreeFormatter {
-    fn default() -> Self {
-        Self::new()
+    #[test]
+    fn test_depth_colors_vary_by_level_and_wrap() {
+        let plain = TreeFormatter::with_charset(CharacterSet::Ascii);
+        assert_eq!(plain.generate_indent(2, &[false, false]), "|   +-- ");
+
+        colored::control::set_override(true);
+        let formatter = TreeFormatter::with_charset(CharacterSet::Ascii).with_depth_colors(true);
+        let shallow = formatter.generate_indent(2, &[false, false]);
+        let depth = DEPTH_PALETTE.len() + 1;
+        let deep = formatter.generate_indent(depth, &vec![false; depth]);
+        let first = "|   ".color(DEPTH_PALETTE[0]).to_string();
+        let second = "+-- ".color(DEPTH_PALETTE[1]).to_string();
+        let wrapped = "+-- ".color(DEPTH_PALETTE[0]).to_string();
+        colored::control::unset_override();
+
+        assert_eq!(shallow, format!("{}{}", first, second));
+        assert!(deep.starts_with(&first));
+        assert!(deep.ends_with(&wrapped));
+    }
+
+    #[test]
+    fn test_last_child_map_for_nested_last_directory() {
+        let formatter = TreeFormatter::with_charset(CharacterSet::Ascii);
+        // a/ (x/ f) and b/ (y/ g, h): b is the last top-level entry even though children follow
+        let map = formatter.compute_last_child_map_for_depths(&[1, 2, 3, 1, 2, 3, 2]);
+        assert_eq!(map[0], vec![false]);
+        assert_eq!(map[3], vec![true]);
+        assert_eq!(map[4], vec![true, false]);
+        assert_eq!(map[5], vec![true, false, true]);
+        assert_eq!(map[6], vec![true, true]);
     }
 }
@@ -1,10 +1,13 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader, Write};
 use std::path::Path;
 use std::sync::OnceLock;
 use syntect::easy::HighlightLines;
-use syntect::highlighting::{Style, ThemeSet};
-use syntect::parsing::SyntaxSet;
-use syntect::util::{as_24_bit_terminal_escaped, LinesWithEndings};
+use syntect::highlighting::ThemeSet;
+use syntect::parsing::{SyntaxReference, SyntaxSet};
+use syntect::util::as_24_bit_terminal_escaped;
 
+use crate::core::search::BINARY_SNIFF_BYTES;
 use crate::error::{LstError, Result};
 
 /// Global syntax set, loaded once
@@ -15,57 +18,230 @@ static THEME_SET: OnceLock<ThemeSet> = OnceLock::new();
 
 /// Get or initialize the syntax set
 fn get_syntax_set() -> &'static SyntaxSet {
-    SYNTAX_SET.get_or_init(|| SyntaxSet::load_defaults_newlines())
+    SYNTAX_SET.get_or_init(SyntaxSet::load_defaults_newlines)
 }
 
 /// Get or initialize the theme set
 fn get_theme_set() -> &'static ThemeSet {
-    THEME_SET.get_or_init(|| ThemeSet::load_defaults())
+    THEME_SET.get_or_init(ThemeSet::load_defaults)
 }
 
+/// Note printed when a preview stops at `--max-preview-lines`
+const PREVIEW_TRUNCATED_NOTE: &str = "...";
+
 /// Print a file's content with syntax highlighting if the extension is supported
-pub fn print_file_with_highlighting(path: &Path) -> Result<()> {
-    let ext = path.extension().and_then(|e| e.to_str()).unwrap_or("");
-    let content = std::fs::read_to_string(path)?;
-    
+///
+/// `language` overrides the extension when given. The file is streamed line by
+/// line, so memory stays bounded however large it is. Binary files get a
+/// one-line summary instead, unless `force_text` asks for a lossy dump.
+pub fn print_file_with_highlighting(
+    path: &Path,
+    language: Option<&str>,
+    max_lines: Option<usize>,
+    force_text: bool,
+    max_filesize: Option<u64>,
+) -> Result<()> {
+    let stdout = std::io::stdout();
+    let mut out = stdout.lock();
+    preview_file(path, &mut out, language, max_lines, force_text, max_filesize)
+}
+
+fn preview_file<W: Write>(
+    path: &Path,
+    out: &mut W,
+    language: Option<&str>,
+    max_lines: Option<usize>,
+    force_text: bool,
+    max_filesize: Option<u64>,
+) -> Result<()> {
+    let ext = language.or_else(|| path.extension().and_then(|e| e.to_str()));
+    // Checked before opening; `--max-preview-lines` doesn't lift the limit
+    let len = std::fs::metadata(path)?.len();
+    if max_filesize.is_some_and(|max| len > max) {
+        writeln!(out, "[skipped: too large, {} bytes]", len)?;
+        return Ok(());
+    }
+    let file = File::open(path)?;
+    let mut reader = BufReader::with_capacity(BINARY_SNIFF_BYTES, file);
+    if !force_text && looks_binary(reader.fill_buf()?) {
+        writeln!(out, "[binary file, {} bytes]", len)?;
+        return Ok(());
+    }
+    highlight_stream(reader, out, ext, max_lines)
+}
+
+/// Whether the start of a file looks like binary data: a NUL byte or invalid UTF-8
+///
+/// A multi-byte character cut off at the end of `sniff` still counts as text.
+fn looks_binary(sniff: &[u8]) -> bool {
+    if sniff.contains(&0) {
+        return true;
+    }
+    match std::str::from_utf8(sniff) {
+        Ok(_) => false,
+        Err(e) => e.error_len().is_some(),
+    }
+}
+
+/// Print content from a reader (e.g. stdin) with syntax highlighting
+///
+/// The syntax comes from `language` (an extension or name such as `rs` or `rust`),
+/// then from the first line (e.g. a shebang); otherwise the content is printed plain.
+pub fn print_reader_with_highlighting<R: BufRead>(
+    reader: R,
+    language: Option<&str>,
+    max_lines: Option<usize>,
+) -> Result<()> {
+    let stdout = std::io::stdout();
+    let mut out = stdout.lock();
+    highlight_stream(reader, &mut out, language, max_lines)
+}
+
+/// Highlight `reader` into `out` one line at a time, decoding invalid UTF-8 lossily
+///
+/// If highlighting fails midway the remaining lines are printed plain and the
+/// error is returned once the preview is complete.
+fn highlight_stream<R: BufRead, W: Write>(
+    mut reader: R,
+    out: &mut W,
+    language: Option<&str>,
+    max_lines: Option<usize>,
+) -> Result<()> {
+    let mut buf = Vec::new();
+    if reader.read_until(b'\n', &mut buf)? == 0 {
+        return Ok(());
+    }
+
     let ps = get_syntax_set();
-    
-    if ps.find_syntax_by_extension(ext).is_some() {
-        match highlight_content(&content, ext) {
-            Ok(()) => Ok(()),
-            Err(e) => {
-                // Fallback to plain text on error
-                println!("{}", content);
-                Err(LstError::HighlightError(format!(
-                    "Syntax highlighting failed: {}. Displayed plain text instead.",
-                    e
-                )))
+    let ts = get_theme_set();
+    let first_line = String::from_utf8_lossy(&buf).into_owned();
+    let mut highlighter = find_syntax(&first_line, language)
+        .map(|syntax| HighlightLines::new(syntax, &ts.themes["base16-ocean.dark"]));
+    let mut failure = None;
+    let mut printed = 0usize;
+    let mut ends_with_newline = true;
+
+    loop {
+        if max_lines.is_some_and(|max| printed >= max) {
+            if !ends_with_newline {
+                writeln!(out)?;
+            }
+            writeln!(out, "{}", PREVIEW_TRUNCATED_NOTE)?;
+            ends_with_newline = true;
+            break;
+        }
+
+        let line = String::from_utf8_lossy(&buf);
+        match highlighter.as_mut().map(|h| h.highlight_line(&line, ps)) {
+            Some(Ok(ranges)) => write!(out, "{}", as_24_bit_terminal_escaped(&ranges[..], false))?,
+            Some(Err(e)) => {
+                // Fallback to plain text for the rest of the preview
+                highlighter = None;
+                failure = Some(e);
+                write!(out, "{}", line)?;
             }
+            None => write!(out, "{}", line)?,
+        }
+        ends_with_newline = line.ends_with('\n');
+        printed += 1;
+
+        buf.clear();
+        if reader.read_until(b'\n', &mut buf)? == 0 {
+            break;
         }
-    } else {
-        // No syntax support, print plain
-        println!("{}", content);
-        Ok(())
+    }
+
+    if !ends_with_newline {
+        writeln!(out)?;
+    }
+    match failure {
+        Some(e) => Err(LstError::HighlightError(format!(
+            "Syntax highlighting failed: {}. Displayed plain text instead.",
+            e
+        ))),
+        None => Ok(()),
     }
 }
 
-/// Highlight content using syntect with the default theme
-fn highlight_content(content: &str, ext: &str) -> Result<()> {
+/// Pick a syntax from a language hint, falling back to the first line
+fn find_syntax(first_line: &str, language: Option<&str>) -> Option<&'static SyntaxReference> {
     let ps = get_syntax_set();
-    let ts = get_theme_set();
-    
-    let syntax = ps
-        .find_syntax_by_extension(ext)
-        .unwrap_or_else(|| ps.find_syntax_plain_text());
-    
-    let mut highlighter = HighlightLines::new(syntax, &ts.themes["base16-ocean.dark"]);
-    
-    for line in LinesWithEndings::from(content) {
-        let ranges: Vec<(Style, &str)> = highlighter
-            .highlight_line(line, ps)
-            .map_err(|e| LstError::HighlightError(e.to_string()))?;
-        print!("{}", as_24_bit_terminal_escaped(&ranges[..], false));
-    }
-    
-    Ok(())
+    language
+        .and_then(|lang| ps.find_syntax_by_extension(lang).or_else(|| ps.find_syntax_by_token(lang)))
+        .or_else(|| ps.find_syntax_by_first_line(first_line))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_language_hint_picks_syntax() {
+        assert_eq!(find_syntax("", Some("rs")).unwrap().name, "Rust");
+        assert_eq!(find_syntax("", Some("rust")).unwrap().name, "Rust");
+    }
+
+    #[test]
+    fn test_first_line_detection_and_plain_fallback() {
+        assert!(find_syntax("#!/bin/bash\n", None).is_some());
+        assert!(find_syntax("just some words\n", None).is_none());
+    }
+
+    fn preview(input: &[u8], max_lines: Option<usize>) -> String {
+        let mut out = Vec::new();
+        highlight_stream(input, &mut out, None, max_lines).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_plain_preview_is_printed_verbatim() {
+        assert_eq!(preview(b"one\ntwo\n", None), "one\ntwo\n");
+        assert_eq!(preview(b"no newline", None), "no newline\n");
+        assert_eq!(preview(b"", None), "");
+    }
+
+    #[test]
+    fn test_preview_stops_at_max_lines() {
+        assert_eq!(preview(b"1\n2\n3\n", Some(2)), "1\n2\n...\n");
+        assert_eq!(preview(b"1\n2\n", Some(2)), "1\n2\n");
+    }
+
+    #[test]
+    fn test_binary_files_are_summarized_unless_forced() {
+        let dir = std::env::temp_dir().join(format!("lst-binary-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let elf = dir.join("app");
+        std::fs::write(&elf, b"\x7fELF\x02\x01\x00\x00hello\n").unwrap();
+        let latin1 = dir.join("notes");
+        std::fs::write(&latin1, b"caf\xe9\n").unwrap();
+        let render = |path: &Path, force_text| {
+            let mut out = Vec::new();
+            preview_file(path, &mut out, None, None, force_text, Some(64)).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+
+        assert_eq!(render(&elf, false), "[binary file, 14 bytes]\n");
+        assert_eq!(render(&latin1, false), "[binary file, 5 bytes]\n");
+        assert!(render(&elf, true).contains("ELF"));
+        assert_eq!(render(&latin1, true), "caf\u{fffd}\n");
+
+        let big = dir.join("big.txt");
+        std::fs::write(&big, "x".repeat(65)).unwrap();
+        assert_eq!(render(&big, false), "[skipped: too large, 65 bytes]\n");
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_truncated_character_is_not_binary() {
+        assert!(!looks_binary("naïve".as_bytes()));
+        assert!(!looks_binary(&"é".as_bytes()[..1]));
+        assert!(looks_binary(b"\xff\xfe"));
+    }
+
+    #[test]
+    fn test_invalid_utf8_is_decoded_lossily() {
+        assert_eq!(preview(b"ok \xff\n", None), "ok \u{fffd}\n");
+    }
 }
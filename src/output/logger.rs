@@ -0,0 +1,61 @@
+use log::{Level, LevelFilter, Log, Metadata, Record};
+
+/// Minimal logger writing `-v` diagnostics to stderr
+struct StderrLogger;
+
+impl Log for StderrLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        if self.enabled(record.metadata()) {
+            eprintln!("[{}] {}", level_label(record.level()), record.args());
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+static LOGGER: StderrLogger = StderrLogger;
+
+/// Level shown for a `-v` count: silent by default, then info, debug and trace
+pub fn level_for(verbosity: u8) -> LevelFilter {
+    match verbosity {
+        0 => LevelFilter::Off,
+        1 => LevelFilter::Info,
+        2 => LevelFilter::Debug,
+        _ => LevelFilter::Trace,
+    }
+}
+
+/// Install the stderr logger at the level chosen by `-v` / `-vv` / `-vvv`
+pub fn init(verbosity: u8) {
+    // A logger may already be set when lst is embedded as a library; keep it
+    if log::set_logger(&LOGGER).is_ok() {
+        log::set_max_level(level_for(verbosity));
+    }
+}
+
+fn level_label(level: Level) -> &'static str {
+    match level {
+        Level::Error => "error",
+        Level::Warn => "warn",
+        Level::Info => "info",
+        Level::Debug => "debug",
+        Level::Trace => "trace",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_verbosity_is_silent() {
+        assert_eq!(level_for(0), LevelFilter::Off);
+        assert_eq!(level_for(1), LevelFilter::Info);
+        assert_eq!(level_for(2), LevelFilter::Debug);
+        assert_eq!(level_for(7), LevelFilter::Trace);
+    }
+}
@@ -1,4 +1,9 @@
+pub mod columns;
 pub mod formatter;
 pub mod highlight;
+pub mod logger;
+pub mod palette;
 pub mod printer;
+pub mod schema;
+pub mod spinner;
 pub mod terminal;
@@ -0,0 +1,248 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use colored::{Color, ColoredString, Colorize};
+
+use crate::error::{LstError, Result};
+
+/// Environment variable holding per-color overrides, e.g. `dir=cyan:size=none:*.rs=red`
+pub const COLORS_ENV: &str = "LST_COLORS";
+
+/// Built-in color schemes (`--palette`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum PaletteName {
+    /// Blue directories, green files, sizes from green to red as they grow
+    #[default]
+    Default,
+    /// Blue/orange scheme that avoids red-green distinctions
+    Colorblind,
+    /// No hues; emphasis comes from bold and underline only
+    Mono,
+}
+
+/// Colors used for each part of an entry line; `None` keeps the terminal's default
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Palette {
+    pub dir: Option<Color>,
+    pub file: Option<Color>,
+    pub executable: Option<Color>,
+    pub symlink: Option<Color>,
+    /// Sizes below [`SizeThresholds::medium`]
+    pub size_small: Option<Color>,
+    pub size_medium: Option<Color>,
+    /// Sizes from [`SizeThresholds::large`] up
+    pub size_large: Option<Color>,
+    pub size_thresholds: SizeThresholds,
+    pub match_count: Option<Color>,
+    pub dup: Option<Color>,
+    /// The `[vanished]` marker of entries deleted mid-walk
+    pub vanished: Option<Color>,
+    /// Colors of regular files by lowercase extension, taking precedence over `file`
+    pub extensions: HashMap<String, Option<Color>>,
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Self::preset(PaletteName::Default)
+    }
+}
+
+impl Palette {
+    /// The colors of a built-in scheme
+    pub fn preset(name: PaletteName) -> Self {
+        match name {
+            PaletteName::Default => Self {
+                dir: Some(Color::Blue),
+                file: Some(Color::Green),
+                executable: Some(Color::BrightGreen),
+                symlink: Some(Color::Cyan),
+                size_small: Some(Color::Green),
+                size_medium: Some(Color::Yellow),
+                size_large: Some(Color::Red),
+                size_thresholds: SizeThresholds::default(),
+                match_count: Some(Color::Magenta),
+                dup: Some(Color::Red),
+                vanished: Some(Color::Yellow),
+                extensions: HashMap::new(),
+            },
+            // Okabe-Ito colors, distinguishable with the common color vision deficiencies
+            PaletteName::Colorblind => Self {
+                dir: Some(Color::TrueColor { r: 0, g: 114, b: 178 }),
+                file: Some(Color::TrueColor { r: 230, g: 159, b: 0 }),
+                executable: Some(Color::TrueColor { r: 213, g: 94, b: 0 }),
+                symlink: Some(Color::TrueColor { r: 86, g: 180, b: 233 }),
+                size_small: Some(Color::TrueColor { r: 86, g: 180, b: 233 }),
+                size_medium: Some(Color::TrueColor { r: 240, g: 228, b: 66 }),
+                size_large: Some(Color::TrueColor { r: 213, g: 94, b: 0 }),
+                size_thresholds: SizeThresholds::default(),
+                match_count: Some(Color::TrueColor { r: 204, g: 121, b: 167 }),
+                dup: Some(Color::TrueColor { r: 213, g: 94, b: 0 }),
+                vanished: Some(Color::TrueColor { r: 240, g: 228, b: 66 }),
+                extensions: HashMap::new(),
+            },
+            PaletteName::Mono => Self {
+                dir: None,
+                file: None,
+                executable: None,
+                symlink: None,
+                size_small: None,
+                size_medium: None,
+                size_large: None,
+                size_thresholds: SizeThresholds::default(),
+                match_count: None,
+                dup: None,
+                vanished: None,
+                extensions: HashMap::new(),
+            },
+        }
+    }
+
+    /// Apply `key=color` overrides separated by `:` (keys: dir, file, exec, symlink, size_small,
+    /// size_medium, size_large, size for all three, match, dup, vanished, or `*.ext` for files with that extension)
+    ///
+    /// Colors are names such as `red` or `bright blue`, or `none` for the default color.
+    /// An unknown color name is logged as a warning (`-v`) and leaves that color as it was.
+    pub fn with_overrides(mut self, spec: &str) -> Result<Self> {
+        for item in spec.split(':').map(str::trim).filter(|s| !s.is_empty()) {
+            let (key, value) = item
+                .split_once('=')
+                .ok_or_else(|| LstError::InvalidColors(format!("expected key=color in {}, got '{}'", COLORS_ENV, item)))?;
+            let Some(color) = parse_color(value.trim()) else {
+                log::warn!("unknown color '{}' in {}; keeping the default", value.trim(), COLORS_ENV);
+                continue;
+            };
+            if let Some(ext) = key.trim().strip_prefix("*.") {
+                self.extensions.insert(ext.to_lowercase(), color);
+                continue;
+            }
+            if key.trim() == "size" {
+                (self.size_small, self.size_medium, self.size_large) = (color, color, color);
+                continue;
+            }
+            let slot = match key.trim() {
+                "dir" => &mut self.dir,
+                "file" => &mut self.file,
+                "exec" => &mut self.executable,
+                "symlink" => &mut self.symlink,
+                "size_small" => &mut self.size_small,
+                "size_medium" => &mut self.size_medium,
+                "size_large" => &mut self.size_large,
+                "match" => &mut self.match_count,
+                "dup" => &mut self.dup,
+                "vanished" => &mut self.vanished,
+                other => return Err(LstError::InvalidColors(format!("unknown color key '{}' in {}", other, COLORS_ENV))),
+            };
+            *slot = color;
+        }
+        Ok(self)
+    }
+
+    /// Color of a size of `bytes`, by where it falls among the thresholds
+    pub fn size_color(&self, bytes: u64) -> Option<Color> {
+        if bytes >= self.size_thresholds.large {
+            self.size_large
+        } else if bytes >= self.size_thresholds.medium {
+            self.size_medium
+        } else {
+            self.size_small
+        }
+    }
+
+    /// Color sizes with different thresholds (`--size-thresholds`)
+    pub fn with_size_thresholds(mut self, thresholds: SizeThresholds) -> Self {
+        self.size_thresholds = thresholds;
+        self
+    }
+
+    /// Color of a regular file named `name`: its extension's color if one is set, else `file`
+    pub fn file_color(&self, name: &str) -> Option<Color> {
+        Path::new(name)
+            .extension()
+            .and_then(|ext| self.extensions.get(&ext.to_string_lossy().to_lowercase()))
+            .copied()
+            .unwrap_or(self.file)
+    }
+}
+
+/// Byte counts at which sizes switch to the medium and to the large color (`--size-thresholds`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SizeThresholds {
+    pub medium: u64,
+    pub large: u64,
+}
+
+impl Default for SizeThresholds {
+    /// 1 MiB and 100 MiB
+    fn default() -> Self {
+        Self { medium: 1 << 20, large: 100 << 20 }
+    }
+}
+
+/// A color name, `none` as `Some(None)`; `None` when the name is unknown
+fn parse_color(value: &str) -> Option<Option<Color>> {
+    if value.eq_ignore_ascii_case("none") {
+        return Some(None);
+    }
+    value.replace(['_', '-'], " ").parse::<Color>().ok().map(Some)
+}
+
+/// Color `text`, or leave it in the default color for `None`
+pub fn paint(text: &str, color: Option<Color>) -> ColoredString {
+    match color {
+        Some(color) => text.color(color),
+        None => text.normal(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_overrides_replace_single_colors() {
+        let palette = Palette::preset(PaletteName::Default)
+            .with_overrides("dir=cyan: size=none :exec=bright_red:size_large=magenta")
+            .unwrap();
+        assert_eq!(palette.dir, Some(Color::Cyan));
+        assert_eq!((palette.size_small, palette.size_medium), (None, None));
+        assert_eq!(palette.size_large, Some(Color::Magenta));
+        assert_eq!(palette.executable, Some(Color::BrightRed));
+        assert_eq!(palette.file, Some(Color::Green));
+
+        assert!(matches!(Palette::default().with_overrides("dir"), Err(LstError::InvalidColors(_))));
+        assert_eq!(Palette::default().with_overrides("dir=mauve").unwrap(), Palette::default());
+        assert!(matches!(Palette::default().with_overrides("folder=red"), Err(LstError::InvalidColors(_))));
+    }
+
+    #[test]
+    fn test_extension_colors_override_file_color() {
+        let palette = Palette::default().with_overrides("*.RS=red:*.md=none:*.txt=mauve").unwrap();
+        assert_eq!(palette.file_color("main.rs"), Some(Color::Red));
+        assert_eq!(palette.file_color("LIB.Rs"), Some(Color::Red));
+        assert_eq!(palette.file_color("README.md"), None);
+        assert_eq!(palette.file_color("notes.txt"), Some(Color::Green));
+        assert_eq!(palette.file_color("Makefile"), Some(Color::Green));
+    }
+
+    #[test]
+    fn test_size_color_follows_thresholds() {
+        let palette = Palette::default();
+        assert_eq!(palette.size_color(0), Some(Color::Green));
+        assert_eq!(palette.size_color((1 << 20) - 1), Some(Color::Green));
+        assert_eq!(palette.size_color(1 << 20), Some(Color::Yellow));
+        assert_eq!(palette.size_color(100 << 20), Some(Color::Red));
+
+        let tight = palette.with_size_thresholds(SizeThresholds { medium: 10, large: 20 });
+        assert_eq!(tight.size_color(15), Some(Color::Yellow));
+        assert_eq!(tight.size_color(20), Some(Color::Red));
+    }
+
+    #[test]
+    fn test_colorblind_preset_avoids_red_and_green() {
+        let palette = Palette::preset(PaletteName::Colorblind);
+        for color in [palette.dir, palette.file, palette.executable, palette.symlink, palette.dup, palette.size_small, palette.size_large] {
+            assert!(!matches!(color, Some(Color::Red | Color::Green | Color::BrightRed | Color::BrightGreen)));
+        }
+        assert_eq!(Palette::preset(PaletteName::Mono).file, None);
+    }
+}
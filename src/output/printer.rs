@@ -1,464 +1,2525 @@
-use std::collections::HashSet;
-use std::io::Write;
+use std::borrow::Cow;
+use std::io::{BufWriter, Write};
+use std::collections::HashMap;
+use std::ops::Range;
 use std::path::{Path, PathBuf};
-use walkdir::{DirEntry, WalkDir};
-use ignore::{WalkBuilder, DirEntry as IgnoreDirEntry};
-use serde_json::json;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::Duration;
+use serde::Serialize;
+use walkdir::DirEntry;
+use ignore::DirEntry as IgnoreDirEntry;
 
 use super::formatter::{
-    format_directory_name, format_file_name, format_file_size, 
-    format_size_colored, TreeFormatter,
+    classify_suffix, display_width, format_child_count, format_count, format_directory_name, file_uri, format_dup_marker, sanitize_name, format_emphasized_name, format_empty_marker, hyperlink, grid_lines, format_collapsed_marker, format_file_name, format_size_bar, format_file_size, format_match_count, format_skipped_marker, format_stopped_marker, format_mount_point_marker, format_vanished_marker, NameLimit, SizeFormat,
+    format_name_with_match, format_size_colored, NameKind, TreeFormatter,
 };
-use super::terminal::CharacterSet;
-use crate::core::search::{build_search_filter, should_print_entry};
-use crate::core::tree::collect_entries;
-use crate::core::filters::should_show_entry;
-use crate::error::Result;
+use super::columns::{render_cells, Cell, Column, Columns};
+use super::palette::Palette;
+use super::spinner::clear_line;
+use super::terminal::{terminal_width, CharacterSet, ColorMode, TreeStyle};
+use crate::core::cancel::cancelled;
+use crate::core::search::{build_search_filter, should_print_entry, SearchFilter, SearchQuery, MAX_CONTENT_BYTES};
+use crate::core::meta::{EntryMeta, FsMetadata, MetaRead, MetadataSource, TimeField};
+use crate::core::entry::FsEntry;
+use crate::core::exec::quote_arg;
+use crate::core::filters::{is_mount_point, is_shown_skipped, is_stopped, GlobFilter, ShowOptions};
+use crate::core::listing::{root_name, tilde_path, Annotations, NodeKind, TreeData, TreeNode};
+use crate::core::stats::{ExtensionStats, StatsTally, Summary};
+use crate::core::timing::Timings;
+use crate::core::tree::{search_candidates, tick, walk_visible, UNLIMITED_DEPTH};
+use crate::core::virtual_tree::VirtualEntry;
+use crate::error::{LstError, Result};
 
 /// Output format options
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
 pub enum OutputFormat {
     /// Plain text with tree characters
+    #[default]
     Text,
     /// JSON structured output
     Json,
 }
 
+impl OutputFormat {
+    /// The format an `-o` file's extension asks for (`.json`, case-insensitive), if any
+    pub fn from_extension(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()?.to_ascii_lowercase().as_str() {
+            "json" => Some(OutputFormat::Json),
+            "txt" => Some(OutputFormat::Text),
+            _ => None,
+        }
+    }
+}
+
+/// How `-o` treats an output file that already exists
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ExportMode {
+    /// Refuse to touch an existing file
+    #[default]
+    CreateNew,
+    /// Truncate an existing file (`--force`)
+    Overwrite,
+    /// Add to the end of an existing file (`--append`)
+    Append,
+}
+
+/// Open `path` for export, honoring the overwrite protection of `mode`
+pub fn open_export(path: &str, mode: ExportMode) -> Result<std::fs::File> {
+    let mut options = std::fs::OpenOptions::new();
+    match mode {
+        ExportMode::CreateNew => options.write(true).create_new(true),
+        ExportMode::Overwrite => options.write(true).create(true).truncate(true),
+        ExportMode::Append => options.append(true).create(true),
+    };
+    options.open(path).map_err(|e| {
+        if e.kind() == std::io::ErrorKind::AlreadyExists {
+            LstError::OutputExists(path.to_string())
+        } else {
+            e.into()
+        }
+    })
+}
+
+/// Default capacity of the terminal output buffer (`--output-buffer`)
+pub const DEFAULT_OUTPUT_BUFFER: usize = 64 * 1024;
+
+/// How long buffered terminal output may wait before it is flushed
+const FLUSH_INTERVAL: Duration = Duration::from_millis(100);
+
+/// A buffered writer that a background thread flushes every `FLUSH_INTERVAL`
+///
+/// Keeps large trees from paying a syscall per line while a slow walk still
+/// shows its progress on screen, even when it pauses between lines. The
+/// thread stops once the writer is dropped.
+struct TimedFlush<W: Write + Send + 'static> {
+    inner: Arc<Mutex<BufWriter<W>>>,
+}
+
+impl<W: Write + Send + 'static> TimedFlush<W> {
+    fn new(inner: W, capacity: usize) -> Self {
+        let inner = Arc::new(Mutex::new(BufWriter::with_capacity(capacity, inner)));
+        let weak = Arc::downgrade(&inner);
+        std::thread::spawn(move || loop {
+            std::thread::sleep(FLUSH_INTERVAL);
+            let Some(inner) = weak.upgrade() else { break };
+            let mut inner = inner.lock().unwrap_or_else(PoisonError::into_inner);
+            if !inner.buffer().is_empty() && inner.flush().is_err() {
+                break;
+            }
+        });
+        Self { inner }
+    }
+
+    fn buffered(&self) -> MutexGuard<'_, BufWriter<W>> {
+        self.inner.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl<W: Write + Send + 'static> Write for TimedFlush<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.buffered().write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.buffered().flush()
+    }
+}
+
+/// Write `value` as pretty JSON, or on one line with `compact`, followed by a single newline
+pub fn write_json_value<W: Write, T: Serialize>(writer: &mut W, value: &T, compact: bool) -> Result<()> {
+    let json = if compact { serde_json::to_string(value) } else { serde_json::to_string_pretty(value) };
+    writeln!(writer, "{}", json.map_err(std::io::Error::other)?)?;
+    Ok(())
+}
+
+/// What heads a text tree
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RootHeader<'a> {
+    /// The root directory's name, e.g. `src/`
+    #[default]
+    Name,
+    /// No header line (`--no-header`)
+    Hidden,
+    /// Custom text (`--header`), also used as the root's JSON name
+    Custom(&'a str),
+}
+
+/// Line printed after a search cut short by `--max-results`
+const TRUNCATION_NOTE: &str = "... and more";
+
+/// Files shown by `--preview` at most, in walk order
+pub const MAX_PREVIEWS: usize = 3;
+
 /// Configuration for tree printing
 pub struct TreeConfig<'a> {
     pub path: &'a Path,
     pub max_depth: usize,
-    pub show_all: bool,
-    pub search_pattern: Option<&'a str>,
-    pub spinner_stop: Option<std::sync::Arc<std::sync::atomic::AtomicBool>>,
-    pub json_output: bool,
+    pub show: ShowOptions,
+    pub search: Option<SearchQuery<'a>>,
+    pub spinner_stop: Option<Arc<AtomicBool>>,
+    /// Counter of scanned entries, shown by the spinner
+    pub progress: Option<Arc<AtomicUsize>>,
+    /// Set once a search finds at least one entry that matched itself
+    pub found: Option<Arc<AtomicBool>>,
+    /// The first listed files matching `preview`, gathered while the tree is written
+    pub previewed: Option<Arc<Mutex<Vec<PathBuf>>>>,
+    /// Time spent per phase, collected for `--time-report`
+    pub timings: Option<Arc<Mutex<Timings>>>,
+    /// Whether commands may show a progress spinner on stderr
+    pub show_spinner: bool,
+    /// What `-o` does when the output file already exists
+    pub export_mode: ExportMode,
+    /// Color mode resolved from `--color`
+    pub color: ColorMode,
+    /// Append type indicators like `ls -F`
+    pub classify: bool,
+    /// Flat `ls -1` style listing without tree glyphs
+    pub oneline: bool,
+    /// Immediate children in `ls`-style columns
+    pub grid: bool,
+    /// Follow the tree with a per-extension size breakdown
+    pub stats: bool,
+    /// Count every hard link of a file in `--stats` totals instead of each file once
+    pub count_links: bool,
+    /// Walk into skipped directories for `--stats` totals even though the tree doesn't show them
+    pub stats_include_skipped: bool,
+    /// Mark files with identical contents
+    pub dupes: bool,
+    /// Show each expanded directory's number of listed children
+    pub counts: bool,
+    /// List an `(empty)` placeholder under expanded directories with no listed children
+    pub mark_empty: bool,
+    /// Pad file names so sibling sizes line up
+    pub pad_names: bool,
+    /// Print each entry's path relative to the root instead of its name
+    pub full_path: bool,
+    /// Show the root's full path with the home directory as `~`, in the header and JSON paths
+    pub tilde: bool,
+    /// Color branch glyphs by nesting level
+    pub depth_colors: bool,
+    /// Print names without sizes and skip stat-ing files where possible
+    pub no_size: bool,
+    /// Follow file sizes with a bar scaled to the largest sibling file (`--bars`)
+    pub bars: bool,
+    /// Lowercase extensions, without the dot, of files printed without a size (`--no-size-for`)
+    pub no_size_for: Vec<String>,
+    /// Shorten names wider than this, where `--truncate` says (`--max-name-width`)
+    pub name_limit: Option<NameLimit>,
+    /// Fields of each entry line and their order (`--columns`)
+    pub columns: Columns,
+    /// Timestamp of the time column (`--time-field`)
+    pub time_field: TimeField,
+    /// Precision and width of the size column (`--size-precision`, `--size-width`)
+    pub size_format: SizeFormat,
+    /// Colors for names, sizes and markers
+    pub palette: Palette,
+    /// Branch glyphs; `Auto` follows the destination
+    pub tree_style: TreeStyle,
+    /// Syntax hint for file previews (an extension such as `rs`)
+    pub language: Option<&'a str>,
+    /// Stop file previews after this many lines
+    pub max_preview_lines: Option<usize>,
+    /// Preview binary files as (lossy) text instead of summarizing them
+    pub force_text: bool,
+    /// Files above this size aren't previewed (`--max-filesize`); `None` for no limit
+    pub max_filesize: Option<u64>,
+    /// Files shown highlighted below a text tree (`--preview`); the default matches none
+    pub preview: GlobFilter,
+    /// Show a file inside the tree this many levels above it instead of previewing it (`--context`)
+    pub context: Option<usize>,
+    /// Entry whose name is emphasized, such as the file `--context` locates
+    pub emphasized: Option<&'a Path>,
+    /// Make names clickable `file://` links (`--hyperlinks`); only set for terminal output
+    pub hyperlinks: bool,
+    /// Follow the tree with `tree`'s `N directories, M files` line, or a `summary` on the JSON root
+    pub report: bool,
+    /// Print only the counts of [`Self::report`], without the tree
+    pub report_only: bool,
+    /// Escape control characters in names; set for terminal output unless `--show-control-chars`
+    pub escape_names: bool,
+    /// Shell-quote names, or whole paths with `full_path` (`--quote-names`)
+    pub quote_names: bool,
+    pub format: OutputFormat,
+    /// First line of a text tree, and the root's name in JSON
+    pub header: RootHeader<'a>,
+    /// Print JSON on a single line instead of pretty-printed
+    pub compact: bool,
+    /// Bytes of terminal output batched per write
+    pub output_buffer: usize,
 }
 
 impl<'a> TreeConfig<'a> {
-    /// Get the output format
-    pub fn format(&self) -> OutputFormat {
-        if self.json_output {
-            OutputFormat::Json
-        } else {
-            OutputFormat::Text
+    /// Create a config for `path` with default options
+    pub fn new(path: &'a Path) -> Self {
+        Self {
+            path,
+            max_depth: 1,
+            show: ShowOptions::default(),
+            search: None,
+            spinner_stop: None,
+            progress: None,
+            found: None,
+            previewed: None,
+            timings: None,
+            show_spinner: true,
+            export_mode: ExportMode::CreateNew,
+            color: ColorMode::Auto,
+            classify: false,
+            oneline: false,
+            grid: false,
+            stats: false,
+            count_links: false,
+            stats_include_skipped: false,
+            dupes: false,
+            counts: false,
+            mark_empty: false,
+            pad_names: false,
+            full_path: false,
+            tilde: false,
+            depth_colors: false,
+            no_size: false,
+            bars: false,
+            no_size_for: Vec::new(),
+            name_limit: None,
+            columns: Columns::default(),
+            time_field: TimeField::Modified,
+            size_format: SizeFormat::default(),
+            palette: Palette::default(),
+            tree_style: TreeStyle::Auto,
+            language: None,
+            max_preview_lines: None,
+            force_text: false,
+            max_filesize: Some(MAX_CONTENT_BYTES),
+            preview: GlobFilter::default(),
+            context: None,
+            emphasized: None,
+            hyperlinks: false,
+            report: false,
+            report_only: false,
+            escape_names: false,
+            quote_names: false,
+            format: OutputFormat::Text,
+            header: RootHeader::Name,
+            compact: false,
+            output_buffer: DEFAULT_OUTPUT_BUFFER,
+        }
+    }
+}
+
+impl TreeConfig<'_> {
+    /// Whether any output needs per-file metadata; `--no-size` alone skips the stat
+    fn needs_metadata(&self) -> bool {
+        !self.no_size || self.bars || self.classify || self.stats || self.dupes || self.columns.needs_entry_meta()
+    }
+
+    /// Whether streaming writers gather `--stats` from the files they print; with
+    /// `--stats-include-skipped` the table needs a walk that doesn't skip anything
+    fn tallies_stats(&self) -> bool {
+        self.stats && !self.stats_include_skipped
+    }
+
+    /// Collect the entries, search filter and metadata this config asks for
+    fn collect(&self, source: &dyn MetadataSource) -> TreeData {
+        let mut data = TreeData::collect(
+            self.path,
+            self.max_depth,
+            &self.show,
+            self.search.as_ref(),
+            self.progress.as_deref(),
+            self.needs_metadata().then_some(source),
+            self.columns.needs_entry_meta(),
+        );
+        if self.dupes {
+            data.mark_duplicates(self.search.as_ref());
+        }
+        if self.counts {
+            data.count_children(self.path, self.search.as_ref(), &self.show, self.max_depth);
+        }
+        if self.mark_empty {
+            data.mark_empty(self.path, self.search.as_ref(), &self.show, self.max_depth);
+        }
+        self.note_matches(&data.filter);
+        self.record_timings(&data.timings);
+        data
+    }
+
+    /// Add to the `--time-report` totals, if the run keeps them
+    pub fn record_timings(&self, timings: &Timings) {
+        if let Some(total) = &self.timings {
+            total.lock().unwrap_or_else(PoisonError::into_inner).add(timings);
+        }
+    }
+
+    /// Per-extension totals for `--stats`
+    ///
+    /// These come from the displayed entries in `collected`, or from a fresh walk when
+    /// nothing was collected yet. With `--stats-include-skipped` the walk always runs
+    /// again and enters the skipped directories the tree leaves out.
+    fn extension_stats(&self, collected: Option<&TreeData>, source: &dyn MetadataSource) -> Vec<(String, ExtensionStats)> {
+        let walked;
+        let data = match (collected, self.stats_include_skipped) {
+            (Some(data), false) => data,
+            (_, true) => {
+                let show = ShowOptions { no_skip: true, show_skipped: false, ..self.show.clone() };
+                walked = TreeData::collect(self.path, self.max_depth, &show, self.search.as_ref(), None, Some(source), false);
+                self.record_timings(&walked.timings);
+                &walked
+            }
+            (None, false) => {
+                walked = self.collect(source);
+                &walked
+            }
+        };
+        data.extension_stats(self.search.as_ref(), self.count_links)
+    }
+
+    /// Counts for `--report`, from the displayed entries in `collected` or from a fresh walk
+    fn summary(&self, collected: Option<&TreeData>) -> Summary {
+        let walked;
+        let data = match collected {
+            Some(data) => data,
+            None => {
+                walked = TreeData::collect(self.path, self.max_depth, &self.show, self.search.as_ref(), None, None, false);
+                self.note_matches(&walked.filter);
+                self.record_timings(&walked.timings);
+                &walked
+            }
+        };
+        data.summary(self.search.as_ref(), self.show.walks_hidden())
+    }
+
+    /// Stop the spinner, if any, before the first line of output so it doesn't draw over it
+    fn stop_spinner(&self) {
+        if let Some(stop) = &self.spinner_stop {
+            stop.store(true, Ordering::Relaxed);
+            clear_line();
+        }
+    }
+
+    /// Record in `found` whether the search pre-pass matched anything
+    fn note_matches(&self, filter: &SearchFilter) {
+        if let (Some(found), Some(_)) = (&self.found, &self.search) {
+            if !filter.matches.is_empty() {
+                found.store(true, Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Record in `previewed` the listed files `--preview` asks for, up to [`MAX_PREVIEWS`]
+    fn note_previews<'e, E: FsEntry + 'e>(&self, listed: impl IntoIterator<Item = &'e E>) {
+        let Some(previewed) = &self.previewed else { return };
+        let mut previewed = previewed.lock().unwrap_or_else(PoisonError::into_inner);
+        let wanted = listed
+            .into_iter()
+            .filter(|e| e.file_type().is_some_and(|ft| ft.is_file()) && self.preview.is_match(e.path(), e.depth()))
+            .take(MAX_PREVIEWS.saturating_sub(previewed.len()))
+            .map(|e| e.path().to_path_buf());
+        previewed.extend(wanted);
+    }
+}
+
+/// Per-entry layout and the annotations printed after an entry's size
+#[derive(Debug, Clone, Copy, Default)]
+struct EntryMarks {
+    /// Columns a file's name (with its classify suffix) is padded to (`--pad-names`)
+    name_width: usize,
+    /// Content match count, when the search shows counts
+    match_count: Option<usize>,
+    /// `--dupes` group number
+    dup_group: Option<usize>,
+    /// `--counts` number of listed children, for expanded directories
+    child_count: Option<usize>,
+    /// A heavy directory listed without its contents (`--show-skipped`)
+    skipped: bool,
+    /// A directory listed without its contents (`--stop-at`, `--stop-at-marker`)
+    stopped: bool,
+    /// A mount point `--one-file-system` didn't descend into
+    mount_point: bool,
+    /// Totals of a large directory listed without its contents (`--collapse-over`)
+    collapsed: Option<ExtensionStats>,
+    /// Size of the largest file among a file's siblings, which its `--bars` bar is scaled to
+    largest_sibling: Option<u64>,
+    /// Deleted between the walk and its stat; printed instead of the size
+    vanished: bool,
+}
+
+impl EntryMarks {
+    fn write<W: Write>(&self, writer: &mut W, colors: Option<&Palette>) -> std::io::Result<()> {
+        if self.vanished {
+            write!(writer, " {}", format_vanished_marker(colors))?;
+        }
+        if self.skipped {
+            write!(writer, " {}", format_skipped_marker(colors))?;
         }
+        if self.stopped {
+            write!(writer, " {}", format_stopped_marker(colors))?;
+        }
+        if self.mount_point {
+            write!(writer, " {}", format_mount_point_marker(colors))?;
+        }
+        if let Some(totals) = self.collapsed {
+            write!(writer, " {}", format_collapsed_marker(totals, colors))?;
+        }
+        if let Some(count) = self.child_count {
+            write!(writer, " {}", format_child_count(count, colors))?;
+        }
+        if let Some(count) = self.match_count {
+            write!(writer, " {}", format_match_count(count, colors))?;
+        }
+        if let Some(group) = self.dup_group {
+            write!(writer, " {}", format_dup_marker(group, colors))?;
+        }
+        Ok(())
     }
 }
 
-/// Holds the collected tree data
-struct TreeData {
-    entries: Vec<DirEntry>,
-    show_dirs: HashSet<PathBuf>,
+/// How individual entry lines are decorated
+#[derive(Debug, Clone, Copy)]
+pub struct EntryStyle<'a> {
+    pub use_color: bool,
+    /// Glyphs for the tree's branches
+    pub charset: CharacterSet,
+    /// Colors applied when `use_color` is set
+    pub palette: &'a Palette,
+    /// Print paths relative to this root instead of bare names (`--full-path`)
+    pub full_path_root: Option<&'a Path>,
+    /// Entry whose name stands out from the rest
+    pub emphasized: Option<&'a Path>,
+    /// Wrap names in OSC 8 hyperlinks to their files
+    pub hyperlinks: bool,
+    /// Print control characters in names as escapes (see [`sanitize_name`])
+    pub escape_names: bool,
+    /// Shell-quote names; with [`Self::full_path_root`] the whole relative path is one quoted word
+    pub quote_names: bool,
+    /// Append `ls -F` type indicators to non-directory names
+    pub classify: bool,
+    /// Print entries flat, without tree glyphs (`-1`)
+    pub oneline: bool,
+    /// Pad file names so sibling sizes line up
+    pub pad_names: bool,
+    /// Color branch glyphs by nesting level
+    pub depth_colors: bool,
+    /// Leave out file sizes
+    pub no_size: bool,
+    /// Follow file sizes with a bar relative to the largest sibling
+    pub bars: bool,
+    /// Leave out the sizes of files with these extensions (see [`TreeConfig::no_size_for`])
+    pub no_size_for: &'a [String],
+    /// Shorten longer names (`--max-name-width`)
+    pub name_limit: Option<NameLimit>,
+    /// Fields around each name
+    pub columns: &'a Columns,
+    /// Timestamp of the time column
+    pub time_field: TimeField,
+    pub size_format: SizeFormat,
 }
 
-impl TreeData {
-    fn collect(config: &TreeConfig) -> Self {
-        let entries = collect_entries(config.path, config.max_depth, config.show_all);
-        let show_dirs = if let Some(pattern) = config.search_pattern {
-            build_search_filter(&entries, pattern, config.show_all)
+impl EntryStyle<'_> {
+    /// The formatted name of the entry at `path`, emphasized when it's [`Self::emphasized`]
+    /// and linked to the file with [`Self::hyperlinks`]
+    fn name(&self, path: &Path, name: &str, matched: Option<Range<usize>>, kind: NameKind) -> String {
+        let formatted = if self.emphasized == Some(path) {
+            format_emphasized_name(name, self.colors())
         } else {
-            HashSet::new()
+            format_name_with_match(name, matched, kind, self.colors())
         };
-        
-        Self { entries, show_dirs }
+        if self.hyperlinks {
+            hyperlink(&formatted, &file_uri(path))
+        } else {
+            formatted
+        }
+    }
+
+    /// The name of `entry` as printed, and where the search matched it
+    ///
+    /// With [`Self::escape_names`] control characters are escaped, and with
+    /// [`Self::quote_names`] the name is shell-quoted; a match is then dropped,
+    /// since its byte range no longer lines up with the name as printed. The same
+    /// goes for names shortened to [`Self::name_limit`], which quoted names never are.
+    fn shown_name<'n>(&self, entry: &'n impl FsEntry, search: Option<&SearchQuery>) -> (Cow<'n, str>, Option<Range<usize>>) {
+        let name = entry.file_name().to_string_lossy();
+        if self.quote_names {
+            let shown = match self.full_path_root {
+                Some(root) => entry.path().strip_prefix(root).unwrap_or(entry.path()).to_string_lossy().replace('\\', "/"),
+                None => name.into_owned(),
+            };
+            return match quote_arg(&shown) {
+                Cow::Borrowed(_) => {
+                    let matched = search.and_then(|q| q.name_match(entry.path()));
+                    let name_start = shown.len() - entry.file_name().to_string_lossy().len();
+                    (Cow::Owned(shown), matched.map(|m| m.start + name_start..m.end + name_start))
+                }
+                Cow::Owned(quoted) => (Cow::Owned(quoted), None),
+            };
+        }
+        let (name, matched) = if self.escape_names && name.chars().any(char::is_control) {
+            (Cow::Owned(sanitize_name(&name)), None)
+        } else {
+            (name, search.and_then(|q| q.name_match(entry.path())))
+        };
+        match self.name_limit.map(|limit| limit.apply(&name)) {
+            Some(Cow::Owned(truncated)) => (Cow::Owned(truncated), None),
+            _ => (name, matched),
+        }
+    }
+
+    /// The palette, or `None` when output is plain
+    fn colors(&self) -> Option<&Palette> {
+        self.use_color.then_some(self.palette)
+    }
+
+    /// Whether `path` has one of the [`Self::no_size_for`] extensions, compared case-insensitively
+    fn hides_size_of(&self, path: &Path) -> bool {
+        !self.no_size_for.is_empty()
+            && path
+                .extension()
+                .and_then(|ext| ext.to_str())
+                .is_some_and(|ext| self.no_size_for.iter().any(|hidden| hidden.eq_ignore_ascii_case(ext)))
+    }
+
+    /// The leading and trailing fields of an entry line
+    fn cells(&self, path: Option<&Path>, meta: Option<&EntryMeta>, is_dir: bool) -> (String, String) {
+        let no_size = self.no_size || (!is_dir && path.is_some_and(|p| self.hides_size_of(p)));
+        let columns = self.columns.as_slice().iter().copied().filter(|&c| !(no_size && c == Column::Size));
+        let cell = Cell { path, meta, is_dir, colors: self.colors(), time_field: self.time_field, size_format: self.size_format };
+        render_cells(columns, &cell)
     }
 }
 
 /// Tree writer that handles directory tree output
 pub struct TreeWriter {
     use_color: bool,
+    /// Branch glyphs when the config leaves `--tree-style` on auto
+    charset: CharacterSet,
+    palette: Palette,
+    meta_source: Arc<dyn MetadataSource>,
 }
 
 impl TreeWriter {
     /// Create a new TreeWriter for terminal output (with color)
     pub fn for_terminal() -> Self {
-        Self { use_color: true }
+        Self {
+            use_color: true,
+            charset: CharacterSet::detect(),
+            palette: Palette::default(),
+            meta_source: Arc::new(FsMetadata),
+        }
     }
 
-    /// Create a new TreeWriter for file output (no color)
+    /// Create a new TreeWriter for file output (no color, ASCII glyphs)
     pub fn for_file() -> Self {
-        Self { use_color: false }
+        Self {
+            use_color: false,
+            charset: CharacterSet::Ascii,
+            palette: Palette::default(),
+            meta_source: Arc::new(FsMetadata),
+        }
     }
 
-    /// Write the tree to the provided writer
-    pub fn write<W: Write>(&self, writer: &mut W, config: &TreeConfig) -> Result<()> {
-        let tree_data = TreeData::collect(config);
-        print_tree(writer, &tree_data.entries, config.search_pattern, &tree_data.show_dirs, self.use_color)?;
-        Ok(())
+    /// Create a TreeWriter for `-o` exports; color is kept only with `--color always`
+    pub fn for_export(color: ColorMode) -> Self {
+        Self {
+            use_color: color.colors_files(),
+            ..Self::for_file()
+        }
+    }
+
+    /// Draw branches in `style` instead of this destination's default
+    pub fn with_tree_style(mut self, style: TreeStyle) -> Self {
+        self.charset = style.resolve(self.charset);
+        self
+    }
+
+    /// Color entries with `palette` instead of the default scheme
+    pub fn with_palette(mut self, palette: Palette) -> Self {
+        self.palette = palette;
+        self
+    }
+
+    /// The palette, or `None` when output is plain
+    fn colors(&self) -> Option<&Palette> {
+        self.use_color.then_some(&self.palette)
+    }
+
+    /// Read entry metadata from a different source (e.g. a counting mock in tests)
+    pub fn with_metadata_source(mut self, source: Arc<dyn MetadataSource>) -> Self {
+        self.meta_source = source;
+        self
+    }
+
+    /// Line decoration for this writer under `config`
+    fn style<'a>(&'a self, config: &'a TreeConfig) -> EntryStyle<'a> {
+        EntryStyle {
+            use_color: self.use_color,
+            charset: config.tree_style.resolve(self.charset),
+            palette: &self.palette,
+            full_path_root: config.full_path.then_some(config.path),
+            emphasized: config.emphasized,
+            hyperlinks: config.hyperlinks,
+            escape_names: config.escape_names,
+            quote_names: config.quote_names,
+            classify: config.classify,
+            oneline: config.oneline,
+            pad_names: config.pad_names,
+            depth_colors: config.depth_colors,
+            no_size: config.no_size,
+            bars: config.bars,
+            no_size_for: &config.no_size_for,
+            name_limit: config.name_limit,
+            columns: &config.columns,
+            time_field: config.time_field,
+            size_format: config.size_format,
+        }
     }
 
-    /// Write tree to a file with a header
+    /// Stat an entry once for display, and tell whether it vanished since the walk yielded it;
+    /// directories only need metadata for the mtime and perms columns, and files under a bare
+    /// `--no-size` none at all
+    fn file_meta(&self, path: &Path, is_dir: bool, config: &TreeConfig) -> (Option<EntryMeta>, bool) {
+        if (is_dir && !config.columns.needs_entry_meta()) || !config.needs_metadata() {
+            (None, false)
+        } else {
+            let read = MetaRead::read(self.meta_source.as_ref(), path);
+            (read.found(), read == MetaRead::Vanished)
+        }
+    }
+
+    /// Write the tree to the provided writer, returning its `--stats` table and `--report` counts when asked for
+    pub fn write<W: Write>(&self, writer: &mut W, config: &TreeConfig) -> Result<TextTotals> {
+        let tree_data = config.collect(self.meta_source.as_ref());
+        config.stop_spinner();
+        print_tree(
+            writer,
+            &tree_data.entries,
+            config.search.as_ref(),
+            &tree_data.filter,
+            &tree_data.metas,
+            &tree_data.annotations,
+            self.style(config),
+        )?;
+        config.note_previews(tree_data.entries.iter().filter(|e| should_print_entry(e, config.search.as_ref(), &tree_data.filter, true)));
+        Ok(self.collected_totals(&tree_data, config))
+    }
+
+    /// The `--stats` table and `--report` counts of a tree written from `tree_data`
+    fn collected_totals(&self, tree_data: &TreeData, config: &TreeConfig) -> TextTotals {
+        TextTotals {
+            stats: config.stats.then(|| config.extension_stats(Some(tree_data), self.meta_source.as_ref())),
+            summary: config.report.then(|| tree_data.summary(config.search.as_ref(), true)),
+        }
+    }
+
+    /// Export the tree to a file in the configured format
     pub fn write_to_file(&self, output_path: &str, config: &TreeConfig) -> Result<()> {
-        let mut file = std::fs::File::create(output_path)?;
-        
-        match config.format() {
-            OutputFormat::Json => self.write_json(&mut file, config)?,
+        let mut file = BufWriter::new(open_export(output_path, config.export_mode)?);
+        self.write_export(&mut file, config)?;
+        file.flush()?;
+
+        writeln!(std::io::stdout().lock(), "Tree exported to {}", output_path)?;
+        Ok(())
+    }
+
+    /// Write the fully collected tree in the configured format
+    ///
+    /// Text gets a root line first, named the way the terminal tree names it.
+    fn write_export<W: Write>(&self, writer: &mut W, config: &TreeConfig) -> Result<()> {
+        if config.report_only {
+            return write_report_only(writer, config);
+        }
+        let totals = match config.format {
+            OutputFormat::Json => self.write_json(writer, config).map(|()| TextTotals::default())?,
+            OutputFormat::Text if config.grid => self.write_grid(writer, config)?,
             OutputFormat::Text => {
-                writeln!(file, ".")?;
-                self.write(&mut file, config)?;
+                write_header(writer, config)?;
+                self.write(writer, config)?
             }
-        }
-        
-        println!("Tree exported to {}", output_path);
-        Ok(())
+        };
+        write_cancel_note(writer, config)?;
+        write_text_report(writer, totals.summary)?;
+        write_text_stats(writer, totals.stats, self.colors())
     }
 
     /// Write tree to terminal (stdout)
+    ///
+    /// Output is batched in a buffer of `config.output_buffer` bytes, which is
+    /// flushed periodically while the walk runs and once more at the end, also
+    /// when writing fails part way.
     pub fn write_to_terminal(&self, config: &TreeConfig) -> Result<()> {
-        let stdout = std::io::stdout();
-        let mut handle = stdout.lock();
-        
-        match config.format() {
-            OutputFormat::Json => self.write_json(&mut handle, config),
-            OutputFormat::Text => self.write_streaming(&mut handle, config),
+        let mut handle = TimedFlush::new(std::io::stdout(), config.output_buffer);
+        let res = self.write_to_handle(&mut handle, config);
+        let flushed = handle.flush();
+        res?;
+        Ok(flushed?)
+    }
+
+    /// The body of [`Self::write_to_terminal`], writing to any handle
+    fn write_to_handle<W: Write>(&self, handle: &mut W, config: &TreeConfig) -> Result<()> {
+        if config.report_only {
+            return write_report_only(handle, config);
+        }
+        let totals = match config.format {
+            OutputFormat::Json => self.write_json(handle, config).map(|()| TextTotals::default())?,
+            OutputFormat::Text if config.grid => self.write_grid(handle, config)?,
+            // Duplicates, padding, counts and empty directories are only known once every entry has been seen
+            OutputFormat::Text if config.dupes || config.pad_names || config.bars || config.counts || config.mark_empty => {
+                write_header(handle, config)?;
+                self.write(handle, config)?
+            }
+            OutputFormat::Text => {
+                write_header(handle, config)?;
+                self.write_streaming(handle, config)?
+            }
+        };
+        write_cancel_note(handle, config)?;
+        write_text_report(handle, totals.summary)?;
+        write_text_stats(handle, totals.stats, self.colors())
+    }
+
+    /// Write immediate children in columns sized to the terminal (`--grid`)
+    fn write_grid<W: Write>(&self, writer: &mut W, config: &TreeConfig) -> Result<TextTotals> {
+        let tree_data = config.collect(self.meta_source.as_ref());
+        let style = self.style(config);
+        let search = config.search.as_ref();
+
+        let cells: Vec<String> = tree_data
+            .entries
+            .iter()
+            .filter(|entry| should_print_entry(entry, search, &tree_data.filter, true))
+            .map(|entry| grid_cell(entry, tree_data.metas.get(entry.path()), search, style))
+            .collect();
+        config.stop_spinner();
+        for line in grid_lines(&cells, terminal_width()) {
+            writeln!(writer, "{}", line)?;
+        }
+
+        if tree_data.filter.truncated {
+            writeln!(writer, "{}", TRUNCATION_NOTE)?;
         }
+        config.note_previews(tree_data.entries.iter().filter(|entry| should_print_entry(entry, search, &tree_data.filter, true)));
+        Ok(self.collected_totals(&tree_data, config))
     }
 
     /// Write directory tree as JSON
     fn write_json<W: Write>(&self, writer: &mut W, config: &TreeConfig) -> Result<()> {
-        let tree_data = TreeData::collect(config);
-        let json_tree = JsonTreeBuilder::build(&tree_data, config);
-        
-        let json_str = serde_json::to_string_pretty(&json_tree)
-            .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
-        writeln!(writer, "{}", json_str)?;
+        let tree_data = config.collect(self.meta_source.as_ref());
+        let mut json_tree = tree_data.to_node(config.path, config.search.as_ref(), config.show.walks_hidden());
+        if let RootHeader::Custom(name) = config.header {
+            json_tree.name = name.to_string();
+        }
+        if config.tilde {
+            json_tree.rebase(config.path, &tilde_path(config.path));
+        }
+        if config.no_size {
+            json_tree.clear_sizes();
+        }
+        if config.report {
+            json_tree.summary = Some(config.summary(Some(&tree_data)));
+        }
+        if config.stats {
+            json_tree.stats = Some(config.extension_stats(Some(&tree_data), self.meta_source.as_ref()).into_iter().collect());
+        }
+        // Lets `--diff` walk as deep as the snapshot did
+        json_tree.depth_limit = Some(if config.max_depth == UNLIMITED_DEPTH { 0 } else { config.max_depth });
+
+        // Collection is done; keep the spinner from drawing over the output
+        config.stop_spinner();
+
+        write_json_value(writer, &json_tree, config.compact)
+    }
+
+    /// Write a virtual tree (built from a path list) in `config.format`
+    ///
+    /// The JSON root node is named after `config.path` like a listing's root; text starts directly with the entries,
+    /// whose names are escaped with `config.escape_names` since they come from untrusted input.
+    pub fn write_virtual<W: Write>(&self, writer: &mut W, config: &TreeConfig, entries: &[VirtualEntry]) -> Result<()> {
+        match config.format {
+            OutputFormat::Json => {
+                let mut tree = JsonTreeBuilder::build_virtual(config.path, entries);
+                if config.no_size {
+                    tree.clear_sizes();
+                }
+                write_json_value(writer, &tree, config.compact)?
+            }
+            OutputFormat::Text => print_virtual_tree(writer, entries, self.style(config))?,
+        }
         Ok(())
     }
 }
 
-/// Helper struct for building JSON tree representation
+/// Helper struct for building the JSON of virtual trees
 struct JsonTreeBuilder;
 
 impl JsonTreeBuilder {
-    fn build(tree_data: &TreeData, config: &TreeConfig) -> serde_json::Value {
-        json!({
-            "name": config.path.file_name().unwrap_or(config.path.as_os_str()).to_string_lossy(),
-            "type": "directory",
-            "path": config.path.to_string_lossy(),
-            "children": Self::build_children(
-                &tree_data.entries,
-                config.path,
-                config.search_pattern,
-                &tree_data.show_dirs,
-                config.show_all
-            )
-        })
-    }
-
-    fn build_children(
-        entries: &[DirEntry],
-        parent_path: &Path,
-        search_pattern: Option<&str>,
-        show_dirs: &HashSet<PathBuf>,
-        show_all: bool,
-    ) -> serde_json::Value {
-        let mut children = Vec::new();
+    /// Build a nested tree from pre-ordered virtual entries under `root`
+    fn build_virtual(root: &Path, entries: &[VirtualEntry]) -> TreeNode {
+        // Stack of (depth, children) for the directories currently open
+        let mut stack: Vec<(usize, Vec<TreeNode>)> = vec![(0, Vec::new())];
 
         for entry in entries {
-            if entry.path().parent() != Some(parent_path) {
-                continue;
-            }
-
-            if let Some(pattern) = search_pattern {
-                if !should_print_entry(entry, Some(pattern), show_dirs, show_all) {
-                    continue;
-                }
-            }
+            Self::close_virtual_dirs(&mut stack, entry.depth);
 
-            let name = entry.file_name().to_string_lossy().to_string();
-            let is_dir = entry.file_type().is_dir();
-            let size = if !is_dir {
-                entry.metadata().map(|m| m.len()).ok()
-            } else {
-                None
+            let node = TreeNode {
+                name: entry.name.clone(),
+                kind: if entry.is_dir { NodeKind::Directory } else { NodeKind::File },
+                path: entry.path.clone(),
+                depth: entry.depth,
+                size: match entry.size {
+                    None if !entry.is_dir && entry.on_disk => std::fs::metadata(&entry.path).ok().map(|m| m.len()),
+                    size => size,
+                },
+                matches: None,
+                dup_group: None,
+                child_count: None,
+                skipped: false,
+                stopped: false,
+                mount_point: false,
+                vanished: false,
+                collapsed: None,
+                empty: false,
+                children: None,
+                truncated: false,
+                stats: None,
+                summary: None,
+                depth_limit: None,
             };
 
-            let mut node = json!({
-                "name": name,
-                "type": if is_dir { "directory" } else { "file" },
-                "path": entry.path().to_string_lossy().to_string(),
-            });
-
-            if let Some(s) = size {
-                node["size"] = json!(s);
+            stack.last_mut().expect("root frame").1.push(node);
+            if entry.is_dir {
+                stack.push((entry.depth, Vec::new()));
             }
+        }
+        Self::close_virtual_dirs(&mut stack, 1);
 
-            if is_dir {
-                let subtree = Self::build_children(entries, entry.path(), search_pattern, show_dirs, show_all);
-                if !subtree.as_array().unwrap().is_empty() || search_pattern.is_none() {
-                    node["children"] = subtree;
-                }
-            }
+        let (_, children) = stack.pop().expect("root frame");
+        TreeNode {
+            name: root_name(root),
+            kind: NodeKind::Directory,
+            path: root.to_path_buf(),
+            depth: 0,
+            size: None,
+            matches: None,
+            dup_group: None,
+            child_count: None,
+            skipped: false,
+            stopped: false,
+            mount_point: false,
+            vanished: false,
+            collapsed: None,
+            empty: false,
+            children: Some(children),
+            truncated: false,
+            stats: None,
+            summary: None,
+            depth_limit: None,
+        }
+    }
 
-            children.push(node);
+    /// Pop every open directory at or below `depth`, attaching its children
+    fn close_virtual_dirs(stack: &mut Vec<(usize, Vec<TreeNode>)>, depth: usize) {
+        while stack.len() > 1 && stack.last().map(|(d, _)| *d >= depth).unwrap_or(false) {
+            let (_, children) = stack.pop().expect("checked above");
+            let parent = &mut stack.last_mut().expect("root frame").1;
+            if let Some(dir) = parent.last_mut() {
+                dir.children = Some(children);
+            }
         }
+    }
+}
 
-        json!(children)
+/// Name shown for the root of a tree, e.g. `src/` for `./src` and the directory's own name for `.`
+fn root_label(path: &Path, tilde: bool) -> String {
+    let name = if tilde { tilde_path(path).to_string_lossy().into_owned() } else { root_name(path) };
+    if name.ends_with(['/', '\\']) {
+        name
+    } else {
+        format!("{}/", name)
+    }
+}
+
+/// Write the root line of a text tree; flat `-1` listings have none
+pub fn write_header<W: Write>(writer: &mut W, config: &TreeConfig) -> std::io::Result<()> {
+    match config.header {
+        _ if config.oneline => Ok(()),
+        RootHeader::Name if config.escape_names => writeln!(writer, "{}", sanitize_name(&root_label(config.path, config.tilde))),
+        RootHeader::Name => writeln!(writer, "{}", root_label(config.path, config.tilde)),
+        RootHeader::Hidden => Ok(()),
+        RootHeader::Custom(text) => writeln!(writer, "{}", text),
     }
 }
 
 /// Print a single directory entry line with proper tree formatting
+///
+/// `meta` is fetched once by the caller; files without it are shown as zero bytes.
 fn print_entry_line<W: Write>(
     writer: &mut W,
-    entry: &DirEntry,
+    entry: &impl FsEntry,
     indent: &str,
-    use_color: bool,
+    meta: Option<&EntryMeta>,
+    search: Option<&SearchQuery>,
+    marks: EntryMarks,
+    style: EntryStyle,
 ) -> std::io::Result<()> {
-    let file_name = entry.file_name().to_string_lossy();
+    let colors = style.colors();
+    let (file_name, matched) = style.shown_name(entry, search);
+    let prefix = parent_prefix(entry.path(), style);
 
-    if entry.file_type().is_dir() {
-        let formatted_name = format_directory_name(&file_name, use_color);
-        writeln!(writer, "{}{}/", indent, formatted_name)
+    let is_dir = entry.is_dir();
+    // Without metadata a vanished file would read as empty
+    let cell_style = if marks.vanished { EntryStyle { no_size: true, ..style } } else { style };
+    let (leading, trailing) = cell_style.cells(Some(entry.path()), meta, is_dir);
+    if is_dir {
+        let formatted_name = style.name(entry.path(), &file_name, matched, NameKind::Directory);
+        write!(writer, "{}{}{}{}/{}", leading, indent, prefix, formatted_name, trailing)?;
     } else {
-        let size = entry.metadata().map(|m| m.len()).unwrap_or(0);
-        let human_size = format_file_size(size);
-        let formatted_name = format_file_name(&file_name, use_color);
-        let formatted_size = format_size_colored(&human_size, use_color);
-        writeln!(writer, "{}{} ({})", indent, formatted_name, formatted_size)
+        let kind = file_kind(entry.is_symlink(), meta);
+        let formatted_name = style.name(entry.path(), &file_name, matched, kind);
+        let suffix = file_suffix(entry, meta, style);
+        let width = display_width(&prefix) + display_width(&file_name) + suffix.len();
+        write!(writer, "{}{}{}{}{}", leading, indent, prefix, formatted_name, suffix)?;
+        write_trailing(writer, &trailing, marks.name_width.saturating_sub(width))?;
+        if let (Some(largest), Some(meta)) = (marks.largest_sibling, meta) {
+            let bar = format_size_bar(meta.size, largest, style.charset, colors);
+            if !bar.is_empty() {
+                write!(writer, " {}", bar)?;
+            }
+        }
     }
+    marks.write(writer, colors)?;
+    writeln!(writer)
 }
 
-/// Print a single directory entry line for ignore::DirEntry
-fn print_entry_line_ignore<W: Write>(
-    writer: &mut W,
-    entry: &IgnoreDirEntry,
-    indent: &str,
-    use_color: bool,
-) -> std::io::Result<()> {
-    let file_name = entry.file_name().to_string_lossy();
-
-    // ignore::DirEntry may not always have metadata/file_type pre-fetched; be defensive
-    if entry.file_type().map(|ft| ft.is_dir()).unwrap_or(false) {
-        let formatted_name = format_directory_name(&file_name, use_color);
-        writeln!(writer, "{}{}/", indent, formatted_name)
+/// Write the fields after a file name, first padding the name when there are any
+fn write_trailing<W: Write>(writer: &mut W, trailing: &str, padding: usize) -> std::io::Result<()> {
+    if trailing.is_empty() {
+        Ok(())
     } else {
-        // Compute size lazily; skip on error for speed
-        let size = std::fs::metadata(entry.path()).map(|m| m.len()).unwrap_or(0);
-        let human_size = format_file_size(size);
-        let formatted_name = format_file_name(&file_name, use_color);
-        let formatted_size = format_size_colored(&human_size, use_color);
-        writeln!(writer, "{}{} ({})", indent, formatted_name, formatted_size)
+        write!(writer, "{:padding$}{}", "", trailing)
     }
 }
 
-/// Print the complete directory tree with proper branching
-pub fn print_tree<W: Write>(
-    writer: &mut W,
-    entries: &[DirEntry],
-    search_pattern: Option<&str>,
-    show_dirs: &HashSet<PathBuf>,
-    use_color: bool,
-) -> std::io::Result<()> {
-    if entries.is_empty() {
-        return Ok(());
+/// The parent directories of `path` relative to the `--full-path` root, colored like directory names
+///
+/// Empty without `--full-path` and for the root's own children, so search
+/// highlighting always lands on the name that follows. Also empty with
+/// `--quote-names`, which quotes the whole path as the name.
+fn parent_prefix(path: &Path, style: EntryStyle) -> String {
+    let Some(root) = style.full_path_root.filter(|_| !style.quote_names) else { return String::new() };
+    let parent = path.parent().unwrap_or(path);
+    let rel = parent.strip_prefix(root).unwrap_or(parent);
+    if rel.as_os_str().is_empty() {
+        return String::new();
     }
+    let mut rel = rel.to_string_lossy().replace('\\', "/");
+    if style.escape_names {
+        rel = sanitize_name(&rel);
+    }
+    format_directory_name(&format!("{}/", rel), style.colors())
+}
 
-    // Use Unicode for better visual output
-    let charset = if use_color {
-        CharacterSet::detect()
+/// Color class of a non-directory entry
+fn file_kind(is_symlink: bool, meta: Option<&EntryMeta>) -> NameKind {
+    if is_symlink {
+        NameKind::Symlink
+    } else if meta.is_some_and(|m| m.executable) {
+        NameKind::Executable
     } else {
-        CharacterSet::Unicode  // Use Unicode for file output too
-    };
-
-    let formatter = TreeFormatter::with_charset(charset);
-    
-    // Filter entries based on search pattern first
-    let filtered_entries: Vec<&DirEntry> = entries
-        .iter()
-        .filter(|entry| should_print_entry(entry, search_pattern, show_dirs, true))
-        .collect();
+        NameKind::File
+    }
+}
 
-    if filtered_entries.is_empty() {
-        return Ok(());
+/// The `-F` indicator for a file, or nothing without `--classify`
+fn file_suffix(entry: &impl FsEntry, meta: Option<&EntryMeta>, style: EntryStyle) -> &'static str {
+    match entry.file_type() {
+        Some(file_type) if style.classify => classify_suffix(file_type, meta.and_then(|m| m.mode)),
+        _ => "",
     }
+}
 
-    // Compute last-child states for all entries
-    let entries_vec: Vec<DirEntry> = filtered_entries.iter().map(|&e| e.clone()).collect();
-    let last_child_map = formatter.compute_last_child_map(&entries_vec);
+/// Room kept for ` (12.34 kB)` when capping `--pad-names` padding to the terminal
+const SIZE_COLUMN_WIDTH: usize = 12;
 
-    // Print each entry with proper indentation
-    for (idx, entry) in entries_vec.iter().enumerate() {
-        let depth = entry.depth();
-        let is_last = last_child_map.get(idx).map(|v| v.as_slice()).unwrap_or(&[]);
-        let indent = formatter.generate_indent(depth, is_last);
-        
-        print_entry_line(writer, entry, &indent, use_color)?;
+/// Widest file name (with classify suffix) among each directory's displayed children
+///
+/// Each width is capped so the padded name and its size still fit in the terminal.
+fn sibling_name_widths<'a>(
+    entries: &[&'a DirEntry],
+    metas: &HashMap<PathBuf, EntryMeta>,
+    style: EntryStyle,
+) -> HashMap<&'a Path, usize> {
+    let mut widths: HashMap<&Path, usize> = HashMap::new();
+    for entry in entries.iter().filter(|e| !e.file_type().is_dir()) {
+        let Some(parent) = entry.path().parent() else { continue };
+        let (name, _) = style.shown_name(*entry, None);
+        let width = display_width(&parent_prefix(entry.path(), style)) + display_width(&name) + file_suffix(*entry, metas.get(entry.path()), style).len();
+        let indent_width = if style.oneline { 0 } else { entry.depth() * 4 };
+        let cap = terminal_width().saturating_sub(indent_width + SIZE_COLUMN_WIDTH);
+        let slot = widths.entry(parent).or_default();
+        *slot = (*slot).max(width.min(cap));
     }
-    
-    Ok(())
+    widths
 }
 
-/// Stream the directory tree while scanning, printing entries incrementally
-impl TreeWriter {
-    fn write_streaming<W: Write>(&self, writer: &mut W, config: &TreeConfig) -> Result<()> {
-        // Use Unicode for better visual output
-        let charset = if self.use_color { CharacterSet::detect() } else { CharacterSet::Unicode };
-        let formatter = TreeFormatter::with_charset(charset);
-
-        // Choose walker: for search, use ignore's fast walker; otherwise use walkdir
-        let searching = config.search_pattern.is_some();
-        let use_ignore = searching;
-        let mut iter_ig_opt = None;
-        let mut iter_wd_opt = None;
-        if use_ignore {
-            let it = WalkBuilder::new(config.path)
-                .max_depth(if config.max_depth == usize::MAX { None } else { Some(config.max_depth) })
-                .hidden(!config.show_all)
-                .git_ignore(true)
-                .git_global(true)
-                .git_exclude(true)
-                    .filter_entry(|e| !crate::core::filters::is_common_skip_os(e.file_name()))
-                    .build()
-                .peekable();
-            iter_ig_opt = Some(it);
-        } else {
-            let it = WalkDir::new(config.path)
-                .min_depth(1)
-                .max_depth(config.max_depth)
-                .into_iter()
-                .filter_entry(|e| should_show_entry(e, config.show_all))
-                .peekable();
-            iter_wd_opt = Some(it);
-        }
-
-        // Track ancestor continuation states per depth
-        let mut ancestor_has_more: Vec<bool> = Vec::new();
-
-        // Precompute search visibility helper
-        // We need show_dirs for search to print parents; compute lazily when needed
-        let show_dirs = if let Some(pattern) = config.search_pattern {
-            let entries = collect_entries(config.path, config.max_depth, config.show_all);
-            build_search_filter(&entries, pattern, config.show_all)
-        } else {
-            std::collections::HashSet::new()
-        };
+/// Size of the largest listed file in each directory, for `--bars`
+fn largest_sibling_sizes<'a>(entries: &[&'a DirEntry], metas: &HashMap<PathBuf, EntryMeta>) -> HashMap<&'a Path, u64> {
+    let mut largest: HashMap<&Path, u64> = HashMap::new();
+    for entry in entries.iter().filter(|e| !e.file_type().is_dir()) {
+        let (Some(parent), Some(meta)) = (entry.path().parent(), metas.get(entry.path())) else { continue };
+        let slot = largest.entry(parent).or_default();
+        *slot = (*slot).max(meta.size);
+    }
+    largest
+}
 
-        let mut first_print_done = false;
-        if use_ignore {
-            let iter_ig = iter_ig_opt.expect("iterator init");
-            let mut iter_ig = iter_ig;
-            while let Some(res) = iter_ig.next() {
-                let entry = match res {
-                    Ok(e) => e,
-                    Err(_) => continue,
-                };
-
-                // ignore walker already handles hidden when configured; apply search filter
-                let name = entry.file_name().to_string_lossy();
-                let matches = match config.search_pattern {
-                    Some(p) => {
-                        let name_lc = name.to_ascii_lowercase();
-                        name_lc.contains(&p.to_ascii_lowercase()) || show_dirs.contains(entry.path())
-                    }
-                    None => true,
-                };
-                if !matches { continue; }
-
-                let depth = entry.depth();
-                let next_depth = iter_ig.peek().and_then(|r| r.as_ref().ok()).map(|e| e.depth());
-
-            // Adjust ancestor stack when depth decreases
-            let current_stack_len = ancestor_has_more.len();
-            if depth < current_stack_len {
-                ancestor_has_more.truncate(depth);
-            } else if depth > current_stack_len {
-                // Extend stack for deeper levels; assume ancestors have more until proven otherwise
-                ancestor_has_more.resize(depth, true);
-            }
+/// Name of an entry as shown in a grid cell: no size, but the same colors and suffixes
+fn grid_cell(entry: &DirEntry, meta: Option<&EntryMeta>, search: Option<&SearchQuery>, style: EntryStyle) -> String {
+    let (file_name, matched) = style.shown_name(entry, search);
+    let is_dir = entry.file_type().is_dir();
+    let kind = if is_dir { NameKind::Directory } else { file_kind(entry.path_is_symlink(), meta) };
+    let name = format_name_with_match(&file_name, matched, kind, style.colors());
+    let suffix = if is_dir { "/" } else { file_suffix(entry, meta, style) };
+    format!("{}{}", name, suffix)
+}
 
-            // Compute is_last flags slice for formatter
-            let mut is_last: Vec<bool> = vec![false; depth];
-            // Ancestors: if ancestor_has_more[i] is false, it's last at that level
-            for i in 0..depth.saturating_sub(1) {
-                is_last[i] = !ancestor_has_more.get(i).copied().unwrap_or(false);
+/// Print a single virtual entry line; sizes are the given ones, else looked up only for files present on disk
+///
+/// Names are escaped and shortened, and sizes formatted or left out, as `style` says for walked entries.
+fn print_virtual_entry_line<W: Write>(writer: &mut W, entry: &VirtualEntry, indent: &str, style: EntryStyle) -> std::io::Result<()> {
+    let colors = style.colors();
+    let name = if style.escape_names { Cow::Owned(sanitize_name(&entry.name)) } else { Cow::Borrowed(entry.name.as_str()) };
+    let name = match style.name_limit {
+        Some(limit) => Cow::Owned(limit.apply(&name).into_owned()),
+        None => name,
+    };
+    if entry.is_dir {
+        let (leading, trailing) = style.cells(Some(&entry.path), None, true);
+        // Root anchors such as `/` already end in a separator
+        let suffix = if name.ends_with(['/', '\\']) { "" } else { "/" };
+        return writeln!(writer, "{}{}{}{}{}", leading, indent, format_directory_name(&name, colors), suffix, trailing);
+    }
+    let (meta, kind) = match entry.size {
+        Some(size) => (Some(EntryMeta { size, ..EntryMeta::default() }), NameKind::File),
+        None if entry.on_disk => match std::fs::metadata(&entry.path) {
+            Ok(meta) => {
+                let is_symlink = std::fs::symlink_metadata(&entry.path).is_ok_and(|m| m.file_type().is_symlink());
+                let meta = EntryMeta::from_metadata(&meta, &entry.path);
+                (Some(meta), file_kind(is_symlink, Some(&meta)))
             }
+            Err(_) => (None, NameKind::File),
+        },
+        None => (None, NameKind::File),
+    };
+    // Without a size a file would read as empty
+    let cell_style = if meta.is_none() { EntryStyle { no_size: true, ..style } } else { style };
+    let (leading, trailing) = cell_style.cells(Some(&entry.path), meta.as_ref(), false);
+    writeln!(writer, "{}{}{}{}", leading, indent, format_file_name(&name, kind, colors), trailing)
+}
 
-            // Current level last-child: if next entry is at shallower depth, this is last
-            let current_is_last = match next_depth {
-                Some(nd) => nd < depth,
-                None => true,
-            };
-            if depth > 0 {
-                is_last[depth - 1] = current_is_last;
-            }
+/// The `--stats` table of the entries a text writer printed, when asked for
+type TextStats = Option<Vec<(String, ExtensionStats)>>;
 
-            // Update ancestor_has_more for current level based on peek
-            if depth > 0 {
-                let idx = depth - 1;
-                ancestor_has_more.resize(depth, true);
-                ancestor_has_more[idx] = !current_is_last;
-            }
+/// What a text writer counted from the entries it printed
+#[derive(Debug, Default, PartialEq)]
+pub struct TextTotals {
+    /// The `--stats` table, when asked for
+    pub stats: TextStats,
+    /// The `--report` counts, when asked for
+    pub summary: Option<Summary>,
+}
 
-            // Stop spinner on first printable entry to avoid overlap
-            if !first_print_done {
-                if let Some(stop) = &config.spinner_stop {
-                    stop.store(true, std::sync::atomic::Ordering::Relaxed);
-                    // Clear spinner line on stderr
-                    eprint!("\r                \r");
-                    let _ = std::io::stderr().flush();
-                }
-                first_print_done = true;
-            }
+/// Print the `--stats` table after a text tree; JSON carries it inline instead
+///
+/// A walk cut short by `--timeout` or Ctrl-C gets no table, as its counts would be partial.
+fn write_text_stats<W: Write>(writer: &mut W, stats: TextStats, colors: Option<&Palette>) -> Result<()> {
+    let Some(stats) = stats.filter(|_| cancelled().is_none()) else {
+        return Ok(());
+    };
+    writeln!(writer)?;
+    print_stats_table(writer, &stats, colors)?;
+    Ok(())
+}
 
-            let indent = formatter.generate_indent(depth, &is_last);
-            print_entry_line_ignore(writer, &entry, &indent, self.use_color)?;
-            }
-        } else {
-            let iter_wd = iter_wd_opt.expect("iterator init");
-            let mut iter_wd = iter_wd;
-            while let Some(res) = iter_wd.next() {
-                let entry = match res {
-                    Ok(e) => e,
-                    Err(_) => continue,
-                };
-
-                if !should_print_entry(&entry, config.search_pattern, &show_dirs, config.show_all) {
-                    continue;
-                }
+/// Mark a text tree whose walk was cut short by `--timeout` or Ctrl-C
+fn write_cancel_note<W: Write>(writer: &mut W, config: &TreeConfig) -> Result<()> {
+    match cancelled() {
+        Some(reason) if config.format == OutputFormat::Text => Ok(writeln!(writer, "{}", reason.note())?),
+        _ => Ok(()),
+    }
+}
 
-                let depth = entry.depth();
-                let next_depth = iter_wd.peek().and_then(|r| r.as_ref().ok()).map(|e| e.depth());
+/// Print the `--report` line after a text tree; JSON carries it as the root's `summary`
+fn write_text_report<W: Write>(writer: &mut W, summary: Option<Summary>) -> Result<()> {
+    let Some(summary) = summary else {
+        return Ok(());
+    };
+    writeln!(writer)?;
+    writeln!(writer, "{}", summary)?;
+    Ok(())
+}
 
-                let current_stack_len = ancestor_has_more.len();
-                if depth < current_stack_len {
-                    ancestor_has_more.truncate(depth);
-                } else if depth > current_stack_len {
-                    ancestor_has_more.resize(depth, true);
-                }
+/// Print only the counts for `--report-only`: the report line, or the `summary` object as JSON
+fn write_report_only<W: Write>(writer: &mut W, config: &TreeConfig) -> Result<()> {
+    let summary = config.summary(None);
+    match config.format {
+        OutputFormat::Json => write_json_value(writer, &summary, config.compact),
+        OutputFormat::Text => Ok(writeln!(writer, "{}", summary)?),
+    }
+}
 
-                let mut is_last: Vec<bool> = vec![false; depth];
-                for i in 0..depth.saturating_sub(1) {
-                    is_last[i] = !ancestor_has_more.get(i).copied().unwrap_or(false);
-                }
+/// Print a per-extension table: files and total size, largest first, then a total row
+pub fn print_stats_table<W: Write>(
+    writer: &mut W,
+    stats: &[(String, ExtensionStats)],
+    colors: Option<&Palette>,
+) -> std::io::Result<()> {
+    let total = stats.iter().fold(ExtensionStats::default(), |acc, (_, s)| ExtensionStats {
+        files: acc.files + s.files,
+        size: acc.size + s.size,
+    });
+    let mut rows: Vec<(&str, String, u64)> =
+        stats.iter().map(|(ext, s)| (ext.as_str(), format_count(s.files), s.size)).collect();
+    rows.push(("Total", format_count(total.files), total.size));
 
-                let current_is_last = match next_depth {
-                    Some(nd) => nd < depth,
-                    None => true,
-                };
-                if depth > 0 {
-                    is_last[depth - 1] = current_is_last;
-                }
+    let ext_width = rows.iter().map(|r| r.0.len()).max().unwrap_or(0).max("Extension".len());
+    let files_width = rows.iter().map(|r| r.1.len()).max().unwrap_or(0).max("Files".len());
+    writeln!(writer, "{:<ext_width$}  {:>files_width$}  {:>9}", "Extension", "Files", "Size")?;
+    for (ext, files, bytes) in rows {
+        let size = format_size_colored(&format!("{:>9}", format_file_size(bytes)), bytes, colors);
+        writeln!(writer, "{:<ext_width$}  {:>files_width$}  {}", ext, files, size)?;
+    }
+    Ok(())
+}
+
+/// Print a virtual tree built from a list of paths
+pub fn print_virtual_tree<W: Write>(
+    writer: &mut W,
+    entries: &[VirtualEntry],
+    style: EntryStyle,
+) -> std::io::Result<()> {
+    let formatter = TreeFormatter::with_charset(style.charset);
+
+    let depths: Vec<usize> = entries.iter().map(|e| e.depth).collect();
+    let last_child_map = formatter.compute_last_child_map_for_depths(&depths);
+
+    for (entry, is_last) in entries.iter().zip(&last_child_map) {
+        let indent = formatter.generate_indent(entry.depth, is_last);
+        print_virtual_entry_line(writer, entry, &indent, style)?;
+    }
+
+    Ok(())
+}
+
+/// Print the complete directory tree with proper branching
+pub fn print_tree<W: Write>(
+    writer: &mut W,
+    entries: &[DirEntry],
+    search: Option<&SearchQuery>,
+    filter: &SearchFilter,
+    metas: &HashMap<PathBuf, EntryMeta>,
+    annotations: &Annotations,
+    style: EntryStyle,
+) -> std::io::Result<()> {
+    let formatter = TreeFormatter::with_charset(style.charset).with_depth_colors(style.use_color && style.depth_colors);
+    
+    // Filter entries based on search pattern first
+    let filtered_entries: Vec<&DirEntry> = entries
+        .iter()
+        .filter(|entry| should_print_entry(entry, search, filter, true))
+        .collect();
+
+    if filtered_entries.is_empty() {
+        // With nothing listed, the only directory that can be marked is the root
+        if !annotations.empty.is_empty() {
+            write_empty_marker(writer, &formatter, &[], style)?;
+        }
+        return Ok(());
+    }
+
+    let name_widths = if style.pad_names {
+        sibling_name_widths(&filtered_entries, metas, style)
+    } else {
+        HashMap::new()
+    };
+    let largest_sizes = if style.bars { largest_sibling_sizes(&filtered_entries, metas) } else { HashMap::new() };
+
+    // Compute last-child states for all entries
+    let entries_vec: Vec<DirEntry> = filtered_entries.iter().map(|&e| e.clone()).collect();
+    let last_child_map = formatter.compute_last_child_map(&entries_vec);
 
-                if depth > 0 {
-                    let idx = depth - 1;
-                    ancestor_has_more.resize(depth, true);
-                    ancestor_has_more[idx] = !current_is_last;
+    // Print each entry with proper indentation
+    for (idx, entry) in entries_vec.iter().enumerate() {
+        let depth = entry.depth();
+        let is_last = last_child_map.get(idx).map(|v| v.as_slice()).unwrap_or(&[]);
+        let indent = if style.oneline { String::new() } else { formatter.generate_indent(depth, is_last) };
+        
+        let marks = EntryMarks {
+            name_width: entry.path().parent().and_then(|p| name_widths.get(p)).copied().unwrap_or(0),
+            match_count: search.and_then(|q| filter.displayed_match_count(entry.path(), q)),
+            dup_group: annotations.dup_groups.get(entry.path()).copied(),
+            child_count: annotations.child_counts.get(entry.path()).copied(),
+            skipped: annotations.skipped.contains(entry.path()),
+            stopped: annotations.stopped.contains(entry.path()),
+            mount_point: annotations.mount_points.contains(entry.path()),
+            collapsed: annotations.collapsed.get(entry.path()).copied(),
+            largest_sibling: entry.path().parent().and_then(|p| largest_sizes.get(p)).copied(),
+            vanished: annotations.vanished.contains(entry.path()),
+        };
+        let meta = metas.get(entry.path());
+        print_entry_line(writer, entry, &indent, meta, search, marks, style)?;
+        if annotations.empty.contains(entry.path()) {
+            write_empty_marker(writer, &formatter, &is_last[..depth.min(is_last.len())], style)?;
+        }
+    }
+
+    if filter.truncated {
+        writeln!(writer, "{}", TRUNCATION_NOTE)?;
+    }
+    
+    Ok(())
+}
+
+/// Print the `--mark-empty` placeholder as the only child of the directory whose
+/// last-child states are `dir_is_last` (empty for the root)
+fn write_empty_marker<W: Write>(
+    writer: &mut W,
+    formatter: &TreeFormatter,
+    dir_is_last: &[bool],
+    style: EntryStyle,
+) -> std::io::Result<()> {
+    if style.oneline {
+        return Ok(());
+    }
+    let mut is_last = dir_is_last.to_vec();
+    is_last.push(true);
+    let indent = formatter.generate_indent(is_last.len(), &is_last);
+    let (leading, _) = style.cells(None, None, false);
+    let padding = " ".repeat(display_width(&leading));
+    writeln!(writer, "{}{}{}", padding, indent, format_empty_marker(style.colors()))
+}
+
+/// Stream the directory tree while scanning, printing entries incrementally
+impl TreeWriter {
+    /// Render a search from its collected candidates instead of walking again
+    ///
+    /// The candidates are already in walk order, so the visible ones are printed as
+    /// they come, with the last-child glyphs worked out from the whole list.
+    fn write_search_collected<W: Write>(&self, writer: &mut W, config: &TreeConfig, query: &SearchQuery) -> Result<TextTotals> {
+        let style = self.style(config);
+        let formatter = TreeFormatter::with_charset(style.charset).with_depth_colors(style.use_color && style.depth_colors);
+
+        let mut timings = Timings::default();
+        let candidates = timings.time(|t| &mut t.walk, || {
+            search_candidates(config.path, config.max_depth, &config.show, query, config.progress.as_deref())
+        });
+        timings.entries = candidates.len();
+        let filter = timings.time(|t| &mut t.search, || build_search_filter(&candidates, query));
+        config.note_matches(&filter);
+        config.stop_spinner();
+
+        let mut tally = config.tallies_stats().then(StatsTally::default);
+        let mut summary = config.report.then(Summary::default);
+        let visible: Vec<&IgnoreDirEntry> = candidates.iter().filter(|e| filter.is_visible(e.path())).collect();
+        let depths: Vec<usize> = visible.iter().map(|e| e.depth()).collect();
+        for (entry, is_last) in visible.iter().zip(formatter.compute_last_child_map_for_depths(&depths)) {
+            let indent = if style.oneline { String::new() } else { formatter.generate_indent(entry.depth(), &is_last) };
+            let is_dir = entry.file_type().is_some_and(|ft| ft.is_dir());
+            let (meta, vanished) = self.file_meta(entry.path(), is_dir, config);
+            let marks = EntryMarks {
+                match_count: filter.displayed_match_count(entry.path(), query),
+                skipped: is_shown_skipped(entry.file_name(), is_dir, &config.show),
+                stopped: is_stopped(entry.path(), entry.depth(), is_dir, &config.show),
+                mount_point: is_mount_point(entry.path(), is_dir, &config.show),
+                vanished,
+                ..EntryMarks::default()
+            };
+            print_entry_line(writer, *entry, &indent, meta.as_ref(), Some(query), marks, style)?;
+            if let Some(summary) = summary.as_mut() {
+                summary.add(is_dir, marks.mount_point);
+            }
+            if let Some(tally) = tally.as_mut().filter(|_| !is_dir) {
+                tally.add(entry.path(), meta);
+            }
+        }
+        if filter.truncated {
+            writeln!(writer, "{}", TRUNCATION_NOTE)?;
+        }
+        config.note_previews(visible.iter().copied());
+
+        config.record_timings(&timings);
+        Ok(TextTotals { stats: self.finish_stats(tally, config), summary })
+    }
+
+    /// The `--stats` table from the files a streaming writer tallied, or from a walk of its own
+    /// when `--stats-include-skipped` counts entries the tree left out
+    fn finish_stats(&self, tally: Option<StatsTally>, config: &TreeConfig) -> TextStats {
+        if !config.stats {
+            return None;
+        }
+        Some(match tally {
+            Some(tally) => tally.finish(config.count_links),
+            None => config.extension_stats(None, self.meta_source.as_ref()),
+        })
+    }
+
+    /// Write a plain listing line by line as the walk goes
+    ///
+    /// Searches and narrowed listings only know which entries they show once the
+    /// whole tree has been walked, so they render from those collected entries instead.
+    fn write_streaming<W: Write>(&self, writer: &mut W, config: &TreeConfig) -> Result<TextTotals> {
+        if let Some(query) = &config.search {
+            return self.write_search_collected(writer, config, query);
+        }
+        if config.show.narrows() {
+            return self.write(writer, config);
+        }
+        let style = self.style(config);
+        let formatter = TreeFormatter::with_charset(style.charset).with_depth_colors(style.use_color && style.depth_colors);
+
+        let walk = walk_visible(config.path, config.max_depth, &config.show);
+        let mut last_children = walk.last_children();
+        // Whether the entry last printed at each depth was the last of its directory
+        let mut is_last: Vec<bool> = Vec::new();
+        let mut timings = Timings::default();
+        let mut tally = config.tallies_stats().then(StatsTally::default);
+        let mut summary = config.report.then(Summary::default);
+        let mut first_print_done = false;
+        for res in walk {
+            let entry = match res {
+                Ok(e) => e,
+                Err(e) => {
+                    log::warn!("{}", e);
+                    continue;
                 }
+            };
+            tick(config.progress.as_deref());
+            timings.entries += 1;
 
-                if !first_print_done {
-                    if let Some(stop) = &config.spinner_stop {
-                        stop.store(true, std::sync::atomic::Ordering::Relaxed);
-                        eprint!("\r                \r");
-                        let _ = std::io::stderr().flush();
-                    }
-                    first_print_done = true;
+            let depth = entry.depth();
+            is_last.truncate(depth - 1);
+            is_last.push(last_children.is_last(entry.path(), depth));
+
+            if !first_print_done {
+                config.stop_spinner();
+                first_print_done = true;
+            }
+
+            let indent = if style.oneline { String::new() } else { formatter.generate_indent(depth, &is_last) };
+            let is_dir = entry.file_type().is_dir();
+            let (meta, vanished) = self.file_meta(entry.path(), is_dir, config);
+            let marks = EntryMarks {
+                skipped: is_shown_skipped(entry.file_name(), is_dir, &config.show),
+                stopped: is_stopped(entry.path(), entry.depth(), is_dir, &config.show),
+                mount_point: is_mount_point(entry.path(), is_dir, &config.show),
+                collapsed: config.show.collapsed(&entry),
+                vanished,
+                ..EntryMarks::default()
+            };
+            print_entry_line(writer, &entry, &indent, meta.as_ref(), None, marks, style)?;
+            config.note_previews([&entry]);
+            if let Some(summary) = summary.as_mut() {
+                summary.add(is_dir, marks.mount_point);
+            }
+            if let Some(tally) = tally.as_mut().filter(|_| !is_dir) {
+                tally.add(entry.path(), meta);
+            }
+        }
+
+        config.record_timings(&timings);
+        Ok(TextTotals { stats: self.finish_stats(tally, config), summary })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::filters::{DepthFromMarker, Rule};
+    use crate::core::git::GitStatus;
+    use crate::core::search::SearchQuery;
+    use crate::core::sort::{SortKey, SortOptions};
+    use std::ffi::OsString;
+
+    /// Create a throwaway git-like repo where `ignored/` is gitignored
+    fn gitignored_fixture(name: &str) -> std::path::PathBuf {
+        let root = std::env::temp_dir().join(format!("lst-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(root.join(".git")).unwrap();
+        std::fs::create_dir_all(root.join("ignored")).unwrap();
+        std::fs::create_dir_all(root.join("kept")).unwrap();
+        std::fs::write(root.join(".gitignore"), "ignored/\n").unwrap();
+        std::fs::write(root.join("ignored/needle.txt"), "").unwrap();
+        std::fs::write(root.join("kept/needle.txt"), "").unwrap();
+        root
+    }
+
+    #[test]
+    fn test_dirs_only_keeps_empty_directories() {
+        let root = gitignored_fixture("skeleton");
+        std::fs::create_dir_all(root.join("kept/empty")).unwrap();
+        let show = ShowOptions { dirs_only: true, ..ShowOptions::default() };
+        let config = TreeConfig { max_depth: UNLIMITED_DEPTH, show, no_size: true, ..TreeConfig::new(&root) };
+
+        let mut text = Vec::new();
+        TreeWriter::for_file().write_streaming(&mut text, &config).unwrap();
+        let text = String::from_utf8(text).unwrap();
+        assert!(text.contains("empty/") && text.contains("kept/"), "{}", text);
+        assert!(!text.contains("needle"), "{}", text);
+
+        fn all_dirs(node: &TreeNode) -> bool {
+            node.kind == NodeKind::Directory && node.children.iter().flatten().all(all_dirs)
+        }
+        let json = TreeConfig { format: OutputFormat::Json, ..config };
+        let mut out = Vec::new();
+        TreeWriter::for_file().write_export(&mut out, &json).unwrap();
+        assert!(all_dirs(&serde_json::from_slice(&out).unwrap()));
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_streamed_tree_matches_collected_for_every_narrowing_filter() {
+        let root = std::env::temp_dir().join(format!("lst-stream-glyphs-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        for dir in ["a_empty", "b_full/sub", "b_full/.dot", "c_proj/src/deep", "d_last/inner"] {
+            std::fs::create_dir_all(root.join(dir)).unwrap();
+        }
+        for file in ["b_full/f.txt", "b_full/sub/g.txt", "b_full/.env", "c_proj/Cargo.toml", "c_proj/src/deep/x.rs", "d_last/inner/h.txt"] {
+            std::fs::write(root.join(file), "data").unwrap();
+        }
+
+        let markers = vec![OsString::from("Cargo.toml")];
+        let shows = [
+            ShowOptions::default(),
+            ShowOptions { hidden_only: true, ..ShowOptions::default() },
+            ShowOptions { empty_dirs: true, ..ShowOptions::default() },
+            ShowOptions { stop_at: vec!["b_full".into()], ..ShowOptions::default() },
+            ShowOptions { depth_from_marker: Some(DepthFromMarker::new(markers, 1)), ..ShowOptions::default() },
+        ];
+        for show in shows {
+            let config = TreeConfig { max_depth: UNLIMITED_DEPTH, show: show.clone(), ..TreeConfig::new(&root) };
+            let mut collected = Vec::new();
+            write_header(&mut collected, &config).unwrap();
+            TreeWriter::for_file().write(&mut collected, &config).unwrap();
+            let mut streamed = Vec::new();
+            TreeWriter::for_file().write_to_handle(&mut streamed, &config).unwrap();
+            assert_eq!(String::from_utf8(streamed).unwrap(), String::from_utf8(collected).unwrap(), "{:?}", show);
+        }
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_show_skipped_marks_heavy_directories() {
+        let root = std::env::temp_dir().join(format!("lst-skipped-marker-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(root.join("node_modules/pkg")).unwrap();
+        std::fs::create_dir_all(root.join("src")).unwrap();
+        std::fs::write(root.join("node_modules/pkg/index.js"), "").unwrap();
+        std::fs::write(root.join("src/main.rs"), "").unwrap();
+        let show = ShowOptions {
+            show_skipped: true,
+            sort: SortOptions::new(Some(SortKey::Name), None, None),
+            ..ShowOptions::default()
+        };
+        let config = TreeConfig { max_depth: UNLIMITED_DEPTH, show: show.clone(), no_size: true, ..TreeConfig::new(&root) };
+
+        let expected = "+-- node_modules/ [skipped]\n`-- src/\n    `-- main.rs\n";
+        let mut streamed = Vec::new();
+        TreeWriter::for_file().write_streaming(&mut streamed, &config).unwrap();
+        assert_eq!(String::from_utf8(streamed).unwrap(), expected);
+        let mut collected = Vec::new();
+        TreeWriter::for_file().write(&mut collected, &config).unwrap();
+        assert!(String::from_utf8(collected).unwrap().ends_with(expected));
+
+        let data = TreeData::collect(&root, UNLIMITED_DEPTH, &show, None, None, None, false);
+        let json = data.to_node(&root, None, false).to_json();
+        assert_eq!(json["children"][0]["skipped"], true);
+        assert_eq!(json["children"][1].get("skipped"), None);
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_stop_at_marks_the_directories_it_lists_without_contents() {
+        let root = std::env::temp_dir().join(format!("lst-stopped-marker-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(root.join("app/src")).unwrap();
+        std::fs::write(root.join("app/src/main.rs"), "").unwrap();
+        let show = ShowOptions { stop_at: vec!["src".into()], ..ShowOptions::default() };
+        let config = TreeConfig { max_depth: UNLIMITED_DEPTH, show: show.clone(), no_size: true, ..TreeConfig::new(&root) };
+
+        let expected = "`-- app/\n    `-- src/ [stopped]\n";
+        let mut streamed = Vec::new();
+        TreeWriter::for_file().write_streaming(&mut streamed, &config).unwrap();
+        assert_eq!(String::from_utf8(streamed).unwrap(), expected);
+        let mut collected = Vec::new();
+        TreeWriter::for_file().write(&mut collected, &config).unwrap();
+        assert!(String::from_utf8(collected).unwrap().ends_with(expected));
+
+        let data = TreeData::collect(&root, UNLIMITED_DEPTH, &show, None, None, None, false);
+        let json = data.to_node(&root, None, false).to_json();
+        let app = &json["children"][0];
+        assert_eq!(app.get("stopped"), None);
+        assert_eq!(app["children"][0]["stopped"], true);
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_depth_from_marker_lists_only_the_way_to_deeper_projects() {
+        let root = std::env::temp_dir().join(format!("lst-marker-chains-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        for dir in ["assets/img/icons/x", "crates/app/src/cli", "docs"] {
+            std::fs::create_dir_all(root.join(dir)).unwrap();
+        }
+        for file in ["assets/logo.svg", "assets/img/icons/x/y.png", "crates/app/Cargo.toml", "crates/app/src/main.rs", "docs/guide.md"] {
+            std::fs::write(root.join(file), "").unwrap();
+        }
+        let markers = vec![OsString::from("Cargo.toml")];
+        let show = ShowOptions { depth_from_marker: Some(DepthFromMarker::new(markers, 1)), ..ShowOptions::default() };
+        let config = TreeConfig { max_depth: UNLIMITED_DEPTH, show, no_size: true, ..TreeConfig::new(&root) };
+
+        let mut out = Vec::new();
+        TreeWriter::for_file().write_streaming(&mut out, &config).unwrap();
+        let expected = "+-- assets/\n+-- crates/\n|   `-- app/\n|       +-- src/\n|       `-- Cargo.toml\n`-- docs/\n";
+        assert_eq!(String::from_utf8(out).unwrap(), expected);
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_git_modified_keeps_ancestors_of_changes_below_the_depth_limit() {
+        let root = std::env::temp_dir().join(format!("lst-git-modified-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(root.join("src/deep")).unwrap();
+        std::fs::create_dir_all(root.join("docs")).unwrap();
+        std::fs::write(root.join("src/deep/lib.rs"), "fn main() {}").unwrap();
+        std::fs::write(root.join("docs/guide.md"), "guide").unwrap();
+        let git = |args: &[&str]| {
+            std::process::Command::new("git")
+                .arg("-C")
+                .arg(&root)
+                .args(["-c", "user.name=lst", "-c", "user.email=lst@example.com"])
+                .args(args)
+                .output()
+                .is_ok_and(|out| out.status.success())
+        };
+        // Nothing to check without git
+        if !(git(&["init", "-q"]) && git(&["add", "."]) && git(&["commit", "-q", "-m", "init"])) {
+            std::fs::remove_dir_all(&root).unwrap();
+            return;
+        }
+        std::fs::write(root.join("src/deep/lib.rs"), "fn main() { todo!() }").unwrap();
+
+        let status = GitStatus::load(&root, false).map(Arc::new);
+        assert!(status.is_some());
+        let show = ShowOptions { git_status: status, ..ShowOptions::default() };
+        let config = TreeConfig { show, no_size: true, ..TreeConfig::new(&root) };
+        let mut out = Vec::new();
+        TreeWriter::for_file().write_to_handle(&mut out, &config).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), format!("{}/\n`-- src/\n", root_name(&root)));
+
+        let deep = TreeConfig { max_depth: UNLIMITED_DEPTH, ..config };
+        let mut out = Vec::new();
+        TreeWriter::for_file().write_to_handle(&mut out, &deep).unwrap();
+        let expected = format!("{}/\n`-- src/\n    `-- deep/\n        `-- lib.rs\n", root_name(&root));
+        assert_eq!(String::from_utf8(out).unwrap(), expected);
+
+        // explain agrees with the listing
+        let canonical = std::fs::canonicalize(&root).unwrap();
+        let show = ShowOptions { git_status: GitStatus::load(&canonical, false).map(Arc::new), ..ShowOptions::default() };
+        let rule_of = |path: &str| crate::commands::explain::explain(&canonical, &canonical.join(path), 1, &show).unwrap().rule;
+        assert_eq!(rule_of("docs"), Rule::NotModified);
+        assert_eq!(rule_of("src"), Rule::Listed);
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_depth_one_prints_only_immediate_children() {
+        let root = gitignored_fixture("depth");
+        let render = |max_depth| {
+            let show = ShowOptions { sort: SortOptions::new(Some(SortKey::Name), None, None), ..ShowOptions::default() };
+            let config = TreeConfig { max_depth, show, no_size: true, ..TreeConfig::new(&root) };
+            let mut collected = Vec::new();
+            TreeWriter::for_file().write(&mut collected, &config).unwrap();
+            let mut streamed = Vec::new();
+            TreeWriter::for_file().write_streaming(&mut streamed, &config).unwrap();
+            let (collected, streamed) = (String::from_utf8(collected).unwrap(), String::from_utf8(streamed).unwrap());
+            assert_eq!(collected, streamed, "depth {}", max_depth);
+            collected
+        };
+
+        assert_eq!(render(1), "+-- ignored/\n`-- kept/\n");
+        let deeper = "+-- ignored/\n|   `-- needle.txt\n`-- kept/\n    `-- needle.txt\n";
+        assert_eq!(render(2), deeper);
+        assert_eq!(render(UNLIMITED_DEPTH), deeper);
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_search_skips_gitignored_matches_consistently() {
+        let root = gitignored_fixture("search-gitignore");
+        let config = TreeConfig {
+            max_depth: UNLIMITED_DEPTH,
+            search: Some(SearchQuery::new("needle", &root)),
+            ..TreeConfig::new(&root)
+        };
+
+        let data = config.collect(&FsMetadata);
+        assert!(data.filter.is_visible(&root.join("kept/needle.txt")));
+        assert!(!data.filter.is_visible(&root.join("ignored/needle.txt")));
+        assert!(!data.filter.show_dirs.contains(&root.join("ignored")));
+
+        let mut collected = Vec::new();
+        TreeWriter::for_file().write(&mut collected, &config).unwrap();
+        let mut streamed = Vec::new();
+        TreeWriter::for_file().write_streaming(&mut streamed, &config).unwrap();
+
+        for output in [collected, streamed] {
+            let text = String::from_utf8(output).unwrap();
+            assert!(text.contains("kept/"), "{}", text);
+            assert!(text.contains("needle.txt"), "{}", text);
+            assert!(!text.contains("ignored"), "{}", text);
+        }
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    /// Metadata source that counts how often each path is stat-ed
+    #[derive(Default)]
+    struct CountingMetadata {
+        calls: std::sync::Mutex<HashMap<PathBuf, usize>>,
+    }
+
+    impl MetadataSource for CountingMetadata {
+        fn stat(&self, path: &Path) -> std::io::Result<EntryMeta> {
+            *self.calls.lock().unwrap().entry(path.to_path_buf()).or_default() += 1;
+            FsMetadata.stat(path)
+        }
+    }
+
+    #[test]
+    fn test_each_entry_is_stat_at_most_once() {
+        let root = gitignored_fixture("stat-once");
+        let search_config = TreeConfig {
+            max_depth: UNLIMITED_DEPTH,
+            search: Some(SearchQuery::new("needle", &root)),
+            ..TreeConfig::new(&root)
+        };
+        let list_config = TreeConfig {
+            max_depth: UNLIMITED_DEPTH,
+            ..TreeConfig::new(&root)
+        };
+
+        for config in [&list_config, &search_config] {
+            for streaming in [false, true] {
+                let counter = Arc::new(CountingMetadata::default());
+                let writer = TreeWriter::for_file().with_metadata_source(counter.clone());
+                let mut out = Vec::new();
+                if streaming {
+                    writer.write_streaming(&mut out, config).unwrap();
+                } else {
+                    writer.write(&mut out, config).unwrap();
                 }
 
-                let indent = formatter.generate_indent(depth, &is_last);
-                print_entry_line(writer, &entry, &indent, self.use_color)?;
+                let calls = counter.calls.lock().unwrap();
+                assert!(!calls.is_empty());
+                assert!(calls.values().all(|&n| n == 1), "{:?}", calls);
             }
         }
 
-        Ok(())
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_stats_come_from_the_written_tree() {
+        let root = gitignored_fixture("stats-once");
+        for search in [None, Some(SearchQuery::new("needle", &root))] {
+            let config = TreeConfig { max_depth: UNLIMITED_DEPTH, stats: true, report: true, search, ..TreeConfig::new(&root) };
+            let counter = Arc::new(CountingMetadata::default());
+            let writer = TreeWriter::for_file().with_metadata_source(counter.clone());
+            let mut out = Vec::new();
+            writer.write_to_handle(&mut out, &config).unwrap();
+            assert!(String::from_utf8(out).unwrap().contains("Total"));
+            let calls = counter.calls.lock().unwrap().clone();
+            assert!(calls.values().all(|&n| n == 1), "{:?}", calls);
+
+            let streamed = writer.write_streaming(&mut Vec::new(), &config).unwrap();
+            let collected = writer.write(&mut Vec::new(), &config).unwrap();
+            assert!(streamed.stats.as_ref().is_some_and(|stats| !stats.is_empty()));
+            assert!(streamed.summary.is_some_and(|summary| summary.files > 0));
+            assert_eq!(streamed, collected);
+        }
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_previews_are_picked_from_the_listed_files() {
+        let root = gitignored_fixture("previews");
+        std::fs::write(root.join("notes.txt"), "hi").unwrap();
+        std::fs::write(root.join("zzz.txt"), "hi").unwrap();
+        let picked = |max_depth: usize, search: Option<SearchQuery>| {
+            let previewed = Arc::new(Mutex::new(Vec::new()));
+            let config = TreeConfig {
+                max_depth,
+                search,
+                show: ShowOptions { sort: SortOptions::new(Some(SortKey::Name), None, None), ..ShowOptions::default() },
+                preview: GlobFilter::new(vec!["*.txt".to_string()], Default::default()).unwrap(),
+                previewed: Some(previewed.clone()),
+                ..TreeConfig::new(&root)
+            };
+            TreeWriter::for_file().write_to_handle(&mut Vec::new(), &config).unwrap();
+            let files = previewed.lock().unwrap().clone();
+            files
+        };
+        // At most three, in the order the tree lists them
+        let all = ["ignored/needle.txt", "kept/needle.txt", "notes.txt"].map(|f| root.join(f));
+        assert_eq!(picked(UNLIMITED_DEPTH, None), all);
+        assert_eq!(picked(1, None), [root.join("notes.txt"), root.join("zzz.txt")]);
+        assert_eq!(picked(UNLIMITED_DEPTH, Some(SearchQuery::new("zzz", &root))), [root.join("zzz.txt")]);
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    /// Metadata source on which one path is deleted after the walk, and another can't be read
+    struct RacingMetadata;
+
+    impl MetadataSource for RacingMetadata {
+        fn stat(&self, path: &Path) -> std::io::Result<EntryMeta> {
+            match path.file_name().and_then(|n| n.to_str()) {
+                Some("gone.txt") => Err(std::io::Error::new(std::io::ErrorKind::NotFound, "gone")),
+                Some("locked.txt") => Err(std::io::Error::new(std::io::ErrorKind::PermissionDenied, "locked")),
+                _ => FsMetadata.stat(path),
+            }
+        }
+    }
+
+    #[test]
+    fn test_entries_deleted_mid_walk_show_vanished_instead_of_a_size() {
+        let root = gitignored_fixture("vanished");
+        for name in ["gone.txt", "locked.txt"] {
+            std::fs::write(root.join(name), "12345").unwrap();
+        }
+        let config = TreeConfig { max_depth: UNLIMITED_DEPTH, ..TreeConfig::new(&root) };
+
+        for streaming in [false, true] {
+            let writer = TreeWriter::for_file().with_metadata_source(Arc::new(RacingMetadata));
+            let mut out = Vec::new();
+            if streaming {
+                writer.write_streaming(&mut out, &config).unwrap();
+            } else {
+                writer.write(&mut out, &config).unwrap();
+            }
+            let text = String::from_utf8(out).unwrap();
+            assert!(text.contains("gone.txt [vanished]\n"), "{}", text);
+            // Other errors keep the old fallback
+            assert!(text.contains("locked.txt (0 B)\n"), "{}", text);
+            assert!(text.contains("needle.txt ("), "{}", text);
+        }
+
+        let writer = TreeWriter::for_file().with_metadata_source(Arc::new(RacingMetadata));
+        let mut json = Vec::new();
+        writer.write_export(&mut json, &TreeConfig { format: OutputFormat::Json, ..config }).unwrap();
+        let tree: TreeNode = serde_json::from_slice(&json).unwrap();
+        let children = tree.children.unwrap();
+        let vanished = |name: &str| children.iter().find(|c| c.name == name).map(|c| c.vanished);
+        assert_eq!(vanished("gone.txt"), Some(true));
+        assert_eq!(vanished("locked.txt"), Some(false));
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_bars_scale_to_the_largest_sibling() {
+        let root = gitignored_fixture("bars");
+        std::fs::write(root.join("kept/big.bin"), [0u8; 80]).unwrap();
+        std::fs::write(root.join("kept/small.bin"), [0u8; 20]).unwrap();
+        std::fs::write(root.join("alone.bin"), [0u8; 3]).unwrap();
+        let config = TreeConfig { max_depth: UNLIMITED_DEPTH, bars: true, ..TreeConfig::new(&root) };
+
+        let mut out = Vec::new();
+        TreeWriter::for_file().with_tree_style(TreeStyle::Unicode).write(&mut out, &config).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert!(text.contains("big.bin (80 B) █\n"), "{}", text);
+        assert!(text.contains("small.bin (20 B) ▂\n"), "{}", text);
+        assert!(text.contains("alone.bin (3 B) █\n"), "{}", text);
+        // Empty files get no bar
+        assert!(text.contains("needle.txt (0 B)\n"), "{}", text);
+
+        let mut out = Vec::new();
+        TreeWriter::for_file().write(&mut out, &config).unwrap();
+        assert!(String::from_utf8(out).unwrap().contains("small.bin (20 B) ##\n"));
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_output_format_from_extension() {
+        assert_eq!(OutputFormat::from_extension(Path::new("out/tree.json")), Some(OutputFormat::Json));
+        assert_eq!(OutputFormat::from_extension(Path::new("TREE.JSON")), Some(OutputFormat::Json));
+        assert_eq!(OutputFormat::from_extension(Path::new("tree.txt")), Some(OutputFormat::Text));
+        assert_eq!(OutputFormat::from_extension(Path::new("tree.md")), None);
+        assert_eq!(OutputFormat::from_extension(Path::new("json")), None);
+    }
+
+    #[test]
+    fn test_no_size_for_hides_only_listed_extensions() {
+        let root = gitignored_fixture("no-size-for");
+        std::fs::write(root.join("kept/Cargo.LOCK"), "lock").unwrap();
+        std::fs::write(root.join("kept/clip.mp4"), "video").unwrap();
+        let config = TreeConfig { max_depth: UNLIMITED_DEPTH, no_size_for: vec!["lock".to_string()], ..TreeConfig::new(&root) };
+
+        let mut out = Vec::new();
+        TreeWriter::for_file().write_streaming(&mut out, &config).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert!(text.contains("Cargo.LOCK\n"), "{}", text);
+        assert!(text.contains("clip.mp4 (5 B)"), "{}", text);
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_no_size_prints_names_without_stat() {
+        let root = gitignored_fixture("no-size");
+        let config = TreeConfig { max_depth: UNLIMITED_DEPTH, no_size: true, ..TreeConfig::new(&root) };
+
+        for streaming in [false, true] {
+            let counter = Arc::new(CountingMetadata::default());
+            let writer = TreeWriter::for_file().with_metadata_source(counter.clone());
+            let mut out = Vec::new();
+            if streaming {
+                writer.write_streaming(&mut out, &config).unwrap();
+            } else {
+                writer.write(&mut out, &config).unwrap();
+            }
+            let text = String::from_utf8(out).unwrap();
+            assert!(text.contains("needle.txt\n"), "{}", text);
+            assert!(!text.contains(" B)"), "{}", text);
+            assert!(counter.calls.lock().unwrap().is_empty());
+        }
+
+        let json_config = TreeConfig { format: OutputFormat::Json, ..config };
+        let mut json = Vec::new();
+        TreeWriter::for_file().write_export(&mut json, &json_config).unwrap();
+        assert!(!String::from_utf8(json).unwrap().contains("\"size\""));
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_timed_flush_batches_lines() {
+        /// Records the size of every write that reaches it
+        #[derive(Default)]
+        struct Writes(Vec<usize>);
+        impl Write for Writes {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                self.0.push(buf.len());
+                Ok(buf.len())
+            }
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let mut out = TimedFlush::new(Writes::default(), 1024);
+        for _ in 0..100 {
+            writeln!(out, "+-- file.txt (0 B)").unwrap();
+        }
+        out.flush().unwrap();
+        let writes = out.buffered().get_ref().0.clone();
+        assert_eq!(writes.iter().sum::<usize>(), 100 * 19);
+        assert!(writes.len() <= 3, "{:?}", writes);
+
+        // A line waiting in the buffer reaches the output without another write
+        writeln!(out, "+-- slow.txt (0 B)").unwrap();
+        std::thread::sleep(FLUSH_INTERVAL * 5);
+        assert_eq!(out.buffered().get_ref().0.last(), Some(&19));
+    }
+
+    #[test]
+    fn test_compact_json_is_one_line() {
+        let root = gitignored_fixture("compact-json");
+        let config = TreeConfig { format: OutputFormat::Json, compact: true, ..TreeConfig::new(&root) };
+        let mut out = Vec::new();
+        TreeWriter::for_file().write_export(&mut out, &config).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert_eq!(text.matches('\n').count(), 1);
+        assert!(text.ends_with("}\n"));
+        let node: TreeNode = serde_json::from_str(&text).unwrap();
+        assert_eq!(node.children.map(|c| c.len()), Some(2));
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_export_matches_requested_format() {
+        let root = gitignored_fixture("export-format");
+        let text_config = TreeConfig::new(&root);
+        let json_config = TreeConfig {
+            format: OutputFormat::Json,
+            ..TreeConfig::new(&root)
+        };
+
+        let mut text = Vec::new();
+        TreeWriter::for_file().write_export(&mut text, &text_config).unwrap();
+        let text = String::from_utf8(text).unwrap();
+        let header = format!("{}/", root.file_name().unwrap().to_string_lossy());
+        assert_eq!(text.lines().next(), Some(header.as_str()));
+
+        let mut json = Vec::new();
+        TreeWriter::for_file().write_export(&mut json, &json_config).unwrap();
+        let value: serde_json::Value = serde_json::from_slice(&json).unwrap();
+        assert_eq!(value["type"], "directory");
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_file_output_is_ascii_unless_tree_style_set() {
+        let root = gitignored_fixture("tree-style");
+        let render = |tree_style| {
+            let config = TreeConfig { max_depth: 2, tree_style, ..TreeConfig::new(&root) };
+            let mut out = Vec::new();
+            TreeWriter::for_file().write(&mut out, &config).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+
+        let auto = render(TreeStyle::Auto);
+        assert!(auto.is_ascii(), "{}", auto);
+        assert!(auto.contains("`-- "));
+        assert!(render(TreeStyle::Unicode).contains("└── "));
+        assert_eq!(render(TreeStyle::Ascii), auto);
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_header_options_agree_across_formats() {
+        let root = gitignored_fixture("header");
+        let export = |header, format| {
+            let mut out = Vec::new();
+            let config = TreeConfig { header, format, ..TreeConfig::new(&root) };
+            TreeWriter::for_file().write_export(&mut out, &config).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+
+        let text = export(RootHeader::Custom("project"), OutputFormat::Text);
+        assert_eq!(text.lines().next(), Some("project"));
+        let json: TreeNode = serde_json::from_str(&export(RootHeader::Custom("project"), OutputFormat::Json)).unwrap();
+        assert_eq!(json.name, "project");
+
+        let json: TreeNode = serde_json::from_str(&export(RootHeader::Name, OutputFormat::Json)).unwrap();
+        assert_eq!(export(RootHeader::Name, OutputFormat::Text).lines().next(), Some(format!("{}/", json.name).as_str()));
+        assert!(export(RootHeader::Hidden, OutputFormat::Text).starts_with("+-- "));
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_root_label() {
+        let cwd = std::env::current_dir().unwrap();
+        assert_eq!(root_label(Path::new("."), false), format!("{}/", cwd.file_name().unwrap().to_string_lossy()));
+        assert_eq!(root_label(Path::new("./src"), false), "src/");
+        assert_eq!(root_label(Path::new("/"), false), "/");
+        assert_eq!(root_label(Path::new("/"), true), "/");
+    }
+
+    /// Export `root` to `out` with the given mode, returning the file contents afterwards
+    fn export_with(root: &Path, out: &Path, mode: ExportMode) -> Result<String> {
+        let config = TreeConfig {
+            export_mode: mode,
+            ..TreeConfig::new(root)
+        };
+        TreeWriter::for_file().write_to_file(out.to_str().unwrap(), &config)?;
+        Ok(std::fs::read_to_string(out).unwrap())
+    }
+
+    #[test]
+    fn test_export_creates_new_file() {
+        let root = gitignored_fixture("export-new");
+        let out = root.join("tree.txt");
+        let text = export_with(&root, &out, ExportMode::CreateNew).unwrap();
+        assert!(text.contains("kept/"), "{}", text);
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_export_refuses_to_overwrite_without_force() {
+        let root = gitignored_fixture("export-exists");
+        let out = root.join("Cargo.toml");
+        std::fs::write(&out, "precious").unwrap();
+
+        let err = export_with(&root, &out, ExportMode::CreateNew).unwrap_err();
+        assert!(matches!(err, LstError::OutputExists(_)), "{}", err);
+        assert_eq!(std::fs::read_to_string(&out).unwrap(), "precious");
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_export_force_truncates() {
+        let root = gitignored_fixture("export-force");
+        let out = root.join("tree.txt");
+        std::fs::write(&out, "precious").unwrap();
+
+        let text = export_with(&root, &out, ExportMode::Overwrite).unwrap();
+        assert!(!text.contains("precious"), "{}", text);
+        assert!(text.contains("kept/"), "{}", text);
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_export_append_keeps_existing_content() {
+        let root = gitignored_fixture("export-append");
+        let out = root.join("tree.txt");
+        std::fs::write(&out, "precious\n").unwrap();
+
+        let text = export_with(&root, &out, ExportMode::Append).unwrap();
+        assert!(text.starts_with("precious\n"), "{}", text);
+        assert!(text.contains("kept/"), "{}", text);
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_export_has_no_escape_codes_unless_asked() {
+        let root = gitignored_fixture("export-color");
+        let config = TreeConfig::new(&root);
+        let export = |mode| {
+            let mut out = Vec::new();
+            TreeWriter::for_export(mode).write_export(&mut out, &config).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        let plain = export(ColorMode::Never);
+        assert!(!plain.contains('\u{1b}'), "{}", plain);
+        assert_eq!(export(ColorMode::Auto), plain);
+
+        // `--color always` keeps the codes, around the same text
+        colored::control::set_override(true);
+        let colored = export(ColorMode::Always);
+        colored::control::unset_override();
+        assert!(colored.contains("\u{1b}["), "{}", colored);
+        let mut stripped = String::new();
+        let mut rest = colored.as_str();
+        while let Some(start) = rest.find('\u{1b}') {
+            stripped.push_str(&rest[..start]);
+            rest = &rest[start..];
+            rest = &rest[rest.find('m').map_or(rest.len(), |end| end + 1)..];
+        }
+        stripped.push_str(rest);
+        assert_eq!(stripped, plain);
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_oneline_lists_children_without_glyphs() {
+        let root = gitignored_fixture("oneline");
+        let config = TreeConfig {
+            oneline: true,
+            show: ShowOptions { hidden: true, ..ShowOptions::default() },
+            ..TreeConfig::new(&root)
+        };
+
+        let mut collected = Vec::new();
+        TreeWriter::for_file().write_export(&mut collected, &config).unwrap();
+        let mut streamed = Vec::new();
+        TreeWriter::for_file().write_streaming(&mut streamed, &config).unwrap();
+
+        for output in [collected, streamed] {
+            let text = String::from_utf8(output).unwrap();
+            let mut lines: Vec<&str> = text.lines().collect();
+            lines.sort();
+            assert_eq!(lines, [".git/", ".gitignore (9 B)", "ignored/", "kept/"], "{}", text);
+        }
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_pad_names_aligns_sibling_sizes() {
+        let root = gitignored_fixture("pad-names");
+        std::fs::write(root.join("kept/a-much-longer-name.txt"), "x").unwrap();
+        std::fs::write(root.join("kept/日本.txt"), "x").unwrap();
+        let config = TreeConfig {
+            max_depth: UNLIMITED_DEPTH,
+            pad_names: true,
+            ..TreeConfig::new(&root)
+        };
+
+        let mut out = Vec::new();
+        TreeWriter::for_file().write_export(&mut out, &config).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        let size_columns: Vec<usize> = text
+            .lines()
+            .filter(|l| l.starts_with("    "))
+            .filter_map(|l| l.find(" (").map(|i| display_width(&l[..i])))
+            .collect();
+        assert_eq!(size_columns.len(), 3, "{}", text);
+        assert!(size_columns.iter().all(|&c| c == size_columns[0]), "{}", text);
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_full_path_prints_paths_relative_to_root() {
+        let root = gitignored_fixture("full-path");
+        let config = TreeConfig {
+            max_depth: UNLIMITED_DEPTH,
+            full_path: true,
+            search: Some(SearchQuery::new("needle", &root)),
+            ..TreeConfig::new(&root)
+        };
+
+        let mut collected = Vec::new();
+        TreeWriter::for_file().write(&mut collected, &config).unwrap();
+        let mut streamed = Vec::new();
+        TreeWriter::for_file().write_streaming(&mut streamed, &config).unwrap();
+
+        for output in [collected, streamed] {
+            let text = String::from_utf8(output).unwrap();
+            assert!(text.contains("-- kept/\n"), "{}", text);
+            assert!(text.contains("-- kept/needle.txt (0 B)"), "{}", text);
+            assert!(!text.contains("ignored"), "{}", text);
+        }
+
+        // A plain listing, hidden entries included, prints every path the same way
+        let show = ShowOptions { hidden: true, sort: SortOptions::new(Some(SortKey::Name), None, None), ..ShowOptions::default() };
+        let config = TreeConfig { search: None, show, no_size: true, ..config };
+        let expected = "+-- .git/\n+-- .gitignore\n+-- ignored/\n|   `-- ignored/needle.txt\n`-- kept/\n    `-- kept/needle.txt\n";
+        let mut streamed = Vec::new();
+        TreeWriter::for_file().write_streaming(&mut streamed, &config).unwrap();
+        assert_eq!(String::from_utf8(streamed).unwrap(), expected);
+        let mut collected = Vec::new();
+        TreeWriter::for_file().write(&mut collected, &config).unwrap();
+        assert!(String::from_utf8(collected).unwrap().ends_with(expected));
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_search_reports_whether_anything_matched() {
+        let root = gitignored_fixture("found");
+        for (pattern, expected) in [("needle", true), ("haystack", false)] {
+            let found = Arc::new(AtomicBool::new(false));
+            let config = TreeConfig {
+                max_depth: UNLIMITED_DEPTH,
+                search: Some(SearchQuery::new(pattern, &root)),
+                found: Some(Arc::clone(&found)),
+                ..TreeConfig::new(&root)
+            };
+            TreeWriter::for_file().write_streaming(&mut Vec::new(), &config).unwrap();
+            assert_eq!(found.load(Ordering::Relaxed), expected, "streamed {}", pattern);
+
+            found.store(false, Ordering::Relaxed);
+            TreeWriter::for_file().write(&mut Vec::new(), &config).unwrap();
+            assert_eq!(found.load(Ordering::Relaxed), expected, "collected {}", pattern);
+        }
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_hidden_only_shows_dotfiles_with_ancestors() {
+        let root = gitignored_fixture("hidden-only");
+        std::fs::write(root.join("kept/.env"), "").unwrap();
+        let config = TreeConfig {
+            max_depth: UNLIMITED_DEPTH,
+            show: ShowOptions { hidden_only: true, ..ShowOptions::default() },
+            ..TreeConfig::new(&root)
+        };
+
+        let mut collected = Vec::new();
+        TreeWriter::for_file().write(&mut collected, &config).unwrap();
+        let mut streamed = Vec::new();
+        TreeWriter::for_file().write_streaming(&mut streamed, &config).unwrap();
+
+        for output in [collected, streamed] {
+            let text = String::from_utf8(output).unwrap();
+            assert!(text.contains("kept/"), "{}", text);
+            assert!(text.contains(".env"), "{}", text);
+            assert!(text.contains(".gitignore"), "{}", text);
+            assert!(!text.contains("needle"), "{}", text);
+            assert!(!text.contains(".git/"), "{}", text);
+        }
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_empty_dirs_lists_only_empty_directories_with_ancestors() {
+        let root = gitignored_fixture("empty-dirs");
+        std::fs::create_dir_all(root.join("kept/hollow/inner")).unwrap();
+        std::fs::create_dir_all(root.join("kept/dotted")).unwrap();
+        std::fs::write(root.join("kept/dotted/.keep"), "").unwrap();
+        let config = |max_depth| TreeConfig {
+            max_depth,
+            show: ShowOptions { empty_dirs: true, ..ShowOptions::default() },
+            ..TreeConfig::new(&root)
+        };
+
+        // A dotfile doesn't count without --all
+        let mut out = Vec::new();
+        TreeWriter::for_file().write_streaming(&mut out, &config(UNLIMITED_DEPTH)).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "`-- kept/\n    +-- dotted/\n    `-- hollow/\n        `-- inner/\n");
+        // Empty directories below the depth limit still keep their ancestors
+        let mut out = Vec::new();
+        TreeWriter::for_file().write_streaming(&mut out, &config(1)).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "`-- kept/\n");
+
+        let with_hidden = ShowOptions { hidden: true, empty_dirs: true, ..ShowOptions::default() };
+        let json = TreeConfig { format: OutputFormat::Json, show: with_hidden, ..config(UNLIMITED_DEPTH) };
+        let mut out = Vec::new();
+        TreeWriter::for_file().write_export(&mut out, &json).unwrap();
+        let tree: TreeNode = serde_json::from_slice(&out).unwrap();
+        fn listed(node: &TreeNode, out: &mut Vec<(String, bool)>) {
+            assert_eq!(node.kind, NodeKind::Directory);
+            out.push((node.name.clone(), node.empty));
+            node.children.iter().flatten().for_each(|child| listed(child, out));
+        }
+        let mut nodes = Vec::new();
+        listed(&tree, &mut nodes);
+        nodes.remove(0);
+        // `hollow` holds only an empty directory, so it's listed as the way to `inner`, not as empty;
+        // the fixture's `.git` is empty, and `.keep` makes `dotted` non-empty with --all
+        let expected = [(".git", true), ("kept", false), ("hollow", false), ("inner", true)].map(|(name, empty)| (name.to_string(), empty));
+        assert_eq!(nodes, expected);
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_both_walkers_render_identical_lines() {
+        let root = gitignored_fixture("fs-entry");
+        std::fs::write(root.join("kept/run.sh"), "#!/bin/sh").unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(root.join("kept/run.sh"), std::fs::Permissions::from_mode(0o755)).unwrap();
+            std::os::unix::fs::symlink("needle.txt", root.join("kept/link")).unwrap();
+        }
+        let config = TreeConfig {
+            classify: true,
+            full_path: true,
+            columns: "perms,name,size".parse().unwrap(),
+            ..TreeConfig::new(&root)
+        };
+        let writer = TreeWriter::for_terminal();
+        let style = writer.style(&config);
+        let query = SearchQuery::new("e", &root);
+
+        let walked: Vec<DirEntry> = walkdir::WalkDir::new(&root).min_depth(1).sort_by_file_name().into_iter().map(|e| e.unwrap()).collect();
+        let searched: Vec<IgnoreDirEntry> = ignore::WalkBuilder::new(&root)
+            .hidden(false)
+            .git_ignore(false)
+            .sort_by_file_name(Ord::cmp)
+            .build()
+            .skip(1)
+            .map(|e| e.unwrap())
+            .collect();
+        assert_eq!(walked.len(), searched.len());
+        for (a, b) in walked.iter().zip(&searched) {
+            assert_eq!(a.path(), b.path());
+            let marks = EntryMarks { name_width: 20, ..EntryMarks::default() };
+            let meta = FsMetadata.stat(a.path()).ok();
+            let (mut from_walkdir, mut from_ignore) = (Vec::new(), Vec::new());
+            print_entry_line(&mut from_walkdir, a, "|-- ", meta.as_ref(), Some(&query), marks, style).unwrap();
+            print_entry_line(&mut from_ignore, b, "|-- ", meta.as_ref(), Some(&query), marks, style).unwrap();
+            assert_eq!(String::from_utf8(from_walkdir).unwrap(), String::from_utf8(from_ignore).unwrap());
+        }
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_escape_names_neutralizes_control_characters() {
+        let root = gitignored_fixture("escape-names");
+        std::fs::write(root.join("kept/evil\x1b[2J\nname"), "").unwrap();
+        let render = |escape_names| {
+            let config = TreeConfig { max_depth: UNLIMITED_DEPTH, escape_names, ..TreeConfig::new(&root) };
+            let mut out = Vec::new();
+            TreeWriter::for_file().write(&mut out, &config).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+
+        let escaped = render(true);
+        assert!(escaped.contains("evil\\e[2J\\nname (0 B)"), "{}", escaped);
+        assert!(!escaped.contains('\x1b'));
+        assert!(render(false).contains("evil\x1b[2J\nname"));
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_escape_names_covers_archive_members_and_stdin_paths() {
+        use crate::core::virtual_tree::{build_detached_entries, build_virtual_entries};
+        let evil = "evil\x1b[2Jname.txt";
+        let render = |entries: &[VirtualEntry], escape_names| {
+            let config = TreeConfig { escape_names, ..TreeConfig::new(Path::new("a.tar")) };
+            let mut out = Vec::new();
+            TreeWriter::for_file().write_virtual(&mut out, &config, entries).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+
+        let members = build_detached_entries(vec![(format!("docs\x07/{}", evil), Some(3))]);
+        let escaped = render(&members, true);
+        assert!(escaped.contains("docs\\x07/\n"), "{}", escaped);
+        assert!(escaped.contains("evil\\e[2Jname.txt (3 B)"), "{}", escaped);
+        assert!(!escaped.contains('\x1b') && !escaped.contains('\x07'));
+        assert!(render(&members, false).contains(evil));
+
+        let listed = build_virtual_entries(&[evil.to_string()]);
+        assert!(!render(&listed, true).contains('\x1b'));
+    }
+
+    #[test]
+    fn test_virtual_json_root_is_named_like_a_listing_root() {
+        use crate::core::virtual_tree::build_detached_entries;
+        let members = build_detached_entries(vec![("docs/notes.md".to_string(), Some(3))]);
+        let config = TreeConfig { format: OutputFormat::Json, ..TreeConfig::new(Path::new("backups/site.tar")) };
+        let mut out = Vec::new();
+        TreeWriter::for_file().write_virtual(&mut out, &config, &members).unwrap();
+        let tree: serde_json::Value = serde_json::from_slice(&out).unwrap();
+        assert_eq!(tree["name"], "site.tar");
+        assert_eq!(tree["path"], "backups/site.tar");
+    }
+
+    #[test]
+    fn test_stdin_tree_is_headed_by_the_root_name_and_honors_no_size() {
+        use crate::core::virtual_tree::build_virtual_entries;
+        // Tests run from the crate root, where `Cargo.toml` is on disk
+        let entries = build_virtual_entries(&["Cargo.toml".to_string()]);
+        let render = |no_size, format| {
+            let config = TreeConfig { no_size, format, ..TreeConfig::new(Path::new(".")) };
+            let mut out = Vec::new();
+            if format == OutputFormat::Text {
+                write_header(&mut out, &config).unwrap();
+            }
+            TreeWriter::for_file().write_virtual(&mut out, &config, &entries).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        let header = format!("{}/\n", root_name(Path::new(".")));
+        assert!(render(false, OutputFormat::Text).starts_with(&format!("{}`-- Cargo.toml (", header)));
+        assert_eq!(render(true, OutputFormat::Text), format!("{}`-- Cargo.toml\n", header));
+        assert!(!render(true, OutputFormat::Json).contains("\"size\""));
+    }
+
+    #[test]
+    fn test_virtual_entries_follow_name_width_and_size_options() {
+        use crate::core::virtual_tree::build_detached_entries;
+        use crate::output::formatter::{NameLimit, SizeFormat, Truncate};
+        let members = build_detached_entries(vec![
+            ("docs/a_rather_long_name.txt".to_string(), Some(1234)),
+            ("docs/notes.md".to_string(), Some(1234)),
+        ]);
+        let config = TreeConfig {
+            name_limit: Some(NameLimit { max_width: 10, truncate: Truncate::End }),
+            size_format: SizeFormat { precision: Some(2), width: None },
+            no_size_for: vec!["md".to_string()],
+            ..TreeConfig::new(Path::new("a.tar"))
+        };
+        let mut out = Vec::new();
+        TreeWriter::for_file().write_virtual(&mut out, &config, &members).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "`-- docs/\n    +-- a_rather_… (1.23 kB)\n    `-- notes.md\n");
+    }
+
+    #[test]
+    fn test_report_counts_what_the_tree_lists() {
+        let root = gitignored_fixture("report");
+        std::fs::create_dir_all(root.join("kept/sub")).unwrap();
+        std::fs::write(root.join("kept/sub/other.txt"), "").unwrap();
+        let render = |config: TreeConfig| {
+            let mut out = Vec::new();
+            TreeWriter::for_file().write_export(&mut out, &config).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        let config = || TreeConfig { max_depth: UNLIMITED_DEPTH, report: true, ..TreeConfig::new(&root) };
+
+        // A search leaves out gitignored matches, and the counts follow
+        let all = render(config());
+        assert!(all.ends_with("\n\n3 directories, 3 files\n"), "{}", all);
+        let searched = render(TreeConfig { search: Some(SearchQuery::new("needle", &root)), ..config() });
+        assert!(searched.ends_with("\n1 directory, 1 file\n"), "{}", searched);
+
+        assert_eq!(render(TreeConfig { report_only: true, ..config() }), "3 directories, 3 files\n");
+        let json = render(TreeConfig { format: OutputFormat::Json, report_only: true, compact: true, ..config() });
+        assert_eq!(json.trim(), r#"{"directories":3,"files":3}"#);
+        let tree: TreeNode = serde_json::from_str(&render(TreeConfig { format: OutputFormat::Json, ..config() })).unwrap();
+        assert_eq!(tree.summary, Some(Summary { directories: 3, files: 3, mount_points: 0 }));
+
+        // The counts come from the tree as written, so the walk is timed once
+        let walked = |report| {
+            let timings = Arc::new(Mutex::new(Timings::default()));
+            render(TreeConfig { report, timings: Some(timings.clone()), ..config() });
+            let entries = timings.lock().unwrap().entries;
+            entries
+        };
+        assert_eq!(walked(true), walked(false));
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_quote_names_makes_names_and_full_paths_shell_safe() {
+        let root = gitignored_fixture("quote-names");
+        std::fs::create_dir_all(root.join("kept/my dir")).unwrap();
+        std::fs::write(root.join("kept/my dir/it's $5.txt"), "").unwrap();
+        std::fs::write(root.join("kept/say \"hi\".md"), "").unwrap();
+        let render = |full_path| {
+            let config = TreeConfig { max_depth: UNLIMITED_DEPTH, quote_names: true, full_path, ..TreeConfig::new(&root) };
+            let mut out = Vec::new();
+            TreeWriter::for_file().write(&mut out, &config).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+
+        let names = render(false);
+        assert!(names.contains("-- 'my dir'/\n"), "{}", names);
+        assert!(names.contains("-- 'it'\\''s $5.txt' (0 B)"), "{}", names);
+        assert!(names.contains("-- 'say \"hi\".md' (0 B)"), "{}", names);
+        assert!(names.contains("-- needle.txt (0 B)"), "{}", names);
+
+        let paths = render(true);
+        assert!(paths.contains("-- 'kept/my dir/it'\\''s $5.txt' (0 B)"), "{}", paths);
+        assert!(paths.contains("-- kept/needle.txt (0 B)"), "{}", paths);
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_mark_empty_lists_a_placeholder() {
+        let root = gitignored_fixture("mark-empty");
+        std::fs::create_dir_all(root.join("kept/vacant")).unwrap();
+        let config = TreeConfig { max_depth: UNLIMITED_DEPTH, mark_empty: true, ..TreeConfig::new(&root) };
+        let mut out = Vec::new();
+        TreeWriter::for_file().write(&mut out, &config).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        let vacant = lines.iter().position(|l| l.ends_with("vacant/")).expect(&text);
+        assert_eq!(lines[vacant + 1], format!("{}    `-- (empty)", &lines[vacant][..4]), "{}", text);
+        assert_eq!(text.matches("(empty)").count(), 1, "{}", text);
+
+        let empty_root = root.join("kept/vacant");
+        let mut out = Vec::new();
+        TreeWriter::for_file().write(&mut out, &TreeConfig { mark_empty: true, ..TreeConfig::new(&empty_root) }).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "`-- (empty)\n");
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_stats_include_skipped_counts_hidden_contents() {
+        let root = gitignored_fixture("stats-skipped");
+        std::fs::create_dir_all(root.join("node_modules/pkg")).unwrap();
+        std::fs::write(root.join("node_modules/pkg/index.js"), "x".repeat(50)).unwrap();
+        let stats_of = |stats_include_skipped| {
+            let config = TreeConfig {
+                format: OutputFormat::Json,
+                max_depth: UNLIMITED_DEPTH,
+                stats: true,
+                stats_include_skipped,
+                ..TreeConfig::new(&root)
+            };
+            let mut out = Vec::new();
+            TreeWriter::for_file().write_export(&mut out, &config).unwrap();
+            let node: TreeNode = serde_json::from_slice(&out).unwrap();
+            assert!(node.children.unwrap().iter().all(|c| c.name != "node_modules"));
+            node.stats.unwrap()
+        };
+
+        // By default the totals agree with the tree, which leaves node_modules out
+        assert!(!stats_of(false).contains_key("js"));
+        assert_eq!(stats_of(true)["js"], ExtensionStats { files: 1, size: 50 });
+        std::fs::remove_dir_all(&root).unwrap();
     }
 }
@@ -0,0 +1,170 @@
+use serde_json::{json, Value};
+
+/// JSON Schema (draft 2020-12) of the tree emitted by `--json`, printed by `--print-schema`
+pub fn tree_schema() -> Value {
+    let count = |description: &str| json!({ "type": "integer", "minimum": 0, "description": description });
+    json!({
+        "$schema": "https://json-schema.org/draft/2020-12/schema",
+        "title": "lst tree",
+        "description": "Directory tree written by `lst --json`; the root node may also carry `truncated`, `stats`, `summary` and `depth_limit`",
+        "$ref": "#/$defs/node",
+        "$defs": {
+            "node": {
+                "type": "object",
+                "required": ["name", "type", "path", "depth"],
+                "additionalProperties": false,
+                "properties": {
+                    "name": { "type": "string", "description": "File name of the entry" },
+                    "type": { "enum": ["directory", "file"] },
+                    "path": {
+                        "oneOf": [
+                            { "type": "string" },
+                            { "type": "array", "items": { "type": "integer", "minimum": 0, "maximum": 255 } }
+                        ],
+                        "description": "Path including the listed root; raw bytes when it isn't valid UTF-8 (Unix), in which case `name` is lossy"
+                    },
+                    "depth": count("Levels below the listed root, which is at 0"),
+                    "size": count("Size in bytes, for files whose metadata could be read"),
+                    "matches": count("Content match count, for files found by a content search"),
+                    "dup_group": count("Group shared by files with identical contents (--dupes)"),
+                    "child_count": count("Listed children of an expanded directory (--counts)"),
+                    "skipped": { "const": true, "description": "Set on a common heavy directory listed without its contents (--show-skipped)" },
+                    "stopped": { "const": true, "description": "Set on a directory the walk listed without descending into (--stop-at, --stop-at-marker)" },
+                    "mount_point": { "const": true, "description": "Set on a mount point listed without descending into (--one-file-system)" },
+                    "vanished": { "const": true, "description": "Set on an entry deleted between the walk and its stat" },
+                    "collapsed": {
+                        "type": "object",
+                        "description": "Files and total size below a large directory listed without its contents (--collapse-over)",
+                        "required": ["files", "size"],
+                        "additionalProperties": false,
+                        "properties": {
+                            "files": count("Number of files below it"),
+                            "size": count("Total size in bytes")
+                        }
+                    },
+                    "empty": { "const": true, "description": "Set on an expanded directory with no listed children (--mark-empty), and on the empty directories an --empty-dirs listing is made of" },
+                    "children": {
+                        "type": "array",
+                        "items": { "$ref": "#/$defs/node" },
+                        "description": "Listed children; absent for files and for directories a search pruned"
+                    },
+                    "truncated": { "const": true, "description": "Set on the root when --max-results cut a search short" },
+                    "stats": {
+                        "type": "object",
+                        "description": "Per-extension totals on the root (--stats)",
+                        "additionalProperties": {
+                            "type": "object",
+                            "required": ["files", "size"],
+                            "additionalProperties": false,
+                            "properties": {
+                                "files": count("Number of files"),
+                                "size": count("Total size in bytes")
+                            }
+                        }
+                    },
+                    "summary": {
+                        "type": "object",
+                        "description": "Listed directories and files below the root, on the root (--report)",
+                        "required": ["directories", "files"],
+                        "additionalProperties": false,
+                        "properties": {
+                            "directories": count("Number of listed directories, not counting the root"),
+                            "files": count("Number of listed files"),
+                            "mount_points": count("Listed mount points not descended into (--one-file-system); absent when none")
+                        }
+                    },
+                    "depth_limit": count("--depth the tree was listed with, 0 for unlimited, on the root; --diff walks as deep")
+                }
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::core::listing::{NodeKind, TreeNode};
+    use crate::core::stats::{ExtensionStats, Summary};
+
+    fn node(name: &str, kind: NodeKind) -> TreeNode {
+        TreeNode {
+            name: name.to_string(),
+            kind,
+            path: PathBuf::from("root").join(name),
+            depth: 1,
+            size: None,
+            matches: None,
+            dup_group: None,
+            child_count: None,
+            skipped: false,
+            stopped: false,
+            mount_point: false,
+            vanished: false,
+            collapsed: None,
+            empty: false,
+            children: None,
+            truncated: false,
+            stats: None,
+            summary: None,
+            depth_limit: None,
+        }
+    }
+
+    #[test]
+    fn test_schema_lists_every_serialized_field() {
+        let full = TreeNode {
+            size: Some(3),
+            matches: Some(1),
+            dup_group: Some(2),
+            child_count: Some(0),
+            skipped: true,
+            stopped: true,
+            mount_point: true,
+            vanished: true,
+            collapsed: Some(ExtensionStats { files: 41, size: 2048 }),
+            empty: true,
+            children: Some(Vec::new()),
+            truncated: true,
+            stats: Some(BTreeMap::from([("rs".to_string(), ExtensionStats { files: 1, size: 3 })])),
+            summary: Some(Summary { directories: 0, files: 1, mount_points: 1 }),
+            depth_limit: Some(0),
+            ..node("a.rs", NodeKind::File)
+        };
+        let fields: Vec<String> = full.to_json().as_object().unwrap().keys().cloned().collect();
+        let schema = tree_schema();
+        let properties = schema["$defs"]["node"]["properties"].as_object().unwrap();
+        let mut documented: Vec<String> = properties.keys().cloned().collect();
+        let mut serialized = fields;
+        documented.sort();
+        serialized.sort();
+        assert_eq!(documented, serialized);
+        // Field order is part of the contract
+        let text = serde_json::to_string(&full).unwrap();
+        let position = |key: &str| text.find(&format!("\"{}\":", key)).unwrap();
+        assert!(position("name") < position("type") && position("type") < position("path"));
+        assert!(position("path") < position("depth") && position("depth") < position("size"));
+        assert!(position("children") < position("stats") && position("stats") < position("summary"));
+    }
+
+    #[test]
+    fn test_json_round_trips_through_tree_node() {
+        let tree = TreeNode {
+            child_count: Some(2),
+            children: Some(vec![
+                TreeNode { size: Some(10), matches: Some(4), ..node("main.rs", NodeKind::File) },
+                TreeNode { children: Some(Vec::new()), ..node("docs", NodeKind::Directory) },
+            ]),
+            truncated: true,
+            depth: 0,
+            ..node("src", NodeKind::Directory)
+        };
+        let text = serde_json::to_string_pretty(&tree).unwrap();
+        assert!(!text.contains("dup_group"), "absent options are omitted");
+        assert!(text.contains("\"depth\": 0") && text.contains("\"depth\": 1"), "depth is always written");
+        let parsed: TreeNode = serde_json::from_str(&text).unwrap();
+        assert_eq!(parsed, tree);
+    }
+}
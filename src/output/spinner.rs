@@ -0,0 +1,112 @@
+use std::io::{IsTerminal, Write};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use super::formatter::format_count;
+
+/// How often the spinner repaints
+const FRAME_INTERVAL: Duration = Duration::from_millis(120);
+
+/// Animated stderr spinner showing how many entries have been scanned so far
+///
+/// Traversal code bumps the shared counter; the spinner thread only reads it
+/// when repainting, so counting costs a single relaxed atomic add per entry.
+pub struct Spinner {
+    stop: Arc<AtomicBool>,
+    scanned: Arc<AtomicUsize>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl Spinner {
+    /// Start spinning on stderr with the given label (e.g. `"Searching..."`)
+    pub fn start(label: &'static str) -> Self {
+        let stop = Arc::new(AtomicBool::new(false));
+        let scanned = Arc::new(AtomicUsize::new(0));
+        let stop_flag = Arc::clone(&stop);
+        let counter = Arc::clone(&scanned);
+
+        let handle = std::thread::spawn(move || {
+            let frames = ["|", "/", "-", "\\"];
+            let mut i = 0usize;
+            eprint!("\x1B[?25l"); // Hide cursor
+            let _ = std::io::stderr().flush();
+            while !stop_flag.load(Ordering::Relaxed) {
+                let count = counter.load(Ordering::Relaxed);
+                if count > 0 {
+                    eprint!("\r{} {} {} entries", label, frames[i % frames.len()], format_count(count));
+                } else {
+                    eprint!("\r{} {}", label, frames[i % frames.len()]);
+                }
+                let _ = std::io::stderr().flush();
+                i = i.wrapping_add(1);
+                std::thread::sleep(FRAME_INTERVAL);
+            }
+            clear_line();
+            eprint!("\x1B[?25h"); // Show cursor
+            let _ = std::io::stderr().flush();
+        });
+
+        Self {
+            stop,
+            scanned,
+            handle: Some(handle),
+        }
+    }
+
+    /// Start a spinner only when requested and stderr is an interactive terminal
+    ///
+    /// Redirected stderr never gets a spinner, so logs stay free of control sequences.
+    pub fn start_if(requested: bool, label: &'static str) -> Option<Self> {
+        if requested && std::io::stderr().is_terminal() {
+            Some(Self::start(label))
+        } else {
+            None
+        }
+    }
+
+    /// Flag that stops the spinner when set (e.g. right before the first line is printed)
+    pub fn stop_flag(&self) -> Arc<AtomicBool> {
+        Arc::clone(&self.stop)
+    }
+
+    /// Counter of scanned entries displayed by the spinner
+    pub fn counter(&self) -> Arc<AtomicUsize> {
+        Arc::clone(&self.scanned)
+    }
+
+    /// Stop the spinner and wait for it to restore the cursor
+    pub fn finish(mut self) {
+        self.shutdown();
+    }
+
+    fn shutdown(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+impl Drop for Spinner {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+/// Clear the current stderr line
+pub fn clear_line() {
+    eprint!("\r\x1B[2K");
+    let _ = std::io::stderr().flush();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_disabled_spinner_is_never_started() {
+        assert!(Spinner::start_if(false, "Searching...").is_none());
+    }
+}
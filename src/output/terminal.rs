@@ -1,9 +1,10 @@
 use std::io::IsTerminal;
 
 /// Terminal character set for tree drawing
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
 pub enum CharacterSet {
     /// Unicode box-drawing characters (├ └ │ ─)
+    #[default]
     Unicode,
     /// ASCII fallback characters (| + -)
     Ascii,
@@ -49,6 +50,98 @@ impl CharacterSet {
     }
 }
 
+/// Which glyphs draw the tree's branches (`--tree-style`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum TreeStyle {
+    /// Detect for the terminal; ASCII for files written with `-o`
+    #[default]
+    Auto,
+    /// Box-drawing characters
+    Unicode,
+    /// `|`, `+--` and `` `-- ``
+    Ascii,
+}
+
+impl TreeStyle {
+    /// The character set to draw with, given the destination's default
+    pub fn resolve(self, default: CharacterSet) -> CharacterSet {
+        match self {
+            TreeStyle::Auto => default,
+            TreeStyle::Unicode => CharacterSet::Unicode,
+            TreeStyle::Ascii => CharacterSet::Ascii,
+        }
+    }
+}
+
+/// When to emit ANSI colors (`--color`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum ColorMode {
+    /// Color the terminal when it supports it; never color exported files
+    #[default]
+    Auto,
+    /// Always color, including files written with `-o`
+    Always,
+    /// Never color
+    Never,
+}
+
+impl ColorMode {
+    /// Make the process-wide color switch follow this mode and the environment
+    pub fn apply(self) {
+        colored::control::set_override(resolve_color(self, std::io::stdout().is_terminal()));
+    }
+
+    /// Whether files written with `-o` keep ANSI codes
+    pub fn colors_files(self) -> bool {
+        self == ColorMode::Always
+    }
+}
+
+/// Whether terminal output is colored
+///
+/// Precedence, highest first: `--color always|never`, `NO_COLOR`, `CLICOLOR_FORCE`,
+/// `CLICOLOR=0`, then whether stdout is a terminal.
+pub fn resolve_color(cli_flag: ColorMode, is_tty: bool) -> bool {
+    color_from_env(cli_flag, is_tty, |name| std::env::var(name).ok())
+}
+
+fn color_from_env(cli_flag: ColorMode, is_tty: bool, var: impl Fn(&str) -> Option<String>) -> bool {
+    // Following no-color.org, an empty NO_COLOR doesn't count
+    let set = |name: &str| var(name).is_some_and(|v| !v.is_empty());
+    match cli_flag {
+        ColorMode::Always => true,
+        ColorMode::Never => false,
+        ColorMode::Auto if set("NO_COLOR") => false,
+        ColorMode::Auto if set("CLICOLOR_FORCE") && var("CLICOLOR_FORCE").as_deref() != Some("0") => true,
+        ColorMode::Auto if var("CLICOLOR").as_deref() == Some("0") => false,
+        ColorMode::Auto => is_tty,
+    }
+}
+
+/// Width of the terminal in columns: `$COLUMNS`, then the stdout window size, then 80
+pub fn terminal_width() -> usize {
+    std::env::var("COLUMNS")
+        .ok()
+        .and_then(|c| c.parse().ok())
+        .filter(|&c| c > 0)
+        .or_else(window_width)
+        .unwrap_or(80)
+}
+
+#[cfg(unix)]
+fn window_width() -> Option<usize> {
+    use std::os::fd::AsRawFd;
+    let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+    // SAFETY: TIOCGWINSZ only writes into the winsize struct we pass
+    let ok = unsafe { libc::ioctl(std::io::stdout().as_raw_fd(), libc::TIOCGWINSZ, &mut size) } == 0;
+    (ok && size.ws_col > 0).then_some(size.ws_col as usize)
+}
+
+#[cfg(not(unix))]
+fn window_width() -> Option<usize> {
+    None
+}
+
 /// Check if the terminal supports Unicode characters
 fn supports_unicode() -> bool {
     // Check if stdout is a terminal
@@ -133,6 +226,60 @@ mod tests {
         assert_eq!(ascii.continuation(), "|   ");
     }
 
+    #[test]
+    fn test_tree_style_overrides_destination_default() {
+        assert_eq!(TreeStyle::Auto.resolve(CharacterSet::Ascii), CharacterSet::Ascii);
+        assert_eq!(TreeStyle::Auto.resolve(CharacterSet::Unicode), CharacterSet::Unicode);
+        assert_eq!(TreeStyle::Unicode.resolve(CharacterSet::Ascii), CharacterSet::Unicode);
+        assert_eq!(TreeStyle::Ascii.resolve(CharacterSet::Unicode), CharacterSet::Ascii);
+    }
+
+    #[test]
+    fn test_only_always_colors_files() {
+        assert!(ColorMode::Always.colors_files());
+        assert!(!ColorMode::Auto.colors_files());
+        assert!(!ColorMode::Never.colors_files());
+    }
+
+    fn resolve_with(flag: ColorMode, is_tty: bool, vars: &[(&str, &str)]) -> bool {
+        color_from_env(flag, is_tty, |name| {
+            vars.iter().find(|(k, _)| *k == name).map(|(_, v)| v.to_string())
+        })
+    }
+
+    #[test]
+    fn test_color_flag_beats_environment() {
+        let all = [("NO_COLOR", "1"), ("CLICOLOR_FORCE", "1"), ("CLICOLOR", "0")];
+        assert!(resolve_with(ColorMode::Always, false, &all));
+        assert!(resolve_with(ColorMode::Always, false, &[("NO_COLOR", "1")]));
+        assert!(!resolve_with(ColorMode::Never, true, &all));
+        assert!(!resolve_with(ColorMode::Never, true, &[("CLICOLOR_FORCE", "1")]));
+    }
+
+    #[test]
+    fn test_no_color_beats_clicolor_force() {
+        assert!(!resolve_with(ColorMode::Auto, true, &[("NO_COLOR", "1"), ("CLICOLOR_FORCE", "1")]));
+        assert!(!resolve_with(ColorMode::Auto, true, &[("NO_COLOR", "1")]));
+        // An empty NO_COLOR is treated as unset
+        assert!(resolve_with(ColorMode::Auto, true, &[("NO_COLOR", "")]));
+    }
+
+    #[test]
+    fn test_clicolor_force_colors_pipes_and_beats_clicolor() {
+        assert!(resolve_with(ColorMode::Auto, false, &[("CLICOLOR_FORCE", "1")]));
+        assert!(resolve_with(ColorMode::Auto, false, &[("CLICOLOR_FORCE", "1"), ("CLICOLOR", "0")]));
+        assert!(!resolve_with(ColorMode::Auto, false, &[("CLICOLOR_FORCE", "0")]));
+    }
+
+    #[test]
+    fn test_clicolor_zero_disables_and_tty_decides_otherwise() {
+        assert!(!resolve_with(ColorMode::Auto, true, &[("CLICOLOR", "0")]));
+        assert!(resolve_with(ColorMode::Auto, true, &[("CLICOLOR", "1")]));
+        assert!(!resolve_with(ColorMode::Auto, false, &[("CLICOLOR", "1")]));
+        assert!(resolve_with(ColorMode::Auto, true, &[]));
+        assert!(!resolve_with(ColorMode::Auto, false, &[]));
+    }
+
     #[test]
     fn test_detect_returns_valid_charset() {
         let charset = CharacterSet::detect();